@@ -63,6 +63,15 @@ export const getProgram = (provider: AnchorProvider, programId: PublicKey = PROG
 };
 
 
+// Mirrors the Rust PaymentCategory enum
+export const PAYMENT_CATEGORIES = {
+    Generic: 0,
+    Salary: 1,
+    Invoice: 2,
+    Gift: 3,
+    Refund: 4,
+} as const;
+
 /**
  * Sends a payment transaction using the Anchor program.
  * @param program - The initialized Anchor program instance.
@@ -70,6 +79,15 @@ export const getProgram = (provider: AnchorProvider, programId: PublicKey = PROG
  * @param receiver - The receiver's PublicKey.
  * @param amountLamports - The amount to send in lamports (as a BN).
  * @param memo - A string memo for the transaction.
+ * @param encryptedMemo - Optional ciphertext for the receiver; leave empty when using a plaintext memo.
+ * @param reference - Optional 32-byte reconciliation key stored on the record (e.g. a hash of an order id).
+ * @param allowBelowRent - Skip the check that the receiver ends up rent-exempt (defaults to false).
+ * @param force - Send even if it looks like a duplicate of the previous payment (defaults to false).
+ * @param category - PaymentCategory discriminant for analytics (see PAYMENT_CATEGORIES, defaults to Generic).
+ * @param idempotencyKey - Optional 16-byte key; reuse it when retrying so the payment is sent at most once.
+ * @param errorOnDuplicate - Fail with DuplicateIdempotencyKey instead of succeeding when the key was already used.
+ * @param allowProgramReceiver - Allow a receiver owned by another program, e.g. its PDA (defaults to false). Executable accounts are always rejected.
+ * @param note - Sender-side bookkeeping note kept on the record apart from the memo (defaults to none). Not encrypted.
  * @returns The transaction signature.
  * @throws Error if the program state is not initialized or other issues occur.
  */
@@ -78,7 +96,16 @@ export const sendPaymentTransaction = async (
     sender: PublicKey,
     receiver: PublicKey,
     amountLamports: BN,
-    memo: string
+    memo: string,
+    encryptedMemo: Buffer = Buffer.alloc(0),
+    reference: number[] | null = null,
+    allowBelowRent: boolean = false,
+    force: boolean = false,
+    category: number = PAYMENT_CATEGORIES.Generic,
+    idempotencyKey: number[] | null = null,
+    errorOnDuplicate: boolean = false,
+    allowProgramReceiver: boolean = false,
+    note: string = ""
 ): Promise<TransactionSignature> => {
 
     // 1. Derive the program state PDA
@@ -87,11 +114,21 @@ export const sendPaymentTransaction = async (
         program.programId
     );
 
-    // 2. Fetch the current transaction count from the program state
-    let currentTotalTransactions: BN;
+    // 2. Fetch the fee collector, whitelist setting, payment hook and fee reserve from the program state
+    let feeCollector: PublicKey;
+    let whitelistEnabled: boolean;
+    let hookProgram: PublicKey | null;
+    let minReserve: BN;
+    let feeSplit: { collector: PublicKey; shareBps: number }[];
+    let dedupWindowSecs: BN;
     try {
         const stateAccount = await program.account.programState.fetch(programStatePDA);
-        currentTotalTransactions = stateAccount.totalTransactions;
+        feeCollector = stateAccount.feeCollector;
+        whitelistEnabled = stateAccount.whitelistEnabled;
+        hookProgram = stateAccount.hookProgram; // Must be passed while a payment hook is configured
+        minReserve = stateAccount.minReserve; // Left with the sender after the payment
+        feeSplit = stateAccount.feeSplit; // Fee collectors go in as remaining accounts, in this order
+        dedupWindowSecs = stateAccount.dedupWindowSecs; // 0 = duplicate detection off
     } catch (error) {
         console.error("Failed to fetch program state:", error);
         // Handle case where state might not be initialized
@@ -99,27 +136,119 @@ export const sendPaymentTransaction = async (
         throw new Error("Program state not found or initialized. Please initialize the program state first.");
     }
 
-    // 3. Derive the transaction record PDA using the fetched count
+    // 3. Fetch the sender's own transaction count from their user state
+    const [userStatePDA] = PublicKey.findProgramAddressSync(
+        [Buffer.from("user"), sender.toBuffer()],
+        program.programId
+    );
+    let currentTransactionCount: BN;
+    let spendLimitEnabled: boolean;
+    try {
+        const userState = await program.account.userState.fetch(userStatePDA);
+        currentTransactionCount = userState.transactionCount;
+        spendLimitEnabled = userState.spendLimitEnabled;
+    } catch (error) {
+        console.error("Failed to fetch user state:", error);
+        throw new Error("User state not found. Please call initialize_user for this wallet first.");
+    }
+
+    // 4. Derive the transaction record PDA using the sender's count
     const [transactionRecordPDA] = PublicKey.findProgramAddressSync(
         [
             Buffer.from("transaction"),
             sender.toBuffer(),
-            currentTotalTransactions.toArrayLike(Buffer, "le", 8) // u64 as 8-byte Little Endian buffer
+            currentTransactionCount.toArrayLike(Buffer, "le", 8) // u64 as 8-byte Little Endian buffer
         ],
         program.programId
     );
 
-    // 4. Call the send_payment instruction
+    // The whitelist entry is an optional account, only passed while the whitelist is enabled
+    const whitelistEntryPDA = whitelistEnabled
+        ? PublicKey.findProgramAddressSync([Buffer.from("whitelist"), receiver.toBuffer()], program.programId)[0]
+        : null;
+    // Likewise, the spend limit account is only required once the sender has set a limit
+    const spendLimitPDA = spendLimitEnabled
+        ? PublicKey.findProgramAddressSync([Buffer.from("limit"), sender.toBuffer()], program.programId)[0]
+        : null;
+    // Blacklist PDAs are always passed; the program checks whether an entry exists at each
+    const [senderBlacklistPDA] = PublicKey.findProgramAddressSync([Buffer.from("blacklist"), sender.toBuffer()], program.programId);
+    const [receiverBlacklistPDA] = PublicKey.findProgramAddressSync([Buffer.from("blacklist"), receiver.toBuffer()], program.programId);
+    // Likewise for the sender's compliance freeze flag
+    const [senderFreezePDA] = PublicKey.findProgramAddressSync([Buffer.from("freeze"), sender.toBuffer()], program.programId);
+    // Created on the receiver's first payment
+    const [receiverStatsPDA] = PublicKey.findProgramAddressSync([Buffer.from("received"), receiver.toBuffer()], program.programId);
+    // Partner senders exempted from the protocol fee pass their exemption PDA
+    const [feeExemptionAddress] = PublicKey.findProgramAddressSync([Buffer.from("fee_exempt"), sender.toBuffer()], program.programId);
+    const feeExemptionPDA = (await program.account.feeExemption.fetchNullable(feeExemptionAddress)) ? feeExemptionAddress : null;
+    // Tracks the last payment to this receiver for the cooldown; created on first use
+    const [paymentPairPDA] = PublicKey.findProgramAddressSync([Buffer.from("pair"), sender.toBuffer(), receiver.toBuffer()], program.programId);
+    // Only passed while duplicate detection is on, so no rent is spent on it otherwise
+    const recentPaymentPDA = dedupWindowSecs.gtn(0)
+        ? PublicKey.findProgramAddressSync([Buffer.from("dedup"), sender.toBuffer()], program.programId)[0]
+        : null;
+    // Only passed together with an idempotency key
+    const idempotencyRecordPDA = idempotencyKey
+        ? PublicKey.findProgramAddressSync([Buffer.from("idem"), sender.toBuffer(), Buffer.from(idempotencyKey)], program.programId)[0]
+        : null;
+    // Receivers who require acceptance get a pending transfer at the record's sequence instead
+    const [receiverAcceptancePDA] = PublicKey.findProgramAddressSync([Buffer.from("accept"), receiver.toBuffer()], program.programId);
+    const pendingTransferPDA = (await program.account.acceptancePolicy.fetchNullable(receiverAcceptancePDA))
+        ? PublicKey.findProgramAddressSync(
+            [Buffer.from("pending"), sender.toBuffer(), currentTransactionCount.toArrayLike(Buffer, "le", 8)],
+            program.programId
+        )[0]
+        : null;
+    // Net-flow ledgers for both wallets; created on first use
+    const [senderLedgerPDA] = PublicKey.findProgramAddressSync([Buffer.from("ledger"), sender.toBuffer()], program.programId);
+    const [receiverLedgerPDA] = PublicKey.findProgramAddressSync([Buffer.from("ledger"), receiver.toBuffer()], program.programId);
+
+    // 5. Pre-flight: the sender pays the record's rent and the network fee on top of the
+    // amount, and must keep the program's fee reserve. Checking here surfaces the same
+    // InsufficientForRentAndPayment / WouldDrainFeeReserve conditions before a
+    // transaction is signed, rather than a generic System Program failure.
+    const connection = program.provider.connection;
+    const recordRent = await connection.getMinimumBalanceForRentExemption(TRANSACTION_RECORD_ACCOUNT_SIZE);
+    // Whatever stays behind must also keep the (data-less) sender account rent-exempt
+    const senderRent = await connection.getMinimumBalanceForRentExemption(0);
+    const senderBalance = await connection.getBalance(sender);
+    const leftover = BN.max(minReserve, new BN(senderRent));
+    const required = amountLamports.add(new BN(recordRent)).add(new BN(TX_FEE_ESTIMATE_LAMPORTS)).add(leftover);
+    if (new BN(senderBalance).lt(required)) {
+        throw new Error(
+            `Insufficient funds: need ${required.toString()} lamports (payment + ${recordRent} record rent + ~${TX_FEE_ESTIMATE_LAMPORTS} fee + ${leftover.toString()} left for rent and fees), have ${senderBalance}.`
+        );
+    }
+
+    // 6. Call the send_payment instruction
     try {
         const txSignature = await program.methods
-            .sendPayment(amountLamports, memo)
+            .sendPayment(amountLamports, memo, encryptedMemo, reference, allowBelowRent, force, category, idempotencyKey, errorOnDuplicate, allowProgramReceiver, note)
             .accounts({
                 sender: sender,
                 receiver: receiver,
+                feeCollector: feeCollector,
                 systemProgram: SystemProgram.programId,
                 transactionRecord: transactionRecordPDA,
+                userState: userStatePDA,
                 programState: programStatePDA,
+                whitelistEntry: whitelistEntryPDA,
+                spendLimit: spendLimitPDA,
+                senderBlacklist: senderBlacklistPDA,
+                receiverBlacklist: receiverBlacklistPDA,
+                senderFreeze: senderFreezePDA,
+                receiverStats: receiverStatsPDA,
+                feeExemption: feeExemptionPDA,
+                paymentPair: paymentPairPDA,
+                recentPayment: recentPaymentPDA,
+                hookProgram: hookProgram,
+                idempotencyRecord: idempotencyRecordPDA,
+                receiverAcceptance: receiverAcceptancePDA,
+                pendingTransfer: pendingTransferPDA,
+                senderLedger: senderLedgerPDA,
+                receiverLedger: receiverLedgerPDA,
+                rentPayer: sender, // The wallet pays the rent itself; a sponsor would have to co-sign, which this flow has no way to collect
             } as any) // Temporary cast to 'any' to bypass TS check
+            .remainingAccounts(feeSplit.map((share) => ({ pubkey: share.collector, isWritable: true, isSigner: false })))
             // Note: No explicit signer needed here if using an AnchorProvider
             // with a connected wallet, as the provider handles signing.
             .rpc();
@@ -141,10 +270,31 @@ export interface TransactionRecordAccount {
     amount: BN;
     timestamp: BN;
     memo: string;
+    mint: PublicKey | null; // null for native SOL payments
+    receiverCount: number; // >1 for split payments
+    fee: BN; // Protocol fee included in `amount`
+    memoEncrypted: boolean;
+    encryptedMemo: Buffer;
+    refundOf: BN | null; // Sequence of the refunded payment, null for regular payments
+    refunded: BN;
+    reference: number[] | null;
+    tip: BN; // Gratuity paid on top of `amount`
+    tipRecipient: PublicKey | null;
+    feeExempt: boolean;
+    schemaVersion: number;
+    prevSequence: BN | null; // Sender's previous record, null for their first
+    category: number; // PaymentCategory discriminant, see PAYMENT_CATEGORIES
+    owner: PublicKey; // Current holder of the receipt; the default key means the sender
+    note: string; // Sender's bookkeeping note, empty when none
+    mintDecimals: number; // Decimals of `mint` for formatting `amount`, 0 for SOL payments
+    data: Buffer; // Binary reference from send_payment_raw, empty when none
+    memoCode: number | null; // MemoDictionary code used instead of `memo`, null for typed memos
 }
 
-// Calculated size based on Rust struct: 8 + 32 + 32 + 8 + 8 + 4 + (200 * 4) = 892
-const TRANSACTION_RECORD_ACCOUNT_SIZE = 892;
+// Calculated size based on Rust struct: 8 + 32 + 32 + 8 + 8 + 4 + (200 * 4) + (1 + 32) + 1 + 8 + 1 + (4 + 256) + (1 + 8) + 8 + (1 + 32) + 8 + (1 + 32) + 1 + 1 + (1 + 8) + 1 + 32 + (4 + 100 * 4) + 1 + (4 + 64) + (1 + 2) = 1806
+const TRANSACTION_RECORD_ACCOUNT_SIZE = 1806;
+// Base fee for a single-signature transaction, mirrors TX_FEE_ESTIMATE_LAMPORTS in the program
+const TX_FEE_ESTIMATE_LAMPORTS = 5000;
 
 /**
  * Fetches the transaction history for a given sender.
@@ -159,9 +309,11 @@ export const getTransactionHistory = async (
     senderPublicKey: PublicKey
 ): Promise<TransactionRecordAccount[]> => {
     const filters: GetProgramAccountsFilter[] = [
-        // 1. Filter by account size
+        // 1. Filter by account discriminator rather than size, since coded records
+        //    are smaller than TRANSACTION_RECORD_ACCOUNT_SIZE and grow_record can
+        //    leave records larger
         {
-            dataSize: TRANSACTION_RECORD_ACCOUNT_SIZE,
+            memcmp: program.coder.accounts.memcmp("TransactionRecord"),
         },
         // 2. Filter by the sender public key (starts at offset 8)
         {
@@ -205,4 +357,67 @@ export const getTransactionHistory = async (
     }
 };
 
+/**
+ * Fetches one page of a sender's history by walking the on-chain `prevSequence` links,
+ * newest first, without scanning every program account.
+ * @param program - The initialized Anchor program instance.
+ * @param senderPublicKey - The PublicKey of the sender whose history to page through.
+ * @param cursor - Sequence to start from (inclusive); defaults to the sender's `headSequence`.
+ * @param pageSize - Maximum number of records to return.
+ * @returns The records on this page and the cursor for the next page (null when exhausted).
+ */
+export const getTransactionPage = async (
+    program: Program<PeerToPeerPayment>,
+    senderPublicKey: PublicKey,
+    cursor: BN | null = null,
+    pageSize: number = 10
+): Promise<{ records: TransactionRecordAccount[]; nextCursor: BN | null }> => {
+    let next = cursor;
+    if (next === null) {
+        const [userStatePDA] = PublicKey.findProgramAddressSync(
+            [Buffer.from("user"), senderPublicKey.toBuffer()],
+            program.programId
+        );
+        const userState = await program.account.userState.fetch(userStatePDA);
+        next = userState.headSequence;
+    }
+
+    const records: TransactionRecordAccount[] = [];
+    while (next !== null && records.length < pageSize) {
+        const [recordPDA] = PublicKey.findProgramAddressSync(
+            [Buffer.from("transaction"), senderPublicKey.toBuffer(), next.toArrayLike(Buffer, "le", 8)],
+            program.programId
+        );
+        const record = await program.account.transactionRecord.fetchNullable(recordPDA) as TransactionRecordAccount | null;
+        if (record === null) {
+            // The record was closed, which breaks the chain at this point
+            next = null;
+            break;
+        }
+        records.push(record);
+        next = record.prevSequence;
+    }
+    return { records, nextCursor: next };
+};
+
+/**
+ * Reads a wallet's lifetime sent and received totals from its UserLedger.
+ * @returns Both totals and their difference (received - sent), or null if the wallet has never paid or been paid.
+ */
+export const getNetFlow = async (
+    program: Program<PeerToPeerPayment>,
+    user: PublicKey
+): Promise<{ totalSent: BN; totalReceived: BN; net: BN } | null> => {
+    const [ledgerPDA] = PublicKey.findProgramAddressSync([Buffer.from("ledger"), user.toBuffer()], program.programId);
+    const ledger = await program.account.userLedger.fetchNullable(ledgerPDA);
+    if (ledger === null) {
+        return null;
+    }
+    return {
+        totalSent: ledger.totalSent,
+        totalReceived: ledger.totalReceived,
+        net: ledger.totalReceived.sub(ledger.totalSent),
+    };
+};
+
 // Removed duplicated code block
\ No newline at end of file
@@ -1,165 +1,9580 @@
 use anchor_lang::prelude::*;
 use anchor_lang::system_program;
 use anchor_lang::solana_program::clock::Clock;
+use anchor_lang::solana_program::entrypoint::MAX_PERMITTED_DATA_INCREASE;
+use anchor_lang::solana_program::hash::hashv;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::invoke;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::{self, Mint, Token, TokenAccount};
 
 declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS"); // Replace with your program ID
 
+// Builds a DirectPayment from the policy, stats and ledger accounts every direct
+// payment instruction declares under the same names. Only where the lamports come
+// from and whose payment it is differ; send_payment overrides the options it exposes.
+macro_rules! direct_payment {
+    ($ctx:ident, $source:expr, $payer:expr) => {
+        DirectPayment {
+            program_state: &mut $ctx.accounts.program_state,
+            system_program: &$ctx.accounts.system_program,
+            source: $source,
+            receiver: &$ctx.accounts.receiver,
+            fee_collector: &$ctx.accounts.fee_collector,
+            fee_split_collectors: $ctx.remaining_accounts,
+            sender_policy: SenderPolicy {
+                blacklist: &$ctx.accounts.sender_blacklist,
+                freeze: &$ctx.accounts.sender_freeze,
+                spend_limit_enabled: $ctx.accounts.user_state.spend_limit_enabled,
+                spend_limit: $ctx.accounts.spend_limit.as_deref_mut(),
+            },
+            receiver_blacklist: &$ctx.accounts.receiver_blacklist,
+            receiver_acceptance: &$ctx.accounts.receiver_acceptance,
+            whitelist_entry: $ctx.accounts.whitelist_entry.as_deref(),
+            receiver_stats: &mut $ctx.accounts.receiver_stats,
+            receiver_stats_bump: $ctx.bumps.receiver_stats,
+            payer: $payer,
+            hook_program: $ctx.accounts.hook_program.as_deref(),
+            sender_ledger: &mut $ctx.accounts.sender_ledger,
+            sender_ledger_bump: $ctx.bumps.sender_ledger,
+            receiver_ledger: &mut $ctx.accounts.receiver_ledger,
+            receiver_ledger_bump: $ctx.bumps.receiver_ledger,
+            fee_exempt: false,
+            allow_program_receiver: false,
+            allow_below_rent: false,
+            pending_transfer: None,
+        }
+    };
+}
+
 #[program]
 pub mod peer_to_peer_payment {
     use super::*;
 
-    pub fn send_payment(ctx: Context<SendPayment>, amount: u64, memo: String) -> Result<()> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn send_payment<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SendPayment<'info>>,
+        amount: u64,
+        memo: String,
+        encrypted_memo: Vec<u8>,
+        reference: Option<[u8; 32]>,
+        allow_below_rent: bool,
+        force: bool,
+        category: u8,
+        idempotency_key: Option<[u8; 16]>,
+        error_on_duplicate: bool,
+        allow_program_receiver: bool,
+        note: String,
+    ) -> Result<()> {
+        // --- Idempotency ---
+        // A client retry reuses the key of the payment it already sent. The payment
+        // is not repeated: either report the duplicate, or give back the accounts
+        // Anchor just created for this attempt and succeed without transferring.
+        match (idempotency_key, ctx.accounts.idempotency_record.as_mut()) {
+            (Some(key), Some(idempotency_record)) => {
+                if idempotency_record.created_at != 0 {
+                    if error_on_duplicate {
+                        return err!(ErrorCode::DuplicateIdempotencyKey);
+                    }
+                    msg!("Idempotency key already used by payment {}; nothing sent",
+                         idempotency_record.sequence);
+                    if let Some(pending_transfer) = &ctx.accounts.pending_transfer {
+                        return pending_transfer.close(ctx.accounts.sender.to_account_info());
+                    }
+                    return Ok(());
+                }
+                idempotency_record.sender = ctx.accounts.sender.key();
+                idempotency_record.key = key;
+                idempotency_record.sequence = ctx.accounts.user_state.transaction_count;
+                idempotency_record.created_at = Clock::get()?.unix_timestamp;
+                idempotency_record.bump = ctx.bumps.idempotency_record.ok_or(ErrorCode::MissingIdempotencyRecord)?;
+            }
+            (None, None) => {}
+            _ => return err!(ErrorCode::MissingIdempotencyRecord),
+        }
+
         // --- Input Validation & Security Checks ---
-        // Check 1: Amount > 0
-        if amount == 0 {
-            return err!(ErrorCode::InvalidAmount);
+        // Checks 1-3: Amount > 0, Sender != Receiver, Memo Length (per-deployment limit)
+        let max_memo_length = ctx.accounts.program_state.max_memo_length as usize;
+        validate_payment(&ctx.accounts.sender.key(), &ctx.accounts.receiver.key(), amount, &memo, max_memo_length)?;
+        // Check 3b: Encrypted memo length, and never both a plaintext and an encrypted memo
+        validate_encrypted_memo(&memo, &encrypted_memo)?;
+        // Check 3g: The sender's private note has its own, independent limit
+        validate_note(&note)?;
+        // Check 3e: Category is a known PaymentCategory
+        PaymentCategory::try_from(category)?;
+        // Check 3d: Plaintext memos follow the configured reconciliation format, if any.
+        // Encrypted memos can't be inspected on-chain and are left to the receiver.
+        if let Some(pattern) = &ctx.accounts.program_state.memo_pattern {
+            if encrypted_memo.is_empty() && !pattern.matches(&memo) {
+                return err!(ErrorCode::MemoFormatInvalid);
+            }
         }
-        // Check 2: Sender != Receiver
-        if ctx.accounts.sender.key() == ctx.accounts.receiver.key() {
-            return err!(ErrorCode::SelfPayment);
+        // Check 4: Sufficient Sender Balance for the payment on top of the record's rent,
+        // unless a sponsor pays that, leaving the sender rent-exempt
+        let record_rent = if ctx.accounts.rent_payer.key() == ctx.accounts.sender.key() {
+            Rent::get()?.minimum_balance(TransactionRecord::LEN)
+        } else {
+            0
+        };
+        ensure_covers_rent_and_payment(&ctx.accounts.sender, amount, record_rent)?;
+        // Check 4b: Keep a reserve so a sender who is also the fee payer isn't stranded
+        let remaining = ctx.accounts.sender.lamports().saturating_sub(amount).saturating_sub(record_rent);
+        if remaining < ctx.accounts.program_state.min_reserve {
+            return err!(ErrorCode::WouldDrainFeeReserve);
         }
-        // Check 3: Memo Length
-        if memo.chars().count() > MAX_MEMO_LENGTH {
-             return err!(ErrorCode::MemoTooLong);
+        // Check 8b: Guard against accidental double-sends to the same receiver
+        let now = Clock::get()?.unix_timestamp;
+        let payment_pair = &mut ctx.accounts.payment_pair;
+        payment_pair.check_cooldown(ctx.accounts.program_state.cooldown_secs, now)?;
+        payment_pair.last_payment_ts = now;
+        payment_pair.bump = ctx.bumps.payment_pair; // Canonical bump, same on every call
+        // Check 8c: Reject an identical retry of the sender's last payment unless forced.
+        // The fingerprint is only needed while deduplication is on.
+        let dedup_window_secs = ctx.accounts.program_state.dedup_window_secs;
+        if dedup_window_secs > 0 && ctx.accounts.recent_payment.is_none() {
+            return err!(ErrorCode::MissingDedupRecord);
         }
-        // Check 4: Sufficient Sender Balance
-        if ctx.accounts.sender.lamports() < amount {
+        if let Some(recent_payment) = ctx.accounts.recent_payment.as_deref_mut() {
+            let payment_hash = hashv(&[
+                ctx.accounts.receiver.key().as_ref(),
+                &amount.to_le_bytes(),
+                memo.as_bytes(),
+            ])
+            .to_bytes();
+            if !force {
+                recent_payment.check_duplicate(&payment_hash, dedup_window_secs, now)?;
+            }
+            recent_payment.payment_hash = payment_hash;
+            recent_payment.sent_at = now;
+            recent_payment.bump = ctx.bumps.recent_payment.ok_or(ErrorCode::MissingDedupRecord)?;
+        }
+
+        // Check 8d: Receivers who require acceptance get a PendingTransfer instead of the
+        // lamports. The policy address is pinned by its seeds, so it can't be skipped.
+        let requires_acceptance = is_acceptance_required(&ctx.accounts.receiver_acceptance);
+        if requires_acceptance != ctx.accounts.pending_transfer.is_some() {
+            return err!(ErrorCode::MissingPendingTransfer);
+        }
+
+        // Checks 3c and 5-9 and the transfer: the rules every direct payment shares, with
+        // the options only this instruction exposes. Partners holding a fee exemption
+        // (seeds checked by the account constraint) pay no fee; a held payment escrows
+        // its fee on the pending transfer too.
+        let fee_exempt = ctx.accounts.fee_exemption.is_some();
+        let fee = DirectPayment {
+            fee_exempt,
+            allow_program_receiver,
+            allow_below_rent,
+            pending_transfer: ctx.accounts.pending_transfer.as_ref().map(|pending_transfer| pending_transfer.as_ref()),
+            ..direct_payment!(ctx, PaymentSource::Wallet(&ctx.accounts.sender), &ctx.accounts.sender)
+        }
+        .validate_and_charge(amount, amount)?;
+        let net_amount = amount.checked_sub(fee).ok_or(ErrorCode::Overflow)?;
+        let held_fee = if requires_acceptance { fee } else { 0 };
+
+        // Debug logs are opt-in: formatting pubkeys and the memo costs compute units
+        // on every payment, and PaymentSent below already carries the same data.
+        let verbose = ctx.accounts.program_state.verbose;
+        if verbose {
+            msg!("Payment Sent: {} lamports ({} fee) from {} to {} with memo: {}",
+                 amount,
+                 fee,
+                 ctx.accounts.sender.key(),
+                 ctx.accounts.receiver.key(),
+                 memo);
+        }
+
+        // Record the transaction
+        let transaction_record = &mut TransactionRecord::new_payment(
+            ctx.accounts.sender.key(),
+            ctx.accounts.receiver.key(),
+            amount,
+            memo.clone(),
+            Clock::get()?.unix_timestamp,
+            fee,
+        );
+        transaction_record.memo_encrypted = !encrypted_memo.is_empty();
+        transaction_record.encrypted_memo = encrypted_memo;
+        transaction_record.reference = reference;
+        transaction_record.fee_exempt = fee_exempt;
+        transaction_record.category = category;
+        transaction_record.note = note;
+
+        // Held payments count towards the receiver's stats and ledger once accepted
+        if let Some(pending_transfer) = ctx.accounts.pending_transfer.as_mut() {
+            pending_transfer.sender = ctx.accounts.sender.key();
+            pending_transfer.receiver = ctx.accounts.receiver.key();
+            pending_transfer.amount = net_amount;
+            pending_transfer.fee = held_fee;
+            pending_transfer.sequence = ctx.accounts.user_state.transaction_count;
+            pending_transfer.created_at = transaction_record.timestamp;
+            pending_transfer.resolved = false;
+            pending_transfer.bump = ctx.bumps.pending_transfer.ok_or(ErrorCode::MissingPendingTransfer)?;
+            ctx.accounts.program_state.open_hold()?;
+            if verbose {
+                msg!("Payment held for {} until accepted, rejected or reclaimed", pending_transfer.receiver);
+            }
+        }
+
+        // Advance the sender's sequence and the global transaction count
+        let program_state = &mut ctx.accounts.program_state;
+        let sequence = advance_sequence(transaction_record, &mut ctx.accounts.user_state, program_state)?;
+        // Only created now, after every check, so a short balance fails with a clear error.
+        // The rent payer funds it; the rent stays with the record and goes to its owner
+        // when it is closed.
+        create_transaction_record(
+            &ctx.accounts.rent_payer,
+            &ctx.accounts.transaction_record,
+            &ctx.accounts.system_program,
+            TransactionRecord::LEN,
+            transaction_record,
+            sequence,
+            ctx.bumps.transaction_record,
+        )?;
+
+        if verbose {
+            msg!("Transaction recorded. Total transactions: {}", program_state.total_transactions);
+        }
+
+        // Canonical off-chain signal; the msg! logs above are for debugging only
+        emit!(PaymentSent {
+            sender: transaction_record.sender,
+            receiver: transaction_record.receiver,
+            amount,
+            memo,
+            timestamp: transaction_record.timestamp,
+            sequence,
+        });
+
+        Ok(())
+    }
+
+    pub fn send_token_payment(ctx: Context<SendTokenPayment>, amount: u64, memo: String, mint_decimals: u8) -> Result<()> {
+        // --- Input Validation & Security Checks ---
+        // Check 0: Neither party is blacklisted, and the receiver is whitelisted while the whitelist is on
+        ensure_not_blacklisted(&ctx.accounts.sender_blacklist)?;
+        ensure_not_blacklisted(&ctx.accounts.receiver_blacklist)?;
+        ensure_whitelisted(ctx.accounts.program_state.whitelist_enabled, ctx.accounts.whitelist_entry.as_deref())?;
+        // Check 0b: Sender is not frozen
+        ensure_not_frozen(&ctx.accounts.sender_freeze)?;
+        // Checks 1-3: Same rules as native SOL payments
+        let max_memo_length = ctx.accounts.program_state.max_memo_length as usize;
+        validate_payment(&ctx.accounts.sender.key(), &ctx.accounts.receiver.key(), amount, &memo, max_memo_length)?;
+        // Check 3b: Within the configured minimum and per-payment cap, in the mint's base units
+        // as for the multisig threshold
+        ensure_amount_within_bounds(&ctx.accounts.program_state, amount)?;
+        // Check 4: Sufficient Sender Token Balance
+        if ctx.accounts.sender_token_account.amount < amount {
             return err!(ErrorCode::InsufficientBalance);
         }
-        // Check 5: Receiver Account Ownership (Must be owned by System Program to receive SOL directly)
-        if *ctx.accounts.receiver.owner != system_program::ID {
-             return err!(ErrorCode::InvalidReceiver);
+        // Check 5: The decimals the client rendered `amount` with are the mint's own
+        if mint_decimals != ctx.accounts.mint.decimals {
+            return err!(ErrorCode::MintDecimalsMismatch);
         }
+        // Check 6: Large payments must go through propose/approve/execute. The threshold
+        // is compared in the mint's base units, and there is no token approval flow, so
+        // token payments above it are refused.
+        ensure_no_approval_required(&ctx.accounts.program_state, amount)?;
+        // Check 7: The payment fits the sender's daily spend limit
+        record_sender_spend(ctx.accounts.user_state.spend_limit_enabled, ctx.accounts.spend_limit.as_deref_mut(), amount)?;
+
+        // Per-mint stats; `max_total_received` is in lamports, so it doesn't apply here
+        let receiver_token_stats = &mut ctx.accounts.receiver_token_stats;
+        receiver_token_stats.claim(ctx.accounts.receiver.key(), ctx.bumps.receiver_token_stats);
+        receiver_token_stats.record_payment(amount, 0)?;
 
-        // Create the CPI context
+        // Create the CPI context for the SPL token transfer
         let cpi_context = CpiContext::new(
-            ctx.accounts.system_program.to_account_info(),
-            system_program::Transfer {
-                from: ctx.accounts.sender.to_account_info(),
-                to: ctx.accounts.receiver.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.sender_token_account.to_account_info(),
+                to: ctx.accounts.receiver_token_account.to_account_info(),
+                authority: ctx.accounts.sender.to_account_info(),
             },
         );
 
         // Execute the transfer
-        system_program::transfer(cpi_context, amount)?;
+        token::transfer(cpi_context, amount)?;
 
-        // Log the payment (optional, could store in an account later)
-        msg!("Payment Sent: {} lamports from {} to {} with memo: {}",
+        msg!("Token Payment Sent: {} units of mint {} from {} to {} with memo: {}",
              amount,
+             ctx.accounts.mint.key(),
              ctx.accounts.sender.key(),
              ctx.accounts.receiver.key(),
              memo);
 
         // Record the transaction
         let transaction_record = &mut ctx.accounts.transaction_record;
-        transaction_record.sender = ctx.accounts.sender.key();
-        transaction_record.receiver = ctx.accounts.receiver.key();
-        transaction_record.amount = amount;
-        transaction_record.memo = memo.clone(); // Clone memo as it was moved in the msg! macro
-        transaction_record.timestamp = Clock::get()?.unix_timestamp;
+        transaction_record.set_inner(TransactionRecord::new_payment(
+            ctx.accounts.sender.key(),
+            ctx.accounts.receiver.key(),
+            amount,
+            memo,
+            Clock::get()?.unix_timestamp,
+            0,
+        ));
+        transaction_record.mint = Some(ctx.accounts.mint.key());
+        transaction_record.mint_decimals = ctx.accounts.mint.decimals;
 
-        // Increment total transaction count
+        // Advance the sender's sequence (shared with SOL payments) and the global count
         let program_state = &mut ctx.accounts.program_state;
-        // Use checked_add for safety against overflow
-        program_state.total_transactions = program_state.total_transactions.checked_add(1).ok_or(ErrorCode::Overflow)?;
+        advance_sequence(&mut ctx.accounts.transaction_record, &mut ctx.accounts.user_state, program_state)?;
+
+        msg!("Transaction recorded. Total transactions: {}", program_state.total_transactions);
+
+        Ok(())
+    }
+
+    pub fn send_split_payment<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SendSplitPayment<'info>>,
+        amounts: Vec<u64>,
+        memo: String,
+    ) -> Result<()> {
+        // Remaining accounts are the fee split collectors, then one receiver group per
+        // entry in `amounts`
+        let (fee_split_collectors, receivers) = split_remaining_accounts(&ctx.accounts.program_state, ctx.remaining_accounts)?;
+
+        // --- Input Validation & Security Checks ---
+        // Every leg is validated before any lamports move, so a bad receiver fails the whole split.
+        // Check 0: Sender is neither blacklisted nor frozen
+        ensure_not_blacklisted(&ctx.accounts.sender_blacklist)?;
+        ensure_not_frozen(&ctx.accounts.sender_freeze)?;
+        // Check 1: One amount per receiver, at least one receiver
+        if receivers.is_empty() || receivers.len() != amounts.len() {
+            return err!(ErrorCode::SplitLengthMismatch);
+        }
+        // Check 1b: Fail early, with a suggested chunk size, instead of running out of compute
+        ensure_within_compute_budget(receivers.len(), MAX_SPLIT_RECEIVERS)?;
+        let receiver_count = u8::try_from(receivers.len()).map_err(|_| ErrorCode::SplitLengthMismatch)?;
+        let max_memo_length = ctx.accounts.program_state.max_memo_length as usize;
+        let mut total: u64 = 0;
+        for (receiver, amount) in receivers.iter().zip(amounts.iter()) {
+            // Check 2: Same per-payment rules as send_payment
+            validate_payment(&ctx.accounts.sender.key(), receiver.receiver.key, *amount, &memo, max_memo_length)?;
+            // Check 2b: Each leg is within the configured minimum and per-payment cap
+            ensure_amount_within_bounds(&ctx.accounts.program_state, *amount)?;
+            // Check 3: Receiver Account Ownership
+            if *receiver.receiver.owner != system_program::ID {
+                return err!(ErrorCode::InvalidReceiver);
+            }
+            // Check 3b: Receiver is not blacklisted and, while the whitelist is on, whitelisted
+            receiver.ensure_may_be_paid(&ctx.accounts.program_state)?;
+            // Check 4: Total must not overflow
+            total = total.checked_add(*amount).ok_or(ErrorCode::Overflow)?;
+        }
+        // Check 5: Sufficient Sender Balance for the whole split, fees included, on top of
+        // the record's rent, leaving the sender rent-exempt
+        let record_rent = Rent::get()?.minimum_balance(TransactionRecord::LEN);
+        ensure_covers_rent_and_payment(&ctx.accounts.sender, total, record_rent)?;
+        // Check 6: Large splits, by their total, must go through propose/approve/execute
+        ensure_no_approval_required(&ctx.accounts.program_state, total)?;
+        // Check 7: The split's total fits the sender's daily spend limit
+        record_sender_spend(ctx.accounts.user_state.spend_limit_enabled, ctx.accounts.spend_limit.as_deref_mut(), total)?;
+
+        // Execute one transfer per receiver, each counted towards its stats and cap.
+        // Each leg pays the fee a send_payment of its amount would; the fees are
+        // collected together once every receiver is paid.
+        let program_state = &ctx.accounts.program_state;
+        let (fee_bps, fee_rounding, max_total_received) = (program_state.fee_bps, program_state.fee_rounding, program_state.max_total_received);
+        let mut fee: u64 = 0;
+        for (receiver, amount) in receivers.iter().zip(amounts.iter()) {
+            let leg_fee = compute_fee(*amount, fee_bps, fee_rounding)?;
+            let net_amount = amount.checked_sub(leg_fee).ok_or(ErrorCode::Overflow)?;
+            fee = fee.checked_add(leg_fee).ok_or(ErrorCode::Overflow)?;
+            receiver.record_payment(&ctx.accounts.sender, &ctx.accounts.system_program, net_amount, max_total_received)?;
+            let cpi_context = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.sender.to_account_info(),
+                    to: receiver.receiver.clone(),
+                },
+            );
+            system_program::transfer(cpi_context, net_amount)?;
+        }
+        let fee_source = PaymentSource::Wallet(&ctx.accounts.sender);
+        for (collector, part) in fee_payouts(fee, &ctx.accounts.program_state, &ctx.accounts.fee_collector, fee_split_collectors)? {
+            fee_source.transfer(&ctx.accounts.system_program, collector, part)?;
+        }
+
+        msg!("Split Payment Sent: {} lamports from {} to {} receivers with memo: {}",
+             total,
+             ctx.accounts.sender.key(),
+             receiver_count,
+             memo);
+
+        // Record one aggregate transaction; `receiver` holds the first receiver
+        let transaction_record = &mut TransactionRecord::new_payment(
+            ctx.accounts.sender.key(),
+            receivers[0].receiver.key(),
+            total,
+            memo,
+            Clock::get()?.unix_timestamp,
+            fee,
+        );
+        transaction_record.receiver_count = receiver_count;
+
+        // Advance the sender's sequence and the global transaction count
+        let program_state = &mut ctx.accounts.program_state;
+        program_state.total_fees_collected = program_state.total_fees_collected.checked_add(fee).ok_or(ErrorCode::Overflow)?;
+        let sequence = advance_sequence(transaction_record, &mut ctx.accounts.user_state, program_state)?;
+        // Only created now, after every check, so a short balance fails with a clear error
+        create_transaction_record(
+            &ctx.accounts.sender,
+            &ctx.accounts.transaction_record,
+            &ctx.accounts.system_program,
+            TransactionRecord::LEN,
+            transaction_record,
+            sequence,
+            ctx.bumps.transaction_record,
+        )?;
 
         msg!("Transaction recorded. Total transactions: {}", program_state.total_transactions);
 
         Ok(())
     }
 
-    pub fn initialize_state(ctx: Context<InitializeState>) -> Result<()> {
+    pub fn send_percentage_split<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SendSplitPayment<'info>>,
+        total: u64,
+        shares: Vec<u16>,
+        memo: String,
+    ) -> Result<()> {
+        // Check 1: Shares are basis points of `total` and must cover all of it
+        let share_sum: u64 = shares.iter().map(|share| *share as u64).sum();
+        if share_sum != BPS_DENOMINATOR {
+            return err!(ErrorCode::InvalidShareSum);
+        }
+        // The rounding remainder goes to the first receiver
+        let amounts = split_by_bps(total, shares.iter().copied())?;
+
+        // Same receivers, checks and aggregate record as an absolute-amount split
+        send_split_payment(ctx, amounts, memo)
+    }
+
+    // Meant to be called once by the deployer, in the setup transaction right after
+    // deploying; only the program's upgrade authority may call it, so nobody can
+    // snipe the state in between. The caller funds the rent; control goes to
+    // `authority`, so a deployer key, a multisig or a governance PDA can be installed
+    // directly. The `init` constraint rejects any second call once the state exists.
+    pub fn initialize_state(ctx: Context<InitializeState>, max_memo_length: u16, authority: Pubkey) -> Result<()> {
+        // The limit is in characters, but can never exceed the bytes reserved for a memo
+        if max_memo_length as usize > MAX_MEMO_BYTES {
+            return err!(ErrorCode::MemoLimitTooLarge);
+        }
+        // The default key can never sign, which would leave the program without an admin
+        if authority == Pubkey::default() {
+            return err!(ErrorCode::InvalidAuthority);
+        }
         ctx.accounts.program_state.total_transactions = 0;
-        msg!("Program state initialized. Total transactions: 0");
+        ctx.accounts.program_state.total_volume = 0;
+        ctx.accounts.program_state.total_fees_collected = 0;
+        ctx.accounts.program_state.total_refunds = 0;
+        ctx.accounts.program_state.active_escrows = 0;
+        ctx.accounts.program_state.total_escrows = 0;
+        ctx.accounts.program_state.total_timelocks = 0;
+        ctx.accounts.program_state.total_streams = 0;
+        ctx.accounts.program_state.total_conditionals = 0;
+        ctx.accounts.program_state.total_refundables = 0;
+        ctx.accounts.program_state.total_scheduled = 0;
+        // Authority for admin instructions such as pausing
+        ctx.accounts.program_state.authority = authority;
+        ctx.accounts.program_state.paused = false;
+        // No protocol fee until the authority configures one
+        ctx.accounts.program_state.fee_bps = 0;
+        ctx.accounts.program_state.fee_collector = authority;
+        ctx.accounts.program_state.min_amount = 0;
+        ctx.accounts.program_state.max_single_payment = 0; // Unlimited
+        ctx.accounts.program_state.max_total_received = 0; // Unlimited
+        ctx.accounts.program_state.min_reserve = TX_FEE_ESTIMATE_LAMPORTS; // One base fee
+        ctx.accounts.program_state.whitelist_enabled = false;
+        ctx.accounts.program_state.multisig = MultisigConfig::default(); // Disabled
+        ctx.accounts.program_state.memo_pattern = None; // Free-form memos
+        ctx.accounts.program_state.teardown_requested_at = 0;
+        ctx.accounts.program_state.schema_version = PROGRAM_STATE_VERSION;
+        ctx.accounts.program_state.cooldown_secs = 0; // No cooldown
+        ctx.accounts.program_state.pending_authority = None;
+        ctx.accounts.program_state.dedup_window_secs = 0; // Deduplication off
+        ctx.accounts.program_state.fee_rounding = FeeRounding::Floor; // Truncate, as before
+        ctx.accounts.program_state.hook_program = None;
+        ctx.accounts.program_state.max_memo_length = max_memo_length;
+        ctx.accounts.program_state.verbose = false; // Events only on the payment path
+        emit!(StateInitialized {
+            authority,
+            payer: ctx.accounts.user.key(),
+            max_memo_length,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        msg!("Program state initialized with authority {}, funded by {}", authority, ctx.accounts.user.key());
         Ok(())
     }
 
-    // TODO: Add instruction to query payment history (will need different seeds/approach)
-}
+    pub fn close_transaction_record(ctx: Context<CloseTransactionRecord>, sequence: u64) -> Result<()> {
+        // reject_transfer and reclaim_transfer write to the record, so it stays open
+        // while the payment is still held for the receiver
+        if is_transfer_held(&ctx.accounts.pending_transfer) {
+            return err!(ErrorCode::TransferPending);
+        }
+        // Ownership is enforced by the account constraint; Anchor's `close = sender`
+        // zeroes the record and returns its rent lamports to the signing owner.
+        msg!("Transaction record {} ({} #{}) closed. Rent refunded to {}",
+             ctx.accounts.transaction_record.key(),
+             ctx.accounts.transaction_record.sender,
+             sequence,
+             ctx.accounts.sender.key());
+        Ok(())
+    }
 
-#[derive(Accounts)]
-#[instruction(amount: u64, memo: String)] // amount/memo passed in args, not needed here unless constraining
-pub struct SendPayment<'info> {
-    #[account(mut)]
-    pub sender: Signer<'info>,
-    /// CHECK: Receiver account does not need to sign, but we need its public key.
-    /// We are transferring SOL to this account, so it must be writable.
-    #[account(mut)]
-    pub receiver: AccountInfo<'info>,
-    pub system_program: Program<'info, System>,
-    #[account(
-        init,
-        payer = sender,
-        space = TransactionRecord::LEN,
-        // Seeds: "transaction", sender pubkey, current total_transactions count (as LE bytes)
-        // This ensures a unique PDA for each transaction by this sender.
-        seeds = [b"transaction", sender.key().as_ref(), program_state.total_transactions.to_le_bytes().as_ref()],
-        bump
-    )]
-    pub transaction_record: Account<'info, TransactionRecord>,
-    #[account(mut, seeds = [b"state"], bump)] // Assuming state PDA is seeded with just "state"
-    pub program_state: Account<'info, ProgramState>,
-}
+    pub fn get_transaction(ctx: Context<GetTransaction>, sequence: u64) -> Result<()> {
+        // The PDA is derived from the seeds constraint, so clients only need the
+        // sender and sequence number. An empty or foreign-owned account means no
+        // payment was recorded under that sequence.
+        let record_info = &ctx.accounts.transaction_record;
+        if record_info.owner != &crate::ID || record_info.data_is_empty() {
+            return err!(ErrorCode::TransactionNotFound);
+        }
+        let data = record_info.try_borrow_data()?;
+        let record = TransactionRecord::try_deserialize(&mut &data[..])
+            .map_err(|_| error!(ErrorCode::TransactionNotFound))?;
 
-#[derive(Accounts)]
-pub struct InitializeState<'info> {
-    #[account(
-        init,
-        payer = user,
-        space = ProgramState::LEN,
-        seeds = [b"state"],
-        bump
-    )]
-    pub program_state: Account<'info, ProgramState>,
-    #[account(mut)]
-    pub user: Signer<'info>, // The user initializing the state
-    pub system_program: Program<'info, System>,
-}
+        emit!(TransactionDetails {
+            sender: record.sender,
+            receiver: record.receiver,
+            amount: record.amount,
+            timestamp: record.timestamp,
+            memo: record.memo,
+            mint: record.mint,
+            sequence,
+        });
 
-// Account to store payment details (example structure)
-#[account]
-pub struct TransactionRecord {
-    pub sender: Pubkey,
-    pub receiver: Pubkey,
-    pub amount: u64,
-    pub timestamp: i64,
-    pub memo: String, // Store the memo
-    // Add other relevant fields like transaction signature, sequence number etc.
-}
+        Ok(())
+    }
 
-// Define space for TransactionRecord - adjust based on actual fields
-const DISCRIMINATOR_LENGTH: usize = 8;
-const PUBLIC_KEY_LENGTH: usize = 32;
-const U64_LENGTH: usize = 8;
-const I64_LENGTH: usize = 8;
-const STRING_LENGTH_PREFIX: usize = 4; // Stores the size of the string
-const MAX_MEMO_LENGTH: usize = 200; // Max length of memo string in characters
-const MAX_MEMO_BYTES: usize = MAX_MEMO_LENGTH * 4; // Max length in bytes (assuming worst-case 4 bytes per char)
+    // Same accounts as get_transaction, but never fails on a missing record and
+    // never deserializes it: the owner and discriminator are enough
+    pub fn record_exists(ctx: Context<GetTransaction>, sequence: u64) -> Result<()> {
+        let record_info = &ctx.accounts.transaction_record;
+        let exists = record_info.owner == &crate::ID && {
+            let data = record_info.try_borrow_data()?;
+            data.len() >= DISCRIMINATOR_LENGTH && &data[..DISCRIMINATOR_LENGTH] == TransactionRecord::DISCRIMINATOR
+        };
 
-impl TransactionRecord {
-    pub const LEN: usize = DISCRIMINATOR_LENGTH
-        + PUBLIC_KEY_LENGTH // sender
-        + PUBLIC_KEY_LENGTH // receiver
-        + U64_LENGTH // amount
-        + I64_LENGTH // timestamp
-        + STRING_LENGTH_PREFIX + MAX_MEMO_BYTES; // memo
-}
+        emit!(RecordExistence {
+            sender: ctx.accounts.sender.key(),
+            sequence,
+            exists,
+        });
 
-// Account to store global program state
-#[account]
-pub struct ProgramState {
-    pub total_transactions: u64,
-}
+        Ok(())
+    }
 
-impl ProgramState {
-    // Define space for ProgramState
-    // Discriminator (8) + total_transactions (u64 = 8)
-    pub const LEN: usize = 8 + 8;
+    pub fn create_escrow(
+        ctx: Context<CreateEscrow>,
+        amount: u64,
+        memo: String,
+        expires_at: i64,
+        arbiter: Pubkey,
+    ) -> Result<()> {
+        // --- Input Validation & Security Checks ---
+        // Check 0: Neither party is blacklisted, and the receiver is whitelisted while the whitelist is on
+        ensure_not_blacklisted(&ctx.accounts.sender_blacklist)?;
+        ensure_not_blacklisted(&ctx.accounts.receiver_blacklist)?;
+        ensure_whitelisted(ctx.accounts.program_state.whitelist_enabled, ctx.accounts.whitelist_entry.as_deref())?;
+        // Check 0b: Sender is not frozen
+        ensure_not_frozen(&ctx.accounts.sender_freeze)?;
+        // Check 0c: Large payments must go through propose/approve/execute
+        ensure_no_approval_required(&ctx.accounts.program_state, amount)?;
+        // Check 0d: The payment fits the sender's daily spend limit
+        record_sender_spend(ctx.accounts.user_state.spend_limit_enabled, ctx.accounts.spend_limit.as_deref_mut(), amount)?;
+        // Checks 1-3: Same rules as direct payments
+        let max_memo_length = ctx.accounts.program_state.max_memo_length as usize;
+        validate_payment(&ctx.accounts.sender.key(), &ctx.accounts.receiver.key(), amount, &memo, max_memo_length)?;
+        // Check 3b: Within the configured minimum and per-payment cap
+        ensure_amount_within_bounds(&ctx.accounts.program_state, amount)?;
+        // Check 4: Sufficient Sender Balance
+        if ctx.accounts.sender.lamports() < amount {
+            return err!(ErrorCode::InsufficientBalance);
+        }
+        // Check 5: Receiver must be able to receive SOL once the escrow is released
+        if *ctx.accounts.receiver.owner != system_program::ID {
+            return err!(ErrorCode::InvalidReceiver);
+        }
+        // Check 6: Expiry, if any, must be in the future (0 = never expires)
+        let now = Clock::get()?.unix_timestamp;
+        if expires_at != 0 && expires_at <= now {
+            return err!(ErrorCode::InvalidExpiry);
+        }
+        // Check 7: The arbiter must be neutral (Pubkey::default() = no arbiter)
+        if arbiter == ctx.accounts.sender.key() || arbiter == ctx.accounts.receiver.key() {
+            return err!(ErrorCode::NotArbiter);
+        }
+
+        // Move the funds into the escrow PDA; they stay there until release or refund
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.sender.to_account_info(),
+                to: ctx.accounts.escrow.to_account_info(),
+            },
+        );
+        system_program::transfer(cpi_context, amount)?;
+
+        let program_state = &mut ctx.accounts.program_state;
+        let escrow = &mut ctx.accounts.escrow;
+        escrow.sender = ctx.accounts.sender.key();
+        escrow.receiver = ctx.accounts.receiver.key();
+        escrow.amount = amount;
+        escrow.memo = memo;
+        escrow.created_at = now;
+        escrow.sequence = program_state.total_escrows;
+        escrow.bump = ctx.bumps.escrow;
+        escrow.expires_at = expires_at;
+        escrow.arbiter = arbiter;
+        escrow.disputed = false;
+        escrow.settled = false;
+
+        program_state.total_escrows = program_state.total_escrows.checked_add(1).ok_or(ErrorCode::Overflow)?;
+        program_state.active_escrows = program_state.active_escrows.checked_add(1).ok_or(ErrorCode::Overflow)?;
+
+        msg!("Escrow {} created: {} lamports from {} to {}",
+             escrow.sequence,
+             amount,
+             escrow.sender,
+             escrow.receiver);
+
+        Ok(())
+    }
+
+    pub fn release_escrow<'info>(ctx: Context<'_, '_, 'info, 'info, ReleaseEscrow<'info>>) -> Result<()> {
+        // Guard: mark settled before any lamports move
+        ctx.accounts.escrow.mark_settled()?;
+        ctx.accounts.program_state.settle_escrow()?;
+        let escrow = &ctx.accounts.escrow;
+        let amount = escrow.amount;
+        ensure_escrow_funded(&escrow.to_account_info(), amount)?;
+        // An expired escrow can only be refunded to the sender
+        if is_expired(escrow.expires_at, Clock::get()?.unix_timestamp) {
+            return err!(ErrorCode::PaymentExpired);
+        }
+        // A disputed escrow is settled by the arbiter only
+        if escrow.disputed {
+            return err!(ErrorCode::EscrowDisputed);
+        }
+
+        // The payment is final, so the fee is taken now, under the fee
+        // configuration current at release
+        let fee = compute_fee(amount, ctx.accounts.program_state.fee_bps, ctx.accounts.program_state.fee_rounding)?;
+        let net_amount = amount.checked_sub(fee).ok_or(ErrorCode::Overflow)?;
+
+        // Counts towards the receiver's stats and the per-receiver cap
+        let receiver_stats = &mut ctx.accounts.receiver_stats;
+        receiver_stats.claim(ctx.accounts.receiver.key(), ctx.bumps.receiver_stats);
+        receiver_stats.record_payment(net_amount, ctx.accounts.program_state.max_total_received)?;
+
+        // The escrow PDA is owned by this program, so lamports can be moved directly.
+        // Remaining rent is returned to the sender by `close = sender`.
+        ctx.accounts.escrow.sub_lamports(amount)?;
+        ctx.accounts.receiver.add_lamports(net_amount)?;
+        for (collector, part) in fee_payouts(fee, &ctx.accounts.program_state, &ctx.accounts.fee_collector, ctx.remaining_accounts)? {
+            collector.add_lamports(part)?;
+        }
+        let program_state = &mut ctx.accounts.program_state;
+        program_state.total_fees_collected = program_state.total_fees_collected.checked_add(fee).ok_or(ErrorCode::Overflow)?;
+
+        // Record the completed payment
+        let escrow = &ctx.accounts.escrow;
+        let transaction_record = &mut ctx.accounts.transaction_record;
+        transaction_record.set_inner(TransactionRecord::new_payment(
+            escrow.sender,
+            escrow.receiver,
+            amount,
+            escrow.memo.clone(),
+            Clock::get()?.unix_timestamp,
+            fee,
+        ));
+
+        advance_sequence(&mut ctx.accounts.transaction_record, &mut ctx.accounts.user_state, &mut ctx.accounts.program_state)?;
+
+        msg!("Escrow {} released: {} lamports to {}", escrow.sequence, amount, escrow.receiver);
+
+        Ok(())
+    }
+
+    pub fn refund_escrow(ctx: Context<RefundEscrow>) -> Result<()> {
+        // Guard: mark settled before any lamports move
+        ctx.accounts.escrow.mark_settled()?;
+        ctx.accounts.program_state.settle_escrow()?;
+        let escrow = &ctx.accounts.escrow;
+        ensure_escrow_funded(&escrow.to_account_info(), escrow.amount)?;
+        // A disputed escrow is settled by the arbiter only
+        if escrow.disputed {
+            return err!(ErrorCode::EscrowDisputed);
+        }
+
+        // `close = sender` returns both the escrowed amount and the rent to the sender
+        msg!("Escrow {} refunded: {} lamports to {}", escrow.sequence, escrow.amount, escrow.sender);
+
+        Ok(())
+    }
+
+    pub fn admin_recover_escrow(ctx: Context<AdminRecoverEscrow>) -> Result<()> {
+        // Last resort for escrows whose counterparties are gone, e.g. ones created
+        // before expiries existed. Disputed escrows are included, since the arbiter
+        // may be gone too; the funds can only ever go back to the original sender.
+        let recoverable_at = add_secs(ctx.accounts.escrow.created_at, ESCROW_RECOVERY_DELAY_SECS)?;
+        let now = Clock::get()?.unix_timestamp;
+        if now < recoverable_at {
+            return err!(ErrorCode::EscrowRecoveryTooEarly);
+        }
+        ctx.accounts.escrow.mark_settled()?;
+        ctx.accounts.program_state.settle_escrow()?;
+        let escrow = &ctx.accounts.escrow;
+        ensure_escrow_funded(&escrow.to_account_info(), escrow.amount)?;
+
+        // `close = sender` returns both the escrowed amount and the rent to the sender
+        emit!(EscrowRecovered {
+            authority: ctx.accounts.authority.key(),
+            escrow: escrow.key(),
+            sender: escrow.sender,
+            amount: escrow.amount,
+            sequence: escrow.sequence,
+            timestamp: now,
+        });
+        msg!("Escrow {} recovered by the authority: {} lamports to {}", escrow.sequence, escrow.amount, escrow.sender);
+
+        Ok(())
+    }
+
+    pub fn create_timelocked_payment(
+        ctx: Context<CreateTimelockedPayment>,
+        amount: u64,
+        unlock_at: i64,
+        memo: String,
+        expires_at: i64,
+    ) -> Result<()> {
+        // --- Input Validation & Security Checks ---
+        // Check 0: Neither party is blacklisted, and the receiver is whitelisted while the whitelist is on
+        ensure_not_blacklisted(&ctx.accounts.sender_blacklist)?;
+        ensure_not_blacklisted(&ctx.accounts.receiver_blacklist)?;
+        ensure_whitelisted(ctx.accounts.program_state.whitelist_enabled, ctx.accounts.whitelist_entry.as_deref())?;
+        // Check 0b: Sender is not frozen
+        ensure_not_frozen(&ctx.accounts.sender_freeze)?;
+        // Check 0c: Large payments must go through propose/approve/execute
+        ensure_no_approval_required(&ctx.accounts.program_state, amount)?;
+        // Check 0d: The payment fits the sender's daily spend limit
+        record_sender_spend(ctx.accounts.user_state.spend_limit_enabled, ctx.accounts.spend_limit.as_deref_mut(), amount)?;
+        // Checks 1-3: Same rules as direct payments
+        let max_memo_length = ctx.accounts.program_state.max_memo_length as usize;
+        validate_payment(&ctx.accounts.sender.key(), &ctx.accounts.receiver.key(), amount, &memo, max_memo_length)?;
+        // Check 3b: Within the configured minimum and per-payment cap
+        ensure_amount_within_bounds(&ctx.accounts.program_state, amount)?;
+        // Check 4: Sufficient Sender Balance
+        if ctx.accounts.sender.lamports() < amount {
+            return err!(ErrorCode::InsufficientBalance);
+        }
+        // Check 5: Receiver must be able to receive SOL when claiming
+        if *ctx.accounts.receiver.owner != system_program::ID {
+            return err!(ErrorCode::InvalidReceiver);
+        }
+        // Check 6: Unlock time must be in the future
+        let now = Clock::get()?.unix_timestamp;
+        if unlock_at <= now {
+            return err!(ErrorCode::InvalidUnlockTime);
+        }
+        // Check 7: Expiry, if any, must leave the receiver a window to claim (0 = never expires)
+        if expires_at != 0 && expires_at <= unlock_at {
+            return err!(ErrorCode::InvalidExpiry);
+        }
+
+        // Lock the funds in the timelock PDA
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.sender.to_account_info(),
+                to: ctx.accounts.timelock.to_account_info(),
+            },
+        );
+        system_program::transfer(cpi_context, amount)?;
+
+        let program_state = &mut ctx.accounts.program_state;
+        let timelock = &mut ctx.accounts.timelock;
+        timelock.sender = ctx.accounts.sender.key();
+        timelock.receiver = ctx.accounts.receiver.key();
+        timelock.amount = amount;
+        timelock.unlock_at = unlock_at;
+        timelock.memo = memo;
+        timelock.created_at = now;
+        timelock.sequence = program_state.total_timelocks;
+        timelock.bump = ctx.bumps.timelock;
+        timelock.expires_at = expires_at;
+
+        program_state.total_timelocks = program_state.total_timelocks.checked_add(1).ok_or(ErrorCode::Overflow)?;
+        program_state.open_hold()?;
+
+        msg!("Timelocked payment {} created: {} lamports from {} to {}, unlocks at {}",
+             timelock.sequence,
+             amount,
+             timelock.sender,
+             timelock.receiver,
+             unlock_at);
+
+        Ok(())
+    }
+
+    pub fn claim_timelocked_payment(ctx: Context<ClaimTimelockedPayment>) -> Result<()> {
+        let timelock = &ctx.accounts.timelock;
+        let amount = timelock.amount;
+        // Check: The lock must have expired, and the claim window must still be open
+        let now = Clock::get()?.unix_timestamp;
+        if now < timelock.unlock_at {
+            return err!(ErrorCode::TimelockNotExpired);
+        }
+        if is_expired(timelock.expires_at, now) {
+            return err!(ErrorCode::PaymentExpired);
+        }
+
+        // Counts towards the receiver's stats and the per-receiver cap
+        let receiver_stats = &mut ctx.accounts.receiver_stats;
+        receiver_stats.claim(ctx.accounts.receiver.key(), ctx.bumps.receiver_stats);
+        receiver_stats.record_payment(amount, ctx.accounts.program_state.max_total_received)?;
+
+        // Pay the receiver; the rent goes back to the sender via `close = sender`
+        ctx.accounts.timelock.sub_lamports(amount)?;
+        ctx.accounts.receiver.add_lamports(amount)?;
+
+        ctx.accounts.program_state.close_hold()?;
+        msg!("Timelocked payment {} claimed: {} lamports to {}",
+             ctx.accounts.timelock.sequence,
+             amount,
+             ctx.accounts.receiver.key());
+
+        Ok(())
+    }
+
+    pub fn set_paused(ctx: Context<SetPaused>, paused: bool) -> Result<()> {
+        // Emergency stop: while paused, no new payments are accepted. Funds already held
+        // can still be released, refunded or reclaimed.
+        // A pending teardown keeps the program paused until the state is closed
+        if !paused && ctx.accounts.program_state.teardown_requested_at != 0 {
+            return err!(ErrorCode::TeardownAlreadyRequested);
+        }
+        ctx.accounts.program_state.paused = paused;
+        emit!(PausedChanged {
+            authority: ctx.accounts.authority.key(),
+            paused,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        msg!("Program paused: {}", paused);
+        Ok(())
+    }
+
+    pub fn set_fee(ctx: Context<SetFee>, fee_bps: u16) -> Result<()> {
+        if fee_bps > MAX_FEE_BPS {
+            return err!(ErrorCode::FeeTooHigh);
+        }
+        let program_state = &mut ctx.accounts.program_state;
+        program_state.fee_bps = fee_bps;
+        program_state.fee_collector = ctx.accounts.fee_collector.key();
+        emit_fee_changed(ctx.accounts.authority.key(), program_state)?;
+        msg!("Protocol fee set to {} bps, collected by {}", fee_bps, program_state.fee_collector);
+        Ok(())
+    }
+
+    pub fn set_fee_split(ctx: Context<SetFeeSplit>, fee_split: Vec<FeeShare>) -> Result<()> {
+        // An empty split sends the whole fee to `fee_collector` again
+        if fee_split.len() > MAX_FEE_COLLECTORS {
+            return err!(ErrorCode::TooManyFeeCollectors);
+        }
+        let share_sum: u64 = fee_split.iter().map(|share| share.share_bps as u64).sum();
+        if !fee_split.is_empty() && share_sum != BPS_DENOMINATOR {
+            return err!(ErrorCode::InvalidFeeSplit);
+        }
+        clear_account_data(&ctx.accounts.program_state.to_account_info())?;
+        let program_state = &mut ctx.accounts.program_state;
+        program_state.fee_split = fee_split;
+        emit_fee_changed(ctx.accounts.authority.key(), program_state)?;
+        msg!("Protocol fee split across {} collectors", program_state.fee_split.len());
+        Ok(())
+    }
+
+    pub fn initialize_user(ctx: Context<InitializeUser>) -> Result<()> {
+        // Per-sender sequence used to derive that sender's transaction record PDAs
+        let user_state = &mut ctx.accounts.user_state;
+        user_state.owner = ctx.accounts.user.key();
+        user_state.transaction_count = 0;
+        user_state.spend_limit_enabled = false;
+        user_state.bump = ctx.bumps.user_state;
+        user_state.last_sequence = 0;
+        user_state.head_sequence = None;
+        msg!("User state initialized for {}", user_state.owner);
+        Ok(())
+    }
+
+    pub fn batch_initialize_users<'info>(
+        ctx: Context<'_, '_, 'info, 'info, BatchInitializeUsers<'info>>,
+        owners: Vec<Pubkey>,
+    ) -> Result<()> {
+        // The UserState PDA of `owners[i]` is passed as writable remaining account `i`
+        let user_states = ctx.remaining_accounts;
+        if owners.len() != user_states.len() {
+            return err!(ErrorCode::SplitLengthMismatch);
+        }
+        // Two keys per user (argument + account) keep the transaction within its size limit
+        if owners.is_empty() || owners.len() > MAX_USER_INIT_BATCH {
+            return err!(ErrorCode::UserBatchTooLarge);
+        }
+
+        let rent = Rent::get()?.minimum_balance(UserState::LEN);
+        let mut created: usize = 0;
+        for (owner, user_state) in owners.iter().zip(user_states) {
+            let (expected, bump) = Pubkey::find_program_address(&[b"user", owner.as_ref()], &crate::ID);
+            if user_state.key() != expected {
+                return err!(anchor_lang::error::ErrorCode::ConstraintSeeds);
+            }
+            // Already onboarded (or pre-funded by someone else): leave it for initialize_user
+            if user_state.lamports() > 0 || !user_state.data_is_empty() {
+                msg!("Skipping {}: user state already exists", owner);
+                continue;
+            }
+
+            // The payer covers rent; the PDA signs for its own creation
+            let signer_seeds: &[&[&[u8]]] = &[&[b"user", owner.as_ref(), &[bump]]];
+            let cpi_context = CpiContext::new_with_signer(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::CreateAccount {
+                    from: ctx.accounts.payer.to_account_info(),
+                    to: user_state.clone(),
+                },
+                signer_seeds,
+            );
+            system_program::create_account(cpi_context, rent, UserState::LEN as u64, &crate::ID)?;
+
+            // Same initial values as initialize_user
+            let state = UserState {
+                owner: *owner,
+                transaction_count: 0,
+                spend_limit_enabled: false,
+                bump,
+                last_sequence: 0,
+                head_sequence: None,
+            };
+            state.try_serialize(&mut &mut user_state.try_borrow_mut_data()?[..])?;
+            created += 1;
+        }
+
+        msg!("Initialized {} of {} user states", created, owners.len());
+        Ok(())
+    }
+
+    pub fn create_subscription(
+        ctx: Context<CreateSubscription>,
+        amount: u64,
+        interval_secs: i64,
+        deposit: u64,
+    ) -> Result<()> {
+        // --- Input Validation & Security Checks ---
+        // Check 1: Amount > 0
+        if amount == 0 {
+            return err!(ErrorCode::InvalidAmount);
+        }
+        // Check 1b: Each charge is within the configured minimum and per-payment cap
+        ensure_amount_within_bounds(&ctx.accounts.program_state, amount)?;
+        // Check 2: Payer != Merchant
+        if ctx.accounts.payer.key() == ctx.accounts.merchant.key() {
+            return err!(ErrorCode::SelfPayment);
+        }
+        // Check 3: Interval must be positive
+        if interval_secs <= 0 {
+            return err!(ErrorCode::InvalidInterval);
+        }
+        // Check 4: Sufficient Payer Balance for the initial deposit
+        if ctx.accounts.payer.lamports() < deposit {
+            return err!(ErrorCode::InsufficientBalance);
+        }
+        // Check 5: The deposit leaves the payer like any payment, and every charge will pay
+        // the merchant, so both sides' compliance rules apply up front
+        ensure_sender_may_pay(
+            &ctx.accounts.program_state,
+            SenderPolicy {
+                blacklist: &ctx.accounts.payer_blacklist,
+                freeze: &ctx.accounts.payer_freeze,
+                spend_limit_enabled: ctx.accounts.user_state.spend_limit_enabled,
+                spend_limit: ctx.accounts.spend_limit.as_deref_mut(),
+            },
+            deposit,
+        )?;
+        ensure_receiver_may_be_paid(
+            &ctx.accounts.program_state,
+            &ctx.accounts.merchant_blacklist,
+            &ctx.accounts.merchant_acceptance,
+            ctx.accounts.whitelist_entry.as_deref(),
+        )?;
+
+        // SOL cannot be pulled from a wallet without its signature, so the payer
+        // prefunds the subscription PDA and the merchant charges from that deposit.
+        if deposit > 0 {
+            let cpi_context = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.payer.to_account_info(),
+                    to: ctx.accounts.subscription.to_account_info(),
+                },
+            );
+            system_program::transfer(cpi_context, deposit)?;
+        }
+
+        let subscription = &mut ctx.accounts.subscription;
+        subscription.payer = ctx.accounts.payer.key();
+        subscription.merchant = ctx.accounts.merchant.key();
+        subscription.amount = amount;
+        subscription.interval_secs = interval_secs;
+        subscription.last_paid = 0; // Never charged, so the first charge is due immediately
+        subscription.active = true;
+        subscription.bump = ctx.bumps.subscription;
+        subscription.pending_amount = None;
+        ctx.accounts.program_state.open_hold()?;
+
+        msg!("Subscription created: {} lamports every {}s from {} to {}",
+             amount,
+             interval_secs,
+             subscription.payer,
+             subscription.merchant);
+
+        Ok(())
+    }
+
+    pub fn fund_subscription(ctx: Context<FundSubscription>, amount: u64) -> Result<()> {
+        // Same compliance rules as create_subscription, for both sides
+        ensure_sender_may_pay(
+            &ctx.accounts.program_state,
+            SenderPolicy {
+                blacklist: &ctx.accounts.payer_blacklist,
+                freeze: &ctx.accounts.payer_freeze,
+                spend_limit_enabled: ctx.accounts.user_state.spend_limit_enabled,
+                spend_limit: ctx.accounts.spend_limit.as_deref_mut(),
+            },
+            amount,
+        )?;
+        ensure_receiver_may_be_paid(
+            &ctx.accounts.program_state,
+            &ctx.accounts.merchant_blacklist,
+            &ctx.accounts.merchant_acceptance,
+            ctx.accounts.whitelist_entry.as_deref(),
+        )?;
+        if amount == 0 {
+            return err!(ErrorCode::InvalidAmount);
+        }
+        if ctx.accounts.payer.lamports() < amount {
+            return err!(ErrorCode::InsufficientBalance);
+        }
+
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.payer.to_account_info(),
+                to: ctx.accounts.subscription.to_account_info(),
+            },
+        );
+        system_program::transfer(cpi_context, amount)?;
+
+        msg!("Subscription funded with {} lamports", amount);
+        Ok(())
+    }
+
+    pub fn charge_subscription(ctx: Context<ChargeSubscription>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let subscription = &ctx.accounts.subscription;
+        let amount = subscription.amount;
+        // Check 1: Interval since the last charge has elapsed (a never-charged
+        // subscription is due immediately, whatever its interval)
+        if subscription.last_paid != 0 {
+            let next_due = add_secs(subscription.last_paid, subscription.interval_secs)?;
+            if now < next_due {
+                return err!(ErrorCode::SubscriptionNotDue);
+            }
+        }
+        // Check 2: The deposit covers this charge without dipping into rent
+        let subscription_info = subscription.to_account_info();
+        let rent_exempt_minimum = Rent::get()?.minimum_balance(subscription_info.data_len());
+        let available = subscription_info.lamports().saturating_sub(rent_exempt_minimum);
+        if available < amount {
+            return err!(ErrorCode::InsufficientBalance);
+        }
+
+        // Counts towards the receiver's stats and the per-receiver cap
+        let receiver_stats = &mut ctx.accounts.receiver_stats;
+        receiver_stats.claim(ctx.accounts.merchant.key(), ctx.bumps.receiver_stats);
+        receiver_stats.record_payment(amount, ctx.accounts.program_state.max_total_received)?;
+
+        ctx.accounts.subscription.sub_lamports(amount)?;
+        ctx.accounts.merchant.add_lamports(amount)?;
+        ctx.accounts.subscription.last_paid = now;
+
+        msg!("Subscription charged: {} lamports from {} to {}",
+             amount,
+             ctx.accounts.subscription.payer,
+             ctx.accounts.subscription.merchant);
+
+        Ok(())
+    }
+
+    pub fn cancel_subscription(ctx: Context<CancelSubscription>) -> Result<()> {
+        // `close = payer` refunds the unspent deposit along with the rent and frees the
+        // PDA, so the same payer and merchant can subscribe again later
+        let refund = ctx.accounts.subscription.to_account_info().lamports();
+        // Subscriptions cancelled before cancel closed the PDA are still there, inactive
+        // and no longer counted as a hold; cancelling one again just closes it
+        if ctx.accounts.subscription.active {
+            ctx.accounts.program_state.close_hold()?;
+        }
+
+        msg!("Subscription canceled. Refunded {} lamports to {}", refund, ctx.accounts.payer.key());
+        Ok(())
+    }
+
+    pub fn propose_subscription_change(ctx: Context<ProposeSubscriptionChange>, new_amount: u64) -> Result<()> {
+        if new_amount == 0 {
+            return err!(ErrorCode::InvalidAmount);
+        }
+        // Charges keep using the current amount until the payer accepts; a new
+        // proposal replaces any earlier one
+        let subscription = &mut ctx.accounts.subscription;
+        subscription.pending_amount = Some(new_amount);
+        emit!(SubscriptionChangeProposed {
+            subscription: subscription.key(),
+            merchant: subscription.merchant,
+            current_amount: subscription.amount,
+            new_amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        msg!("Subscription change proposed: {} -> {} lamports", subscription.amount, new_amount);
+        Ok(())
+    }
+
+    pub fn accept_subscription_change(ctx: Context<AcceptSubscriptionChange>, expected_amount: u64) -> Result<()> {
+        let subscription = &mut ctx.accounts.subscription;
+        let new_amount = subscription.pending_amount.take().ok_or(ErrorCode::NoPendingChange)?;
+        // A later proposal replaces the pending amount, so the payer names the one
+        // they agreed to and a replacement sent ahead of their accept is refused
+        if new_amount != expected_amount {
+            return err!(ErrorCode::SubscriptionChangeMismatch);
+        }
+        let previous_amount = subscription.amount;
+        subscription.amount = new_amount;
+        emit!(SubscriptionChangeAccepted {
+            subscription: subscription.key(),
+            payer: subscription.payer,
+            previous_amount,
+            new_amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        msg!("Subscription change accepted: {} -> {} lamports", previous_amount, new_amount);
+        Ok(())
+    }
+
+    pub fn migrate_subscription(ctx: Context<MigrateSubscription>) -> Result<()> {
+        let subscription_info = ctx.accounts.subscription.to_account_info();
+        {
+            let data = subscription_info.try_borrow_data()?;
+            if data.len() < DISCRIMINATOR_LENGTH || &data[..DISCRIMINATOR_LENGTH] != Subscription::DISCRIMINATOR {
+                return err!(ErrorCode::SubscriptionNotFound);
+            }
+        }
+        // Accounts created before `pending_amount` lack its bytes; nothing else changed
+        let current_len = subscription_info.data_len();
+        if current_len >= Subscription::LEN {
+            return err!(ErrorCode::SubscriptionAlreadyMigrated);
+        }
+
+        // The payer tops up the rent, so the deposit above it is left untouched
+        let top_up = Rent::get()?
+            .minimum_balance(Subscription::LEN)
+            .saturating_sub(Rent::get()?.minimum_balance(current_len));
+        if top_up > 0 {
+            let cpi_context = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.payer.to_account_info(),
+                    to: subscription_info.clone(),
+                },
+            );
+            system_program::transfer(cpi_context, top_up)?;
+        }
+        // Zeroed new bytes read back as `pending_amount = None`
+        subscription_info.realloc(Subscription::LEN, true)?;
+
+        msg!("Subscription {} migrated ({} -> {} bytes)",
+             subscription_info.key(),
+             current_len,
+             Subscription::LEN);
+        Ok(())
+    }
+
+    pub fn reset_state(ctx: Context<ResetState>) -> Result<()> {
+        // Only the analytics counter is reset. Escrow and timelock counters are part of
+        // live PDA seeds, so resetting them would collide with existing accounts.
+        ctx.accounts.program_state.total_transactions = 0;
+        emit!(StateReset {
+            authority: ctx.accounts.authority.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        msg!("Program state reset. Total transactions: 0");
+        Ok(())
+    }
+
+    pub fn set_min_amount(ctx: Context<SetMinAmount>, min_amount: u64) -> Result<()> {
+        ctx.accounts.program_state.min_amount = min_amount;
+        emit_limit_changed(ctx.accounts.authority.key(), LimitKind::MinAmount, min_amount)?;
+        msg!("Minimum payment amount set to {} lamports", min_amount);
+        Ok(())
+    }
+
+    pub fn set_min_reserve(ctx: Context<SetMinReserve>, min_reserve: u64) -> Result<()> {
+        ctx.accounts.program_state.min_reserve = min_reserve;
+        emit_limit_changed(ctx.accounts.authority.key(), LimitKind::MinReserve, min_reserve)?;
+        msg!("Sender fee reserve set to {} lamports", min_reserve);
+        Ok(())
+    }
+
+    pub fn set_max_single_payment(ctx: Context<SetMaxSinglePayment>, max_single_payment: u64) -> Result<()> {
+        ctx.accounts.program_state.max_single_payment = max_single_payment;
+        emit_limit_changed(ctx.accounts.authority.key(), LimitKind::MaxSinglePayment, max_single_payment)?;
+        msg!("Maximum single payment set to {} lamports (0 = unlimited)", max_single_payment);
+        Ok(())
+    }
+
+    pub fn set_max_total_received(ctx: Context<SetMaxSinglePayment>, max_total_received: u64) -> Result<()> {
+        // Receivers already above a new, lower cap keep their funds but can't receive more
+        ctx.accounts.program_state.max_total_received = max_total_received;
+        emit_limit_changed(ctx.accounts.authority.key(), LimitKind::MaxTotalReceived, max_total_received)?;
+        msg!("Maximum total received per receiver set to {} lamports (0 = unlimited)", max_total_received);
+        Ok(())
+    }
+
+    pub fn set_max_memo_length(ctx: Context<SetMaxMemoLength>, max_memo_length: u16) -> Result<()> {
+        // Same bound as initialize_state; records already written keep their memos
+        if max_memo_length as usize > MAX_MEMO_BYTES {
+            return err!(ErrorCode::MemoLimitTooLarge);
+        }
+        ctx.accounts.program_state.max_memo_length = max_memo_length;
+        emit_limit_changed(ctx.accounts.authority.key(), LimitKind::MaxMemoLength, max_memo_length as u64)?;
+        msg!("Maximum memo length set to {} characters", max_memo_length);
+        Ok(())
+    }
+
+    pub fn refund_payment(ctx: Context<RefundPayment>, original_sequence: u64, amount: u64) -> Result<()> {
+        let original = &ctx.accounts.original_record;
+
+        // --- Input Validation & Security Checks ---
+        // Checks 1-2: Amount > 0, Refunder != Original Sender
+        let max_memo_length = ctx.accounts.program_state.max_memo_length as usize;
+        validate_payment(&ctx.accounts.refunder.key(), &original.sender, amount, "", max_memo_length)?;
+        // Check 3: Only native SOL payments can be refunded here
+        if original.mint.is_some() {
+            return err!(ErrorCode::InvalidMint);
+        }
+        // Check 4: Total refunds never exceed the original amount
+        let refunded = original.refunded.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+        if refunded > original.amount {
+            return err!(ErrorCode::RefundExceedsOriginal);
+        }
+        // Check 5: Sufficient Refunder Balance
+        if ctx.accounts.refunder.lamports() < amount {
+            return err!(ErrorCode::InsufficientBalance);
+        }
+        // Check 6: A refund leaves the refunder like any payment, so the refunder must be
+        // neither blacklisted nor frozen and stays within their daily limit, and the
+        // original sender must be payable
+        ensure_sender_may_pay(
+            &ctx.accounts.program_state,
+            SenderPolicy {
+                blacklist: &ctx.accounts.refunder_blacklist,
+                freeze: &ctx.accounts.refunder_freeze,
+                spend_limit_enabled: ctx.accounts.user_state.spend_limit_enabled,
+                spend_limit: ctx.accounts.spend_limit.as_deref_mut(),
+            },
+            amount,
+        )?;
+        ensure_receiver_may_be_paid(
+            &ctx.accounts.program_state,
+            &ctx.accounts.original_sender_blacklist,
+            &ctx.accounts.original_sender_acceptance,
+            ctx.accounts.whitelist_entry.as_deref(),
+        )?;
+
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.refunder.to_account_info(),
+                to: ctx.accounts.original_sender.to_account_info(),
+            },
+        );
+        system_program::transfer(cpi_context, amount)?;
+
+        ctx.accounts.original_record.refunded = refunded;
+
+        // Record the refund as a payment from the refunder back to the original sender
+        let transaction_record = &mut ctx.accounts.transaction_record;
+        transaction_record.set_inner(TransactionRecord::new_payment(
+            ctx.accounts.refunder.key(),
+            ctx.accounts.original_sender.key(),
+            amount,
+            String::new(),
+            Clock::get()?.unix_timestamp,
+            0,
+        ));
+        transaction_record.refund_of = Some(original_sequence);
+        transaction_record.category = PaymentCategory::Refund as u8;
+
+        let program_state = &mut ctx.accounts.program_state;
+        advance_sequence(transaction_record, &mut ctx.accounts.user_state, program_state)?;
+        program_state.total_refunds = program_state.total_refunds.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+
+        msg!("Refund Sent: {} lamports from {} to {} for transaction {}",
+             amount,
+             transaction_record.sender,
+             transaction_record.receiver,
+             original_sequence);
+
+        Ok(())
+    }
+
+    pub fn send_batch_payment<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SendBatchPayment<'info>>,
+        payments: Vec<PaymentInstruction>,
+        memo: String,
+    ) -> Result<()> {
+        // Remaining accounts are the fee split collectors, then one group per receiver,
+        // referenced by group index
+        let (fee_split_collectors, receivers) = split_remaining_accounts(&ctx.accounts.program_state, ctx.remaining_accounts)?;
+
+        // --- Input Validation & Security Checks ---
+        // All payments are validated and summed before any lamports move.
+        // Check 0: Sender is neither blacklisted nor frozen
+        ensure_not_blacklisted(&ctx.accounts.sender_blacklist)?;
+        ensure_not_frozen(&ctx.accounts.sender_freeze)?;
+        // Check 1: Non-empty batch that fits the compute budget; past MAX_BATCH_SIZE it fails
+        // early, with a suggested chunk size, instead of running out of compute
+        if payments.is_empty() {
+            return err!(ErrorCode::InvalidAmount);
+        }
+        ensure_within_compute_budget(payments.len(), MAX_BATCH_SIZE)?;
+        // The first failing payment is reported through BatchPaymentFailed before the
+        // whole batch reverts, so senders can tell which entry to fix
+        let max_memo_length = ctx.accounts.program_state.max_memo_length as usize;
+        let mut total: u64 = 0;
+        for (index, payment) in payments.iter().enumerate() {
+            let leg = (|| -> Result<u64> {
+                // Check 2: Receiver index points at a group of remaining accounts
+                let receiver = receivers
+                    .get(payment.receiver_index as usize)
+                    .ok_or(ErrorCode::InvalidReceiverIndex)?;
+                // Check 3: Same per-payment rules as send_payment
+                validate_payment(&ctx.accounts.sender.key(), receiver.receiver.key, payment.amount, &memo, max_memo_length)?;
+                // Check 3b: Each payment is within the configured minimum and per-payment cap
+                ensure_amount_within_bounds(&ctx.accounts.program_state, payment.amount)?;
+                // Check 4: Receiver Account Ownership
+                if *receiver.receiver.owner != system_program::ID {
+                    return err!(ErrorCode::InvalidReceiver);
+                }
+                // Check 4b: Receiver is not blacklisted and, while the whitelist is on, whitelisted
+                receiver.ensure_may_be_paid(&ctx.accounts.program_state)?;
+                // Check 5: Total must not overflow
+                Ok(total.checked_add(payment.amount).ok_or(ErrorCode::Overflow)?)
+            })();
+            total = leg.map_err(|error| report_batch_failure(index, error))?;
+        }
+        // Check 6: Sufficient Sender Balance for the whole batch, fees included, on top of
+        // the record's rent, leaving the sender rent-exempt
+        let record_rent = Rent::get()?.minimum_balance(TransactionRecord::LEN);
+        ensure_covers_rent_and_payment(&ctx.accounts.sender, total, record_rent)?;
+        // Check 7: Large batches, by their total, must go through propose/approve/execute
+        ensure_no_approval_required(&ctx.accounts.program_state, total)?;
+        // Check 8: The batch's total fits the sender's daily spend limit
+        record_sender_spend(ctx.accounts.user_state.spend_limit_enabled, ctx.accounts.spend_limit.as_deref_mut(), total)?;
+
+        // Execute one transfer per payment, each counted towards its receiver's stats and
+        // cap. Each payment pays the fee a send_payment of its amount would; the fees are
+        // collected together once every receiver is paid.
+        let program_state = &ctx.accounts.program_state;
+        let (fee_bps, fee_rounding, max_total_received) = (program_state.fee_bps, program_state.fee_rounding, program_state.max_total_received);
+        let mut fee: u64 = 0;
+        for payment in payments.iter() {
+            let receiver = &receivers[payment.receiver_index as usize];
+            let payment_fee = compute_fee(payment.amount, fee_bps, fee_rounding)?;
+            let net_amount = payment.amount.checked_sub(payment_fee).ok_or(ErrorCode::Overflow)?;
+            fee = fee.checked_add(payment_fee).ok_or(ErrorCode::Overflow)?;
+            receiver.record_payment(&ctx.accounts.sender, &ctx.accounts.system_program, net_amount, max_total_received)?;
+            let cpi_context = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.sender.to_account_info(),
+                    to: receiver.receiver.clone(),
+                },
+            );
+            system_program::transfer(cpi_context, net_amount)?;
+        }
+        let fee_source = PaymentSource::Wallet(&ctx.accounts.sender);
+        for (collector, part) in fee_payouts(fee, &ctx.accounts.program_state, &ctx.accounts.fee_collector, fee_split_collectors)? {
+            fee_source.transfer(&ctx.accounts.system_program, collector, part)?;
+        }
+
+        // Bounded by MAX_BATCH_SIZE through the compute budget check, so this always fits in a u8
+        let payment_count = payments.len() as u8;
+
+        msg!("Batch Payment Sent: {} lamports from {} in {} payments with memo: {}",
+             total,
+             ctx.accounts.sender.key(),
+             payment_count,
+             memo);
+
+        // Record one aggregate transaction; `receiver` holds the first payment's receiver
+        let transaction_record = &mut TransactionRecord::new_payment(
+            ctx.accounts.sender.key(),
+            receivers[payments[0].receiver_index as usize].receiver.key(),
+            total,
+            memo,
+            Clock::get()?.unix_timestamp,
+            fee,
+        );
+        transaction_record.receiver_count = payment_count;
+
+        // Advance the sender's sequence and the global transaction count
+        let program_state = &mut ctx.accounts.program_state;
+        program_state.total_fees_collected = program_state.total_fees_collected.checked_add(fee).ok_or(ErrorCode::Overflow)?;
+        let sequence = advance_sequence(transaction_record, &mut ctx.accounts.user_state, program_state)?;
+        // Only created now, after every check, so a short balance fails with a clear error
+        create_transaction_record(
+            &ctx.accounts.sender,
+            &ctx.accounts.transaction_record,
+            &ctx.accounts.system_program,
+            TransactionRecord::LEN,
+            transaction_record,
+            sequence,
+            ctx.bumps.transaction_record,
+        )?;
+
+        msg!("Transaction recorded. Total transactions: {}", program_state.total_transactions);
+
+        Ok(())
+    }
+
+    pub fn set_whitelist_enabled(ctx: Context<SetWhitelistEnabled>, enabled: bool) -> Result<()> {
+        ctx.accounts.program_state.whitelist_enabled = enabled;
+        emit!(WhitelistEnabledChanged {
+            authority: ctx.accounts.authority.key(),
+            enabled,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        msg!("Receiver whitelist enabled: {}", enabled);
+        Ok(())
+    }
+
+    pub fn add_whitelist(ctx: Context<AddWhitelist>, expires_at: i64) -> Result<()> {
+        // 0 = never expires; otherwise the entry must still be valid when added
+        if expires_at != 0 && expires_at <= Clock::get()?.unix_timestamp {
+            return err!(ErrorCode::InvalidExpiry);
+        }
+        let whitelist_entry = &mut ctx.accounts.whitelist_entry;
+        whitelist_entry.address = ctx.accounts.address.key();
+        whitelist_entry.bump = ctx.bumps.whitelist_entry;
+        whitelist_entry.expires_at = expires_at;
+        emit_access_list_changed(ctx.accounts.authority.key(), AccessList::Whitelist, whitelist_entry.address, true)?;
+        msg!("Whitelisted receiver {}", whitelist_entry.address);
+        Ok(())
+    }
+
+    pub fn remove_whitelist(ctx: Context<RemoveWhitelist>) -> Result<()> {
+        // `close = authority` deletes the entry and refunds its rent
+        emit_access_list_changed(ctx.accounts.authority.key(), AccessList::Whitelist, ctx.accounts.whitelist_entry.address, false)?;
+        msg!("Removed receiver {} from whitelist", ctx.accounts.whitelist_entry.address);
+        Ok(())
+    }
+
+    pub fn prune_whitelist(ctx: Context<PruneWhitelist>) -> Result<()> {
+        // Anyone may clean up an expired entry; `close = authority` returns the rent
+        // to the authority, who paid it in add_whitelist
+        emit_access_list_changed(ctx.accounts.caller.key(), AccessList::Whitelist, ctx.accounts.whitelist_entry.address, false)?;
+        msg!("Pruned expired whitelist entry for {} (expired at {})",
+             ctx.accounts.whitelist_entry.address,
+             ctx.accounts.whitelist_entry.expires_at);
+        Ok(())
+    }
+
+    pub fn create_invoice(ctx: Context<CreateInvoice>, invoice_id: u64, amount: u64, memo: String) -> Result<()> {
+        // --- Input Validation & Security Checks ---
+        // Check 1: Amount > 0
+        if amount == 0 {
+            return err!(ErrorCode::InvalidAmount);
+        }
+        // Check 2: Memo Length and Content
+        validate_memo(&memo, ctx.accounts.program_state.max_memo_length as usize)?;
+
+        let invoice = &mut ctx.accounts.invoice;
+        invoice.merchant = ctx.accounts.merchant.key();
+        invoice.invoice_id = invoice_id;
+        invoice.amount = amount;
+        invoice.memo = memo;
+        invoice.paid = false;
+        invoice.payer = None;
+        invoice.bump = ctx.bumps.invoice;
+
+        msg!("Invoice {} created by {} for {} lamports", invoice_id, invoice.merchant, amount);
+        Ok(())
+    }
+
+    pub fn pay_invoice(ctx: Context<PayInvoice>, amount: u64) -> Result<()> {
+        let invoice = &ctx.accounts.invoice;
+        // --- Input Validation & Security Checks ---
+        // Check 0: Payer is neither blacklisted nor frozen, stays within their daily limit,
+        // and large payments go through propose/approve/execute
+        ensure_sender_may_pay(
+            &ctx.accounts.program_state,
+            SenderPolicy {
+                blacklist: &ctx.accounts.payer_blacklist,
+                freeze: &ctx.accounts.payer_freeze,
+                spend_limit_enabled: ctx.accounts.user_state.spend_limit_enabled,
+                spend_limit: ctx.accounts.spend_limit.as_deref_mut(),
+            },
+            amount,
+        )?;
+        // Check 0b: Merchant is not blacklisted, and is whitelisted while the whitelist is on
+        ensure_receiver_may_be_paid(
+            &ctx.accounts.program_state,
+            &ctx.accounts.merchant_blacklist,
+            &ctx.accounts.merchant_acceptance,
+            ctx.accounts.whitelist_entry.as_deref(),
+        )?;
+        // Check 1: No double payment
+        if invoice.paid {
+            return err!(ErrorCode::InvoiceAlreadyPaid);
+        }
+        // Check 2: Amount matches the invoice exactly
+        if amount != invoice.amount {
+            return err!(ErrorCode::InvoiceAmountMismatch);
+        }
+        // Check 2b: Within the configured minimum and per-payment cap
+        ensure_amount_within_bounds(&ctx.accounts.program_state, amount)?;
+        // Check 3: Payer != Merchant
+        if ctx.accounts.payer.key() == invoice.merchant {
+            return err!(ErrorCode::SelfPayment);
+        }
+        // Check 4: Sufficient Payer Balance
+        if ctx.accounts.payer.lamports() < amount {
+            return err!(ErrorCode::InsufficientBalance);
+        }
+
+        // Counts towards the receiver's stats and the per-receiver cap
+        let receiver_stats = &mut ctx.accounts.receiver_stats;
+        receiver_stats.claim(ctx.accounts.merchant.key(), ctx.bumps.receiver_stats);
+        receiver_stats.record_payment(amount, ctx.accounts.program_state.max_total_received)?;
+
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.payer.to_account_info(),
+                to: ctx.accounts.merchant.to_account_info(),
+            },
+        );
+        system_program::transfer(cpi_context, amount)?;
+
+        let invoice = &mut ctx.accounts.invoice;
+        invoice.paid = true;
+        invoice.payer = Some(ctx.accounts.payer.key());
+
+        msg!("Invoice {} paid: {} lamports from {} to {}",
+             invoice.invoice_id,
+             amount,
+             ctx.accounts.payer.key(),
+             invoice.merchant);
+
+        Ok(())
+    }
+
+    pub fn set_spend_limit(ctx: Context<SetSpendLimit>, daily_limit: u64) -> Result<()> {
+        let spend_limit = &mut ctx.accounts.spend_limit;
+        // First call creates the limit account and opens the first window
+        if spend_limit.owner == Pubkey::default() {
+            spend_limit.owner = ctx.accounts.owner.key();
+            spend_limit.spent_today = 0;
+            spend_limit.window_start = Clock::get()?.unix_timestamp;
+            spend_limit.bump = ctx.bumps.spend_limit;
+        }
+        spend_limit.daily_limit = daily_limit;
+        // From now on send_payment requires the limit account for this sender
+        ctx.accounts.user_state.spend_limit_enabled = true;
+
+        msg!("Daily spend limit for {} set to {} lamports", spend_limit.owner, daily_limit);
+        Ok(())
+    }
+
+    pub fn set_multisig_config(
+        ctx: Context<SetMultisigConfig>,
+        threshold_amount: u64,
+        required_approvals: u8,
+        approvers: Vec<Pubkey>,
+    ) -> Result<()> {
+        // A threshold of 0 disables the multisig requirement. The approval count must be
+        // meaningful either way, since execute_payment still applies it to payments
+        // proposed before the threshold was switched off.
+        if approvers.len() > MAX_APPROVERS {
+            return err!(ErrorCode::InvalidMultisigConfig);
+        }
+        if required_approvals == 0 || required_approvals as usize > approvers.len() {
+            return err!(ErrorCode::InvalidMultisigConfig);
+        }
+        ctx.accounts.program_state.multisig = MultisigConfig {
+            threshold_amount,
+            required_approvals,
+            approvers,
+        };
+        let multisig = &ctx.accounts.program_state.multisig;
+        emit!(MultisigConfigChanged {
+            authority: ctx.accounts.authority.key(),
+            threshold_amount,
+            required_approvals,
+            approvers: multisig.approvers.clone(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        msg!("Multisig config set: {} of {} approvals above {} lamports",
+             required_approvals,
+             ctx.accounts.program_state.multisig.approvers.len(),
+             threshold_amount);
+        Ok(())
+    }
+
+    pub fn propose_payment(ctx: Context<ProposePayment>, payment_id: u64, amount: u64, memo: String) -> Result<()> {
+        // --- Input Validation & Security Checks ---
+        // Checks 1-3: Same rules as direct payments
+        let max_memo_length = ctx.accounts.program_state.max_memo_length as usize;
+        validate_payment(&ctx.accounts.sender.key(), &ctx.accounts.receiver.key(), amount, &memo, max_memo_length)?;
+        // Check 3b: Within the configured minimum and per-payment cap
+        ensure_amount_within_bounds(&ctx.accounts.program_state, amount)?;
+        // Check 4: Receiver Account Ownership
+        if *ctx.accounts.receiver.owner != system_program::ID {
+            return err!(ErrorCode::InvalidReceiver);
+        }
+
+        let pending_payment = &mut ctx.accounts.pending_payment;
+        pending_payment.sender = ctx.accounts.sender.key();
+        pending_payment.receiver = ctx.accounts.receiver.key();
+        pending_payment.amount = amount;
+        pending_payment.memo = memo;
+        pending_payment.payment_id = payment_id;
+        pending_payment.approvals = Vec::new();
+        pending_payment.bump = ctx.bumps.pending_payment;
+
+        msg!("Payment {} proposed: {} lamports from {} to {}",
+             payment_id,
+             amount,
+             pending_payment.sender,
+             pending_payment.receiver);
+        Ok(())
+    }
+
+    pub fn approve_payment(ctx: Context<ApprovePayment>) -> Result<()> {
+        let approver = ctx.accounts.approver.key();
+        // Check 1: Signer is a configured approver
+        if !ctx.accounts.program_state.multisig.approvers.contains(&approver) {
+            return err!(ErrorCode::NotApprover);
+        }
+        // Check 2: Each approver counts once
+        let pending_payment = &mut ctx.accounts.pending_payment;
+        if pending_payment.approvals.contains(&approver) {
+            return err!(ErrorCode::DuplicateApproval);
+        }
+        pending_payment.approvals.push(approver);
+
+        msg!("Payment {} approved by {} ({} approvals)",
+             pending_payment.payment_id,
+             approver,
+             pending_payment.approvals.len());
+        Ok(())
+    }
+
+    pub fn execute_payment<'info>(ctx: Context<'_, '_, 'info, 'info, ExecutePayment<'info>>) -> Result<()> {
+        let pending_payment = &ctx.accounts.pending_payment;
+        let amount = pending_payment.amount;
+        let multisig = &ctx.accounts.program_state.multisig;
+
+        // --- Input Validation & Security Checks ---
+        // Check 0: Neither party is blacklisted, in case either was blocked after proposing
+        ensure_not_blacklisted(&ctx.accounts.sender_blacklist)?;
+        ensure_not_blacklisted(&ctx.accounts.receiver_blacklist)?;
+        // Check 0b: Sender is not frozen
+        ensure_not_frozen(&ctx.accounts.sender_freeze)?;
+        // Check 1: Enough approvals from approvers that are still configured
+        let valid_approvals = pending_payment
+            .approvals
+            .iter()
+            .filter(|approver| multisig.approvers.contains(approver))
+            .count();
+        // A state that was never configured has required_approvals == 0, which must not
+        // let a proposal through without any approvals
+        if multisig.required_approvals == 0 || valid_approvals < multisig.required_approvals as usize {
+            return err!(ErrorCode::InsufficientApprovals);
+        }
+        // Check 1b: The receiver is whitelisted and the payment fits the sender's daily
+        // limit, as for send_payment
+        ensure_whitelisted(ctx.accounts.program_state.whitelist_enabled, ctx.accounts.whitelist_entry.as_deref())?;
+        record_sender_spend(ctx.accounts.user_state.spend_limit_enabled, ctx.accounts.spend_limit.as_deref_mut(), amount)?;
+        // Check 2: Sufficient Sender Balance
+        if ctx.accounts.sender.lamports() < amount {
+            return err!(ErrorCode::InsufficientBalance);
+        }
+
+        // Same fee split as send_payment
+        let fee = compute_fee(amount, ctx.accounts.program_state.fee_bps, ctx.accounts.program_state.fee_rounding)?;
+        let net_amount = amount.checked_sub(fee).ok_or(ErrorCode::Overflow)?;
+        // Counts towards the receiver's stats and the per-receiver cap
+        let receiver_stats = &mut ctx.accounts.receiver_stats;
+        receiver_stats.claim(ctx.accounts.receiver.key(), ctx.bumps.receiver_stats);
+        receiver_stats.record_payment(net_amount, ctx.accounts.program_state.max_total_received)?;
+
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.sender.to_account_info(),
+                to: ctx.accounts.receiver.to_account_info(),
+            },
+        );
+        system_program::transfer(cpi_context, net_amount)?;
+        // Fee split collectors are passed as remaining accounts, as for send_payment
+        let fee_source = PaymentSource::Wallet(&ctx.accounts.sender);
+        for (collector, part) in fee_payouts(fee, &ctx.accounts.program_state, &ctx.accounts.fee_collector, ctx.remaining_accounts)? {
+            fee_source.transfer(&ctx.accounts.system_program, collector, part)?;
+        }
+
+        // Record the transaction; the pending payment is closed by `close = sender`
+        let pending_payment = &ctx.accounts.pending_payment;
+        let transaction_record = &mut ctx.accounts.transaction_record;
+        transaction_record.set_inner(TransactionRecord::new_payment(
+            pending_payment.sender,
+            pending_payment.receiver,
+            amount,
+            pending_payment.memo.clone(),
+            Clock::get()?.unix_timestamp,
+            fee,
+        ));
+
+        let program_state = &mut ctx.accounts.program_state;
+        advance_sequence(&mut ctx.accounts.transaction_record, &mut ctx.accounts.user_state, program_state)?;
+        program_state.total_volume = program_state.total_volume.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+        program_state.total_fees_collected = program_state.total_fees_collected.checked_add(fee).ok_or(ErrorCode::Overflow)?;
+
+        msg!("Payment {} executed: {} lamports ({} fee) to {}",
+             pending_payment.payment_id,
+             amount,
+             fee,
+             pending_payment.receiver);
+        Ok(())
+    }
+
+    pub fn initialize_fee_vault(ctx: Context<InitializeFeeVault>) -> Result<()> {
+        // Program-owned fee collector. Point `fee_collector` at it with `set_fee`
+        // so collected fees can later be swept with `withdraw_fees`.
+        ctx.accounts.fee_vault.bump = ctx.bumps.fee_vault;
+        emit!(FeeVaultInitialized {
+            authority: ctx.accounts.authority.key(),
+            fee_vault: ctx.accounts.fee_vault.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        msg!("Fee vault initialized at {}", ctx.accounts.fee_vault.key());
+        Ok(())
+    }
+
+    pub fn withdraw_fees(ctx: Context<WithdrawFees>, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return err!(ErrorCode::InvalidAmount);
+        }
+        // The vault must stay rent-exempt after the withdrawal
+        let vault_info = ctx.accounts.fee_vault.to_account_info();
+        let rent_exempt_minimum = Rent::get()?.minimum_balance(vault_info.data_len());
+        let available = vault_info.lamports().saturating_sub(rent_exempt_minimum);
+        if amount > available {
+            return err!(ErrorCode::InsufficientFeeBalance);
+        }
+
+        ctx.accounts.fee_vault.sub_lamports(amount)?;
+        ctx.accounts.destination.add_lamports(amount)?;
+        emit!(FeesWithdrawn {
+            authority: ctx.accounts.authority.key(),
+            destination: ctx.accounts.destination.key(),
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Withdrew {} lamports of fees to {}", amount, ctx.accounts.destination.key());
+        Ok(())
+    }
+
+    pub fn create_stream(
+        ctx: Context<CreateStream>,
+        total: u64,
+        start_ts: i64,
+        end_ts: i64,
+        cliff_ts: i64,
+    ) -> Result<()> {
+        // --- Input Validation & Security Checks ---
+        // Check 0: Neither party is blacklisted, and the receiver is whitelisted while the whitelist is on
+        ensure_not_blacklisted(&ctx.accounts.sender_blacklist)?;
+        ensure_not_blacklisted(&ctx.accounts.receiver_blacklist)?;
+        ensure_whitelisted(ctx.accounts.program_state.whitelist_enabled, ctx.accounts.whitelist_entry.as_deref())?;
+        // Check 0b: Sender is not frozen
+        ensure_not_frozen(&ctx.accounts.sender_freeze)?;
+        // Check 0c: Large payments must go through propose/approve/execute
+        ensure_no_approval_required(&ctx.accounts.program_state, total)?;
+        // Check 0d: The payment fits the sender's daily spend limit
+        record_sender_spend(ctx.accounts.user_state.spend_limit_enabled, ctx.accounts.spend_limit.as_deref_mut(), total)?;
+        // Check 1: Total > 0
+        if total == 0 {
+            return err!(ErrorCode::InvalidAmount);
+        }
+        // Check 1b: The whole stream is within the configured minimum and per-payment cap
+        ensure_amount_within_bounds(&ctx.accounts.program_state, total)?;
+        // Check 2: Sender != Recipient
+        if ctx.accounts.sender.key() == ctx.accounts.recipient.key() {
+            return err!(ErrorCode::SelfPayment);
+        }
+        // Check 3: Schedule must end after it starts
+        if end_ts <= start_ts {
+            return err!(ErrorCode::InvalidStreamSchedule);
+        }
+        // Check 3b: Optional cliff (0 = none) must fall within the schedule
+        if cliff_ts != 0 && (cliff_ts < start_ts || cliff_ts > end_ts) {
+            return err!(ErrorCode::InvalidStreamSchedule);
+        }
+        // Check 4: Sufficient Sender Balance
+        if ctx.accounts.sender.lamports() < total {
+            return err!(ErrorCode::InsufficientBalance);
+        }
+
+        // Lock the full amount in the stream PDA
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.sender.to_account_info(),
+                to: ctx.accounts.stream.to_account_info(),
+            },
+        );
+        system_program::transfer(cpi_context, total)?;
+
+        let program_state = &mut ctx.accounts.program_state;
+        let stream = &mut ctx.accounts.stream;
+        stream.sender = ctx.accounts.sender.key();
+        stream.recipient = ctx.accounts.recipient.key();
+        stream.total = total;
+        stream.withdrawn = 0;
+        stream.start_ts = start_ts;
+        stream.end_ts = end_ts;
+        stream.cliff_ts = cliff_ts;
+        stream.canceled = false;
+        stream.sequence = program_state.total_streams;
+        stream.bump = ctx.bumps.stream;
+
+        program_state.total_streams = program_state.total_streams.checked_add(1).ok_or(ErrorCode::Overflow)?;
+        program_state.open_hold()?;
+
+        msg!("Stream {} created: {} lamports from {} to {} between {} and {}",
+             stream.sequence,
+             total,
+             stream.sender,
+             stream.recipient,
+             start_ts,
+             end_ts);
+        Ok(())
+    }
+
+    pub fn withdraw_stream(ctx: Context<WithdrawStream>) -> Result<()> {
+        let stream = &ctx.accounts.stream;
+        let vested = stream.vested_amount(Clock::get()?.unix_timestamp)?;
+        let withdrawable = vested.checked_sub(stream.withdrawn).ok_or(ErrorCode::Overflow)?;
+        if withdrawable == 0 {
+            return err!(ErrorCode::NothingVested);
+        }
+
+        // Counts towards the receiver's stats and the per-receiver cap
+        let receiver_stats = &mut ctx.accounts.receiver_stats;
+        receiver_stats.claim(ctx.accounts.recipient.key(), ctx.bumps.receiver_stats);
+        receiver_stats.record_payment(withdrawable, ctx.accounts.program_state.max_total_received)?;
+
+        ctx.accounts.stream.sub_lamports(withdrawable)?;
+        ctx.accounts.recipient.add_lamports(withdrawable)?;
+        let stream = &mut ctx.accounts.stream;
+        stream.withdrawn = vested;
+
+        msg!("Stream {}: withdrew {} lamports ({} of {} total)",
+             stream.sequence,
+             withdrawable,
+             stream.withdrawn,
+             stream.total);
+        Ok(())
+    }
+
+    pub fn cancel_stream(ctx: Context<CancelStream>) -> Result<()> {
+        let stream = &mut ctx.accounts.stream;
+        // Mark canceled before any lamports move so a replay sees the flag
+        if stream.canceled {
+            return err!(ErrorCode::StreamAlreadyCanceled);
+        }
+        stream.canceled = true;
+
+        // Pay the recipient everything vested so far (nothing before the cliff);
+        // the unvested remainder and the rent go back to the sender via `close = sender`
+        let vested = stream.vested_amount(Clock::get()?.unix_timestamp)?;
+        let owed = vested.checked_sub(stream.withdrawn).ok_or(ErrorCode::Overflow)?;
+        let sequence = stream.sequence;
+        if owed > 0 {
+            // Counted but not capped: the vested part is already the recipient's, and
+            // a cap here would stop the sender getting the unvested remainder back
+            let receiver_stats = &mut ctx.accounts.receiver_stats;
+            receiver_stats.claim(ctx.accounts.recipient.key(), ctx.bumps.receiver_stats);
+            receiver_stats.record_payment(owed, 0)?;
+            ctx.accounts.stream.sub_lamports(owed)?;
+            ctx.accounts.recipient.add_lamports(owed)?;
+        }
+
+        ctx.accounts.program_state.close_hold()?;
+        msg!("Stream {} canceled: {} lamports paid to recipient, remainder refunded to {}",
+             sequence,
+             owed,
+             ctx.accounts.sender.key());
+        Ok(())
+    }
+
+    pub fn create_conditional_payment(
+        ctx: Context<CreateConditionalPayment>,
+        amount: u64,
+        price_threshold: i64,
+        feed_id: [u8; 32],
+        memo: String,
+    ) -> Result<()> {
+        // --- Input Validation & Security Checks ---
+        // Check 0: Neither party is blacklisted, the receiver is whitelisted while the whitelist is on,
+        // and the sender is not frozen
+        ensure_not_blacklisted(&ctx.accounts.sender_blacklist)?;
+        ensure_not_blacklisted(&ctx.accounts.receiver_blacklist)?;
+        ensure_whitelisted(ctx.accounts.program_state.whitelist_enabled, ctx.accounts.whitelist_entry.as_deref())?;
+        ensure_not_frozen(&ctx.accounts.sender_freeze)?;
+        // Check 0b: Large payments must go through propose/approve/execute
+        ensure_no_approval_required(&ctx.accounts.program_state, amount)?;
+        // Check 0c: The payment fits the sender's daily spend limit
+        record_sender_spend(ctx.accounts.user_state.spend_limit_enabled, ctx.accounts.spend_limit.as_deref_mut(), amount)?;
+        // Checks 1-3: Same rules as direct payments
+        let max_memo_length = ctx.accounts.program_state.max_memo_length as usize;
+        validate_payment(&ctx.accounts.sender.key(), &ctx.accounts.receiver.key(), amount, &memo, max_memo_length)?;
+        // Check 3b: Within the configured minimum and per-payment cap
+        ensure_amount_within_bounds(&ctx.accounts.program_state, amount)?;
+        // Check 4: Sufficient Sender Balance
+        if ctx.accounts.sender.lamports() < amount {
+            return err!(ErrorCode::InsufficientBalance);
+        }
+        // Check 5: Receiver must be able to receive SOL on release
+        if *ctx.accounts.receiver.owner != system_program::ID {
+            return err!(ErrorCode::InvalidReceiver);
+        }
+
+        // Escrow the funds until the price condition is met or the sender reclaims them
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.sender.to_account_info(),
+                to: ctx.accounts.conditional_payment.to_account_info(),
+            },
+        );
+        system_program::transfer(cpi_context, amount)?;
+
+        let program_state = &mut ctx.accounts.program_state;
+        let conditional_payment = &mut ctx.accounts.conditional_payment;
+        conditional_payment.sender = ctx.accounts.sender.key();
+        conditional_payment.receiver = ctx.accounts.receiver.key();
+        conditional_payment.amount = amount;
+        conditional_payment.price_threshold = price_threshold;
+        conditional_payment.feed_id = feed_id;
+        conditional_payment.memo = memo;
+        conditional_payment.sequence = program_state.total_conditionals;
+        conditional_payment.bump = ctx.bumps.conditional_payment;
+        conditional_payment.reclaimable_at = add_secs(Clock::get()?.unix_timestamp, CONDITIONAL_RECLAIM_DELAY_SECS)?;
+
+        program_state.total_conditionals = program_state.total_conditionals.checked_add(1).ok_or(ErrorCode::Overflow)?;
+        program_state.open_hold()?;
+        ctx.accounts.receiver_stats.claim(ctx.accounts.receiver.key(), ctx.bumps.receiver_stats);
+
+        msg!("Conditional payment {} created: {} lamports from {} to {} once price >= {}",
+             conditional_payment.sequence,
+             amount,
+             conditional_payment.sender,
+             conditional_payment.receiver,
+             price_threshold);
+        Ok(())
+    }
+
+    pub fn execute_conditional_payment(ctx: Context<ExecuteConditionalPayment>) -> Result<()> {
+        // Permissionless: anyone may crank this once the condition holds, since funds
+        // can only go to the stored receiver.
+        let conditional_payment = &ctx.accounts.conditional_payment;
+        let price = load_pyth_price(
+            &ctx.accounts.price_update,
+            &conditional_payment.feed_id,
+            Clock::get()?.unix_timestamp,
+        )?;
+        if price < conditional_payment.price_threshold {
+            return err!(ErrorCode::PriceConditionNotMet);
+        }
+
+        // Counts towards the receiver's stats and the per-receiver cap
+        let amount = conditional_payment.amount;
+        ctx.accounts.receiver_stats.record_payment(amount, ctx.accounts.program_state.max_total_received)?;
+
+        // Pay the receiver; the rent goes back to the sender via `close = sender`
+        ctx.accounts.conditional_payment.sub_lamports(amount)?;
+        ctx.accounts.receiver.add_lamports(amount)?;
+
+        ctx.accounts.program_state.close_hold()?;
+        msg!("Conditional payment {} executed at price {}: {} lamports to {}",
+             ctx.accounts.conditional_payment.sequence,
+             price,
+             amount,
+             ctx.accounts.receiver.key());
+        Ok(())
+    }
+
+    pub fn reclaim_conditional_payment(ctx: Context<ReclaimConditionalPayment>) -> Result<()> {
+        // Strictly after the delay, so a reclaim can't race an execute landing in the
+        // same slot the condition is met
+        if Clock::get()?.unix_timestamp <= ctx.accounts.conditional_payment.reclaimable_at {
+            return err!(ErrorCode::ConditionalPaymentPending);
+        }
+        ctx.accounts.program_state.close_hold()?;
+        // `close = sender` returns both the escrowed amount and the rent
+        msg!("Conditional payment {} reclaimed by {}",
+             ctx.accounts.conditional_payment.sequence,
+             ctx.accounts.sender.key());
+        Ok(())
+    }
+
+    pub fn send_payment_with_tip<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SendPaymentWithTip<'info>>,
+        amount: u64,
+        tip: u64,
+        memo: String,
+    ) -> Result<()> {
+        // --- Input Validation & Security Checks ---
+        // Checks 1-3: Amount > 0, Sender != Receiver, Memo Length
+        let max_memo_length = ctx.accounts.program_state.max_memo_length as usize;
+        validate_payment(&ctx.accounts.sender.key(), &ctx.accounts.receiver.key(), amount, &memo, max_memo_length)?;
+        // Check 4: Sender covers the payment and the tip
+        let total = amount.checked_add(tip).ok_or(ErrorCode::Overflow)?;
+        if ctx.accounts.sender.lamports() < total {
+            return err!(ErrorCode::InsufficientBalance);
+        }
+        // Check 5: The tip recipient must be able to receive SOL directly and is held to
+        // the same compliance rules; the receiver is checked with the base payment below
+        if tip > 0 {
+            ensure_not_blacklisted(&ctx.accounts.tip_recipient_blacklist)?;
+            if *ctx.accounts.tip_recipient.owner != system_program::ID {
+                return err!(ErrorCode::InvalidReceiver);
+            }
+            if ctx.accounts.tip_recipient.key() == ctx.accounts.sender.key() {
+                return err!(ErrorCode::SelfPayment);
+            }
+        }
+        // Checks 6-9 and the base transfer: the rules every direct payment shares. The
+        // minimum, the cap and the fee apply to the base amount; the tip counts towards
+        // the sender's limits and is passed on in full.
+        let fee = direct_payment!(ctx, PaymentSource::Wallet(&ctx.accounts.sender), &ctx.accounts.sender)
+            .validate_and_charge(amount, total)?;
+
+        if tip > 0 {
+            let tip_context = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.sender.to_account_info(),
+                    to: ctx.accounts.tip_recipient.to_account_info(),
+                },
+            );
+            system_program::transfer(tip_context, tip)?;
+        }
+
+        msg!("Payment Sent: {} lamports ({} fee) plus {} tip from {} to {} with memo: {}",
+             amount,
+             fee,
+             tip,
+             ctx.accounts.sender.key(),
+             ctx.accounts.receiver.key(),
+             memo);
+
+        // Record the transaction
+        let transaction_record = &mut ctx.accounts.transaction_record;
+        transaction_record.set_inner(TransactionRecord::new_payment(
+            ctx.accounts.sender.key(),
+            ctx.accounts.receiver.key(),
+            amount,
+            memo.clone(),
+            Clock::get()?.unix_timestamp,
+            fee,
+        ));
+        transaction_record.tip = tip;
+        transaction_record.tip_recipient = (tip > 0).then(|| ctx.accounts.tip_recipient.key());
+
+        let program_state = &mut ctx.accounts.program_state;
+        let sequence = advance_sequence(transaction_record, &mut ctx.accounts.user_state, program_state)?;
+
+        emit!(PaymentSent {
+            sender: transaction_record.sender,
+            receiver: transaction_record.receiver,
+            amount,
+            memo,
+            timestamp: transaction_record.timestamp,
+            sequence,
+        });
+
+        Ok(())
+    }
+
+    pub fn deposit_to_vault(ctx: Context<DepositToVault>, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return err!(ErrorCode::InvalidAmount);
+        }
+        // The vault is a data-less, system-owned PDA, so a plain transfer creates/funds it.
+        // The first deposit must cover the rent-exempt minimum or the runtime rejects it.
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.owner.to_account_info(),
+                to: ctx.accounts.vault.to_account_info(),
+            },
+        );
+        system_program::transfer(cpi_context, amount)?;
+
+        msg!("Deposited {} lamports into vault {}", amount, ctx.accounts.vault.key());
+        Ok(())
+    }
+
+    pub fn withdraw_from_vault(ctx: Context<WithdrawFromVault>, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return err!(ErrorCode::InvalidAmount);
+        }
+        ensure_vault_remainder(&ctx.accounts.vault, amount)?;
+        vault_transfer(
+            &ctx.accounts.system_program,
+            &ctx.accounts.vault,
+            &ctx.accounts.owner.to_account_info(),
+            &ctx.accounts.owner.key(),
+            ctx.bumps.vault,
+            amount,
+        )?;
+
+        msg!("Withdrew {} lamports from vault {}", amount, ctx.accounts.vault.key());
+        Ok(())
+    }
+
+    pub fn send_payment_from_vault<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SendPaymentFromVault<'info>>,
+        amount: u64,
+        memo: String,
+    ) -> Result<()> {
+        // The owner signs to authorize the transfer, but the relayer is the fee payer and
+        // pays the record's rent; the lamports themselves come out of the owner's vault.
+        let owner = ctx.accounts.owner.key();
+
+        // --- Input Validation & Security Checks ---
+        // Checks 1-3: Amount > 0, Sender != Receiver, Memo Length
+        let max_memo_length = ctx.accounts.program_state.max_memo_length as usize;
+        validate_payment(&owner, &ctx.accounts.receiver.key(), amount, &memo, max_memo_length)?;
+        if ctx.accounts.receiver.key() == ctx.accounts.vault.key() {
+            return err!(ErrorCode::SelfPayment);
+        }
+        // Check 4: The vault covers the payment
+        ensure_vault_remainder(&ctx.accounts.vault, amount)?;
+        // Checks 5-8 and the transfer: the rules every direct payment shares, with the
+        // owner's policy accounts
+        let fee = direct_payment!(ctx, PaymentSource::Vault { vault: &ctx.accounts.vault, owner, bump: ctx.bumps.vault }, &ctx.accounts.owner)
+            .validate_and_charge(amount, amount)?;
+
+        msg!("Vault Payment Sent: {} lamports ({} fee) from {} to {}, relayed by {}",
+             amount,
+             fee,
+             owner,
+             ctx.accounts.receiver.key(),
+             ctx.accounts.relayer.key());
+
+        // Record the transaction under the owner's history
+        let transaction_record = &mut ctx.accounts.transaction_record;
+        transaction_record.set_inner(TransactionRecord::new_payment(
+            owner,
+            ctx.accounts.receiver.key(),
+            amount,
+            memo.clone(),
+            Clock::get()?.unix_timestamp,
+            fee,
+        ));
+
+        let program_state = &mut ctx.accounts.program_state;
+        let sequence = advance_sequence(transaction_record, &mut ctx.accounts.user_state, program_state)?;
+
+        emit!(PaymentSent {
+            sender: owner,
+            receiver: transaction_record.receiver,
+            amount,
+            memo,
+            timestamp: transaction_record.timestamp,
+            sequence,
+        });
+
+        Ok(())
+    }
+
+    pub fn update_memo(ctx: Context<UpdateMemo>, new_memo: String) -> Result<()> {
+        // Same length and content rules as a new payment under the current configured
+        // limit, with any room grow_record added
+        let configured_length = ctx.accounts.program_state.max_memo_length as usize;
+        let transaction_record = &mut ctx.accounts.transaction_record;
+        let (max_memo_length, max_memo_bytes) = TransactionRecord::memo_limits(transaction_record.to_account_info().data_len(), configured_length);
+        validate_memo_within(&new_memo, max_memo_length, max_memo_bytes)?;
+        if transaction_record.memo_encrypted && !new_memo.is_empty() {
+            return err!(ErrorCode::ConflictingMemos);
+        }
+        // A coded record has no room for a memo; its code is permanent
+        if transaction_record.memo_code.is_some() {
+            return err!(ErrorCode::CodedMemo);
+        }
+        let window_end = add_secs(transaction_record.timestamp, MEMO_EDIT_WINDOW_SECS)?;
+        if Clock::get()?.unix_timestamp > window_end {
+            return err!(ErrorCode::MemoEditWindowClosed);
+        }
+
+        msg!("Memo on {} updated from '{}' to '{}'",
+             transaction_record.key(),
+             transaction_record.memo,
+             new_memo);
+        clear_account_data(&transaction_record.to_account_info())?;
+        transaction_record.memo = new_memo;
+        Ok(())
+    }
+
+    pub fn add_blacklist(ctx: Context<AddBlacklist>) -> Result<()> {
+        let blacklist = &mut ctx.accounts.blacklist;
+        blacklist.address = ctx.accounts.address.key();
+        blacklist.bump = ctx.bumps.blacklist;
+        emit_access_list_changed(ctx.accounts.authority.key(), AccessList::Blacklist, blacklist.address, true)?;
+        msg!("Blacklisted address {}", blacklist.address);
+        Ok(())
+    }
+
+    pub fn remove_blacklist(ctx: Context<RemoveBlacklist>) -> Result<()> {
+        // `close = authority` deletes the entry and refunds its rent
+        emit_access_list_changed(ctx.accounts.authority.key(), AccessList::Blacklist, ctx.accounts.blacklist.address, false)?;
+        msg!("Removed address {} from blacklist", ctx.accounts.blacklist.address);
+        Ok(())
+    }
+
+    pub fn freeze_user(ctx: Context<FreezeUser>) -> Result<()> {
+        // Unlike a blacklist entry this only stops the user's outgoing payments
+        let freeze_flag = &mut ctx.accounts.freeze_flag;
+        freeze_flag.user = ctx.accounts.user.key();
+        freeze_flag.frozen_at = Clock::get()?.unix_timestamp;
+        freeze_flag.bump = ctx.bumps.freeze_flag;
+        emit_access_list_changed(ctx.accounts.authority.key(), AccessList::Frozen, freeze_flag.user, true)?;
+        msg!("Outgoing payments frozen for {}", freeze_flag.user);
+        Ok(())
+    }
+
+    pub fn unfreeze_user(ctx: Context<UnfreezeUser>) -> Result<()> {
+        // `close = authority` deletes the flag and refunds its rent
+        emit_access_list_changed(ctx.accounts.authority.key(), AccessList::Frozen, ctx.accounts.freeze_flag.user, false)?;
+        msg!("Outgoing payments unfrozen for {}", ctx.accounts.freeze_flag.user);
+        Ok(())
+    }
+
+    pub fn set_memo_pattern(ctx: Context<SetMemoPattern>, memo_pattern: Option<MemoPattern>) -> Result<()> {
+        if let Some(pattern) = &memo_pattern {
+            pattern.validate()?;
+            msg!("Memo pattern set: '{}' followed by {} alphanumeric characters",
+                 pattern.prefix,
+                 pattern.suffix_length);
+        } else {
+            msg!("Memo pattern cleared, free-form memos allowed");
+        }
+        clear_account_data(&ctx.accounts.program_state.to_account_info())?;
+        ctx.accounts.program_state.memo_pattern = memo_pattern.clone();
+        emit!(MemoPatternChanged {
+            authority: ctx.accounts.authority.key(),
+            memo_pattern,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    pub fn reclaim_timelocked_payment(ctx: Context<ReclaimTimelockedPayment>) -> Result<()> {
+        // Once an unclaimed timelock has expired, the sender gets everything back
+        if !is_expired(ctx.accounts.timelock.expires_at, Clock::get()?.unix_timestamp) {
+            return err!(ErrorCode::PaymentNotExpired);
+        }
+
+        ctx.accounts.program_state.close_hold()?;
+        // `close = sender` returns both the locked amount and the rent
+        msg!("Expired timelocked payment {} reclaimed by {}",
+             ctx.accounts.timelock.sequence,
+             ctx.accounts.sender.key());
+        Ok(())
+    }
+
+    pub fn approve_delegate(ctx: Context<ApproveDelegate>, allowance: u64) -> Result<()> {
+        // Calling again replaces the delegate and resets the allowance
+        let payment_delegate = &mut ctx.accounts.payment_delegate;
+        payment_delegate.owner = ctx.accounts.owner.key();
+        payment_delegate.delegate = ctx.accounts.delegate.key();
+        payment_delegate.allowance = allowance;
+        payment_delegate.bump = ctx.bumps.payment_delegate;
+
+        msg!("{} may now send up to {} lamports from {}'s vault",
+             payment_delegate.delegate,
+             allowance,
+             payment_delegate.owner);
+        Ok(())
+    }
+
+    pub fn revoke_delegate(ctx: Context<RevokeDelegate>) -> Result<()> {
+        // `close = owner` deletes the grant and refunds its rent
+        msg!("Delegate {} revoked by {}",
+             ctx.accounts.payment_delegate.delegate,
+             ctx.accounts.owner.key());
+        Ok(())
+    }
+
+    pub fn send_payment_as_delegate<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SendPaymentAsDelegate<'info>>,
+        amount: u64,
+        memo: String,
+    ) -> Result<()> {
+        // A delegate can't sign for the owner's wallet, so delegated payments are drawn
+        // from the owner's `[b"vault", owner]` PDA, which the program signs for.
+        let owner = ctx.accounts.owner.key();
+
+        // --- Input Validation & Security Checks ---
+        // Checks 1-3: Amount > 0, Sender != Receiver, Memo Length
+        let max_memo_length = ctx.accounts.program_state.max_memo_length as usize;
+        validate_payment(&owner, &ctx.accounts.receiver.key(), amount, &memo, max_memo_length)?;
+        if ctx.accounts.receiver.key() == ctx.accounts.vault.key() {
+            return err!(ErrorCode::SelfPayment);
+        }
+        // Check 4: Remaining allowance covers the gross amount
+        let payment_delegate = &mut ctx.accounts.payment_delegate;
+        payment_delegate.allowance = payment_delegate
+            .allowance
+            .checked_sub(amount)
+            .ok_or(ErrorCode::DelegateAllowanceExceeded)?;
+        // Check 5: The vault covers the payment
+        ensure_vault_remainder(&ctx.accounts.vault, amount)?;
+        // Checks 6-9 and the transfer: the rules every direct payment shares, with the
+        // owner's policy accounts
+        let fee = direct_payment!(ctx, PaymentSource::Vault { vault: &ctx.accounts.vault, owner, bump: ctx.bumps.vault }, &ctx.accounts.owner)
+            .validate_and_charge(amount, amount)?;
+
+        msg!("Delegated Payment Sent: {} lamports ({} fee) from {} to {} by {}, {} allowance left",
+             amount,
+             fee,
+             owner,
+             ctx.accounts.receiver.key(),
+             ctx.accounts.delegate.key(),
+             ctx.accounts.payment_delegate.allowance);
+
+        // Record the transaction under the owner's history
+        let transaction_record = &mut ctx.accounts.transaction_record;
+        transaction_record.set_inner(TransactionRecord::new_payment(
+            owner,
+            ctx.accounts.receiver.key(),
+            amount,
+            memo.clone(),
+            Clock::get()?.unix_timestamp,
+            fee,
+        ));
+
+        let program_state = &mut ctx.accounts.program_state;
+        let sequence = advance_sequence(transaction_record, &mut ctx.accounts.user_state, program_state)?;
+
+        emit!(PaymentSent {
+            sender: owner,
+            receiver: transaction_record.receiver,
+            amount,
+            memo,
+            timestamp: transaction_record.timestamp,
+            sequence,
+        });
+
+        Ok(())
+    }
+
+    pub fn grant_fee_exemption(ctx: Context<GrantFeeExemption>) -> Result<()> {
+        let fee_exemption = &mut ctx.accounts.fee_exemption;
+        fee_exemption.sender = ctx.accounts.sender.key();
+        fee_exemption.bump = ctx.bumps.fee_exemption;
+        emit_access_list_changed(ctx.accounts.authority.key(), AccessList::FeeExempt, fee_exemption.sender, true)?;
+        msg!("Sender {} is now exempt from the protocol fee", fee_exemption.sender);
+        Ok(())
+    }
+
+    pub fn revoke_fee_exemption(ctx: Context<RevokeFeeExemption>) -> Result<()> {
+        // `close = authority` deletes the exemption and refunds its rent
+        emit_access_list_changed(ctx.accounts.authority.key(), AccessList::FeeExempt, ctx.accounts.fee_exemption.sender, false)?;
+        msg!("Fee exemption revoked for {}", ctx.accounts.fee_exemption.sender);
+        Ok(())
+    }
+
+    pub fn begin_teardown(ctx: Context<BeginTeardown>) -> Result<()> {
+        let program_state = &mut ctx.accounts.program_state;
+        if program_state.teardown_requested_at != 0 {
+            return err!(ErrorCode::TeardownAlreadyRequested);
+        }
+        program_state.teardown_requested_at = Clock::get()?.unix_timestamp;
+        // No new payments during the delay
+        program_state.paused = true;
+        emit!(PausedChanged {
+            authority: ctx.accounts.authority.key(),
+            paused: true,
+            timestamp: program_state.teardown_requested_at,
+        });
+
+        msg!("Teardown requested at {}; state can be closed after {} seconds",
+             program_state.teardown_requested_at,
+             TEARDOWN_DELAY_SECS);
+        Ok(())
+    }
+
+    pub fn finalize_teardown(ctx: Context<FinalizeTeardown>) -> Result<()> {
+        let requested_at = ctx.accounts.program_state.teardown_requested_at;
+        if requested_at == 0 {
+            return err!(ErrorCode::TeardownNotRequested);
+        }
+        // The delay gives users time to notice a compromised authority before the state is gone
+        let ready_at = add_secs(requested_at, TEARDOWN_DELAY_SECS)?;
+        if Clock::get()?.unix_timestamp < ready_at {
+            return err!(ErrorCode::TeardownDelayNotElapsed);
+        }
+        // Escrows and held payments are paid out through instructions that load the
+        // state, so closing it first would strand their lamports
+        let program_state = &ctx.accounts.program_state;
+        if program_state.active_escrows > 0 || program_state.open_holds > 0 {
+            return err!(ErrorCode::HoldsOutstanding);
+        }
+
+        // `close = authority` deletes the state PDA and refunds its rent
+        emit!(StateClosed {
+            authority: ctx.accounts.authority.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        msg!("Program state closed by {}", ctx.accounts.authority.key());
+        Ok(())
+    }
+
+    pub fn close_records_range<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CloseRecordsRange<'info>>,
+        start_seq: u64,
+        end_seq: u64,
+    ) -> Result<()> {
+        // For each sequence in `start_seq..=end_seq`, in order, the caller passes the
+        // writable record followed by the address of its `[b"pending", sender, sequence]` PDA
+        let accounts = ctx.remaining_accounts;
+        let sender = ctx.accounts.sender.key();
+
+        // Check 1: Non-empty range within the per-call cap
+        let range_len = end_seq
+            .checked_sub(start_seq)
+            .and_then(|span| span.checked_add(1))
+            .ok_or(ErrorCode::InvalidAmount)?;
+        if range_len > MAX_CLOSE_RANGE as u64 {
+            return err!(ErrorCode::RangeTooLarge);
+        }
+        // Check 2: A record and a pending transfer address per sequence
+        if accounts.len() as u64 != range_len * 2 {
+            return err!(ErrorCode::TransactionNotFound);
+        }
+
+        for (sequence, pair) in (start_seq..=end_seq).zip(accounts.chunks(2)) {
+            let (record_info, pending_info) = (&pair[0], &pair[1]);
+            // Check 3: Both accounts belong to the signer's payment at this sequence
+            let seed = sequence.to_le_bytes();
+            let (expected_record, _) = Pubkey::find_program_address(&[b"transaction", sender.as_ref(), seed.as_ref()], ctx.program_id);
+            let (expected_pending, _) = Pubkey::find_program_address(&[b"pending", sender.as_ref(), seed.as_ref()], ctx.program_id);
+            if record_info.key() != expected_record || pending_info.key() != expected_pending {
+                return err!(ErrorCode::TransactionNotFound);
+            }
+            let record: Account<'info, TransactionRecord> = Account::try_from(record_info)?;
+            // Check 4: Same ownership rule as close_transaction_record
+            if record.current_owner() != sender {
+                return err!(ErrorCode::UnauthorizedClose);
+            }
+            // Check 5: Same hold rule as close_transaction_record
+            if is_transfer_held(pending_info) {
+                return err!(ErrorCode::TransferPending);
+            }
+            record.close(ctx.accounts.sender.to_account_info())?;
+        }
+
+        msg!("Closed {} transaction records ({}..={}). Rent refunded to {}",
+             range_len,
+             start_seq,
+             end_seq,
+             sender);
+        Ok(())
+    }
+
+    pub fn sweep_dust<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SweepDust<'info>>,
+        sequences: Vec<u64>,
+    ) -> Result<()> {
+        // For each sequence the caller passes the record, followed by the address of
+        // its `[b"pending", sender, sequence]` PDA so held payments can be recognised
+        let accounts = ctx.remaining_accounts;
+        let sender = ctx.accounts.sender.key();
+
+        // Check 1: Non-empty list within the per-call cap
+        if sequences.is_empty() || sequences.len() > MAX_CLOSE_RANGE {
+            return err!(ErrorCode::RangeTooLarge);
+        }
+        // Check 2: A record and a pending transfer address per sequence
+        if accounts.len() != sequences.len() * 2 {
+            return err!(ErrorCode::TransactionNotFound);
+        }
+
+        let mut reclaimed: u64 = 0;
+        let mut swept: usize = 0;
+        for (sequence, pair) in sequences.iter().zip(accounts.chunks(2)) {
+            let (record_info, pending_info) = (&pair[0], &pair[1]);
+            // Check 3: Both accounts belong to the signer's payment at this sequence
+            let seed = sequence.to_le_bytes();
+            let (expected_record, _) = Pubkey::find_program_address(&[b"transaction", sender.as_ref(), seed.as_ref()], ctx.program_id);
+            let (expected_pending, _) = Pubkey::find_program_address(&[b"pending", sender.as_ref(), seed.as_ref()], ctx.program_id);
+            if record_info.key() != expected_record || pending_info.key() != expected_pending {
+                return err!(ErrorCode::TransactionNotFound);
+            }
+            let record: Account<'info, TransactionRecord> = Account::try_from(record_info)?;
+            // Check 4: Same ownership rule as close_transaction_record
+            if record.current_owner() != sender {
+                return err!(ErrorCode::UnauthorizedClose);
+            }
+            // Only finalized SOL micro-payments are swept: larger records, token records
+            // (whose amount is in the mint's base units, not lamports) and payments still
+            // waiting on the receiver (whose resolution writes to the record) are kept
+            if record.mint.is_some() || record.amount >= DUST_THRESHOLD_LAMPORTS || is_transfer_held(pending_info) {
+                continue;
+            }
+
+            // Close by hand so all the rent goes back in a single credit below
+            let rent = record_info.lamports();
+            record_info.sub_lamports(rent)?;
+            record_info.assign(&system_program::ID);
+            record_info.realloc(0, false)?;
+            reclaimed = reclaimed.checked_add(rent).ok_or(ErrorCode::Overflow)?;
+            swept += 1;
+        }
+        if reclaimed > 0 {
+            ctx.accounts.sender.add_lamports(reclaimed)?;
+        }
+
+        msg!("Swept {} of {} dust records. {} lamports of rent refunded to {}",
+             swept,
+             sequences.len(),
+             reclaimed,
+             sender);
+        Ok(())
+    }
+
+    pub fn migrate_record(ctx: Context<MigrateRecord>) -> Result<()> {
+        let record_info = ctx.accounts.transaction_record.to_account_info();
+        // Only this program's transaction records can be migrated
+        {
+            let data = record_info.try_borrow_data()?;
+            if data.len() < DISCRIMINATOR_LENGTH || &data[..DISCRIMINATOR_LENGTH] != TransactionRecord::DISCRIMINATOR {
+                return err!(ErrorCode::TransactionNotFound);
+            }
+        }
+
+        // Grow older, smaller accounts to the current size. New bytes are zeroed, which
+        // deserializes as the defaults (None / 0 / false) for every appended field; the
+        // write below clears any bytes a shrunk memo or note left behind.
+        let current_len = record_info.data_len();
+        if current_len < TransactionRecord::LEN {
+            let rent = Rent::get()?;
+            let top_up = rent
+                .minimum_balance(TransactionRecord::LEN)
+                .saturating_sub(record_info.lamports());
+            if top_up > 0 {
+                let cpi_context = CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.sender.to_account_info(),
+                        to: record_info.clone(),
+                    },
+                );
+                system_program::transfer(cpi_context, top_up)?;
+            }
+            record_info.realloc(TransactionRecord::LEN, true)?;
+        }
+
+        let mut data = record_info.try_borrow_mut_data()?;
+        let mut record = TransactionRecord::try_deserialize(&mut &data[..])?;
+        if record.current_owner() != ctx.accounts.sender.key() {
+            return err!(ErrorCode::Unauthorized);
+        }
+        // Guard: migration only runs on outdated versions
+        if record.schema_version >= TRANSACTION_RECORD_VERSION {
+            return err!(ErrorCode::RecordAlreadyMigrated);
+        }
+        let from_version = record.schema_version;
+        record.schema_version = TRANSACTION_RECORD_VERSION;
+        // v4: records that predate ownership belong to their sender
+        if record.owner == Pubkey::default() {
+            record.owner = record.sender;
+        }
+        write_account_data(&record, &mut data)?;
+
+        msg!("Transaction record {} migrated from schema v{} to v{} ({} -> {} bytes)",
+             record_info.key(),
+             from_version,
+             TRANSACTION_RECORD_VERSION,
+             current_len,
+             TransactionRecord::LEN);
+        Ok(())
+    }
+
+    // Brings a ProgramState written by an older program version up to the current
+    // layout. Escrows opened before v9 and other held payments opened before v16 were
+    // never counted, so the authority passes the numbers still open (from
+    // getProgramAccounts); each is ignored for versions that already count it. A
+    // baseline state predates the authority field, so the program's upgrade authority
+    // migrates it, passing `program_data`, and becomes its authority.
+    pub fn migrate_state(ctx: Context<MigrateState>, active_escrows: u64, open_holds: u64) -> Result<()> {
+        let state_info = ctx.accounts.program_state.to_account_info();
+        {
+            let data = state_info.try_borrow_data()?;
+            if data.len() < DISCRIMINATOR_LENGTH || &data[..DISCRIMINATOR_LENGTH] != ProgramState::DISCRIMINATOR {
+                return err!(ErrorCode::StateNotInitialized);
+            }
+        }
+
+        // Fields are only ever appended, so zeroed new bytes read as their defaults; the
+        // write below clears any bytes a shrunk Option or Vec left behind
+        let current_len = state_info.data_len();
+        if current_len < ProgramState::LEN {
+            let top_up = Rent::get()?
+                .minimum_balance(ProgramState::LEN)
+                .saturating_sub(state_info.lamports());
+            if top_up > 0 {
+                let cpi_context = CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.authority.to_account_info(),
+                        to: state_info.clone(),
+                    },
+                );
+                system_program::transfer(cpi_context, top_up)?;
+            }
+            state_info.realloc(ProgramState::LEN, true)?;
+        }
+
+        let mut data = state_info.try_borrow_mut_data()?;
+        let mut state = ProgramState::try_deserialize(&mut &data[..])?;
+        if state.schema_version == 0 && state.authority == Pubkey::default() {
+            // A baseline state holds only total_transactions and never had an authority,
+            // so the program's upgrade authority claims it, as initialize_state requires
+            let upgrade_authority = ctx.accounts.program_data.as_ref().and_then(|program_data| program_data.upgrade_authority_address);
+            if upgrade_authority != Some(ctx.accounts.authority.key()) {
+                return err!(ErrorCode::NotUpgradeAuthority);
+            }
+            state.authority = ctx.accounts.authority.key();
+            state.fee_collector = ctx.accounts.authority.key();
+        } else if state.authority != ctx.accounts.authority.key() {
+            return err!(ErrorCode::Unauthorized);
+        }
+        // Guard: migration only runs on outdated versions
+        if state.schema_version >= PROGRAM_STATE_VERSION {
+            return err!(ErrorCode::StateAlreadyMigrated);
+        }
+        let from_version = state.schema_version;
+        // v7: the limit send_payment enforced before it became configurable
+        if from_version < 7 {
+            state.max_memo_length = MAX_MEMO_LENGTH as u16;
+        }
+        if from_version < 9 {
+            state.active_escrows = active_escrows;
+        }
+        // v13: one base fee, as initialize_state sets
+        if from_version < 13 {
+            state.min_reserve = TX_FEE_ESTIMATE_LAMPORTS;
+        }
+        if from_version < 16 {
+            state.open_holds = open_holds;
+        }
+        state.schema_version = PROGRAM_STATE_VERSION;
+        write_account_data(&state, &mut data)?;
+        emit!(StateMigrated {
+            authority: state.authority,
+            from_version,
+            to_version: PROGRAM_STATE_VERSION,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Program state migrated from schema v{} to v{} ({} -> {} bytes)",
+             from_version,
+             PROGRAM_STATE_VERSION,
+             current_len,
+             ProgramState::LEN);
+        Ok(())
+    }
+
+    // Makes room on a record for a memo longer than TransactionRecord::LEN reserves;
+    // update_memo then accepts up to `memo_limits` of the grown size
+    pub fn grow_record(ctx: Context<GrowRecord>, additional_bytes: u32) -> Result<()> {
+        // Solana caps how much one instruction may grow an account
+        let additional_bytes = additional_bytes as usize;
+        if additional_bytes == 0 || additional_bytes > MAX_PERMITTED_DATA_INCREASE {
+            return err!(ErrorCode::ReallocTooLarge);
+        }
+        let record_info = ctx.accounts.transaction_record.to_account_info();
+        let current_len = record_info.data_len();
+        let new_len = current_len.checked_add(additional_bytes).ok_or(ErrorCode::Overflow)?;
+
+        // Keep the record rent-exempt at its new size
+        let top_up = Rent::get()?
+            .minimum_balance(new_len)
+            .saturating_sub(record_info.lamports());
+        if top_up > 0 {
+            let cpi_context = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.sender.to_account_info(),
+                    to: record_info.clone(),
+                },
+            );
+            system_program::transfer(cpi_context, top_up)?;
+        }
+        // Borsh length prefixes describe the stored values, not the account size, so
+        // the zeroed tail is simply spare room and no prefix needs rewriting.
+        record_info.realloc(new_len, true)?;
+
+        msg!("Transaction record {} grown from {} to {} bytes",
+             record_info.key(),
+             current_len,
+             new_len);
+        Ok(())
+    }
+
+    pub fn send_wrapped_payment(ctx: Context<SendWrappedPayment>, amount: u64, memo: String, sync_native: bool) -> Result<()> {
+        // --- Input Validation & Security Checks ---
+        // Check 0: Neither party is blacklisted, and the receiver is whitelisted while the whitelist is on
+        ensure_not_blacklisted(&ctx.accounts.sender_blacklist)?;
+        ensure_not_blacklisted(&ctx.accounts.receiver_blacklist)?;
+        ensure_whitelisted(ctx.accounts.program_state.whitelist_enabled, ctx.accounts.whitelist_entry.as_deref())?;
+        // Check 0b: Sender is not frozen
+        ensure_not_frozen(&ctx.accounts.sender_freeze)?;
+        // Check 0c: Large payments must go through propose/approve/execute
+        ensure_no_approval_required(&ctx.accounts.program_state, amount)?;
+        // Check 0d: The payment fits the sender's daily spend limit
+        record_sender_spend(ctx.accounts.user_state.spend_limit_enabled, ctx.accounts.spend_limit.as_deref_mut(), amount)?;
+        // Checks 1-3: Same rules as native SOL payments
+        let max_memo_length = ctx.accounts.program_state.max_memo_length as usize;
+        validate_payment(&ctx.accounts.sender.key(), &ctx.accounts.receiver.key(), amount, &memo, max_memo_length)?;
+        // Check 3b: Within the configured minimum and per-payment cap
+        ensure_amount_within_bounds(&ctx.accounts.program_state, amount)?;
+
+        // Lamports sent straight to a wSOL account only count once synced
+        if sync_native {
+            let sync_context = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::SyncNative {
+                    account: ctx.accounts.sender_token_account.to_account_info(),
+                },
+            );
+            token::sync_native(sync_context)?;
+            ctx.accounts.sender_token_account.reload()?;
+        }
+        // Check 4: Sufficient wrapped balance
+        if ctx.accounts.sender_token_account.amount < amount {
+            return err!(ErrorCode::InsufficientBalance);
+        }
+
+        // wSOL is 1:1 with lamports, so it counts towards the same stats and cap
+        let receiver_stats = &mut ctx.accounts.receiver_stats;
+        receiver_stats.claim(ctx.accounts.receiver.key(), ctx.bumps.receiver_stats);
+        receiver_stats.record_payment(amount, ctx.accounts.program_state.max_total_received)?;
+
+        let cpi_context = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.sender_token_account.to_account_info(),
+                to: ctx.accounts.receiver_token_account.to_account_info(),
+                authority: ctx.accounts.sender.to_account_info(),
+            },
+        );
+        token::transfer(cpi_context, amount)?;
+
+        msg!("Wrapped SOL Payment Sent: {} lamports from {} to {} with memo: {}",
+             amount,
+             ctx.accounts.sender.key(),
+             ctx.accounts.receiver.key(),
+             memo);
+
+        // Record the transaction
+        let transaction_record = &mut ctx.accounts.transaction_record;
+        transaction_record.set_inner(TransactionRecord::new_payment(
+            ctx.accounts.sender.key(),
+            ctx.accounts.receiver.key(),
+            amount,
+            memo,
+            Clock::get()?.unix_timestamp,
+            0,
+        ));
+        transaction_record.mint = Some(ctx.accounts.mint.key());
+        transaction_record.mint_decimals = ctx.accounts.mint.decimals;
+
+        let program_state = &mut ctx.accounts.program_state;
+        advance_sequence(&mut ctx.accounts.transaction_record, &mut ctx.accounts.user_state, program_state)?;
+
+        msg!("Transaction recorded. Total transactions: {}", program_state.total_transactions);
+
+        Ok(())
+    }
+
+    pub fn atomic_swap(ctx: Context<AtomicSwap>, swap_id: u64, amount_a: u64, amount_b: u64) -> Result<()> {
+        // --- Input Validation & Security Checks ---
+        // Check 1: Both legs move something, the lamport leg within the configured
+        // minimum and per-payment cap
+        if amount_a == 0 || amount_b == 0 {
+            return err!(ErrorCode::InvalidAmount);
+        }
+        ensure_amount_within_bounds(&ctx.accounts.program_state, amount_a)?;
+        // Check 2: Two distinct parties
+        if ctx.accounts.party_a.key() == ctx.accounts.party_b.key() {
+            return err!(ErrorCode::SelfPayment);
+        }
+        // Check 2b: Both parties send one leg and receive the other, so each must be
+        // allowed to pay and to be paid. Party B's leg is compared in the mint's base
+        // units, as for token payments.
+        ensure_sender_may_pay(
+            &ctx.accounts.program_state,
+            SenderPolicy {
+                blacklist: &ctx.accounts.party_a_blacklist,
+                freeze: &ctx.accounts.party_a_freeze,
+                spend_limit_enabled: ctx.accounts.party_a_user_state.spend_limit_enabled,
+                spend_limit: ctx.accounts.party_a_spend_limit.as_deref_mut(),
+            },
+            amount_a,
+        )?;
+        ensure_sender_may_pay(
+            &ctx.accounts.program_state,
+            SenderPolicy {
+                blacklist: &ctx.accounts.party_b_blacklist,
+                freeze: &ctx.accounts.party_b_freeze,
+                spend_limit_enabled: ctx.accounts.party_b_user_state.spend_limit_enabled,
+                spend_limit: ctx.accounts.party_b_spend_limit.as_deref_mut(),
+            },
+            amount_b,
+        )?;
+        ensure_receiver_may_be_paid(
+            &ctx.accounts.program_state,
+            &ctx.accounts.party_a_blacklist,
+            &ctx.accounts.party_a_acceptance,
+            ctx.accounts.party_a_whitelist.as_deref(),
+        )?;
+        ensure_receiver_may_be_paid(
+            &ctx.accounts.program_state,
+            &ctx.accounts.party_b_blacklist,
+            &ctx.accounts.party_b_acceptance,
+            ctx.accounts.party_b_whitelist.as_deref(),
+        )?;
+        // Check 3: Both balances cover their leg. Any failure, here or in either
+        // transfer, reverts the whole instruction so neither leg settles alone.
+        if ctx.accounts.party_a.lamports() < amount_a {
+            return err!(ErrorCode::InsufficientBalance);
+        }
+        if ctx.accounts.party_b_token_account.amount < amount_b {
+            return err!(ErrorCode::InsufficientBalance);
+        }
+
+        // Leg A: lamports from A to B
+        let sol_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.party_a.to_account_info(),
+                to: ctx.accounts.party_b.to_account_info(),
+            },
+        );
+        system_program::transfer(sol_context, amount_a)?;
+
+        // Leg B: tokens from B to A
+        let token_context = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.party_b_token_account.to_account_info(),
+                to: ctx.accounts.party_a_token_account.to_account_info(),
+                authority: ctx.accounts.party_b.to_account_info(),
+            },
+        );
+        token::transfer(token_context, amount_b)?;
+
+        let swap_record = &mut ctx.accounts.swap_record;
+        swap_record.party_a = ctx.accounts.party_a.key();
+        swap_record.party_b = ctx.accounts.party_b.key();
+        swap_record.swap_id = swap_id;
+        swap_record.amount_a = amount_a;
+        swap_record.mint_b = ctx.accounts.mint.key();
+        swap_record.amount_b = amount_b;
+        swap_record.timestamp = Clock::get()?.unix_timestamp;
+        swap_record.bump = ctx.bumps.swap_record;
+
+        msg!("Swap {} settled: {} lamports from {} for {} units of mint {} from {}",
+             swap_id,
+             amount_a,
+             swap_record.party_a,
+             amount_b,
+             swap_record.mint_b,
+             swap_record.party_b);
+        Ok(())
+    }
+
+    pub fn set_cooldown(ctx: Context<SetCooldown>, cooldown_secs: i64) -> Result<()> {
+        if cooldown_secs < 0 {
+            return err!(ErrorCode::InvalidInterval);
+        }
+        ctx.accounts.program_state.cooldown_secs = cooldown_secs;
+        emit_limit_changed(ctx.accounts.authority.key(), LimitKind::CooldownSecs, cooldown_secs as u64)?;
+        msg!("Per-pair payment cooldown set to {} seconds", cooldown_secs);
+        Ok(())
+    }
+
+    pub fn create_pool(ctx: Context<CreatePool>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.beneficiary = ctx.accounts.beneficiary.key();
+        pool.total_deposited = 0;
+        pool.total_withdrawn = 0;
+        pool.bump = ctx.bumps.pool;
+        msg!("Donation pool {} created for {}", pool.key(), pool.beneficiary);
+        Ok(())
+    }
+
+    pub fn donate(ctx: Context<Donate>, amount: u64, memo: String) -> Result<()> {
+        // --- Input Validation & Security Checks ---
+        // Check 0: Donor is neither blacklisted nor frozen, stays within their daily limit,
+        // and large payments go through propose/approve/execute
+        ensure_sender_may_pay(
+            &ctx.accounts.program_state,
+            SenderPolicy {
+                blacklist: &ctx.accounts.donor_blacklist,
+                freeze: &ctx.accounts.donor_freeze,
+                spend_limit_enabled: ctx.accounts.user_state.spend_limit_enabled,
+                spend_limit: ctx.accounts.spend_limit.as_deref_mut(),
+            },
+            amount,
+        )?;
+        // Check 0b: The pool only ever pays its beneficiary, so the beneficiary must be payable
+        ensure_receiver_may_be_paid(
+            &ctx.accounts.program_state,
+            &ctx.accounts.beneficiary_blacklist,
+            &ctx.accounts.beneficiary_acceptance,
+            ctx.accounts.whitelist_entry.as_deref(),
+        )?;
+        // Checks 1-3: Same rules as direct payments, the beneficiary can't donate to themselves
+        let max_memo_length = ctx.accounts.program_state.max_memo_length as usize;
+        validate_payment(&ctx.accounts.donor.key(), &ctx.accounts.pool.beneficiary, amount, &memo, max_memo_length)?;
+        // Check 3b: Within the configured minimum and per-payment cap
+        ensure_amount_within_bounds(&ctx.accounts.program_state, amount)?;
+        // Check 4: Sufficient Donor Balance
+        if ctx.accounts.donor.lamports() < amount {
+            return err!(ErrorCode::InsufficientBalance);
+        }
+
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.donor.to_account_info(),
+                to: ctx.accounts.pool.to_account_info(),
+            },
+        );
+        system_program::transfer(cpi_context, amount)?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.total_deposited = pool.total_deposited.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+
+        // One cumulative record per donor and pool, created on their first donation
+        let donation_record = &mut ctx.accounts.donation_record;
+        if donation_record.donor == Pubkey::default() {
+            donation_record.pool = pool.key();
+            donation_record.donor = ctx.accounts.donor.key();
+            donation_record.bump = ctx.bumps.donation_record;
+        }
+        donation_record.total_donated = donation_record.total_donated.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+        donation_record.donation_count = donation_record.donation_count.checked_add(1).ok_or(ErrorCode::Overflow)?;
+        donation_record.last_donated_at = Clock::get()?.unix_timestamp;
+
+        msg!("Donation of {} lamports from {} to pool {} with memo: {}",
+             amount,
+             donation_record.donor,
+             pool.key(),
+             memo);
+        Ok(())
+    }
+
+    pub fn withdraw_pool(ctx: Context<WithdrawPool>, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return err!(ErrorCode::InvalidAmount);
+        }
+        // The pool must stay rent-exempt after the withdrawal
+        let pool_info = ctx.accounts.pool.to_account_info();
+        let rent_exempt_minimum = Rent::get()?.minimum_balance(pool_info.data_len());
+        let available = pool_info.lamports().saturating_sub(rent_exempt_minimum);
+        if amount > available {
+            return err!(ErrorCode::InsufficientBalance);
+        }
+
+        ctx.accounts.pool.sub_lamports(amount)?;
+        ctx.accounts.beneficiary.add_lamports(amount)?;
+        let pool = &mut ctx.accounts.pool;
+        pool.total_withdrawn = pool.total_withdrawn.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+
+        msg!("Beneficiary {} withdrew {} lamports from pool {}",
+             pool.beneficiary,
+             amount,
+             pool.key());
+        Ok(())
+    }
+
+    pub fn create_campaign(ctx: Context<CreateCampaign>, campaign_id: u64, goal: u64, deadline: i64) -> Result<()> {
+        if goal == 0 {
+            return err!(ErrorCode::InvalidAmount);
+        }
+        if deadline <= Clock::get()?.unix_timestamp {
+            return err!(ErrorCode::InvalidUnlockTime);
+        }
+
+        let campaign = &mut ctx.accounts.campaign;
+        campaign.creator = ctx.accounts.creator.key();
+        campaign.campaign_id = campaign_id;
+        campaign.goal = goal;
+        campaign.raised = 0;
+        campaign.deadline = deadline;
+        campaign.bump = ctx.bumps.campaign;
+
+        msg!("Campaign {} by {} created: goal {} lamports, deadline {}",
+             campaign_id,
+             campaign.creator,
+             goal,
+             deadline);
+        Ok(())
+    }
+
+    pub fn contribute(ctx: Context<Contribute>, amount: u64) -> Result<()> {
+        // The backer is neither blacklisted nor frozen, stays within their daily limit,
+        // and large payments go through propose/approve/execute
+        ensure_sender_may_pay(
+            &ctx.accounts.program_state,
+            SenderPolicy {
+                blacklist: &ctx.accounts.backer_blacklist,
+                freeze: &ctx.accounts.backer_freeze,
+                spend_limit_enabled: ctx.accounts.user_state.spend_limit_enabled,
+                spend_limit: ctx.accounts.spend_limit.as_deref_mut(),
+            },
+            amount,
+        )?;
+        // A successful campaign pays its creator, so the creator must be payable
+        ensure_receiver_may_be_paid(
+            &ctx.accounts.program_state,
+            &ctx.accounts.creator_blacklist,
+            &ctx.accounts.creator_acceptance,
+            ctx.accounts.whitelist_entry.as_deref(),
+        )?;
+        if amount == 0 {
+            return err!(ErrorCode::InvalidAmount);
+        }
+        // Within the configured minimum and per-payment cap
+        ensure_amount_within_bounds(&ctx.accounts.program_state, amount)?;
+        if Clock::get()?.unix_timestamp >= ctx.accounts.campaign.deadline {
+            return err!(ErrorCode::CampaignEnded);
+        }
+        if ctx.accounts.backer.lamports() < amount {
+            return err!(ErrorCode::InsufficientBalance);
+        }
+
+        // Pledges are held on the campaign PDA until claimed or refunded
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.backer.to_account_info(),
+                to: ctx.accounts.campaign.to_account_info(),
+            },
+        );
+        system_program::transfer(cpi_context, amount)?;
+
+        let campaign = &mut ctx.accounts.campaign;
+        campaign.raised = campaign.raised.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+
+        let contribution = &mut ctx.accounts.contribution;
+        if contribution.backer == Pubkey::default() {
+            contribution.campaign = campaign.key();
+            contribution.backer = ctx.accounts.backer.key();
+            contribution.bump = ctx.bumps.contribution;
+        }
+        contribution.amount = contribution.amount.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+
+        msg!("{} contributed {} lamports to campaign {} ({} of {} raised)",
+             contribution.backer,
+             amount,
+             campaign.campaign_id,
+             campaign.raised,
+             campaign.goal);
+        Ok(())
+    }
+
+    pub fn claim_funds(ctx: Context<ClaimFunds>) -> Result<()> {
+        let campaign = &ctx.accounts.campaign;
+        if Clock::get()?.unix_timestamp < campaign.deadline {
+            return err!(ErrorCode::CampaignStillActive);
+        }
+        if campaign.raised < campaign.goal {
+            return err!(ErrorCode::GoalNotMet);
+        }
+
+        // `close = creator` pays out everything raised plus the campaign's rent
+        msg!("Campaign {} succeeded: {} lamports claimed by {}",
+             campaign.campaign_id,
+             campaign.raised,
+             campaign.creator);
+        Ok(())
+    }
+
+    pub fn refund_contribution(ctx: Context<RefundContribution>) -> Result<()> {
+        let campaign = &ctx.accounts.campaign;
+        if Clock::get()?.unix_timestamp < campaign.deadline {
+            return err!(ErrorCode::CampaignStillActive);
+        }
+        if campaign.raised >= campaign.goal {
+            return err!(ErrorCode::CampaignSucceeded);
+        }
+
+        // Return the pledge from the campaign; `close = backer` refunds the record's rent
+        let amount = ctx.accounts.contribution.amount;
+        ctx.accounts.campaign.sub_lamports(amount)?;
+        ctx.accounts.backer.add_lamports(amount)?;
+        let campaign = &mut ctx.accounts.campaign;
+        campaign.raised = campaign.raised.checked_sub(amount).ok_or(ErrorCode::Overflow)?;
+
+        msg!("Refunded {} lamports to {} from unsuccessful campaign {}",
+             amount,
+             ctx.accounts.backer.key(),
+             campaign.campaign_id);
+        Ok(())
+    }
+
+    pub fn propose_authority(ctx: Context<ProposeAuthority>, new_authority: Pubkey) -> Result<()> {
+        // The current authority keeps control until `new_authority` signs accept_authority
+        ctx.accounts.program_state.pending_authority = Some(new_authority);
+        emit!(AuthorityTransferProposed {
+            authority: ctx.accounts.authority.key(),
+            pending_authority: Some(new_authority),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        msg!("Authority transfer to {} proposed by {}", new_authority, ctx.accounts.authority.key());
+        Ok(())
+    }
+
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        clear_account_data(&ctx.accounts.program_state.to_account_info())?;
+        let program_state = &mut ctx.accounts.program_state;
+        let previous = program_state.authority;
+        program_state.authority = ctx.accounts.new_authority.key();
+        program_state.pending_authority = None;
+        emit!(AuthorityChanged {
+            previous_authority: previous,
+            new_authority: program_state.authority,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        msg!("Authority transferred from {} to {}", previous, program_state.authority);
+        Ok(())
+    }
+
+    pub fn cancel_authority_transfer(ctx: Context<CancelAuthorityTransfer>) -> Result<()> {
+        clear_account_data(&ctx.accounts.program_state.to_account_info())?;
+        ctx.accounts.program_state.pending_authority = None;
+        emit!(AuthorityTransferProposed {
+            authority: ctx.accounts.authority.key(),
+            pending_authority: None,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        msg!("Pending authority transfer cancelled");
+        Ok(())
+    }
+
+    pub fn close_recent_payment(ctx: Context<CloseRecentPayment>) -> Result<()> {
+        // Only once the fingerprint can no longer catch a retry
+        let recent_payment = &ctx.accounts.recent_payment;
+        let window_ends_at = recent_payment.sent_at.saturating_add(ctx.accounts.program_state.dedup_window_secs);
+        if Clock::get()?.unix_timestamp < window_ends_at {
+            return err!(ErrorCode::DedupWindowOpen);
+        }
+        // `close = sender` refunds the rent
+        msg!("Duplicate payment record of {} closed", ctx.accounts.sender.key());
+        Ok(())
+    }
+
+    pub fn set_dedup_window(ctx: Context<SetDedupWindow>, dedup_window_secs: i64) -> Result<()> {
+        if dedup_window_secs < 0 {
+            return err!(ErrorCode::InvalidInterval);
+        }
+        ctx.accounts.program_state.dedup_window_secs = dedup_window_secs;
+        emit_limit_changed(ctx.accounts.authority.key(), LimitKind::DedupWindowSecs, dedup_window_secs as u64)?;
+        msg!("Duplicate payment window set to {} seconds", dedup_window_secs);
+        Ok(())
+    }
+
+    pub fn set_fee_rounding(ctx: Context<SetFeeRounding>, fee_rounding: FeeRounding) -> Result<()> {
+        ctx.accounts.program_state.fee_rounding = fee_rounding;
+        emit_fee_changed(ctx.accounts.authority.key(), &ctx.accounts.program_state)?;
+        msg!("Fee rounding set to {:?}", fee_rounding);
+        Ok(())
+    }
+
+    pub fn set_verbose_logging(ctx: Context<SetVerboseLogging>, verbose: bool) -> Result<()> {
+        ctx.accounts.program_state.verbose = verbose;
+        emit!(LoggingChanged {
+            authority: ctx.accounts.authority.key(),
+            verbose,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        msg!("Verbose payment logging: {}", verbose);
+        Ok(())
+    }
+
+    pub fn set_hook_program(ctx: Context<SetHookProgram>, hook_program: Option<Pubkey>) -> Result<()> {
+        // Calling back into this program from the hook would allow reentrancy
+        if hook_program == Some(crate::ID) || hook_program == Some(system_program::ID) {
+            return err!(ErrorCode::InvalidHookProgram);
+        }
+        clear_account_data(&ctx.accounts.program_state.to_account_info())?;
+        ctx.accounts.program_state.hook_program = hook_program;
+        emit!(HookProgramChanged {
+            authority: ctx.accounts.authority.key(),
+            hook_program,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        match hook_program {
+            Some(program_id) => msg!("Payment hook set to {}", program_id),
+            None => msg!("Payment hook cleared"),
+        }
+        Ok(())
+    }
+
+    pub fn dispute_escrow(ctx: Context<DisputeEscrow>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow;
+        if escrow.arbiter == Pubkey::default() {
+            return err!(ErrorCode::NotArbiter);
+        }
+        if escrow.disputed {
+            return err!(ErrorCode::EscrowDisputed);
+        }
+        // From here on neither party can release or refund unilaterally
+        escrow.disputed = true;
+        msg!("Escrow {} disputed by {}; arbiter {} will decide",
+             escrow.sequence,
+             ctx.accounts.party.key(),
+             escrow.arbiter);
+        Ok(())
+    }
+
+    pub fn arbitrate_escrow<'info>(ctx: Context<'_, '_, 'info, 'info, ArbitrateEscrow<'info>>, to_seller: bool) -> Result<()> {
+        // Guard: mark settled before any lamports move
+        ctx.accounts.escrow.mark_settled()?;
+        ctx.accounts.program_state.settle_escrow()?;
+        let escrow = &ctx.accounts.escrow;
+        if !escrow.disputed {
+            return err!(ErrorCode::EscrowNotDisputed);
+        }
+        let amount = escrow.amount;
+        ensure_escrow_funded(&escrow.to_account_info(), amount)?;
+
+        // Awarding the buyer (sender) needs no transfer: `close = sender` returns everything
+        if to_seller {
+            // Same fee as a release
+            let fee = compute_fee(amount, ctx.accounts.program_state.fee_bps, ctx.accounts.program_state.fee_rounding)?;
+            let net_amount = amount.checked_sub(fee).ok_or(ErrorCode::Overflow)?;
+            // Counts towards the receiver's stats and the per-receiver cap
+            let receiver_stats = &mut ctx.accounts.receiver_stats;
+            receiver_stats.claim(ctx.accounts.receiver.key(), ctx.bumps.receiver_stats);
+            receiver_stats.record_payment(net_amount, ctx.accounts.program_state.max_total_received)?;
+            ctx.accounts.escrow.sub_lamports(amount)?;
+            ctx.accounts.receiver.add_lamports(net_amount)?;
+            for (collector, part) in fee_payouts(fee, &ctx.accounts.program_state, &ctx.accounts.fee_collector, ctx.remaining_accounts)? {
+                collector.add_lamports(part)?;
+            }
+            let program_state = &mut ctx.accounts.program_state;
+            program_state.total_fees_collected = program_state.total_fees_collected.checked_add(fee).ok_or(ErrorCode::Overflow)?;
+        }
+
+        let escrow = &ctx.accounts.escrow;
+        emit!(EscrowArbitrated {
+            escrow: escrow.key(),
+            sequence: escrow.sequence,
+            arbiter: escrow.arbiter,
+            sender: escrow.sender,
+            receiver: escrow.receiver,
+            amount,
+            to_seller,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        msg!("Escrow {} arbitrated: {} lamports to the {}",
+             escrow.sequence,
+             amount,
+             if to_seller { "seller" } else { "buyer" });
+        Ok(())
+    }
+
+    pub fn verify_transaction(
+        ctx: Context<VerifyTransaction>,
+        expected_amount: u64,
+        expected_receiver: Pubkey,
+    ) -> Result<()> {
+        // `Account` already checked the owner and discriminator, so the record was
+        // written by this program; only its contents are left to compare.
+        let record = &ctx.accounts.transaction_record;
+        if record.amount != expected_amount || record.receiver != expected_receiver {
+            return err!(ErrorCode::RecordMismatch);
+        }
+        Ok(())
+    }
+
+    pub fn send_payment_refundable(
+        ctx: Context<SendPaymentRefundable>,
+        amount: u64,
+        memo: String,
+        grace_secs: i64,
+    ) -> Result<()> {
+        // --- Input Validation & Security Checks ---
+        // Check 0: Neither party is blacklisted, and the receiver is whitelisted while the whitelist is on
+        ensure_not_blacklisted(&ctx.accounts.sender_blacklist)?;
+        ensure_not_blacklisted(&ctx.accounts.receiver_blacklist)?;
+        ensure_whitelisted(ctx.accounts.program_state.whitelist_enabled, ctx.accounts.whitelist_entry.as_deref())?;
+        // Check 0b: Sender is not frozen
+        ensure_not_frozen(&ctx.accounts.sender_freeze)?;
+        // Check 0c: Large payments must go through propose/approve/execute
+        ensure_no_approval_required(&ctx.accounts.program_state, amount)?;
+        // Check 0d: The payment fits the sender's daily spend limit
+        record_sender_spend(ctx.accounts.user_state.spend_limit_enabled, ctx.accounts.spend_limit.as_deref_mut(), amount)?;
+        // Checks 1-3: Same rules as direct payments
+        let max_memo_length = ctx.accounts.program_state.max_memo_length as usize;
+        validate_payment(&ctx.accounts.sender.key(), &ctx.accounts.receiver.key(), amount, &memo, max_memo_length)?;
+        // Check 3b: Within the configured minimum and per-payment cap
+        ensure_amount_within_bounds(&ctx.accounts.program_state, amount)?;
+        // Check 4: Sufficient Sender Balance
+        if ctx.accounts.sender.lamports() < amount {
+            return err!(ErrorCode::InsufficientBalance);
+        }
+        // Check 5: Receiver must be able to receive SOL when finalizing
+        if *ctx.accounts.receiver.owner != system_program::ID {
+            return err!(ErrorCode::InvalidReceiver);
+        }
+        // Check 6: The sender needs a non-empty window to cancel in
+        if grace_secs <= 0 {
+            return err!(ErrorCode::InvalidGracePeriod);
+        }
+        let now = Clock::get()?.unix_timestamp;
+        let grace_ends_at = add_secs(now, grace_secs)?;
+
+        // Hold the funds in the refundable PDA until the grace period is over
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.sender.to_account_info(),
+                to: ctx.accounts.refundable.to_account_info(),
+            },
+        );
+        system_program::transfer(cpi_context, amount)?;
+
+        let program_state = &mut ctx.accounts.program_state;
+        let refundable = &mut ctx.accounts.refundable;
+        refundable.sender = ctx.accounts.sender.key();
+        refundable.receiver = ctx.accounts.receiver.key();
+        refundable.amount = amount;
+        refundable.memo = memo;
+        refundable.created_at = now;
+        refundable.grace_ends_at = grace_ends_at;
+        refundable.sequence = program_state.total_refundables;
+        refundable.bump = ctx.bumps.refundable;
+
+        program_state.total_refundables = program_state.total_refundables.checked_add(1).ok_or(ErrorCode::Overflow)?;
+        program_state.open_hold()?;
+
+        msg!("Refundable payment {} created: {} lamports from {} to {}, cancellable until {}",
+             refundable.sequence,
+             amount,
+             refundable.sender,
+             refundable.receiver,
+             grace_ends_at);
+
+        Ok(())
+    }
+
+    pub fn finalize_refundable(ctx: Context<FinalizeRefundable>) -> Result<()> {
+        let refundable = &ctx.accounts.refundable;
+        let amount = refundable.amount;
+        // Check: The sender's cancellation window must be over
+        if Clock::get()?.unix_timestamp < refundable.grace_ends_at {
+            return err!(ErrorCode::GracePeriodActive);
+        }
+
+        // Counts towards the receiver's stats and the per-receiver cap
+        let receiver_stats = &mut ctx.accounts.receiver_stats;
+        receiver_stats.claim(ctx.accounts.receiver.key(), ctx.bumps.receiver_stats);
+        receiver_stats.record_payment(amount, ctx.accounts.program_state.max_total_received)?;
+
+        // Pay the receiver; the rent goes back to the sender via `close = sender`
+        ctx.accounts.refundable.sub_lamports(amount)?;
+        ctx.accounts.receiver.add_lamports(amount)?;
+
+        ctx.accounts.program_state.close_hold()?;
+        msg!("Refundable payment {} finalized: {} lamports to {}",
+             ctx.accounts.refundable.sequence,
+             amount,
+             ctx.accounts.receiver.key());
+
+        Ok(())
+    }
+
+    pub fn cancel_refundable(ctx: Context<CancelRefundable>) -> Result<()> {
+        // Check: Cancelling is only possible while the grace period is running
+        if Clock::get()?.unix_timestamp >= ctx.accounts.refundable.grace_ends_at {
+            return err!(ErrorCode::GracePeriodExpired);
+        }
+
+        ctx.accounts.program_state.close_hold()?;
+        // `close = sender` returns both the held amount and the rent
+        msg!("Refundable payment {} cancelled: {} lamports back to {}",
+             ctx.accounts.refundable.sequence,
+             ctx.accounts.refundable.amount,
+             ctx.accounts.sender.key());
+        Ok(())
+    }
+
+    pub fn schedule_payment(
+        ctx: Context<SchedulePayment>,
+        amount: u64,
+        execute_at: i64,
+        cranker_tip: u64,
+        memo: String,
+    ) -> Result<()> {
+        // --- Input Validation & Security Checks ---
+        // Check 0: Neither party is blacklisted, the receiver is whitelisted while the whitelist is on,
+        // and the sender is not frozen
+        ensure_not_blacklisted(&ctx.accounts.sender_blacklist)?;
+        ensure_not_blacklisted(&ctx.accounts.receiver_blacklist)?;
+        ensure_whitelisted(ctx.accounts.program_state.whitelist_enabled, ctx.accounts.whitelist_entry.as_deref())?;
+        ensure_not_frozen(&ctx.accounts.sender_freeze)?;
+        // Check 0b: Large payments must go through propose/approve/execute
+        ensure_no_approval_required(&ctx.accounts.program_state, amount)?;
+        // Check 0c: The payment fits the sender's daily spend limit
+        record_sender_spend(ctx.accounts.user_state.spend_limit_enabled, ctx.accounts.spend_limit.as_deref_mut(), amount)?;
+        // Checks 1-3: Same rules as direct payments
+        let max_memo_length = ctx.accounts.program_state.max_memo_length as usize;
+        validate_payment(&ctx.accounts.sender.key(), &ctx.accounts.receiver.key(), amount, &memo, max_memo_length)?;
+        // Check 3b: Within the configured minimum and per-payment cap
+        ensure_amount_within_bounds(&ctx.accounts.program_state, amount)?;
+        // Check 4: Sufficient Sender Balance for the payment and the cranker's tip
+        let total = amount.checked_add(cranker_tip).ok_or(ErrorCode::Overflow)?;
+        if ctx.accounts.sender.lamports() < total {
+            return err!(ErrorCode::InsufficientBalance);
+        }
+        // Check 5: Receiver must be able to receive SOL on execution
+        if *ctx.accounts.receiver.owner != system_program::ID {
+            return err!(ErrorCode::InvalidReceiver);
+        }
+        // Check 6: Execution time must be in the future
+        let now = Clock::get()?.unix_timestamp;
+        if execute_at <= now {
+            return err!(ErrorCode::InvalidUnlockTime);
+        }
+
+        // Fund the payment and the tip up front so any cranker can execute it
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.sender.to_account_info(),
+                to: ctx.accounts.scheduled_payment.to_account_info(),
+            },
+        );
+        system_program::transfer(cpi_context, total)?;
+
+        let program_state = &mut ctx.accounts.program_state;
+        let scheduled_payment = &mut ctx.accounts.scheduled_payment;
+        scheduled_payment.sender = ctx.accounts.sender.key();
+        scheduled_payment.receiver = ctx.accounts.receiver.key();
+        scheduled_payment.amount = amount;
+        scheduled_payment.cranker_tip = cranker_tip;
+        scheduled_payment.execute_at = execute_at;
+        scheduled_payment.memo = memo;
+        scheduled_payment.created_at = now;
+        scheduled_payment.sequence = program_state.total_scheduled;
+        scheduled_payment.bump = ctx.bumps.scheduled_payment;
+        scheduled_payment.executed = false;
+
+        program_state.total_scheduled = program_state.total_scheduled.checked_add(1).ok_or(ErrorCode::Overflow)?;
+        program_state.open_hold()?;
+
+        msg!("Payment {} scheduled: {} lamports from {} to {} at {}, cranker tip {}",
+             scheduled_payment.sequence,
+             amount,
+             scheduled_payment.sender,
+             scheduled_payment.receiver,
+             execute_at,
+             cranker_tip);
+        Ok(())
+    }
+
+    pub fn execute_scheduled(ctx: Context<ExecuteScheduled>) -> Result<()> {
+        // Permissionless: anyone may crank this once it is due, since the payment
+        // can only go to the stored receiver and the cranker only earns the tip.
+        let scheduled_payment = &mut ctx.accounts.scheduled_payment;
+        if Clock::get()?.unix_timestamp < scheduled_payment.execute_at {
+            return err!(ErrorCode::NotYetScheduled);
+        }
+        // Guard: mark executed before any lamports move
+        if scheduled_payment.executed {
+            return err!(ErrorCode::ScheduledPaymentAlreadyExecuted);
+        }
+        scheduled_payment.executed = true;
+        let amount = scheduled_payment.amount;
+        let cranker_tip = scheduled_payment.cranker_tip;
+
+        // Counts towards the receiver's stats and the per-receiver cap
+        let receiver_stats = &mut ctx.accounts.receiver_stats;
+        receiver_stats.claim(ctx.accounts.receiver.key(), ctx.bumps.receiver_stats);
+        receiver_stats.record_payment(amount, ctx.accounts.program_state.max_total_received)?;
+
+        // Pay the receiver and the cranker; the rent goes back to the sender via `close = sender`
+        ctx.accounts.scheduled_payment.sub_lamports(amount)?;
+        ctx.accounts.receiver.add_lamports(amount)?;
+        if cranker_tip > 0 {
+            ctx.accounts.scheduled_payment.sub_lamports(cranker_tip)?;
+            ctx.accounts.cranker.add_lamports(cranker_tip)?;
+        }
+
+        ctx.accounts.program_state.close_hold()?;
+        msg!("Scheduled payment {} executed by {}: {} lamports to {}, {} tip",
+             ctx.accounts.scheduled_payment.sequence,
+             ctx.accounts.cranker.key(),
+             amount,
+             ctx.accounts.receiver.key(),
+             cranker_tip);
+        Ok(())
+    }
+
+    pub fn require_acceptance(ctx: Context<RequireAcceptance>) -> Result<()> {
+        // From now on send_payment holds payments to this receiver in a PendingTransfer
+        let acceptance_policy = &mut ctx.accounts.acceptance_policy;
+        acceptance_policy.receiver = ctx.accounts.receiver.key();
+        acceptance_policy.bump = ctx.bumps.acceptance_policy;
+        msg!("{} now accepts incoming payments explicitly", acceptance_policy.receiver);
+        Ok(())
+    }
+
+    pub fn disable_acceptance(ctx: Context<DisableAcceptance>) -> Result<()> {
+        // `close = receiver` deletes the policy and refunds its rent. Transfers that are
+        // already pending still have to be accepted, rejected or reclaimed.
+        msg!("{} receives payments directly again", ctx.accounts.receiver.key());
+        Ok(())
+    }
+
+    pub fn accept_transfer<'info>(ctx: Context<'_, '_, 'info, 'info, AcceptTransfer<'info>>) -> Result<()> {
+        ctx.accounts.pending_transfer.mark_resolved()?;
+        let amount = ctx.accounts.pending_transfer.amount;
+        let fee = ctx.accounts.pending_transfer.fee;
+
+        // Credit the receiver; the rent goes back to the sender via `close = sender`
+        ctx.accounts.pending_transfer.sub_lamports(amount)?;
+        ctx.accounts.receiver.add_lamports(amount)?;
+        ctx.accounts.receiver_stats.record_payment(amount, ctx.accounts.program_state.max_total_received)?;
+        ctx.accounts.receiver_ledger.record_received(amount)?;
+
+        // The payment is final, so the escrowed fee is collected now, under the
+        // fee configuration current at acceptance
+        let payouts = fee_payouts(fee, &ctx.accounts.program_state, &ctx.accounts.fee_collector, ctx.remaining_accounts)?;
+        for (collector, part) in payouts {
+            ctx.accounts.pending_transfer.sub_lamports(part)?;
+            collector.add_lamports(part)?;
+        }
+        let program_state = &mut ctx.accounts.program_state;
+        program_state.total_fees_collected = program_state.total_fees_collected.checked_add(fee).ok_or(ErrorCode::Overflow)?;
+        program_state.close_hold()?;
+
+        msg!("Pending transfer {} from {} accepted: {} lamports to {}",
+             ctx.accounts.pending_transfer.sequence,
+             ctx.accounts.pending_transfer.sender,
+             amount,
+             ctx.accounts.receiver.key());
+        Ok(())
+    }
+
+    pub fn reject_transfer(ctx: Context<RejectTransfer>) -> Result<()> {
+        ctx.accounts.pending_transfer.mark_resolved()?;
+        // The record reflects that the receiver never kept the funds
+        let pending_transfer = &ctx.accounts.pending_transfer;
+        let amount = pending_transfer.amount.checked_add(pending_transfer.fee).ok_or(ErrorCode::Overflow)?;
+        let transaction_record = &mut ctx.accounts.transaction_record;
+        transaction_record.refunded = transaction_record.refunded.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+        ctx.accounts.sender_ledger.reverse_sent(amount)?;
+
+        ctx.accounts.program_state.close_hold()?;
+        // `close = sender` returns the held amount, the escrowed fee and the rent
+        msg!("Pending transfer {} rejected by {}: {} lamports back to {}",
+             ctx.accounts.pending_transfer.sequence,
+             ctx.accounts.receiver.key(),
+             amount,
+             ctx.accounts.sender.key());
+        Ok(())
+    }
+
+    pub fn reclaim_transfer(ctx: Context<ReclaimTransfer>) -> Result<()> {
+        // The receiver gets PENDING_TRANSFER_TIMEOUT_SECS to decide, and the reclaim
+        // only opens once they are over, so it can't race an accept in the same slot
+        let pending_transfer = &ctx.accounts.pending_transfer;
+        let reclaimable_at = add_secs(pending_transfer.created_at, PENDING_TRANSFER_TIMEOUT_SECS)?;
+        if Clock::get()?.unix_timestamp <= reclaimable_at {
+            return err!(ErrorCode::TransferPending);
+        }
+        ctx.accounts.pending_transfer.mark_resolved()?;
+        let pending_transfer = &ctx.accounts.pending_transfer;
+        let amount = pending_transfer.amount.checked_add(pending_transfer.fee).ok_or(ErrorCode::Overflow)?;
+        let transaction_record = &mut ctx.accounts.transaction_record;
+        transaction_record.refunded = transaction_record.refunded.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+        ctx.accounts.sender_ledger.reverse_sent(amount)?;
+
+        ctx.accounts.program_state.close_hold()?;
+        // `close = sender` returns the held amount, the escrowed fee and the rent
+        msg!("Unanswered pending transfer {} reclaimed by {}: {} lamports",
+             ctx.accounts.pending_transfer.sequence,
+             ctx.accounts.sender.key(),
+             amount);
+        Ok(())
+    }
+
+    pub fn transfer_record_ownership(ctx: Context<TransferRecordOwnership>, new_owner: Pubkey) -> Result<()> {
+        // The default key means "owned by the sender", so it can't be an explicit owner
+        if new_owner == Pubkey::default() {
+            return err!(ErrorCode::InvalidRecordOwner);
+        }
+        let transaction_record = &mut ctx.accounts.transaction_record;
+        let previous_owner = transaction_record.current_owner();
+        transaction_record.owner = new_owner;
+
+        emit!(RecordOwnershipTransferred {
+            record: transaction_record.key(),
+            previous_owner,
+            new_owner,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        msg!("Transaction record {} transferred from {} to {}",
+             transaction_record.key(),
+             previous_owner,
+             new_owner);
+        Ok(())
+    }
+
+    pub fn create_memo_template(ctx: Context<CreateMemoTemplate>, template_id: u64, template: String) -> Result<()> {
+        // Same character rules as a memo; placeholders are plain text until rendered
+        validate_memo(&template, ctx.accounts.program_state.max_memo_length as usize)?;
+        let memo_template = &mut ctx.accounts.memo_template;
+        memo_template.owner = ctx.accounts.owner.key();
+        memo_template.template_id = template_id;
+        memo_template.template = template;
+        memo_template.bump = ctx.bumps.memo_template;
+        msg!("Memo template {} created for {}", template_id, memo_template.owner);
+        Ok(())
+    }
+
+    pub fn close_memo_template(ctx: Context<CloseMemoTemplate>) -> Result<()> {
+        // `close = owner` deletes the template and refunds its rent
+        msg!("Memo template {} closed", ctx.accounts.memo_template.template_id);
+        Ok(())
+    }
+
+    pub fn send_payment_from_template<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SendPaymentFromTemplate<'info>>,
+        _template_id: u64,
+        amount: u64,
+    ) -> Result<()> {
+        // Render the memo first so it goes through the same checks as a typed one
+        let now = Clock::get()?.unix_timestamp;
+        let memo = render_memo_template(&ctx.accounts.memo_template.template, amount, now);
+
+        // --- Input Validation & Security Checks ---
+        // Checks 1-3: Amount > 0, Sender != Receiver, rendered memo within the deployment's limit
+        let max_memo_length = ctx.accounts.program_state.max_memo_length as usize;
+        validate_payment(&ctx.accounts.sender.key(), &ctx.accounts.receiver.key(), amount, &memo, max_memo_length)?;
+        // Check 4: Sufficient Sender Balance, leaving the sender rent-exempt
+        ensure_covers_rent_and_payment(&ctx.accounts.sender, amount, Rent::get()?.minimum_balance(TransactionRecord::LEN))?;
+        // Checks 5-8 and the transfer: the rules every direct payment shares
+        let fee = direct_payment!(ctx, PaymentSource::Wallet(&ctx.accounts.sender), &ctx.accounts.sender)
+            .validate_and_charge(amount, amount)?;
+
+        // Record the transaction with the rendered memo
+        let transaction_record = &mut TransactionRecord::new_payment(
+            ctx.accounts.sender.key(),
+            ctx.accounts.receiver.key(),
+            amount,
+            memo.clone(),
+            now,
+            fee,
+        );
+
+        let program_state = &mut ctx.accounts.program_state;
+        let sequence = advance_sequence(transaction_record, &mut ctx.accounts.user_state, program_state)?;
+        // Only created now, after every check, so a short balance fails with a clear error
+        create_transaction_record(
+            &ctx.accounts.sender,
+            &ctx.accounts.transaction_record,
+            &ctx.accounts.system_program,
+            TransactionRecord::LEN,
+            transaction_record,
+            sequence,
+            ctx.bumps.transaction_record,
+        )?;
+
+        emit!(PaymentSent {
+            sender: transaction_record.sender,
+            receiver: transaction_record.receiver,
+            amount,
+            memo,
+            timestamp: now,
+            sequence,
+        });
+
+        Ok(())
+    }
+
+    pub fn send_payment_raw<'info>(ctx: Context<'_, '_, 'info, 'info, SendPaymentRaw<'info>>, amount: u64, data: Vec<u8>) -> Result<()> {
+        // --- Input Validation & Security Checks ---
+        // Checks 1-3: Amount > 0, Sender != Receiver; the memo stays empty
+        let max_memo_length = ctx.accounts.program_state.max_memo_length as usize;
+        validate_payment(&ctx.accounts.sender.key(), &ctx.accounts.receiver.key(), amount, "", max_memo_length)?;
+        // Check 3b: Raw data within its own cap; the bytes are never interpreted on-chain
+        if data.len() > MAX_RAW_DATA_BYTES {
+            return err!(ErrorCode::RawDataTooLong);
+        }
+        // Check 4: Sufficient Sender Balance, leaving the sender rent-exempt
+        ensure_covers_rent_and_payment(&ctx.accounts.sender, amount, Rent::get()?.minimum_balance(TransactionRecord::LEN))?;
+        // Checks 5-8 and the transfer: the rules every direct payment shares
+        let now = Clock::get()?.unix_timestamp;
+        let fee = direct_payment!(ctx, PaymentSource::Wallet(&ctx.accounts.sender), &ctx.accounts.sender)
+            .validate_and_charge(amount, amount)?;
+
+        // Record the transaction; the bytes go to `data`, never into the memo
+        let transaction_record = &mut TransactionRecord::new_payment(
+            ctx.accounts.sender.key(),
+            ctx.accounts.receiver.key(),
+            amount,
+            String::new(),
+            now,
+            fee,
+        );
+        transaction_record.data = data;
+
+        let program_state = &mut ctx.accounts.program_state;
+        let sequence = advance_sequence(transaction_record, &mut ctx.accounts.user_state, program_state)?;
+        // Only created now, after every check, so a short balance fails with a clear error
+        create_transaction_record(
+            &ctx.accounts.sender,
+            &ctx.accounts.transaction_record,
+            &ctx.accounts.system_program,
+            TransactionRecord::LEN,
+            transaction_record,
+            sequence,
+            ctx.bumps.transaction_record,
+        )?;
+
+        emit!(PaymentSent {
+            sender: transaction_record.sender,
+            receiver: transaction_record.receiver,
+            amount,
+            memo: String::new(),
+            timestamp: now,
+            sequence,
+        });
+
+        Ok(())
+    }
+
+    pub fn send_payment_compact<'info>(ctx: Context<'_, '_, 'info, 'info, SendPaymentCompact<'info>>, amount: u64, memo: String) -> Result<()> {
+        // --- Input Validation & Security Checks ---
+        // Checks 1-3: Amount > 0, Sender != Receiver, memo within the deployment's limit
+        let max_memo_length = ctx.accounts.program_state.max_memo_length as usize;
+        validate_payment(&ctx.accounts.sender.key(), &ctx.accounts.receiver.key(), amount, &memo, max_memo_length)?;
+        // Check 4: Sufficient Sender Balance, leaving the sender rent-exempt
+        ensure_covers_rent_and_payment(&ctx.accounts.sender, amount, Rent::get()?.minimum_balance(CompactReceipt::LEN))?;
+        // Checks 5-8 and the transfer: the rules every direct payment shares
+        let now = Clock::get()?.unix_timestamp;
+        direct_payment!(ctx, PaymentSource::Wallet(&ctx.accounts.sender), &ctx.accounts.sender)
+            .validate_and_charge(amount, amount)?;
+
+        // Anchor only the hash; the sender keeps the preimage (all of it is also in the event)
+        let hash = compact_receipt_hash(&ctx.accounts.sender.key(), &ctx.accounts.receiver.key(), amount, now, &memo);
+        let receipt_counter = &mut ctx.accounts.receipt_counter;
+        receipt_counter.claim(ctx.accounts.sender.key(), ctx.bumps.receipt_counter);
+        let index = receipt_counter.count;
+        receipt_counter.count = index.checked_add(1).ok_or(ErrorCode::Overflow)?;
+
+        // Only created now, after every check, so a short balance fails with a clear error
+        let sender_key = ctx.accounts.sender.key();
+        let index_bytes = index.to_le_bytes();
+        let signer_seeds: &[&[u8]] = &[b"receipt", sender_key.as_ref(), &index_bytes, &[ctx.bumps.compact_receipt]];
+        create_program_account(
+            &ctx.accounts.sender,
+            &ctx.accounts.compact_receipt,
+            &ctx.accounts.system_program,
+            CompactReceipt::LEN,
+            signer_seeds,
+            &CompactReceipt { hash, bump: ctx.bumps.compact_receipt },
+        )?;
+
+        // Compact receipts have their own numbering, so the record sequence stays gap-free
+        let program_state = &mut ctx.accounts.program_state;
+        program_state.total_transactions = program_state.total_transactions.checked_add(1).ok_or(ErrorCode::Overflow)?;
+
+        emit!(CompactPaymentSent {
+            sender: ctx.accounts.sender.key(),
+            receiver: ctx.accounts.receiver.key(),
+            amount,
+            memo,
+            timestamp: now,
+            index,
+            hash,
+        });
+
+        Ok(())
+    }
+
+    pub fn send_usd_payment<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SendUsdPayment<'info>>,
+        usd_amount: u64,
+        min_out: u64,
+        max_in: u64,
+        memo: String,
+    ) -> Result<()> {
+        // --- Oracle Quote ---
+        // The lamport amount is only known once the price is read, so the payer signs
+        // bounds instead: at least `min_out` and at most `max_in` lamports.
+        let now = Clock::get()?.unix_timestamp;
+        let quote = load_pyth_price_message(&ctx.accounts.price_update, &SOL_USD_FEED_ID, now)?;
+        let amount = usd_to_lamports(usd_amount, quote.price, quote.exponent)?;
+        if amount < min_out || amount > max_in {
+            return err!(ErrorCode::SlippageExceeded);
+        }
+
+        // --- Input Validation & Security Checks ---
+        // Checks 1-3: Amount > 0, Sender != Receiver, memo within the deployment's limit
+        let max_memo_length = ctx.accounts.program_state.max_memo_length as usize;
+        validate_payment(&ctx.accounts.sender.key(), &ctx.accounts.receiver.key(), amount, &memo, max_memo_length)?;
+        // Check 4: Sufficient Sender Balance, leaving the sender rent-exempt
+        ensure_covers_rent_and_payment(&ctx.accounts.sender, amount, Rent::get()?.minimum_balance(TransactionRecord::LEN))?;
+        // Checks 5-8 and the transfer: the rules every direct payment shares
+        let fee = direct_payment!(ctx, PaymentSource::Wallet(&ctx.accounts.sender), &ctx.accounts.sender)
+            .validate_and_charge(amount, amount)?;
+
+        msg!("USD payment: {} micro-USD = {} lamports at price {}e{}",
+             usd_amount,
+             amount,
+             quote.price,
+             quote.exponent);
+
+        // Record the lamports actually moved
+        let transaction_record = &mut TransactionRecord::new_payment(
+            ctx.accounts.sender.key(),
+            ctx.accounts.receiver.key(),
+            amount,
+            memo.clone(),
+            now,
+            fee,
+        );
+
+        let program_state = &mut ctx.accounts.program_state;
+        let sequence = advance_sequence(transaction_record, &mut ctx.accounts.user_state, program_state)?;
+        // Only created now, after every check, so a short balance fails with a clear error
+        create_transaction_record(
+            &ctx.accounts.sender,
+            &ctx.accounts.transaction_record,
+            &ctx.accounts.system_program,
+            TransactionRecord::LEN,
+            transaction_record,
+            sequence,
+            ctx.bumps.transaction_record,
+        )?;
+
+        emit!(PaymentSent {
+            sender: transaction_record.sender,
+            receiver: transaction_record.receiver,
+            amount,
+            memo,
+            timestamp: now,
+            sequence,
+        });
+
+        Ok(())
+    }
+
+    pub fn prepare_record(ctx: Context<PrepareRecord>) -> Result<()> {
+        // Only the sender's next sequence can be prepared. Until a payment fills it in,
+        // every other payment instruction fails with RecordAlreadyExists at that
+        // sequence, so it must be sent with send_payment_prepared, or the prepared record
+        // closed with close_transaction_record to free it up again.
+        let transaction_record = &mut ctx.accounts.transaction_record;
+        transaction_record.sender = ctx.accounts.sender.key();
+        transaction_record.owner = transaction_record.sender;
+        transaction_record.schema_version = TRANSACTION_RECORD_VERSION;
+        msg!("Record {} prepared for sequence {}",
+             transaction_record.key(),
+             ctx.accounts.user_state.transaction_count);
+        Ok(())
+    }
+
+    pub fn send_payment_prepared<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SendPaymentPrepared<'info>>,
+        amount: u64,
+        memo: String,
+    ) -> Result<()> {
+        // --- Input Validation & Security Checks ---
+        // Checks 1-3: Amount > 0, Sender != Receiver, memo within the deployment's limit
+        let max_memo_length = ctx.accounts.program_state.max_memo_length as usize;
+        validate_payment(&ctx.accounts.sender.key(), &ctx.accounts.receiver.key(), amount, &memo, max_memo_length)?;
+        // Check 4: Sufficient Sender Balance, leaving the sender rent-exempt. The record
+        // already exists, so its rent is not counted again.
+        ensure_covers_rent_and_payment(&ctx.accounts.sender, amount, 0)?;
+        // Checks 5-8 and the transfer: the rules every direct payment shares
+        let fee = direct_payment!(ctx, PaymentSource::Wallet(&ctx.accounts.sender), &ctx.accounts.sender)
+            .validate_and_charge(amount, amount)?;
+        let now = Clock::get()?.unix_timestamp;
+
+        // Fill in the record prepared in advance; its rent was paid by prepare_record
+        let transaction_record = &mut ctx.accounts.transaction_record;
+        transaction_record.set_inner(TransactionRecord::new_payment(
+            ctx.accounts.sender.key(),
+            ctx.accounts.receiver.key(),
+            amount,
+            memo.clone(),
+            now,
+            fee,
+        ));
+
+        let sequence = advance_sequence(transaction_record, &mut ctx.accounts.user_state, &mut ctx.accounts.program_state)?;
+
+        emit!(PaymentSent {
+            sender: transaction_record.sender,
+            receiver: transaction_record.receiver,
+            amount,
+            memo,
+            timestamp: now,
+            sequence,
+        });
+
+        Ok(())
+    }
+
+    pub fn register_memo(ctx: Context<RegisterMemo>, code: u16, text: String) -> Result<()> {
+        // Codes are permanent: `init` refuses to redefine one, so records written
+        // under a code always keep their meaning. The text must pass the same limit
+        // send_payment_coded checks it against.
+        validate_memo(&text, ctx.accounts.program_state.max_memo_length as usize)?;
+        let memo_dictionary = &mut ctx.accounts.memo_dictionary;
+        memo_dictionary.code = code;
+        memo_dictionary.text = text;
+        memo_dictionary.bump = ctx.bumps.memo_dictionary;
+        emit!(MemoRegistered {
+            authority: ctx.accounts.authority.key(),
+            code,
+            text: memo_dictionary.text.clone(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        msg!("Memo code {} registered: {}", code, memo_dictionary.text);
+        Ok(())
+    }
+
+    pub fn send_payment_coded<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SendPaymentCoded<'info>>,
+        amount: u64,
+        memo_code: u16,
+    ) -> Result<()> {
+        // The dictionary text stands in for the memo in every check
+        let memo = ctx.accounts.memo_dictionary.text.clone();
+
+        // --- Input Validation & Security Checks ---
+        // Checks 1-3: Amount > 0, Sender != Receiver, memo within the deployment's limit
+        let max_memo_length = ctx.accounts.program_state.max_memo_length as usize;
+        validate_payment(&ctx.accounts.sender.key(), &ctx.accounts.receiver.key(), amount, &memo, max_memo_length)?;
+        // Check 4: Sufficient Sender Balance, leaving the sender rent-exempt
+        ensure_covers_rent_and_payment(&ctx.accounts.sender, amount, Rent::get()?.minimum_balance(TransactionRecord::CODED_LEN))?;
+        // Checks 5-8 and the transfer: the rules every direct payment shares
+        let now = Clock::get()?.unix_timestamp;
+        let fee = direct_payment!(ctx, PaymentSource::Wallet(&ctx.accounts.sender), &ctx.accounts.sender)
+            .validate_and_charge(amount, amount)?;
+
+        // Record the code only; readers resolve it through the dictionary. Nothing
+        // else is variable-length, so the record is allocated at CODED_LEN.
+        let transaction_record = &mut TransactionRecord::new_payment(
+            ctx.accounts.sender.key(),
+            ctx.accounts.receiver.key(),
+            amount,
+            String::new(),
+            now,
+            fee,
+        );
+        transaction_record.memo_code = Some(memo_code);
+
+        let program_state = &mut ctx.accounts.program_state;
+        let sequence = advance_sequence(transaction_record, &mut ctx.accounts.user_state, program_state)?;
+        // Only created now, after every check, so a short balance fails with a clear error
+        create_transaction_record(
+            &ctx.accounts.sender,
+            &ctx.accounts.transaction_record,
+            &ctx.accounts.system_program,
+            TransactionRecord::CODED_LEN,
+            transaction_record,
+            sequence,
+            ctx.bumps.transaction_record,
+        )?;
+
+        // The event carries the resolved text so indexers need no dictionary lookup
+        emit!(PaymentSent {
+            sender: transaction_record.sender,
+            receiver: transaction_record.receiver,
+            amount,
+            memo,
+            timestamp: now,
+            sequence,
+        });
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(
+    amount: u64,
+    memo: String,
+    encrypted_memo: Vec<u8>,
+    reference: Option<[u8; 32]>,
+    allow_below_rent: bool,
+    force: bool,
+    category: u8,
+    idempotency_key: Option<[u8; 16]>, // Seeds the optional idempotency record
+)]
+pub struct SendPayment<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    /// CHECK: Receiver account does not need to sign, but we need its public key.
+    /// We are transferring SOL to this account, so it must be writable.
+    #[account(mut)]
+    pub receiver: AccountInfo<'info>,
+    /// CHECK: Must match the fee collector configured on the program state.
+    #[account(mut, address = program_state.fee_collector @ ErrorCode::InvalidFeeCollector)]
+    pub fee_collector: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+    // Seeds: "transaction", sender pubkey, sender's own transaction_count (as LE bytes)
+    // Per-user sequencing means concurrent senders never race on the same seed.
+    /// CHECK: Created by the handler once the payment's checks pass; the seeds pin it to the sender's next sequence.
+    #[account(mut, seeds = [b"transaction", sender.key().as_ref(), user_state.transaction_count.to_le_bytes().as_ref()], bump)]
+    pub transaction_record: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"user", sender.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        mut,
+        seeds = [b"state"], // Assuming state PDA is seeded with just "state"
+        bump,
+        constraint = !program_state.paused @ ErrorCode::ProgramPaused
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    // Only required while `program_state.whitelist_enabled` is set
+    #[account(seeds = [b"whitelist", receiver.key().as_ref()], bump = whitelist_entry.bump)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
+    // Required once the sender has set a daily limit
+    #[account(mut, seeds = [b"limit", sender.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", sender.key().as_ref()], bump)]
+    pub sender_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", receiver.key().as_ref()], bump)]
+    pub receiver_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds, so a frozen sender can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", sender.key().as_ref()], bump)]
+    pub sender_freeze: UncheckedAccount<'info>,
+    // Created by the first payment to this receiver
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = ReceiverStats::LEN,
+        seeds = [b"received", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_stats: Account<'info, ReceiverStats>,
+    // Present only for senders the authority has exempted from the protocol fee
+    #[account(seeds = [b"fee_exempt", sender.key().as_ref()], bump = fee_exemption.bump)]
+    pub fee_exemption: Option<Account<'info, FeeExemption>>,
+    // Created by the sender's first payment to this receiver
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = PaymentPair::LEN,
+        seeds = [b"pair", sender.key().as_ref(), receiver.key().as_ref()],
+        bump
+    )]
+    pub payment_pair: Account<'info, PaymentPair>,
+    // Fingerprint of the sender's last payment, overwritten on every payment. Required
+    // while `program_state.dedup_window_secs` is set; leave it out otherwise, so no rent
+    // is charged, and reclaim it with close_recent_payment.
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = RecentPayment::LEN,
+        seeds = [b"dedup", sender.key().as_ref()],
+        bump
+    )]
+    pub recent_payment: Option<Account<'info, RecentPayment>>,
+    /// CHECK: Required while `program_state.hook_program` is set; id and executability are checked in `invoke_payment_hook`.
+    pub hook_program: Option<UncheckedAccount<'info>>,
+    // Required exactly when an idempotency key is passed; created by its first use
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = IdempotencyRecord::LEN,
+        seeds = [b"idem", sender.key().as_ref(), idempotency_key.unwrap_or_default().as_ref()],
+        bump
+    )]
+    pub idempotency_record: Option<Account<'info, IdempotencyRecord>>,
+    /// CHECK: Address is pinned by the seeds; an `AcceptancePolicy` here means the payment is held for the receiver.
+    #[account(seeds = [b"accept", receiver.key().as_ref()], bump)]
+    pub receiver_acceptance: UncheckedAccount<'info>,
+    // Required exactly when the receiver requires acceptance; shares the record's sequence
+    #[account(
+        init,
+        payer = sender,
+        space = PendingTransfer::LEN,
+        seeds = [b"pending", sender.key().as_ref(), user_state.transaction_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub pending_transfer: Option<Account<'info, PendingTransfer>>,
+    // Both ledgers are created by the wallet's first payment either way
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = UserLedger::LEN,
+        seeds = [b"ledger", sender.key().as_ref()],
+        bump,
+        // `init` runs ahead of any handler check, and on a self-payment `receiver_ledger`
+        // would initialize this same account a second time
+        constraint = sender.key() != receiver.key() @ ErrorCode::SelfPayment
+    )]
+    pub sender_ledger: Box<Account<'info, UserLedger>>,
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = UserLedger::LEN,
+        seeds = [b"ledger", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_ledger: Box<Account<'info, UserLedger>>,
+    // Pays the record's rent: a relayer or sponsor, or the sender itself when unsponsored
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SendTokenPayment<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    /// CHECK: Receiver wallet does not need to sign; it is only used as the
+    /// authority of the receiver's associated token account.
+    pub receiver: AccountInfo<'info>,
+    pub mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = sender,
+    )]
+    pub sender_token_account: Account<'info, TokenAccount>,
+    // Compare the token accounts themselves rather than relying on the wallet-level
+    // SelfPayment check in the handler; this constraint runs first
+    #[account(
+        mut,
+        constraint = receiver_token_account.key() != sender_token_account.key() @ ErrorCode::SelfTokenPayment,
+        associated_token::mint = mint,
+        associated_token::authority = receiver,
+    )]
+    pub receiver_token_account: Account<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = sender,
+        space = TransactionRecord::LEN,
+        // Same seeds as SOL payments so token and SOL records share one sequence per sender.
+        seeds = [b"transaction", sender.key().as_ref(), user_state.transaction_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub transaction_record: Account<'info, TransactionRecord>,
+    #[account(mut, seeds = [b"user", sender.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        constraint = !program_state.paused @ ErrorCode::ProgramPaused
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", sender.key().as_ref()], bump)]
+    pub sender_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", receiver.key().as_ref()], bump)]
+    pub receiver_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds, so a frozen sender can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", sender.key().as_ref()], bump)]
+    pub sender_freeze: UncheckedAccount<'info>,
+    // Token amounts are tracked per mint, in the mint's base units
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = ReceiverStats::LEN,
+        seeds = [b"received", receiver.key().as_ref(), mint.key().as_ref()],
+        bump
+    )]
+    pub receiver_token_stats: Box<Account<'info, ReceiverStats>>,
+    // Required once the sender has set a daily limit
+    #[account(mut, seeds = [b"limit", sender.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+    // Only required while `program_state.whitelist_enabled` is set
+    #[account(seeds = [b"whitelist", receiver.key().as_ref()], bump = whitelist_entry.bump)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
+}
+
+#[derive(Accounts)]
+pub struct SendSplitPayment<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Created by the handler once the payment's checks pass; the seeds pin it to the sender's next sequence.
+    #[account(mut, seeds = [b"transaction", sender.key().as_ref(), user_state.transaction_count.to_le_bytes().as_ref()], bump)]
+    pub transaction_record: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"user", sender.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        constraint = !program_state.paused @ ErrorCode::ProgramPaused
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", sender.key().as_ref()], bump)]
+    pub sender_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds, so a frozen sender can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", sender.key().as_ref()], bump)]
+    pub sender_freeze: UncheckedAccount<'info>,
+    // Required once the sender has set a daily limit
+    #[account(mut, seeds = [b"limit", sender.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+    /// CHECK: Must match the fee collector configured on the program state.
+    #[account(mut, address = program_state.fee_collector @ ErrorCode::InvalidFeeCollector)]
+    pub fee_collector: AccountInfo<'info>,
+    // Fee split collectors, then the receivers with their blacklist, whitelist and
+    // stats PDAs, are passed via `remaining_accounts`
+}
+
+#[derive(Accounts)]
+#[instruction(sequence: u64)]
+pub struct CloseTransactionRecord<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    #[account(
+        mut,
+        close = sender,
+        seeds = [b"transaction", transaction_record.sender.as_ref(), sequence.to_le_bytes().as_ref()],
+        bump,
+        // Only the record's current owner (the sender unless transferred) can reclaim its rent.
+        constraint = transaction_record.current_owner() == sender.key() @ ErrorCode::UnauthorizedClose
+    )]
+    pub transaction_record: Account<'info, TransactionRecord>,
+    /// CHECK: Address is pinned by the seeds; the handler keeps the record if a `PendingTransfer` lives there.
+    #[account(seeds = [b"pending", transaction_record.sender.as_ref(), sequence.to_le_bytes().as_ref()], bump)]
+    pub pending_transfer: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(sequence: u64)]
+pub struct GetTransaction<'info> {
+    /// CHECK: Only used to derive the record PDA; the sender does not need to sign a read.
+    pub sender: AccountInfo<'info>,
+    /// CHECK: May be uninitialized; ownership and discriminator are checked in the handler.
+    #[account(
+        seeds = [b"transaction", sender.key().as_ref(), sequence.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub transaction_record: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeState<'info> {
+    #[account(
+        init,
+        payer = user,
+        space = ProgramState::LEN,
+        seeds = [b"state"],
+        bump
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    #[account(mut)]
+    pub user: Signer<'info>, // Pays the rent; gets no special rights
+    // Binds initialization to whoever holds the program's upgrade authority
+    #[account(
+        seeds = [crate::ID.as_ref()],
+        bump,
+        seeds::program = anchor_lang::solana_program::bpf_loader_upgradeable::ID,
+        constraint = program_data.upgrade_authority_address == Some(user.key()) @ ErrorCode::NotUpgradeAuthority
+    )]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateEscrow<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    /// CHECK: Receiver does not sign; it is recorded on the escrow and checked on release.
+    pub receiver: AccountInfo<'info>,
+    #[account(
+        init,
+        payer = sender,
+        space = Escrow::LEN,
+        seeds = [b"escrow", sender.key().as_ref(), receiver.key().as_ref(), program_state.total_escrows.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        constraint = !program_state.paused @ ErrorCode::ProgramPaused
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", sender.key().as_ref()], bump)]
+    pub sender_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", receiver.key().as_ref()], bump)]
+    pub receiver_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds, so a frozen sender can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", sender.key().as_ref()], bump)]
+    pub sender_freeze: UncheckedAccount<'info>,
+    // Holds `spend_limit_enabled`
+    #[account(seeds = [b"user", sender.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    // Required once the sender has set a daily limit
+    #[account(mut, seeds = [b"limit", sender.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+    // Only required while `program_state.whitelist_enabled` is set
+    #[account(seeds = [b"whitelist", receiver.key().as_ref()], bump = whitelist_entry.bump)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
+}
+
+#[derive(Accounts)]
+pub struct ReleaseEscrow<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    /// CHECK: Must match the receiver stored on the escrow.
+    #[account(mut, address = escrow.receiver @ ErrorCode::InvalidReceiver)]
+    pub receiver: AccountInfo<'info>,
+    #[account(
+        mut,
+        close = sender,
+        seeds = [b"escrow", escrow.sender.as_ref(), escrow.receiver.as_ref(), escrow.sequence.to_le_bytes().as_ref()],
+        bump = escrow.bump,
+        // Only the sender can release their escrow
+        constraint = escrow.sender == sender.key() @ ErrorCode::UnauthorizedEscrowAction
+    )]
+    pub escrow: Account<'info, Escrow>,
+    #[account(
+        init,
+        payer = sender,
+        space = TransactionRecord::LEN,
+        seeds = [b"transaction", sender.key().as_ref(), user_state.transaction_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub transaction_record: Account<'info, TransactionRecord>,
+    #[account(mut, seeds = [b"user", sender.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    // Works while paused
+    #[account(mut, seeds = [b"state"], bump)]
+    pub program_state: Account<'info, ProgramState>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Must match the fee collector configured on the program state.
+    #[account(mut, address = program_state.fee_collector @ ErrorCode::InvalidFeeCollector)]
+    pub fee_collector: AccountInfo<'info>,
+    // Created by the first payment to this receiver
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = ReceiverStats::LEN,
+        seeds = [b"received", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_stats: Account<'info, ReceiverStats>,
+    // Fee split collectors, if any, are passed via `remaining_accounts`
+}
+
+#[derive(Accounts)]
+pub struct RefundEscrow<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    #[account(
+        mut,
+        close = sender,
+        seeds = [b"escrow", escrow.sender.as_ref(), escrow.receiver.as_ref(), escrow.sequence.to_le_bytes().as_ref()],
+        bump = escrow.bump,
+        // Only the sender can refund their escrow
+        constraint = escrow.sender == sender.key() @ ErrorCode::UnauthorizedEscrowAction
+    )]
+    pub escrow: Account<'info, Escrow>,
+    // Works while paused
+    #[account(mut, seeds = [b"state"], bump)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct AdminRecoverEscrow<'info> {
+    pub authority: Signer<'info>,
+    /// CHECK: Must be the escrow's original sender; receives the funds and rent.
+    #[account(mut, address = escrow.sender)]
+    pub sender: AccountInfo<'info>,
+    #[account(
+        mut,
+        close = sender,
+        seeds = [b"escrow", escrow.sender.as_ref(), escrow.receiver.as_ref(), escrow.sequence.to_le_bytes().as_ref()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+    // Works while paused, so stuck funds can be returned during an emergency stop
+    #[account(mut, seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct CreateTimelockedPayment<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    /// CHECK: Receiver does not sign at creation; it must sign to claim.
+    pub receiver: AccountInfo<'info>,
+    #[account(
+        init,
+        payer = sender,
+        space = TimelockedPayment::LEN,
+        seeds = [b"timelock", sender.key().as_ref(), receiver.key().as_ref(), program_state.total_timelocks.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub timelock: Account<'info, TimelockedPayment>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        constraint = !program_state.paused @ ErrorCode::ProgramPaused
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", sender.key().as_ref()], bump)]
+    pub sender_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", receiver.key().as_ref()], bump)]
+    pub receiver_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds, so a frozen sender can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", sender.key().as_ref()], bump)]
+    pub sender_freeze: UncheckedAccount<'info>,
+    // Holds `spend_limit_enabled`
+    #[account(seeds = [b"user", sender.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    // Required once the sender has set a daily limit
+    #[account(mut, seeds = [b"limit", sender.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+    // Only required while `program_state.whitelist_enabled` is set
+    #[account(seeds = [b"whitelist", receiver.key().as_ref()], bump = whitelist_entry.bump)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimTimelockedPayment<'info> {
+    #[account(mut, address = timelock.receiver @ ErrorCode::InvalidReceiver)]
+    pub receiver: Signer<'info>,
+    /// CHECK: Must match the sender stored on the timelock; receives the rent back.
+    #[account(mut, address = timelock.sender)]
+    pub sender: AccountInfo<'info>,
+    #[account(
+        mut,
+        close = sender,
+        seeds = [b"timelock", timelock.sender.as_ref(), timelock.receiver.as_ref(), timelock.sequence.to_le_bytes().as_ref()],
+        bump = timelock.bump
+    )]
+    pub timelock: Account<'info, TimelockedPayment>,
+    // Works while paused
+    #[account(mut, seeds = [b"state"], bump)]
+    pub program_state: Account<'info, ProgramState>,
+    // Created by the first payment to this receiver
+    #[account(
+        init_if_needed,
+        payer = receiver,
+        space = ReceiverStats::LEN,
+        seeds = [b"received", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_stats: Account<'info, ReceiverStats>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetPaused<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct SetFee<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+    /// CHECK: Any account able to receive SOL; stored as the new fee collector.
+    pub fee_collector: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetFeeSplit<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct BatchInitializeUsers<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    // UserState PDAs are passed via `remaining_accounts`
+}
+
+#[derive(Accounts)]
+pub struct InitializeUser<'info> {
+    #[account(
+        init,
+        payer = user,
+        space = UserState::LEN,
+        seeds = [b"user", user.key().as_ref()],
+        bump
+    )]
+    pub user_state: Account<'info, UserState>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateSubscription<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: Merchant does not sign at creation; it must sign to charge.
+    pub merchant: AccountInfo<'info>,
+    #[account(
+        init,
+        payer = payer,
+        space = Subscription::LEN,
+        seeds = [b"sub", payer.key().as_ref(), merchant.key().as_ref()],
+        bump
+    )]
+    pub subscription: Account<'info, Subscription>,
+    #[account(mut, seeds = [b"state"], bump, constraint = !program_state.paused @ ErrorCode::ProgramPaused)]
+    pub program_state: Account<'info, ProgramState>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Address is pinned by the seeds, so a frozen payer can't leave it out; the handler rejects the subscription if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", payer.key().as_ref()], bump)]
+    pub payer_freeze: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the subscription if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", payer.key().as_ref()], bump)]
+    pub payer_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the subscription if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", merchant.key().as_ref()], bump)]
+    pub merchant_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; an `AcceptancePolicy` here means the receiver can only be paid through send_payment.
+    #[account(seeds = [b"accept", merchant.key().as_ref()], bump)]
+    pub merchant_acceptance: UncheckedAccount<'info>,
+    // Only required while `program_state.whitelist_enabled` is set
+    #[account(seeds = [b"whitelist", merchant.key().as_ref()], bump = whitelist_entry.bump)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
+    // Holds `spend_limit_enabled`
+    #[account(seeds = [b"user", payer.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    // Required once the payer has set a daily limit
+    #[account(mut, seeds = [b"limit", payer.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+}
+
+#[derive(Accounts)]
+pub struct FundSubscription<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"sub", payer.key().as_ref(), subscription.merchant.as_ref()],
+        bump = subscription.bump,
+        constraint = subscription.active @ ErrorCode::SubscriptionInactive
+    )]
+    pub subscription: Account<'info, Subscription>,
+    #[account(seeds = [b"state"], bump, constraint = !program_state.paused @ ErrorCode::ProgramPaused)]
+    pub program_state: Account<'info, ProgramState>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Address is pinned by the seeds, so a frozen payer can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", payer.key().as_ref()], bump)]
+    pub payer_freeze: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", payer.key().as_ref()], bump)]
+    pub payer_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", subscription.merchant.as_ref()], bump)]
+    pub merchant_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; an `AcceptancePolicy` here means the receiver can only be paid through send_payment.
+    #[account(seeds = [b"accept", subscription.merchant.as_ref()], bump)]
+    pub merchant_acceptance: UncheckedAccount<'info>,
+    // Only required while `program_state.whitelist_enabled` is set
+    #[account(seeds = [b"whitelist", subscription.merchant.as_ref()], bump = whitelist_entry.bump)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
+    // Holds `spend_limit_enabled`
+    #[account(seeds = [b"user", payer.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    // Required once the payer has set a daily limit
+    #[account(mut, seeds = [b"limit", payer.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+}
+
+#[derive(Accounts)]
+pub struct ChargeSubscription<'info> {
+    #[account(mut)]
+    pub merchant: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"sub", subscription.payer.as_ref(), merchant.key().as_ref()],
+        bump = subscription.bump,
+        constraint = subscription.active @ ErrorCode::SubscriptionInactive
+    )]
+    pub subscription: Account<'info, Subscription>,
+    #[account(seeds = [b"state"], bump, constraint = !program_state.paused @ ErrorCode::ProgramPaused)]
+    pub program_state: Account<'info, ProgramState>,
+    // Created by the first payment to this receiver
+    #[account(
+        init_if_needed,
+        payer = merchant,
+        space = ReceiverStats::LEN,
+        seeds = [b"received", merchant.key().as_ref()],
+        bump
+    )]
+    pub receiver_stats: Account<'info, ReceiverStats>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelSubscription<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        mut,
+        close = payer,
+        seeds = [b"sub", payer.key().as_ref(), subscription.merchant.as_ref()],
+        bump = subscription.bump
+    )]
+    pub subscription: Account<'info, Subscription>,
+    // Works while paused
+    #[account(mut, seeds = [b"state"], bump)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeSubscriptionChange<'info> {
+    pub merchant: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"sub", subscription.payer.as_ref(), subscription.merchant.as_ref()],
+        bump = subscription.bump,
+        has_one = merchant @ ErrorCode::UnauthorizedSubscriptionChange,
+        constraint = subscription.active @ ErrorCode::SubscriptionInactive
+    )]
+    pub subscription: Account<'info, Subscription>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptSubscriptionChange<'info> {
+    pub payer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"sub", subscription.payer.as_ref(), subscription.merchant.as_ref()],
+        bump = subscription.bump,
+        has_one = payer @ ErrorCode::UnauthorizedSubscriptionChange,
+        constraint = subscription.active @ ErrorCode::SubscriptionInactive
+    )]
+    pub subscription: Account<'info, Subscription>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateSubscription<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: Only used to derive the subscription PDA.
+    pub merchant: AccountInfo<'info>,
+    /// CHECK: May be in an older layout that no longer deserializes as `Subscription`;
+    /// the seeds and owner are checked here, the discriminator in the handler.
+    #[account(mut, seeds = [b"sub", payer.key().as_ref(), merchant.key().as_ref()], bump, owner = crate::ID)]
+    pub subscription: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ResetState<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct SetMinAmount<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct SetMinReserve<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct SetMaxSinglePayment<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+#[instruction(original_sequence: u64)]
+pub struct RefundPayment<'info> {
+    #[account(mut)]
+    pub refunder: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"transaction", original_record.sender.as_ref(), original_sequence.to_le_bytes().as_ref()],
+        bump,
+        // Only the receiver of the original payment can refund it
+        constraint = original_record.receiver == refunder.key() @ ErrorCode::UnauthorizedRefund
+    )]
+    pub original_record: Box<Account<'info, TransactionRecord>>,
+    /// CHECK: Must be the sender of the original payment; receives the refund.
+    #[account(mut, address = original_record.sender @ ErrorCode::InvalidReceiver)]
+    pub original_sender: AccountInfo<'info>,
+    #[account(
+        init,
+        payer = refunder,
+        space = TransactionRecord::LEN,
+        seeds = [b"transaction", refunder.key().as_ref(), user_state.transaction_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub transaction_record: Box<Account<'info, TransactionRecord>>,
+    #[account(mut, seeds = [b"user", refunder.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        constraint = !program_state.paused @ ErrorCode::ProgramPaused
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the refund if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", refunder.key().as_ref()], bump)]
+    pub refunder_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds, so a frozen refunder can't leave it out; the handler rejects the refund if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", refunder.key().as_ref()], bump)]
+    pub refunder_freeze: UncheckedAccount<'info>,
+    // Required once the refunder has set a daily limit
+    #[account(mut, seeds = [b"limit", refunder.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the refund if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", original_sender.key().as_ref()], bump)]
+    pub original_sender_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; an `AcceptancePolicy` here means the receiver can only be paid through send_payment.
+    #[account(seeds = [b"accept", original_sender.key().as_ref()], bump)]
+    pub original_sender_acceptance: UncheckedAccount<'info>,
+    // Only required while `program_state.whitelist_enabled` is set
+    #[account(seeds = [b"whitelist", original_sender.key().as_ref()], bump = whitelist_entry.bump)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
+}
+
+#[derive(Accounts)]
+pub struct SendBatchPayment<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Created by the handler once the payment's checks pass; the seeds pin it to the sender's next sequence.
+    #[account(mut, seeds = [b"transaction", sender.key().as_ref(), user_state.transaction_count.to_le_bytes().as_ref()], bump)]
+    pub transaction_record: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"user", sender.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        constraint = !program_state.paused @ ErrorCode::ProgramPaused
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", sender.key().as_ref()], bump)]
+    pub sender_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds, so a frozen sender can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", sender.key().as_ref()], bump)]
+    pub sender_freeze: UncheckedAccount<'info>,
+    // Required once the sender has set a daily limit
+    #[account(mut, seeds = [b"limit", sender.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+    /// CHECK: Must match the fee collector configured on the program state.
+    #[account(mut, address = program_state.fee_collector @ ErrorCode::InvalidFeeCollector)]
+    pub fee_collector: AccountInfo<'info>,
+    // Fee split collectors, then the receivers with their blacklist, whitelist and
+    // stats PDAs, are passed via `remaining_accounts`
+}
+
+#[derive(Accounts)]
+pub struct SetWhitelistEnabled<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct AddWhitelist<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+    /// CHECK: The receiver address being whitelisted.
+    pub address: AccountInfo<'info>,
+    #[account(
+        init,
+        payer = authority,
+        space = WhitelistEntry::LEN,
+        seeds = [b"whitelist", address.key().as_ref()],
+        bump
+    )]
+    pub whitelist_entry: Account<'info, WhitelistEntry>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveWhitelist<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"whitelist", whitelist_entry.address.as_ref()],
+        bump = whitelist_entry.bump
+    )]
+    pub whitelist_entry: Account<'info, WhitelistEntry>,
+}
+
+#[derive(Accounts)]
+pub struct PruneWhitelist<'info> {
+    pub caller: Signer<'info>,
+    /// CHECK: Must be the program authority; receives the entry's rent.
+    #[account(mut, address = program_state.authority @ ErrorCode::Unauthorized)]
+    pub authority: AccountInfo<'info>,
+    #[account(seeds = [b"state"], bump)]
+    pub program_state: Account<'info, ProgramState>,
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"whitelist", whitelist_entry.address.as_ref()],
+        bump = whitelist_entry.bump,
+        constraint = is_expired(whitelist_entry.expires_at, Clock::get()?.unix_timestamp) @ ErrorCode::WhitelistEntryNotExpired
+    )]
+    pub whitelist_entry: Account<'info, WhitelistEntry>,
+}
+
+#[derive(Accounts)]
+#[instruction(invoice_id: u64)]
+pub struct CreateInvoice<'info> {
+    #[account(mut)]
+    pub merchant: Signer<'info>,
+    #[account(
+        init,
+        payer = merchant,
+        space = Invoice::LEN,
+        seeds = [b"invoice", merchant.key().as_ref(), invoice_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub invoice: Account<'info, Invoice>,
+    pub system_program: Program<'info, System>,
+    #[account(seeds = [b"state"], bump)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct PayInvoice<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    /// CHECK: Must match the merchant stored on the invoice.
+    #[account(mut, address = invoice.merchant @ ErrorCode::InvalidReceiver)]
+    pub merchant: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"invoice", invoice.merchant.as_ref(), invoice.invoice_id.to_le_bytes().as_ref()],
+        bump = invoice.bump
+    )]
+    pub invoice: Account<'info, Invoice>,
+    #[account(seeds = [b"state"], bump, constraint = !program_state.paused @ ErrorCode::ProgramPaused)]
+    pub program_state: Account<'info, ProgramState>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Address is pinned by the seeds, so a frozen payer can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", payer.key().as_ref()], bump)]
+    pub payer_freeze: UncheckedAccount<'info>,
+    // Created by the first payment to this receiver
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = ReceiverStats::LEN,
+        seeds = [b"received", merchant.key().as_ref()],
+        bump
+    )]
+    pub receiver_stats: Account<'info, ReceiverStats>,
+    // Holds `spend_limit_enabled`
+    #[account(seeds = [b"user", payer.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    // Required once the payer has set a daily limit
+    #[account(mut, seeds = [b"limit", payer.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+    // Only required while `program_state.whitelist_enabled` is set
+    #[account(seeds = [b"whitelist", merchant.key().as_ref()], bump = whitelist_entry.bump)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", payer.key().as_ref()], bump)]
+    pub payer_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", merchant.key().as_ref()], bump)]
+    pub merchant_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; an `AcceptancePolicy` here means the receiver can only be paid through send_payment.
+    #[account(seeds = [b"accept", merchant.key().as_ref()], bump)]
+    pub merchant_acceptance: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetSpendLimit<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = SpendLimit::LEN,
+        seeds = [b"limit", owner.key().as_ref()],
+        bump
+    )]
+    pub spend_limit: Account<'info, SpendLimit>,
+    #[account(mut, seeds = [b"user", owner.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetMultisigConfig<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+#[instruction(payment_id: u64)]
+pub struct ProposePayment<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    /// CHECK: Receiver does not sign; it is recorded on the pending payment.
+    pub receiver: AccountInfo<'info>,
+    #[account(
+        init,
+        payer = sender,
+        space = PendingPayment::LEN,
+        seeds = [b"pending", sender.key().as_ref(), payment_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub pending_payment: Account<'info, PendingPayment>,
+    #[account(seeds = [b"state"], bump, constraint = !program_state.paused @ ErrorCode::ProgramPaused)]
+    pub program_state: Account<'info, ProgramState>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ApprovePayment<'info> {
+    pub approver: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"pending", pending_payment.sender.as_ref(), pending_payment.payment_id.to_le_bytes().as_ref()],
+        bump = pending_payment.bump
+    )]
+    pub pending_payment: Account<'info, PendingPayment>,
+    #[account(seeds = [b"state"], bump)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct ExecutePayment<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    /// CHECK: Must match the receiver stored on the pending payment.
+    #[account(mut, address = pending_payment.receiver @ ErrorCode::InvalidReceiver)]
+    pub receiver: AccountInfo<'info>,
+    /// CHECK: Must match the fee collector configured on the program state.
+    #[account(mut, address = program_state.fee_collector @ ErrorCode::InvalidFeeCollector)]
+    pub fee_collector: AccountInfo<'info>,
+    #[account(
+        mut,
+        close = sender,
+        seeds = [b"pending", sender.key().as_ref(), pending_payment.payment_id.to_le_bytes().as_ref()],
+        bump = pending_payment.bump
+    )]
+    pub pending_payment: Box<Account<'info, PendingPayment>>,
+    #[account(
+        init,
+        payer = sender,
+        space = TransactionRecord::LEN,
+        seeds = [b"transaction", sender.key().as_ref(), user_state.transaction_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub transaction_record: Box<Account<'info, TransactionRecord>>,
+    #[account(mut, seeds = [b"user", sender.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        constraint = !program_state.paused @ ErrorCode::ProgramPaused
+    )]
+    pub program_state: Box<Account<'info, ProgramState>>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", sender.key().as_ref()], bump)]
+    pub sender_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", receiver.key().as_ref()], bump)]
+    pub receiver_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds, so a frozen sender can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", sender.key().as_ref()], bump)]
+    pub sender_freeze: UncheckedAccount<'info>,
+    // Created by the first payment to this receiver
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = ReceiverStats::LEN,
+        seeds = [b"received", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_stats: Box<Account<'info, ReceiverStats>>,
+    // Only required while `program_state.whitelist_enabled` is set
+    #[account(seeds = [b"whitelist", receiver.key().as_ref()], bump = whitelist_entry.bump)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
+    // Required once the sender has set a daily limit
+    #[account(mut, seeds = [b"limit", sender.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeFeeVault<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+    #[account(
+        init,
+        payer = authority,
+        space = FeeVault::LEN,
+        seeds = [b"fee_vault"],
+        bump
+    )]
+    pub fee_vault: Account<'info, FeeVault>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFees<'info> {
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+    #[account(mut, seeds = [b"fee_vault"], bump = fee_vault.bump)]
+    pub fee_vault: Account<'info, FeeVault>,
+    /// CHECK: Authority-chosen account that receives the withdrawn fees.
+    #[account(mut)]
+    pub destination: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateStream<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    /// CHECK: Recipient does not sign at creation; it must sign to withdraw.
+    pub recipient: AccountInfo<'info>,
+    #[account(
+        init,
+        payer = sender,
+        space = Stream::LEN,
+        seeds = [b"stream", sender.key().as_ref(), recipient.key().as_ref(), program_state.total_streams.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub stream: Account<'info, Stream>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        constraint = !program_state.paused @ ErrorCode::ProgramPaused
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", sender.key().as_ref()], bump)]
+    pub sender_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", recipient.key().as_ref()], bump)]
+    pub receiver_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds, so a frozen sender can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", sender.key().as_ref()], bump)]
+    pub sender_freeze: UncheckedAccount<'info>,
+    // Holds `spend_limit_enabled`
+    #[account(seeds = [b"user", sender.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    // Required once the sender has set a daily limit
+    #[account(mut, seeds = [b"limit", sender.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+    // Only required while `program_state.whitelist_enabled` is set
+    #[account(seeds = [b"whitelist", recipient.key().as_ref()], bump = whitelist_entry.bump)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawStream<'info> {
+    #[account(mut, address = stream.recipient @ ErrorCode::InvalidReceiver)]
+    pub recipient: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"stream", stream.sender.as_ref(), stream.recipient.as_ref(), stream.sequence.to_le_bytes().as_ref()],
+        bump = stream.bump,
+        constraint = !stream.canceled @ ErrorCode::StreamAlreadyCanceled
+    )]
+    pub stream: Account<'info, Stream>,
+    // Works while paused
+    #[account(seeds = [b"state"], bump)]
+    pub program_state: Account<'info, ProgramState>,
+    // Created by the first payment to this receiver
+    #[account(
+        init_if_needed,
+        payer = recipient,
+        space = ReceiverStats::LEN,
+        seeds = [b"received", recipient.key().as_ref()],
+        bump
+    )]
+    pub receiver_stats: Account<'info, ReceiverStats>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelStream<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    /// CHECK: Must match the recipient stored on the stream.
+    #[account(mut, address = stream.recipient @ ErrorCode::InvalidReceiver)]
+    pub recipient: AccountInfo<'info>,
+    #[account(
+        mut,
+        close = sender,
+        // Deriving with the signer's key means only the stream creator can cancel
+        seeds = [b"stream", sender.key().as_ref(), stream.recipient.as_ref(), stream.sequence.to_le_bytes().as_ref()],
+        bump = stream.bump
+    )]
+    pub stream: Account<'info, Stream>,
+    // Works while paused
+    #[account(mut, seeds = [b"state"], bump)]
+    pub program_state: Account<'info, ProgramState>,
+    // Created by the first payment to this receiver
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = ReceiverStats::LEN,
+        seeds = [b"received", recipient.key().as_ref()],
+        bump
+    )]
+    pub receiver_stats: Account<'info, ReceiverStats>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CreateConditionalPayment<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    /// CHECK: Receiver does not sign; it is recorded on the conditional payment.
+    pub receiver: AccountInfo<'info>,
+    #[account(
+        init,
+        payer = sender,
+        space = ConditionalPayment::LEN,
+        seeds = [b"conditional", sender.key().as_ref(), receiver.key().as_ref(), program_state.total_conditionals.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub conditional_payment: Account<'info, ConditionalPayment>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        constraint = !program_state.paused @ ErrorCode::ProgramPaused
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", sender.key().as_ref()], bump)]
+    pub sender_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", receiver.key().as_ref()], bump)]
+    pub receiver_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds, so a frozen sender can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", sender.key().as_ref()], bump)]
+    pub sender_freeze: UncheckedAccount<'info>,
+    // Created when the conditional payment is, so the permissionless execute finds it
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = ReceiverStats::LEN,
+        seeds = [b"received", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_stats: Account<'info, ReceiverStats>,
+    // Holds `spend_limit_enabled`
+    #[account(seeds = [b"user", sender.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    // Required once the sender has set a daily limit
+    #[account(mut, seeds = [b"limit", sender.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+    // Only required while `program_state.whitelist_enabled` is set
+    #[account(seeds = [b"whitelist", receiver.key().as_ref()], bump = whitelist_entry.bump)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteConditionalPayment<'info> {
+    /// CHECK: Must match the receiver stored on the conditional payment.
+    #[account(mut, address = conditional_payment.receiver @ ErrorCode::InvalidReceiver)]
+    pub receiver: AccountInfo<'info>,
+    /// CHECK: Must match the sender stored on the conditional payment; receives the rent back.
+    #[account(mut, address = conditional_payment.sender)]
+    pub sender: AccountInfo<'info>,
+    #[account(
+        mut,
+        close = sender,
+        seeds = [b"conditional", conditional_payment.sender.as_ref(), conditional_payment.receiver.as_ref(), conditional_payment.sequence.to_le_bytes().as_ref()],
+        bump = conditional_payment.bump
+    )]
+    pub conditional_payment: Account<'info, ConditionalPayment>,
+    /// CHECK: Pyth `PriceUpdateV2` account; owner, discriminator, feed and staleness are checked in the handler.
+    pub price_update: AccountInfo<'info>,
+    // Works while paused
+    #[account(mut, seeds = [b"state"], bump)]
+    pub program_state: Account<'info, ProgramState>,
+    // Created by create_conditional_payment
+    #[account(mut, seeds = [b"received", receiver.key().as_ref()], bump = receiver_stats.bump)]
+    pub receiver_stats: Account<'info, ReceiverStats>,
+}
+
+#[derive(Accounts)]
+pub struct ReclaimConditionalPayment<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    #[account(
+        mut,
+        close = sender,
+        // Deriving with the signer's key means only the creator can reclaim
+        seeds = [b"conditional", sender.key().as_ref(), conditional_payment.receiver.as_ref(), conditional_payment.sequence.to_le_bytes().as_ref()],
+        bump = conditional_payment.bump
+    )]
+    pub conditional_payment: Account<'info, ConditionalPayment>,
+    // Works while paused
+    #[account(mut, seeds = [b"state"], bump)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct SendPaymentWithTip<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    /// CHECK: Receiver of the base payment; ownership is checked in the handler.
+    #[account(mut)]
+    pub receiver: AccountInfo<'info>,
+    /// CHECK: Receiver of the tip (e.g. the worker rather than the platform); ownership is checked in the handler when `tip > 0`.
+    #[account(mut)]
+    pub tip_recipient: AccountInfo<'info>,
+    /// CHECK: Must match the fee collector configured on the program state.
+    #[account(mut, address = program_state.fee_collector @ ErrorCode::InvalidFeeCollector)]
+    pub fee_collector: AccountInfo<'info>,
+    // Fee split collectors, if configured, are passed via `remaining_accounts`
+    pub system_program: Program<'info, System>,
+    #[account(
+        init,
+        payer = sender,
+        space = TransactionRecord::LEN,
+        seeds = [b"transaction", sender.key().as_ref(), user_state.transaction_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub transaction_record: Account<'info, TransactionRecord>,
+    #[account(mut, seeds = [b"user", sender.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        constraint = !program_state.paused @ ErrorCode::ProgramPaused
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    #[account(seeds = [b"whitelist", receiver.key().as_ref()], bump = whitelist_entry.bump)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
+    #[account(mut, seeds = [b"limit", sender.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", sender.key().as_ref()], bump)]
+    pub sender_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", receiver.key().as_ref()], bump)]
+    pub receiver_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects a tip if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", tip_recipient.key().as_ref()], bump)]
+    pub tip_recipient_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds, so a frozen sender can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", sender.key().as_ref()], bump)]
+    pub sender_freeze: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; an `AcceptancePolicy` here means the receiver can only be paid through send_payment.
+    #[account(seeds = [b"accept", receiver.key().as_ref()], bump)]
+    pub receiver_acceptance: UncheckedAccount<'info>,
+    // Created by the first payment to this receiver
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = ReceiverStats::LEN,
+        seeds = [b"received", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_stats: Account<'info, ReceiverStats>,
+    // Both ledgers are created by the wallet's first payment either way
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = UserLedger::LEN,
+        seeds = [b"ledger", sender.key().as_ref()],
+        bump,
+        constraint = sender.key() != receiver.key() @ ErrorCode::SelfPayment
+    )]
+    pub sender_ledger: Box<Account<'info, UserLedger>>,
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = UserLedger::LEN,
+        seeds = [b"ledger", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_ledger: Box<Account<'info, UserLedger>>,
+    /// CHECK: Required while `program_state.hook_program` is set; id and executability are checked in `invoke_payment_hook`.
+    pub hook_program: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct DepositToVault<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(mut, seeds = [b"vault", owner.key().as_ref()], bump)]
+    pub vault: SystemAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFromVault<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(mut, seeds = [b"vault", owner.key().as_ref()], bump)]
+    pub vault: SystemAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SendPaymentFromVault<'info> {
+    // Authorizes the payment; does not pay fees or rent
+    pub owner: Signer<'info>,
+    // Fee payer for the transaction and rent payer for the record
+    #[account(mut)]
+    pub relayer: Signer<'info>,
+    #[account(mut, seeds = [b"vault", owner.key().as_ref()], bump)]
+    pub vault: SystemAccount<'info>,
+    /// CHECK: Receiver does not sign; ownership is checked in the handler.
+    #[account(mut)]
+    pub receiver: AccountInfo<'info>,
+    /// CHECK: Must match the fee collector configured on the program state.
+    #[account(mut, address = program_state.fee_collector @ ErrorCode::InvalidFeeCollector)]
+    pub fee_collector: AccountInfo<'info>,
+    // Fee split collectors, if configured, are passed via `remaining_accounts`
+    pub system_program: Program<'info, System>,
+    #[account(
+        init,
+        payer = relayer,
+        space = TransactionRecord::LEN,
+        seeds = [b"transaction", owner.key().as_ref(), user_state.transaction_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub transaction_record: Account<'info, TransactionRecord>,
+    #[account(mut, seeds = [b"user", owner.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        constraint = !program_state.paused @ ErrorCode::ProgramPaused
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    #[account(seeds = [b"whitelist", receiver.key().as_ref()], bump = whitelist_entry.bump)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
+    #[account(mut, seeds = [b"limit", owner.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", owner.key().as_ref()], bump)]
+    pub sender_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", receiver.key().as_ref()], bump)]
+    pub receiver_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds, so a frozen sender can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", owner.key().as_ref()], bump)]
+    pub sender_freeze: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; an `AcceptancePolicy` here means the receiver can only be paid through send_payment.
+    #[account(seeds = [b"accept", receiver.key().as_ref()], bump)]
+    pub receiver_acceptance: UncheckedAccount<'info>,
+    // Created by the first payment to this receiver
+    #[account(
+        init_if_needed,
+        payer = relayer,
+        space = ReceiverStats::LEN,
+        seeds = [b"received", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_stats: Account<'info, ReceiverStats>,
+    // Both ledgers are created by the wallet's first payment either way
+    #[account(
+        init_if_needed,
+        payer = relayer,
+        space = UserLedger::LEN,
+        seeds = [b"ledger", owner.key().as_ref()],
+        bump,
+        constraint = owner.key() != receiver.key() @ ErrorCode::SelfPayment
+    )]
+    pub sender_ledger: Box<Account<'info, UserLedger>>,
+    #[account(
+        init_if_needed,
+        payer = relayer,
+        space = UserLedger::LEN,
+        seeds = [b"ledger", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_ledger: Box<Account<'info, UserLedger>>,
+    /// CHECK: Required while `program_state.hook_program` is set; id and executability are checked in `invoke_payment_hook`.
+    pub hook_program: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMemo<'info> {
+    pub sender: Signer<'info>,
+    #[account(
+        mut,
+        constraint = transaction_record.current_owner() == sender.key() @ ErrorCode::UnauthorizedMemoEdit
+    )]
+    pub transaction_record: Account<'info, TransactionRecord>,
+    #[account(seeds = [b"state"], bump)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct AddBlacklist<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+    /// CHECK: The address being blocked from sending or receiving.
+    pub address: AccountInfo<'info>,
+    #[account(
+        init,
+        payer = authority,
+        space = Blacklist::LEN,
+        seeds = [b"blacklist", address.key().as_ref()],
+        bump
+    )]
+    pub blacklist: Account<'info, Blacklist>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveBlacklist<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"blacklist", blacklist.address.as_ref()],
+        bump = blacklist.bump
+    )]
+    pub blacklist: Account<'info, Blacklist>,
+}
+
+#[derive(Accounts)]
+pub struct FreezeUser<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+    /// CHECK: The user whose outgoing payments are put on hold.
+    pub user: AccountInfo<'info>,
+    #[account(
+        init,
+        payer = authority,
+        space = FreezeFlag::LEN,
+        seeds = [b"freeze", user.key().as_ref()],
+        bump
+    )]
+    pub freeze_flag: Account<'info, FreezeFlag>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UnfreezeUser<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"freeze", freeze_flag.user.as_ref()],
+        bump = freeze_flag.bump
+    )]
+    pub freeze_flag: Account<'info, FreezeFlag>,
+}
+
+#[derive(Accounts)]
+pub struct SetMemoPattern<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct ReclaimTimelockedPayment<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    #[account(
+        mut,
+        close = sender,
+        // Deriving with the signer's key means only the creator can reclaim
+        seeds = [b"timelock", sender.key().as_ref(), timelock.receiver.as_ref(), timelock.sequence.to_le_bytes().as_ref()],
+        bump = timelock.bump
+    )]
+    pub timelock: Account<'info, TimelockedPayment>,
+    // Works while paused
+    #[account(mut, seeds = [b"state"], bump)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveDelegate<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    /// CHECK: The third party allowed to spend from the owner's vault.
+    pub delegate: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = PaymentDelegate::LEN,
+        seeds = [b"delegate", owner.key().as_ref()],
+        bump
+    )]
+    pub payment_delegate: Account<'info, PaymentDelegate>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeDelegate<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"delegate", owner.key().as_ref()],
+        bump = payment_delegate.bump
+    )]
+    pub payment_delegate: Account<'info, PaymentDelegate>,
+}
+
+#[derive(Accounts)]
+pub struct SendPaymentAsDelegate<'info> {
+    // Fee payer for the transaction and rent payer for the record
+    #[account(mut)]
+    pub delegate: Signer<'info>,
+    /// CHECK: Owner of the vault and the grant; does not sign.
+    pub owner: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [b"delegate", owner.key().as_ref()],
+        bump = payment_delegate.bump,
+        constraint = payment_delegate.delegate == delegate.key() @ ErrorCode::NotAuthorizedDelegate
+    )]
+    pub payment_delegate: Account<'info, PaymentDelegate>,
+    #[account(mut, seeds = [b"vault", owner.key().as_ref()], bump)]
+    pub vault: SystemAccount<'info>,
+    /// CHECK: Receiver does not sign; ownership is checked in the handler.
+    #[account(mut)]
+    pub receiver: AccountInfo<'info>,
+    /// CHECK: Must match the fee collector configured on the program state.
+    #[account(mut, address = program_state.fee_collector @ ErrorCode::InvalidFeeCollector)]
+    pub fee_collector: AccountInfo<'info>,
+    // Fee split collectors, if configured, are passed via `remaining_accounts`
+    pub system_program: Program<'info, System>,
+    #[account(
+        init,
+        payer = delegate,
+        space = TransactionRecord::LEN,
+        seeds = [b"transaction", owner.key().as_ref(), user_state.transaction_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub transaction_record: Account<'info, TransactionRecord>,
+    #[account(mut, seeds = [b"user", owner.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        constraint = !program_state.paused @ ErrorCode::ProgramPaused
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    #[account(seeds = [b"whitelist", receiver.key().as_ref()], bump = whitelist_entry.bump)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
+    #[account(mut, seeds = [b"limit", owner.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", owner.key().as_ref()], bump)]
+    pub sender_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", receiver.key().as_ref()], bump)]
+    pub receiver_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds, so a frozen sender can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", owner.key().as_ref()], bump)]
+    pub sender_freeze: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; an `AcceptancePolicy` here means the receiver can only be paid through send_payment.
+    #[account(seeds = [b"accept", receiver.key().as_ref()], bump)]
+    pub receiver_acceptance: UncheckedAccount<'info>,
+    // Created by the first payment to this receiver
+    #[account(
+        init_if_needed,
+        payer = delegate,
+        space = ReceiverStats::LEN,
+        seeds = [b"received", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_stats: Account<'info, ReceiverStats>,
+    // Both ledgers are created by the wallet's first payment either way
+    #[account(
+        init_if_needed,
+        payer = delegate,
+        space = UserLedger::LEN,
+        seeds = [b"ledger", owner.key().as_ref()],
+        bump,
+        constraint = owner.key() != receiver.key() @ ErrorCode::SelfPayment
+    )]
+    pub sender_ledger: Box<Account<'info, UserLedger>>,
+    #[account(
+        init_if_needed,
+        payer = delegate,
+        space = UserLedger::LEN,
+        seeds = [b"ledger", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_ledger: Box<Account<'info, UserLedger>>,
+    /// CHECK: Required while `program_state.hook_program` is set; id and executability are checked in `invoke_payment_hook`.
+    pub hook_program: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct GrantFeeExemption<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+    /// CHECK: The partner sender being exempted.
+    pub sender: AccountInfo<'info>,
+    #[account(
+        init,
+        payer = authority,
+        space = FeeExemption::LEN,
+        seeds = [b"fee_exempt", sender.key().as_ref()],
+        bump
+    )]
+    pub fee_exemption: Account<'info, FeeExemption>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeFeeExemption<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"fee_exempt", fee_exemption.sender.as_ref()],
+        bump = fee_exemption.bump
+    )]
+    pub fee_exemption: Account<'info, FeeExemption>,
+}
+
+#[derive(Accounts)]
+pub struct BeginTeardown<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeTeardown<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        close = authority,
+        seeds = [b"state"],
+        bump,
+        has_one = authority @ ErrorCode::Unauthorized
+    )]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct CloseRecordsRange<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    // Records and their pending transfer addresses are passed via `remaining_accounts`
+}
+
+#[derive(Accounts)]
+pub struct SweepDust<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    // Records and their pending transfer addresses are passed via `remaining_accounts`
+}
+
+#[derive(Accounts)]
+pub struct MigrateRecord<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    /// CHECK: May be in an older layout that no longer deserializes as `TransactionRecord`;
+    /// ownership is checked here, discriminator and sender in the handler.
+    #[account(mut, owner = crate::ID)]
+    pub transaction_record: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateState<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    /// CHECK: May be in an older layout that no longer deserializes as `ProgramState`;
+    /// the seeds and owner are checked here, discriminator and authority in the handler.
+    #[account(mut, seeds = [b"state"], bump, owner = crate::ID)]
+    pub program_state: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    // Required only to claim a baseline state, which has no authority of its own
+    #[account(
+        seeds = [crate::ID.as_ref()],
+        bump,
+        seeds::program = anchor_lang::solana_program::bpf_loader_upgradeable::ID
+    )]
+    pub program_data: Option<Account<'info, ProgramData>>,
+}
+
+#[derive(Accounts)]
+pub struct GrowRecord<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    // Records in an older layout need migrate_record first
+    #[account(mut, constraint = transaction_record.current_owner() == sender.key() @ ErrorCode::Unauthorized)]
+    pub transaction_record: Box<Account<'info, TransactionRecord>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SendWrappedPayment<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    /// CHECK: Receiver wallet does not need to sign; it is only used as the
+    /// authority of the receiver's wSOL associated token account.
+    pub receiver: AccountInfo<'info>,
+    #[account(address = token::spl_token::native_mint::ID @ ErrorCode::InvalidMint)]
+    pub mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = sender,
+    )]
+    pub sender_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = receiver,
+    )]
+    pub receiver_token_account: Account<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = sender,
+        space = TransactionRecord::LEN,
+        seeds = [b"transaction", sender.key().as_ref(), user_state.transaction_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub transaction_record: Account<'info, TransactionRecord>,
+    #[account(mut, seeds = [b"user", sender.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        constraint = !program_state.paused @ ErrorCode::ProgramPaused
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", sender.key().as_ref()], bump)]
+    pub sender_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", receiver.key().as_ref()], bump)]
+    pub receiver_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds, so a frozen sender can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", sender.key().as_ref()], bump)]
+    pub sender_freeze: UncheckedAccount<'info>,
+    // Created by the first payment to this receiver
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = ReceiverStats::LEN,
+        seeds = [b"received", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_stats: Account<'info, ReceiverStats>,
+    // Required once the sender has set a daily limit
+    #[account(mut, seeds = [b"limit", sender.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+    // Only required while `program_state.whitelist_enabled` is set
+    #[account(seeds = [b"whitelist", receiver.key().as_ref()], bump = whitelist_entry.bump)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
+}
+
+#[derive(Accounts)]
+#[instruction(swap_id: u64)]
+pub struct AtomicSwap<'info> {
+    // Pays the SOL leg and the swap record's rent
+    #[account(mut)]
+    pub party_a: Signer<'info>,
+    // Pays the token leg
+    #[account(mut)]
+    pub party_b: Signer<'info>,
+    pub mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = party_a,
+    )]
+    pub party_a_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = party_b,
+    )]
+    pub party_b_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer = party_a,
+        space = SwapRecord::LEN,
+        seeds = [b"swap", party_a.key().as_ref(), party_b.key().as_ref(), swap_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub swap_record: Account<'info, SwapRecord>,
+    #[account(seeds = [b"state"], bump, constraint = !program_state.paused @ ErrorCode::ProgramPaused)]
+    pub program_state: Account<'info, ProgramState>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Address is pinned by the seeds, so a frozen party can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", party_a.key().as_ref()], bump)]
+    pub party_a_freeze: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds, so a frozen party can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", party_b.key().as_ref()], bump)]
+    pub party_b_freeze: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the swap if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", party_a.key().as_ref()], bump)]
+    pub party_a_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the swap if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", party_b.key().as_ref()], bump)]
+    pub party_b_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; an `AcceptancePolicy` here means the receiver can only be paid through send_payment.
+    #[account(seeds = [b"accept", party_a.key().as_ref()], bump)]
+    pub party_a_acceptance: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; an `AcceptancePolicy` here means the receiver can only be paid through send_payment.
+    #[account(seeds = [b"accept", party_b.key().as_ref()], bump)]
+    pub party_b_acceptance: UncheckedAccount<'info>,
+    // Only required while `program_state.whitelist_enabled` is set
+    #[account(seeds = [b"whitelist", party_a.key().as_ref()], bump = party_a_whitelist.bump)]
+    pub party_a_whitelist: Option<Account<'info, WhitelistEntry>>,
+    // Only required while `program_state.whitelist_enabled` is set
+    #[account(seeds = [b"whitelist", party_b.key().as_ref()], bump = party_b_whitelist.bump)]
+    pub party_b_whitelist: Option<Account<'info, WhitelistEntry>>,
+    // Holds `spend_limit_enabled`
+    #[account(seeds = [b"user", party_a.key().as_ref()], bump = party_a_user_state.bump)]
+    pub party_a_user_state: Account<'info, UserState>,
+    // Holds `spend_limit_enabled`
+    #[account(seeds = [b"user", party_b.key().as_ref()], bump = party_b_user_state.bump)]
+    pub party_b_user_state: Account<'info, UserState>,
+    // Required once party A has set a daily limit
+    #[account(mut, seeds = [b"limit", party_a.key().as_ref()], bump = party_a_spend_limit.bump)]
+    pub party_a_spend_limit: Option<Account<'info, SpendLimit>>,
+    // Required once party B has set a daily limit
+    #[account(mut, seeds = [b"limit", party_b.key().as_ref()], bump = party_b_spend_limit.bump)]
+    pub party_b_spend_limit: Option<Account<'info, SpendLimit>>,
+}
+
+#[derive(Accounts)]
+pub struct SetMaxMemoLength<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct SetCooldown<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct CreatePool<'info> {
+    #[account(mut)]
+    pub beneficiary: Signer<'info>,
+    #[account(
+        init,
+        payer = beneficiary,
+        space = Pool::LEN,
+        seeds = [b"pool", beneficiary.key().as_ref()],
+        bump
+    )]
+    pub pool: Account<'info, Pool>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Donate<'info> {
+    #[account(mut)]
+    pub donor: Signer<'info>,
+    #[account(mut, seeds = [b"pool", pool.beneficiary.as_ref()], bump = pool.bump)]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        init_if_needed,
+        payer = donor,
+        space = DonationRecord::LEN,
+        seeds = [b"donation", pool.key().as_ref(), donor.key().as_ref()],
+        bump
+    )]
+    pub donation_record: Account<'info, DonationRecord>,
+    #[account(seeds = [b"state"], bump, constraint = !program_state.paused @ ErrorCode::ProgramPaused)]
+    pub program_state: Account<'info, ProgramState>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Address is pinned by the seeds, so a frozen donor can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", donor.key().as_ref()], bump)]
+    pub donor_freeze: UncheckedAccount<'info>,
+    // Holds `spend_limit_enabled`
+    #[account(seeds = [b"user", donor.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    // Required once the donor has set a daily limit
+    #[account(mut, seeds = [b"limit", donor.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", donor.key().as_ref()], bump)]
+    pub donor_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", pool.beneficiary.as_ref()], bump)]
+    pub beneficiary_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; an `AcceptancePolicy` here means the receiver can only be paid through send_payment.
+    #[account(seeds = [b"accept", pool.beneficiary.as_ref()], bump)]
+    pub beneficiary_acceptance: UncheckedAccount<'info>,
+    // Only required while `program_state.whitelist_enabled` is set
+    #[account(seeds = [b"whitelist", pool.beneficiary.as_ref()], bump = whitelist_entry.bump)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawPool<'info> {
+    #[account(mut)]
+    pub beneficiary: Signer<'info>,
+    #[account(
+        mut,
+        // Deriving with the signer's key means only the beneficiary can withdraw
+        seeds = [b"pool", beneficiary.key().as_ref()],
+        bump = pool.bump
+    )]
+    pub pool: Account<'info, Pool>,
+    // No program state: a pool stays withdrawable through a pause and after teardown
+}
+
+#[derive(Accounts)]
+#[instruction(campaign_id: u64)]
+pub struct CreateCampaign<'info> {
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    #[account(
+        init,
+        payer = creator,
+        space = Campaign::LEN,
+        seeds = [b"campaign", creator.key().as_ref(), campaign_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub campaign: Account<'info, Campaign>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Contribute<'info> {
+    #[account(mut)]
+    pub backer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"campaign", campaign.creator.as_ref(), campaign.campaign_id.to_le_bytes().as_ref()],
+        bump = campaign.bump
+    )]
+    pub campaign: Account<'info, Campaign>,
+    #[account(
+        init_if_needed,
+        payer = backer,
+        space = Contribution::LEN,
+        seeds = [b"contribution", campaign.key().as_ref(), backer.key().as_ref()],
+        bump
+    )]
+    pub contribution: Account<'info, Contribution>,
+    #[account(seeds = [b"state"], bump, constraint = !program_state.paused @ ErrorCode::ProgramPaused)]
+    pub program_state: Account<'info, ProgramState>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Address is pinned by the seeds, so a frozen backer can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", backer.key().as_ref()], bump)]
+    pub backer_freeze: UncheckedAccount<'info>,
+    // Holds `spend_limit_enabled`
+    #[account(seeds = [b"user", backer.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    // Required once the backer has set a daily limit
+    #[account(mut, seeds = [b"limit", backer.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", backer.key().as_ref()], bump)]
+    pub backer_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", campaign.creator.as_ref()], bump)]
+    pub creator_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; an `AcceptancePolicy` here means the receiver can only be paid through send_payment.
+    #[account(seeds = [b"accept", campaign.creator.as_ref()], bump)]
+    pub creator_acceptance: UncheckedAccount<'info>,
+    // Only required while `program_state.whitelist_enabled` is set
+    #[account(seeds = [b"whitelist", campaign.creator.as_ref()], bump = whitelist_entry.bump)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimFunds<'info> {
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    #[account(
+        mut,
+        close = creator,
+        // Deriving with the signer's key means only the creator can claim
+        seeds = [b"campaign", creator.key().as_ref(), campaign.campaign_id.to_le_bytes().as_ref()],
+        bump = campaign.bump
+    )]
+    pub campaign: Account<'info, Campaign>,
+    // No program state: raised funds stay claimable through a pause and after teardown
+}
+
+#[derive(Accounts)]
+pub struct RefundContribution<'info> {
+    #[account(mut)]
+    pub backer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"campaign", campaign.creator.as_ref(), campaign.campaign_id.to_le_bytes().as_ref()],
+        bump = campaign.bump
+    )]
+    pub campaign: Account<'info, Campaign>,
+    #[account(
+        mut,
+        close = backer,
+        // Deriving with the signer's key means backers can only reclaim their own pledge
+        seeds = [b"contribution", campaign.key().as_ref(), backer.key().as_ref()],
+        bump = contribution.bump
+    )]
+    pub contribution: Account<'info, Contribution>,
+    // No program state: pledges stay refundable through a pause and after teardown
+}
+
+#[derive(Accounts)]
+pub struct ProposeAuthority<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    pub new_authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        constraint = program_state.pending_authority == Some(new_authority.key()) @ ErrorCode::NotPendingAuthority
+    )]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct CancelAuthorityTransfer<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct CloseRecentPayment<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    #[account(mut, close = sender, seeds = [b"dedup", sender.key().as_ref()], bump = recent_payment.bump)]
+    pub recent_payment: Account<'info, RecentPayment>,
+    #[account(seeds = [b"state"], bump)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct SetDedupWindow<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct SetFeeRounding<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct SetVerboseLogging<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct SetHookProgram<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut, seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct DisputeEscrow<'info> {
+    // Either the buyer (sender) or the seller (receiver)
+    #[account(
+        constraint = party.key() == escrow.sender || party.key() == escrow.receiver @ ErrorCode::UnauthorizedEscrowAction
+    )]
+    pub party: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow.sender.as_ref(), escrow.receiver.as_ref(), escrow.sequence.to_le_bytes().as_ref()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+    #[account(seeds = [b"state"], bump, constraint = !program_state.paused @ ErrorCode::ProgramPaused)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct ArbitrateEscrow<'info> {
+    // Pays the receiver's stats rent the first time an escrow is awarded to them
+    #[account(mut, address = escrow.arbiter @ ErrorCode::NotArbiter)]
+    pub arbiter: Signer<'info>,
+    /// CHECK: Must match the sender stored on the escrow; receives the rent, and the funds if awarded.
+    #[account(mut, address = escrow.sender)]
+    pub sender: AccountInfo<'info>,
+    /// CHECK: Must match the receiver stored on the escrow.
+    #[account(mut, address = escrow.receiver @ ErrorCode::InvalidReceiver)]
+    pub receiver: AccountInfo<'info>,
+    #[account(
+        mut,
+        close = sender,
+        seeds = [b"escrow", escrow.sender.as_ref(), escrow.receiver.as_ref(), escrow.sequence.to_le_bytes().as_ref()],
+        bump = escrow.bump
+    )]
+    pub escrow: Account<'info, Escrow>,
+    // Works while paused
+    #[account(mut, seeds = [b"state"], bump)]
+    pub program_state: Account<'info, ProgramState>,
+    /// CHECK: Must match the fee collector configured on the program state.
+    #[account(mut, address = program_state.fee_collector @ ErrorCode::InvalidFeeCollector)]
+    pub fee_collector: AccountInfo<'info>,
+    // Created by the first payment to this receiver
+    #[account(
+        init_if_needed,
+        payer = arbiter,
+        space = ReceiverStats::LEN,
+        seeds = [b"received", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_stats: Account<'info, ReceiverStats>,
+    pub system_program: Program<'info, System>,
+    // Fee split collectors, if any, are passed via `remaining_accounts`
+}
+
+// Read-only assertion over an existing record, cheap enough to call via CPI
+#[derive(Accounts)]
+pub struct VerifyTransaction<'info> {
+    pub transaction_record: Account<'info, TransactionRecord>,
+}
+
+#[derive(Accounts)]
+pub struct SendPaymentRefundable<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    /// CHECK: Receiver does not sign at creation; it must sign to finalize.
+    pub receiver: AccountInfo<'info>,
+    #[account(
+        init,
+        payer = sender,
+        space = RefundablePayment::LEN,
+        seeds = [b"refundable", sender.key().as_ref(), receiver.key().as_ref(), program_state.total_refundables.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub refundable: Account<'info, RefundablePayment>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        constraint = !program_state.paused @ ErrorCode::ProgramPaused
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", sender.key().as_ref()], bump)]
+    pub sender_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", receiver.key().as_ref()], bump)]
+    pub receiver_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds, so a frozen sender can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", sender.key().as_ref()], bump)]
+    pub sender_freeze: UncheckedAccount<'info>,
+    // Holds `spend_limit_enabled`
+    #[account(seeds = [b"user", sender.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    // Required once the sender has set a daily limit
+    #[account(mut, seeds = [b"limit", sender.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+    // Only required while `program_state.whitelist_enabled` is set
+    #[account(seeds = [b"whitelist", receiver.key().as_ref()], bump = whitelist_entry.bump)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeRefundable<'info> {
+    #[account(mut, address = refundable.receiver @ ErrorCode::InvalidReceiver)]
+    pub receiver: Signer<'info>,
+    /// CHECK: Must match the sender stored on the payment; receives the rent back.
+    #[account(mut, address = refundable.sender)]
+    pub sender: AccountInfo<'info>,
+    #[account(
+        mut,
+        close = sender,
+        seeds = [b"refundable", refundable.sender.as_ref(), refundable.receiver.as_ref(), refundable.sequence.to_le_bytes().as_ref()],
+        bump = refundable.bump
+    )]
+    pub refundable: Account<'info, RefundablePayment>,
+    // Works while paused
+    #[account(mut, seeds = [b"state"], bump)]
+    pub program_state: Account<'info, ProgramState>,
+    // Created by the first payment to this receiver
+    #[account(
+        init_if_needed,
+        payer = receiver,
+        space = ReceiverStats::LEN,
+        seeds = [b"received", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_stats: Account<'info, ReceiverStats>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelRefundable<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    #[account(
+        mut,
+        close = sender,
+        // Deriving with the signer's key means only the creator can cancel
+        seeds = [b"refundable", sender.key().as_ref(), refundable.receiver.as_ref(), refundable.sequence.to_le_bytes().as_ref()],
+        bump = refundable.bump
+    )]
+    pub refundable: Account<'info, RefundablePayment>,
+    // Works while paused
+    #[account(mut, seeds = [b"state"], bump)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct SchedulePayment<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    /// CHECK: Receiver never signs; it only needs to accept SOL on execution.
+    pub receiver: AccountInfo<'info>,
+    #[account(
+        init,
+        payer = sender,
+        space = ScheduledPayment::LEN,
+        seeds = [b"scheduled", sender.key().as_ref(), receiver.key().as_ref(), program_state.total_scheduled.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub scheduled_payment: Account<'info, ScheduledPayment>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        constraint = !program_state.paused @ ErrorCode::ProgramPaused
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", sender.key().as_ref()], bump)]
+    pub sender_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", receiver.key().as_ref()], bump)]
+    pub receiver_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds, so a frozen sender can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", sender.key().as_ref()], bump)]
+    pub sender_freeze: UncheckedAccount<'info>,
+    // Holds `spend_limit_enabled`
+    #[account(seeds = [b"user", sender.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    // Required once the sender has set a daily limit
+    #[account(mut, seeds = [b"limit", sender.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+    // Only required while `program_state.whitelist_enabled` is set
+    #[account(seeds = [b"whitelist", receiver.key().as_ref()], bump = whitelist_entry.bump)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteScheduled<'info> {
+    #[account(mut)]
+    pub cranker: Signer<'info>,
+    /// CHECK: Must match the receiver stored on the scheduled payment.
+    #[account(mut, address = scheduled_payment.receiver @ ErrorCode::InvalidReceiver)]
+    pub receiver: AccountInfo<'info>,
+    /// CHECK: Must match the sender stored on the scheduled payment; receives the rent back.
+    #[account(mut, address = scheduled_payment.sender)]
+    pub sender: AccountInfo<'info>,
+    #[account(
+        mut,
+        close = sender,
+        seeds = [b"scheduled", scheduled_payment.sender.as_ref(), scheduled_payment.receiver.as_ref(), scheduled_payment.sequence.to_le_bytes().as_ref()],
+        bump = scheduled_payment.bump
+    )]
+    pub scheduled_payment: Account<'info, ScheduledPayment>,
+    // Works while paused
+    #[account(mut, seeds = [b"state"], bump)]
+    pub program_state: Account<'info, ProgramState>,
+    // Created by the first payment to this receiver
+    #[account(
+        init_if_needed,
+        payer = cranker,
+        space = ReceiverStats::LEN,
+        seeds = [b"received", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_stats: Account<'info, ReceiverStats>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RequireAcceptance<'info> {
+    #[account(mut)]
+    pub receiver: Signer<'info>,
+    #[account(
+        init,
+        payer = receiver,
+        space = AcceptancePolicy::LEN,
+        seeds = [b"accept", receiver.key().as_ref()],
+        bump
+    )]
+    pub acceptance_policy: Account<'info, AcceptancePolicy>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DisableAcceptance<'info> {
+    #[account(mut)]
+    pub receiver: Signer<'info>,
+    #[account(
+        mut,
+        close = receiver,
+        seeds = [b"accept", receiver.key().as_ref()],
+        bump = acceptance_policy.bump
+    )]
+    pub acceptance_policy: Account<'info, AcceptancePolicy>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptTransfer<'info> {
+    #[account(mut, address = pending_transfer.receiver @ ErrorCode::InvalidReceiver)]
+    pub receiver: Signer<'info>,
+    /// CHECK: Must match the sender stored on the pending transfer; receives the rent back.
+    #[account(mut, address = pending_transfer.sender)]
+    pub sender: AccountInfo<'info>,
+    #[account(
+        mut,
+        close = sender,
+        seeds = [b"pending", pending_transfer.sender.as_ref(), pending_transfer.sequence.to_le_bytes().as_ref()],
+        bump = pending_transfer.bump
+    )]
+    pub pending_transfer: Account<'info, PendingTransfer>,
+    // Created by send_payment alongside the pending transfer
+    #[account(mut, seeds = [b"received", receiver.key().as_ref()], bump = receiver_stats.bump)]
+    pub receiver_stats: Account<'info, ReceiverStats>,
+    #[account(mut, seeds = [b"ledger", receiver.key().as_ref()], bump = receiver_ledger.bump)]
+    pub receiver_ledger: Account<'info, UserLedger>,
+    // Works while paused
+    #[account(mut, seeds = [b"state"], bump)]
+    pub program_state: Account<'info, ProgramState>,
+    /// CHECK: Must match the fee collector configured on the program state.
+    #[account(mut, address = program_state.fee_collector @ ErrorCode::InvalidFeeCollector)]
+    pub fee_collector: AccountInfo<'info>,
+    // Fee split collectors, if configured, are passed via `remaining_accounts`
+}
+
+#[derive(Accounts)]
+pub struct RejectTransfer<'info> {
+    #[account(address = pending_transfer.receiver @ ErrorCode::InvalidReceiver)]
+    pub receiver: Signer<'info>,
+    /// CHECK: Must match the sender stored on the pending transfer; receives the funds and rent back.
+    #[account(mut, address = pending_transfer.sender)]
+    pub sender: AccountInfo<'info>,
+    #[account(
+        mut,
+        close = sender,
+        seeds = [b"pending", pending_transfer.sender.as_ref(), pending_transfer.sequence.to_le_bytes().as_ref()],
+        bump = pending_transfer.bump
+    )]
+    pub pending_transfer: Account<'info, PendingTransfer>,
+    #[account(
+        mut,
+        seeds = [b"transaction", pending_transfer.sender.as_ref(), pending_transfer.sequence.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub transaction_record: Box<Account<'info, TransactionRecord>>,
+    #[account(mut, seeds = [b"ledger", pending_transfer.sender.as_ref()], bump = sender_ledger.bump)]
+    pub sender_ledger: Account<'info, UserLedger>,
+    // Works while paused
+    #[account(mut, seeds = [b"state"], bump)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct ReclaimTransfer<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    #[account(
+        mut,
+        close = sender,
+        // Deriving with the signer's key means only the sender can reclaim
+        seeds = [b"pending", sender.key().as_ref(), pending_transfer.sequence.to_le_bytes().as_ref()],
+        bump = pending_transfer.bump
+    )]
+    pub pending_transfer: Account<'info, PendingTransfer>,
+    #[account(
+        mut,
+        seeds = [b"transaction", sender.key().as_ref(), pending_transfer.sequence.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub transaction_record: Box<Account<'info, TransactionRecord>>,
+    #[account(mut, seeds = [b"ledger", sender.key().as_ref()], bump = sender_ledger.bump)]
+    pub sender_ledger: Account<'info, UserLedger>,
+    // Works while paused
+    #[account(mut, seeds = [b"state"], bump)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct TransferRecordOwnership<'info> {
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        constraint = transaction_record.current_owner() == owner.key() @ ErrorCode::NotRecordOwner
+    )]
+    pub transaction_record: Box<Account<'info, TransactionRecord>>,
+}
+
+#[derive(Accounts)]
+#[instruction(template_id: u64)]
+pub struct CreateMemoTemplate<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        init,
+        payer = owner,
+        space = MemoTemplate::LEN,
+        seeds = [b"template", owner.key().as_ref(), template_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub memo_template: Account<'info, MemoTemplate>,
+    pub system_program: Program<'info, System>,
+    #[account(seeds = [b"state"], bump)]
+    pub program_state: Account<'info, ProgramState>,
+}
+
+#[derive(Accounts)]
+pub struct CloseMemoTemplate<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        close = owner,
+        seeds = [b"template", owner.key().as_ref(), memo_template.template_id.to_le_bytes().as_ref()],
+        bump = memo_template.bump
+    )]
+    pub memo_template: Account<'info, MemoTemplate>,
+}
+
+#[derive(Accounts)]
+#[instruction(template_id: u64)]
+pub struct SendPaymentFromTemplate<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    /// CHECK: Receiver of the payment; ownership is checked in the handler.
+    #[account(mut)]
+    pub receiver: AccountInfo<'info>,
+    /// CHECK: Must match the fee collector configured on the program state.
+    #[account(mut, address = program_state.fee_collector @ ErrorCode::InvalidFeeCollector)]
+    pub fee_collector: AccountInfo<'info>,
+    // Fee split collectors, if configured, are passed via `remaining_accounts`
+    // Senders can only use their own templates
+    #[account(seeds = [b"template", sender.key().as_ref(), template_id.to_le_bytes().as_ref()], bump = memo_template.bump)]
+    pub memo_template: Account<'info, MemoTemplate>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Created by the handler once the payment's checks pass; the seeds pin it to the sender's next sequence.
+    #[account(mut, seeds = [b"transaction", sender.key().as_ref(), user_state.transaction_count.to_le_bytes().as_ref()], bump)]
+    pub transaction_record: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"user", sender.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        constraint = !program_state.paused @ ErrorCode::ProgramPaused
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    #[account(seeds = [b"whitelist", receiver.key().as_ref()], bump = whitelist_entry.bump)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
+    #[account(mut, seeds = [b"limit", sender.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", sender.key().as_ref()], bump)]
+    pub sender_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", receiver.key().as_ref()], bump)]
+    pub receiver_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds, so a frozen sender can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", sender.key().as_ref()], bump)]
+    pub sender_freeze: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; an `AcceptancePolicy` here means the receiver can only be paid through send_payment.
+    #[account(seeds = [b"accept", receiver.key().as_ref()], bump)]
+    pub receiver_acceptance: UncheckedAccount<'info>,
+    // Created by the first payment to this receiver
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = ReceiverStats::LEN,
+        seeds = [b"received", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_stats: Account<'info, ReceiverStats>,
+    // Both ledgers are created by the wallet's first payment either way
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = UserLedger::LEN,
+        seeds = [b"ledger", sender.key().as_ref()],
+        bump,
+        constraint = sender.key() != receiver.key() @ ErrorCode::SelfPayment
+    )]
+    pub sender_ledger: Box<Account<'info, UserLedger>>,
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = UserLedger::LEN,
+        seeds = [b"ledger", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_ledger: Box<Account<'info, UserLedger>>,
+    /// CHECK: Required while `program_state.hook_program` is set; id and executability are checked in `invoke_payment_hook`.
+    pub hook_program: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct SendPaymentRaw<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    /// CHECK: Receiver of the payment; ownership is checked in the handler.
+    #[account(mut)]
+    pub receiver: AccountInfo<'info>,
+    /// CHECK: Must match the fee collector configured on the program state.
+    #[account(mut, address = program_state.fee_collector @ ErrorCode::InvalidFeeCollector)]
+    pub fee_collector: AccountInfo<'info>,
+    // Fee split collectors, if configured, are passed via `remaining_accounts`
+    pub system_program: Program<'info, System>,
+    /// CHECK: Created by the handler once the payment's checks pass; the seeds pin it to the sender's next sequence.
+    #[account(mut, seeds = [b"transaction", sender.key().as_ref(), user_state.transaction_count.to_le_bytes().as_ref()], bump)]
+    pub transaction_record: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"user", sender.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        constraint = !program_state.paused @ ErrorCode::ProgramPaused
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    /// CHECK: Address is pinned by the seeds, so a frozen sender can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", sender.key().as_ref()], bump)]
+    pub sender_freeze: UncheckedAccount<'info>,
+    #[account(seeds = [b"whitelist", receiver.key().as_ref()], bump = whitelist_entry.bump)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
+    #[account(mut, seeds = [b"limit", sender.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", sender.key().as_ref()], bump)]
+    pub sender_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", receiver.key().as_ref()], bump)]
+    pub receiver_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; an `AcceptancePolicy` here means the receiver can only be paid through send_payment.
+    #[account(seeds = [b"accept", receiver.key().as_ref()], bump)]
+    pub receiver_acceptance: UncheckedAccount<'info>,
+    // Created by the first payment to this receiver
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = ReceiverStats::LEN,
+        seeds = [b"received", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_stats: Account<'info, ReceiverStats>,
+    // Both ledgers are created by the wallet's first payment either way
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = UserLedger::LEN,
+        seeds = [b"ledger", sender.key().as_ref()],
+        bump,
+        constraint = sender.key() != receiver.key() @ ErrorCode::SelfPayment
+    )]
+    pub sender_ledger: Box<Account<'info, UserLedger>>,
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = UserLedger::LEN,
+        seeds = [b"ledger", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_ledger: Box<Account<'info, UserLedger>>,
+    /// CHECK: Required while `program_state.hook_program` is set; id and executability are checked in `invoke_payment_hook`.
+    pub hook_program: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct SendPaymentCompact<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    /// CHECK: Receiver of the payment; ownership is checked in the handler.
+    #[account(mut)]
+    pub receiver: AccountInfo<'info>,
+    /// CHECK: Must match the fee collector configured on the program state.
+    #[account(mut, address = program_state.fee_collector @ ErrorCode::InvalidFeeCollector)]
+    pub fee_collector: AccountInfo<'info>,
+    // Fee split collectors, if configured, are passed via `remaining_accounts`
+    pub system_program: Program<'info, System>,
+    // Created by the sender's first compact payment
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = ReceiptCounter::LEN,
+        seeds = [b"receipt_counter", sender.key().as_ref()],
+        bump
+    )]
+    pub receipt_counter: Account<'info, ReceiptCounter>,
+    /// CHECK: Created by the handler once the payment's checks pass; the seeds pin it to the sender's next receipt index.
+    #[account(mut, seeds = [b"receipt", sender.key().as_ref(), receipt_counter.count.to_le_bytes().as_ref()], bump)]
+    pub compact_receipt: UncheckedAccount<'info>,
+    #[account(seeds = [b"user", sender.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        constraint = !program_state.paused @ ErrorCode::ProgramPaused
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    /// CHECK: Address is pinned by the seeds, so a frozen sender can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", sender.key().as_ref()], bump)]
+    pub sender_freeze: UncheckedAccount<'info>,
+    #[account(seeds = [b"whitelist", receiver.key().as_ref()], bump = whitelist_entry.bump)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
+    #[account(mut, seeds = [b"limit", sender.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", sender.key().as_ref()], bump)]
+    pub sender_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", receiver.key().as_ref()], bump)]
+    pub receiver_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; an `AcceptancePolicy` here means the receiver can only be paid through send_payment.
+    #[account(seeds = [b"accept", receiver.key().as_ref()], bump)]
+    pub receiver_acceptance: UncheckedAccount<'info>,
+    // Created by the first payment to this receiver
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = ReceiverStats::LEN,
+        seeds = [b"received", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_stats: Account<'info, ReceiverStats>,
+    // Both ledgers are created by the wallet's first payment either way
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = UserLedger::LEN,
+        seeds = [b"ledger", sender.key().as_ref()],
+        bump,
+        constraint = sender.key() != receiver.key() @ ErrorCode::SelfPayment
+    )]
+    pub sender_ledger: Box<Account<'info, UserLedger>>,
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = UserLedger::LEN,
+        seeds = [b"ledger", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_ledger: Box<Account<'info, UserLedger>>,
+    /// CHECK: Required while `program_state.hook_program` is set; id and executability are checked in `invoke_payment_hook`.
+    pub hook_program: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct SendUsdPayment<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    /// CHECK: Receiver of the payment; ownership is checked in the handler.
+    #[account(mut)]
+    pub receiver: AccountInfo<'info>,
+    /// CHECK: Must match the fee collector configured on the program state.
+    #[account(mut, address = program_state.fee_collector @ ErrorCode::InvalidFeeCollector)]
+    pub fee_collector: AccountInfo<'info>,
+    // Fee split collectors, if configured, are passed via `remaining_accounts`
+    /// CHECK: Pyth `PriceUpdateV2` account for SOL/USD; owner, discriminator, feed and staleness are checked in the handler.
+    pub price_update: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Created by the handler once the payment's checks pass; the seeds pin it to the sender's next sequence.
+    #[account(mut, seeds = [b"transaction", sender.key().as_ref(), user_state.transaction_count.to_le_bytes().as_ref()], bump)]
+    pub transaction_record: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"user", sender.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        constraint = !program_state.paused @ ErrorCode::ProgramPaused
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    /// CHECK: Address is pinned by the seeds, so a frozen sender can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", sender.key().as_ref()], bump)]
+    pub sender_freeze: UncheckedAccount<'info>,
+    #[account(seeds = [b"whitelist", receiver.key().as_ref()], bump = whitelist_entry.bump)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
+    #[account(mut, seeds = [b"limit", sender.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", sender.key().as_ref()], bump)]
+    pub sender_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", receiver.key().as_ref()], bump)]
+    pub receiver_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; an `AcceptancePolicy` here means the receiver can only be paid through send_payment.
+    #[account(seeds = [b"accept", receiver.key().as_ref()], bump)]
+    pub receiver_acceptance: UncheckedAccount<'info>,
+    // Created by the first payment to this receiver
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = ReceiverStats::LEN,
+        seeds = [b"received", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_stats: Account<'info, ReceiverStats>,
+    // Both ledgers are created by the wallet's first payment either way
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = UserLedger::LEN,
+        seeds = [b"ledger", sender.key().as_ref()],
+        bump,
+        constraint = sender.key() != receiver.key() @ ErrorCode::SelfPayment
+    )]
+    pub sender_ledger: Box<Account<'info, UserLedger>>,
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = UserLedger::LEN,
+        seeds = [b"ledger", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_ledger: Box<Account<'info, UserLedger>>,
+    /// CHECK: Required while `program_state.hook_program` is set; id and executability are checked in `invoke_payment_hook`.
+    pub hook_program: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct PrepareRecord<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    // Same address send_payment would create for the sender's next payment
+    #[account(
+        init,
+        payer = sender,
+        space = TransactionRecord::LEN,
+        seeds = [b"transaction", sender.key().as_ref(), user_state.transaction_count.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub transaction_record: Account<'info, TransactionRecord>,
+    #[account(seeds = [b"user", sender.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SendPaymentPrepared<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    /// CHECK: Receiver of the payment; ownership is checked in the handler.
+    #[account(mut)]
+    pub receiver: AccountInfo<'info>,
+    /// CHECK: Must match the fee collector configured on the program state.
+    #[account(mut, address = program_state.fee_collector @ ErrorCode::InvalidFeeCollector)]
+    pub fee_collector: AccountInfo<'info>,
+    // Fee split collectors, if configured, are passed via `remaining_accounts`
+    pub system_program: Program<'info, System>,
+    // Created by prepare_record. The seeds pin it to the sender's next sequence, and
+    // a record already written has a timestamp
+    #[account(
+        mut,
+        seeds = [b"transaction", sender.key().as_ref(), user_state.transaction_count.to_le_bytes().as_ref()],
+        bump,
+        constraint = transaction_record.timestamp == 0 @ ErrorCode::RecordNotPrepared
+    )]
+    pub transaction_record: Account<'info, TransactionRecord>,
+    #[account(mut, seeds = [b"user", sender.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        constraint = !program_state.paused @ ErrorCode::ProgramPaused
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    /// CHECK: Address is pinned by the seeds, so a frozen sender can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", sender.key().as_ref()], bump)]
+    pub sender_freeze: UncheckedAccount<'info>,
+    #[account(seeds = [b"whitelist", receiver.key().as_ref()], bump = whitelist_entry.bump)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
+    #[account(mut, seeds = [b"limit", sender.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", sender.key().as_ref()], bump)]
+    pub sender_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", receiver.key().as_ref()], bump)]
+    pub receiver_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; an `AcceptancePolicy` here means the receiver can only be paid through send_payment.
+    #[account(seeds = [b"accept", receiver.key().as_ref()], bump)]
+    pub receiver_acceptance: UncheckedAccount<'info>,
+    // Created by the first payment to this receiver
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = ReceiverStats::LEN,
+        seeds = [b"received", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_stats: Account<'info, ReceiverStats>,
+    // Both ledgers are created by the wallet's first payment either way
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = UserLedger::LEN,
+        seeds = [b"ledger", sender.key().as_ref()],
+        bump,
+        constraint = sender.key() != receiver.key() @ ErrorCode::SelfPayment
+    )]
+    pub sender_ledger: Box<Account<'info, UserLedger>>,
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = UserLedger::LEN,
+        seeds = [b"ledger", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_ledger: Box<Account<'info, UserLedger>>,
+    /// CHECK: Required while `program_state.hook_program` is set; id and executability are checked in `invoke_payment_hook`.
+    pub hook_program: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+#[instruction(code: u16)]
+pub struct RegisterMemo<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(seeds = [b"state"], bump, has_one = authority @ ErrorCode::Unauthorized)]
+    pub program_state: Account<'info, ProgramState>,
+    #[account(
+        init,
+        payer = authority,
+        space = MemoDictionary::LEN,
+        seeds = [b"memo_dict", code.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub memo_dictionary: Account<'info, MemoDictionary>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(amount: u64, memo_code: u16)]
+pub struct SendPaymentCoded<'info> {
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    /// CHECK: Receiver of the payment; ownership is checked in the handler.
+    #[account(mut)]
+    pub receiver: AccountInfo<'info>,
+    /// CHECK: Must match the fee collector configured on the program state.
+    #[account(mut, address = program_state.fee_collector @ ErrorCode::InvalidFeeCollector)]
+    pub fee_collector: AccountInfo<'info>,
+    // Fee split collectors, if configured, are passed via `remaining_accounts`
+    #[account(seeds = [b"memo_dict", memo_code.to_le_bytes().as_ref()], bump = memo_dictionary.bump)]
+    pub memo_dictionary: Account<'info, MemoDictionary>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: Created by the handler once the payment's checks pass; the seeds pin it to the sender's next sequence.
+    #[account(mut, seeds = [b"transaction", sender.key().as_ref(), user_state.transaction_count.to_le_bytes().as_ref()], bump)]
+    pub transaction_record: UncheckedAccount<'info>,
+    #[account(mut, seeds = [b"user", sender.key().as_ref()], bump = user_state.bump)]
+    pub user_state: Account<'info, UserState>,
+    #[account(
+        mut,
+        seeds = [b"state"],
+        bump,
+        constraint = !program_state.paused @ ErrorCode::ProgramPaused
+    )]
+    pub program_state: Account<'info, ProgramState>,
+    /// CHECK: Address is pinned by the seeds, so a frozen sender can't leave it out; the handler rejects the payment if a `FreezeFlag` lives there.
+    #[account(seeds = [b"freeze", sender.key().as_ref()], bump)]
+    pub sender_freeze: UncheckedAccount<'info>,
+    #[account(seeds = [b"whitelist", receiver.key().as_ref()], bump = whitelist_entry.bump)]
+    pub whitelist_entry: Option<Account<'info, WhitelistEntry>>,
+    #[account(mut, seeds = [b"limit", sender.key().as_ref()], bump = spend_limit.bump)]
+    pub spend_limit: Option<Account<'info, SpendLimit>>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", sender.key().as_ref()], bump)]
+    pub sender_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; the handler rejects the payment if a `Blacklist` lives there.
+    #[account(seeds = [b"blacklist", receiver.key().as_ref()], bump)]
+    pub receiver_blacklist: UncheckedAccount<'info>,
+    /// CHECK: Address is pinned by the seeds; an `AcceptancePolicy` here means the receiver can only be paid through send_payment.
+    #[account(seeds = [b"accept", receiver.key().as_ref()], bump)]
+    pub receiver_acceptance: UncheckedAccount<'info>,
+    // Created by the first payment to this receiver
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = ReceiverStats::LEN,
+        seeds = [b"received", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_stats: Account<'info, ReceiverStats>,
+    // Both ledgers are created by the wallet's first payment either way
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = UserLedger::LEN,
+        seeds = [b"ledger", sender.key().as_ref()],
+        bump,
+        constraint = sender.key() != receiver.key() @ ErrorCode::SelfPayment
+    )]
+    pub sender_ledger: Box<Account<'info, UserLedger>>,
+    #[account(
+        init_if_needed,
+        payer = sender,
+        space = UserLedger::LEN,
+        seeds = [b"ledger", receiver.key().as_ref()],
+        bump
+    )]
+    pub receiver_ledger: Box<Account<'info, UserLedger>>,
+    /// CHECK: Required while `program_state.hook_program` is set; id and executability are checked in `invoke_payment_hook`.
+    pub hook_program: Option<UncheckedAccount<'info>>,
+}
+
+// Account to store payment details (example structure)
+#[account]
+#[derive(Default)]
+pub struct TransactionRecord {
+    pub sender: Pubkey,
+    pub receiver: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+    pub memo: String, // Store the memo
+    // Appended after the memo so records created before this field existed
+    // still deserialize (the zeroed tail reads back as `None`).
+    pub mint: Option<Pubkey>, // SPL token mint, `None` for native SOL payments
+    pub receiver_count: u8, // Number of receivers paid (>1 for split payments, 0 on older records)
+    pub fee: u64, // Protocol fee taken out of `amount`
+    pub memo_encrypted: bool, // True when the memo is carried in `encrypted_memo` instead of `memo`
+    pub encrypted_memo: Vec<u8>, // Client-side ECIES ciphertext readable by the receiver; never decrypted on-chain
+    pub refund_of: Option<u64>, // Set on refunds: sequence of the refunded payment in the receiver's history
+    pub refunded: u64, // Total refunded against this payment so far
+    pub reference: Option<[u8; 32]>, // Client-supplied reconciliation key, e.g. a hash of an off-chain order id
+    pub tip: u64, // Gratuity paid on top of `amount`, not subject to the protocol fee
+    pub tip_recipient: Option<Pubkey>, // Who received the tip, `None` when no tip was given
+    pub fee_exempt: bool, // The sender held a FeeExemption, so no protocol fee was charged
+    pub schema_version: u8, // Layout version, see TRANSACTION_RECORD_VERSION; 0 = written before versioning
+    pub prev_sequence: Option<u64>, // Sender's previous record, `None` for their first
+    pub category: u8, // PaymentCategory discriminant; records from other instructions stay Generic
+    pub owner: Pubkey, // Holder of the receipt, who may close it or edit its memo; see `current_owner`
+    pub note: String, // Sender's own bookkeeping note, empty = none. Stored in the clear like the memo
+    pub mint_decimals: u8, // Decimals of `mint` for displaying `amount`; 0 for native SOL payments
+    pub data: Vec<u8>, // Opaque binary reference from `send_payment_raw` (e.g. a 32-byte hash), empty = none
+    pub memo_code: Option<u16>, // MemoDictionary code standing in for `memo`, set by `send_payment_coded`
+    // Add other relevant fields like transaction signature, sequence number etc.
+}
+
+// Define space for TransactionRecord - adjust based on actual fields
+const DISCRIMINATOR_LENGTH: usize = 8;
+const PUBLIC_KEY_LENGTH: usize = 32;
+const U64_LENGTH: usize = 8;
+const I64_LENGTH: usize = 8;
+const STRING_LENGTH_PREFIX: usize = 4; // Stores the size of the string
+const VEC_LENGTH_PREFIX: usize = 4; // Stores the number of elements in a Vec
+const REFERENCE_LENGTH: usize = 32;
+const FEED_ID_LENGTH: usize = 32;
+const HASH_LENGTH: usize = 32;
+const IDEMPOTENCY_KEY_LENGTH: usize = 16;
+const OPTION_PREFIX: usize = 1; // Borsh tag for Option<T>
+const U8_LENGTH: usize = 1;
+const BOOL_LENGTH: usize = 1;
+const U16_LENGTH: usize = 2;
+const MAX_MEMO_LENGTH: usize = 200; // Max length of memo string in characters
+const MAX_MEMO_BYTES: usize = MAX_MEMO_LENGTH * 4; // Max length in bytes (assuming worst-case 4 bytes per char)
+const MAX_NOTE_LENGTH: usize = 100; // Max length of the sender's note in characters
+const MAX_NOTE_BYTES: usize = MAX_NOTE_LENGTH * 4;
+const MAX_ENCRYPTED_MEMO_BYTES: usize = 256; // Ciphertext incl. ephemeral key, nonce and tag
+const MAX_RAW_DATA_BYTES: usize = 64; // Room for a hash plus a small tag; see `send_payment_raw`
+const BPS_DENOMINATOR: u64 = 10_000;
+// Compute estimates for split/batch payments against the default per-instruction
+// limit. Each transfer covers the System Program CPI plus per-leg validation (memo
+// scan, policy PDAs, receiver stats), and the base covers account deserialization,
+// the record's CreateAccount CPI and logging. The `*_stays_within_its_compute_estimate`
+// tests in tests/program_test.rs meter these on the SBF build and fail when a
+// measurement falls outside 80-100% of its estimate, so update a figure from their
+// output whenever the program changes.
+const DEFAULT_COMPUTE_UNIT_LIMIT: u64 = 200_000;
+#[constant]
+pub const SPLIT_BASE_CU_ESTIMATE: u64 = 45_000;
+#[constant]
+pub const BATCH_BASE_CU_ESTIMATE: u64 = 45_000;
+#[constant]
+pub const CU_PER_TRANSFER_ESTIMATE: u64 = 7_500;
+// Most receivers one send_split_payment accepts, and most entries one
+// send_batch_payment accepts: as many transfers as the estimates above fit within
+// the default compute limit
+#[constant]
+pub const MAX_SPLIT_RECEIVERS: usize = max_transfers_for_compute_budget(SPLIT_BASE_CU_ESTIMATE);
+#[constant]
+pub const MAX_BATCH_SIZE: usize = max_transfers_for_compute_budget(BATCH_BASE_CU_ESTIMATE);
+// Expected compute units of the other common instructions, exported (and in the IDL)
+// so clients can size a ComputeBudget request. These are upper bounds for a first
+// payment, where every init_if_needed account is still created, with verbose logging
+// off, held to the same 80-100% band by tests/program_test.rs (on the SBF build) and
+// tests/my_first_transaction.ts.
+#[constant]
+pub const SEND_PAYMENT_CU_ESTIMATE: u64 = 90_000;
+#[constant]
+pub const CREATE_ESCROW_CU_ESTIMATE: u64 = 25_000;
+#[constant]
+pub const RELEASE_ESCROW_CU_ESTIMATE: u64 = 40_000;
+const MAX_USER_INIT_BATCH: usize = 10; // Per batch_initialize_users call
+const MAX_CLOSE_RANGE: usize = 10; // One PDA derivation per record keeps bulk closes within compute limits
+const DUST_THRESHOLD_LAMPORTS: u64 = 100_000; // sweep_dust closes records for payments below this
+const SPEND_LIMIT_WINDOW_SECS: i64 = 86_400; // 24 hours
+const MAX_APPROVERS: usize = 5;
+const MAX_FEE_COLLECTORS: usize = 5; // One fee transfer CPI each in send_payment
+const MAX_MEMO_PREFIX_BYTES: usize = 16;
+const TEARDOWN_DELAY_SECS: i64 = 7 * 86_400; // 7 days between begin_teardown and finalize_teardown
+const TX_FEE_ESTIMATE_LAMPORTS: u64 = 5_000; // Base fee for a single-signature transaction
+const ESCROW_RECOVERY_DELAY_SECS: i64 = 90 * 86_400; // 90 days after creation before admin_recover_escrow
+const MEMO_EDIT_WINDOW_SECS: i64 = 300; // Senders may fix a memo for 5 minutes after sending
+const PENDING_TRANSFER_TIMEOUT_SECS: i64 = 7 * 86_400; // Receivers have 7 days before the sender may reclaim
+const CONDITIONAL_RECLAIM_DELAY_SECS: i64 = 7 * 86_400; // Conditional payments can be executed for 7 days before a reclaim
+// Pyth Solana Receiver program, owner of `PriceUpdateV2` accounts
+const PYTH_RECEIVER_PROGRAM_ID: Pubkey = anchor_lang::solana_program::pubkey!("rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ");
+const PRICE_UPDATE_V2_DISCRIMINATOR: [u8; 8] = [34, 241, 35, 99, 157, 126, 244, 205]; // sha256("account:PriceUpdateV2")[..8]
+const MAX_PRICE_AGE_SECS: i64 = 60;
+// Pyth SOL/USD feed, the only price `send_usd_payment` converts with
+const SOL_USD_FEED_ID: [u8; 32] = [
+    0xef, 0x0d, 0x8b, 0x6f, 0xda, 0x2c, 0xeb, 0xa4, 0x1d, 0xa1, 0x5d, 0x40, 0x95, 0xd1, 0xda, 0x39,
+    0x2a, 0x0d, 0x2f, 0x8e, 0xd0, 0xc6, 0xc7, 0xbc, 0x0f, 0x4c, 0xfa, 0xc8, 0xc2, 0x80, 0xb5, 0x6d,
+];
+const USD_AMOUNT_DECIMALS: u32 = 6; // `send_usd_payment` amounts are in micro-USD
+// Hook programs implement an Anchor-style `on_payment(amount: u64)` instruction
+// receiving `[sender, receiver]` as read-only, non-signer accounts.
+const ON_PAYMENT_DISCRIMINATOR: [u8; 8] = [109, 220, 129, 184, 2, 252, 9, 79]; // sha256("global:on_payment")[..8]
+const MAX_FEE_BPS: u16 = 1_000; // 10%
+// Account layout versions. Bump when appending fields, and teach the migration path
+// about the new defaults.
+//   TransactionRecord: 0 = any layout written before versioning (mint .. fee_exempt
+//                      appended over time), 1 = adds `schema_version`,
+//                      2 = adds `prev_sequence` (None on migrated records),
+//                      3 = adds `category` (Generic on migrated records),
+//                      4 = adds `owner` (migrated records default to the sender),
+//                      5 = adds `note` (migrated records have none),
+//                      6 = adds `mint_decimals` (0 on migrated records),
+//                      7 = adds `data` (empty on migrated records),
+//                      8 = adds `memo_code` (None on migrated records).
+//   ProgramState:      0 = before versioning, 1 = adds `schema_version`,
+//                      2 = adds `cooldown_secs`, 3 = adds `pending_authority`,
+//                      4 = adds `dedup_window_secs`, 5 = adds `fee_rounding`,
+//                      6 = adds `hook_program`, 7 = adds `max_memo_length`
+//                      (MAX_MEMO_LENGTH on migrated state), 8 = adds `total_refundables`,
+//                      9 = adds `total_fees_collected`, `total_refunds` and
+//                      `active_escrows`, 10 = adds `max_single_payment`,
+//                      11 = adds `total_scheduled`, 12 = adds `verbose`,
+//                      13 = adds `min_reserve` (TX_FEE_ESTIMATE_LAMPORTS on migrated
+//                      state), 14 = adds `fee_split`, 15 = adds `max_total_received`,
+//                      16 = adds `open_holds` (passed to migrate_state).
+//                      Fields not listed with a default migrate as zero / None / empty.
+const TRANSACTION_RECORD_VERSION: u8 = 8;
+pub const PROGRAM_STATE_VERSION: u8 = 16;
+
+impl TransactionRecord {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // sender
+        + PUBLIC_KEY_LENGTH // receiver
+        + U64_LENGTH // amount
+        + I64_LENGTH // timestamp
+        + STRING_LENGTH_PREFIX + MAX_MEMO_BYTES // memo
+        + OPTION_PREFIX + PUBLIC_KEY_LENGTH // mint
+        + U8_LENGTH // receiver_count
+        + U64_LENGTH // fee
+        + BOOL_LENGTH // memo_encrypted
+        + VEC_LENGTH_PREFIX + MAX_ENCRYPTED_MEMO_BYTES // encrypted_memo
+        + OPTION_PREFIX + U64_LENGTH // refund_of
+        + U64_LENGTH // refunded
+        + OPTION_PREFIX + REFERENCE_LENGTH // reference
+        + U64_LENGTH // tip
+        + OPTION_PREFIX + PUBLIC_KEY_LENGTH // tip_recipient
+        + BOOL_LENGTH // fee_exempt
+        + U8_LENGTH // schema_version
+        + OPTION_PREFIX + U64_LENGTH // prev_sequence
+        + U8_LENGTH // category
+        + PUBLIC_KEY_LENGTH // owner
+        + STRING_LENGTH_PREFIX + MAX_NOTE_BYTES // note
+        + U8_LENGTH // mint_decimals
+        + VEC_LENGTH_PREFIX + MAX_RAW_DATA_BYTES // data
+        + OPTION_PREFIX + U16_LENGTH; // memo_code
+
+    // A send_payment_coded record. The code stands in for the memo and the other
+    // variable-length fields stay empty, so no room is allocated for any of them.
+    pub const CODED_LEN: usize = Self::LEN - MAX_MEMO_BYTES - MAX_ENCRYPTED_MEMO_BYTES - MAX_NOTE_BYTES - MAX_RAW_DATA_BYTES;
+
+    // Memo limits of a record whose account holds `data_len` bytes: `max_memo_length`
+    // characters in MAX_MEMO_BYTES, plus whatever grow_record added on top of LEN, at
+    // the same worst case of 4 bytes per character
+    pub fn memo_limits(data_len: usize, max_memo_length: usize) -> (usize, usize) {
+        let extra_bytes = data_len.saturating_sub(Self::LEN);
+        (max_memo_length + extra_bytes / 4, MAX_MEMO_BYTES + extra_bytes)
+    }
+
+    // A single-receiver native SOL payment record; the receipt starts with its sender.
+    // `amount` is the gross amount, `fee` the part of it the protocol took. Callers set
+    // whatever else their instruction records (mint, tip, reference, ...) afterwards.
+    pub fn new_payment(sender: Pubkey, receiver: Pubkey, amount: u64, memo: String, timestamp: i64, fee: u64) -> Self {
+        Self {
+            sender,
+            receiver,
+            amount,
+            timestamp,
+            memo,
+            receiver_count: 1,
+            fee,
+            schema_version: TRANSACTION_RECORD_VERSION,
+            owner: sender,
+            ..Default::default()
+        }
+    }
+
+    // Records written before `owner` existed read it as the default key and belong to their sender
+    pub fn current_owner(&self) -> Pubkey {
+        if self.owner == Pubkey::default() {
+            self.sender
+        } else {
+            self.owner
+        }
+    }
+}
+
+// Account to store global program state
+#[account]
+pub struct ProgramState {
+    // Append-only: new fields go at the end with a PROGRAM_STATE_VERSION bump
+    pub total_transactions: u64,
+    pub total_escrows: u64, // Escrow sequence counter used in escrow PDA seeds
+    pub total_timelocks: u64, // Timelock sequence counter used in timelock PDA seeds
+    pub total_streams: u64, // Stream sequence counter used in stream PDA seeds
+    pub total_conditionals: u64, // Conditional payment sequence counter used in PDA seeds
+    pub authority: Pubkey, // Admin allowed to call privileged instructions
+    pub paused: bool, // Emergency stop for new payments; held funds can still leave
+    pub fee_bps: u16, // Protocol fee in basis points, capped at MAX_FEE_BPS
+    pub fee_collector: Pubkey, // Receives the protocol fee
+    pub min_amount: u64, // Smallest accepted payment in lamports, 0 = no minimum
+    pub total_volume: u64, // Lifetime gross lamports sent through fee-charging payment instructions
+    pub whitelist_enabled: bool, // When set, send_payment only pays whitelisted receivers
+    pub multisig: MultisigConfig, // Approval requirement for large payments
+    pub memo_pattern: Option<MemoPattern>, // Required memo format for send_payment, `None` = free-form
+    pub teardown_requested_at: i64, // Set by begin_teardown, 0 = no teardown pending
+    pub schema_version: u8, // Layout version, see PROGRAM_STATE_VERSION; 0 = written before versioning
+    pub cooldown_secs: i64, // Minimum gap between payments from one sender to one receiver, 0 = off
+    pub pending_authority: Option<Pubkey>, // Proposed new authority, must sign accept_authority
+    pub dedup_window_secs: i64, // Identical payments within this window are rejected unless forced, 0 = off
+    pub fee_rounding: FeeRounding, // How basis-point fees round to whole lamports
+    pub hook_program: Option<Pubkey>, // Program notified via `on_payment` after each send_payment, `None` = off
+    pub max_memo_length: u16, // Payment memo limit in characters, at most MAX_MEMO_BYTES
+    pub total_refundables: u64, // Refundable payment sequence counter used in PDA seeds
+    pub total_fees_collected: u64, // Lifetime protocol fees paid to the fee collector
+    pub total_refunds: u64, // Lifetime lamports returned through refund_payment
+    pub active_escrows: u64, // Escrows created but not yet released, refunded or arbitrated
+    pub max_single_payment: u64, // Largest accepted payment in lamports, 0 = unlimited
+    pub total_scheduled: u64, // Scheduled payment sequence counter used in PDA seeds
+    pub verbose: bool, // Adds debug msg! logs to send_payment on top of its event
+    pub min_reserve: u64, // Lamports send_payment always leaves with the sender for future fees
+    pub fee_split: Vec<FeeShare>, // send_payment fee recipients, at most MAX_FEE_COLLECTORS; empty = all to fee_collector
+    pub max_total_received: u64, // Cap on a receiver's ReceiverStats total, checked as funds arrive; 0 = unlimited
+    pub open_holds: u64, // Timelocks, subscriptions, streams and conditional, refundable, scheduled or pending payments still open
+}
+
+impl ProgramState {
+    // Called once per escrow as it is settled, paired with the increment in create_escrow
+    pub fn settle_escrow(&mut self) -> Result<()> {
+        self.active_escrows = self.active_escrows.checked_sub(1).ok_or(ErrorCode::CounterUnderflow)?;
+        Ok(())
+    }
+
+    // Called when an account holding a user's funds outside an escrow is created
+    pub fn open_hold(&mut self) -> Result<()> {
+        self.open_holds = self.open_holds.checked_add(1).ok_or(ErrorCode::Overflow)?;
+        Ok(())
+    }
+
+    // Called once per such account as it is closed or canceled, paired with `open_hold`
+    pub fn close_hold(&mut self) -> Result<()> {
+        self.open_holds = self.open_holds.checked_sub(1).ok_or(ErrorCode::CounterUnderflow)?;
+        Ok(())
+    }
+
+    // Define space for ProgramState
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + U64_LENGTH // total_transactions
+        + U64_LENGTH // total_escrows
+        + U64_LENGTH // total_timelocks
+        + U64_LENGTH // total_streams
+        + U64_LENGTH // total_conditionals
+        + PUBLIC_KEY_LENGTH // authority
+        + BOOL_LENGTH // paused
+        + U16_LENGTH // fee_bps
+        + PUBLIC_KEY_LENGTH // fee_collector
+        + U64_LENGTH // min_amount
+        + U64_LENGTH // total_volume
+        + BOOL_LENGTH // whitelist_enabled
+        + MultisigConfig::LEN // multisig
+        + OPTION_PREFIX + MemoPattern::LEN // memo_pattern
+        + I64_LENGTH // teardown_requested_at
+        + U8_LENGTH // schema_version
+        + I64_LENGTH // cooldown_secs
+        + OPTION_PREFIX + PUBLIC_KEY_LENGTH // pending_authority
+        + I64_LENGTH // dedup_window_secs
+        + U8_LENGTH // fee_rounding (borsh enum tag)
+        + OPTION_PREFIX + PUBLIC_KEY_LENGTH // hook_program
+        + U16_LENGTH // max_memo_length
+        + U64_LENGTH // total_refundables
+        + U64_LENGTH // total_fees_collected
+        + U64_LENGTH // total_refunds
+        + U64_LENGTH // active_escrows
+        + U64_LENGTH // max_single_payment
+        + U64_LENGTH // total_scheduled
+        + BOOL_LENGTH // verbose
+        + U64_LENGTH // min_reserve
+        + VEC_LENGTH_PREFIX + MAX_FEE_COLLECTORS * FeeShare::LEN // fee_split
+        + U64_LENGTH // max_total_received
+        + U64_LENGTH; // open_holds
+}
+
+// Funds held by the program until the sender releases or refunds them.
+// The escrowed lamports live on the PDA itself, on top of its rent.
+#[account]
+pub struct Escrow {
+    pub sender: Pubkey,
+    pub receiver: Pubkey,
+    pub amount: u64,
+    pub memo: String,
+    pub created_at: i64,
+    pub sequence: u64, // Value of `total_escrows` at creation, part of the PDA seeds
+    pub bump: u8,
+    pub expires_at: i64, // After this, the escrow can no longer be released, only refunded; 0 = never
+    pub arbiter: Pubkey, // Neutral party who settles disputes, Pubkey::default() = none
+    pub disputed: bool, // Set by dispute_escrow; only the arbiter can settle from then on
+    pub settled: bool, // Set before any payout so the escrow can never be settled twice
+}
+
+impl Escrow {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // sender
+        + PUBLIC_KEY_LENGTH // receiver
+        + U64_LENGTH // amount
+        + STRING_LENGTH_PREFIX + MAX_MEMO_BYTES // memo
+        + I64_LENGTH // created_at
+        + U64_LENGTH // sequence
+        + U8_LENGTH // bump
+        + I64_LENGTH // expires_at
+        + PUBLIC_KEY_LENGTH // arbiter
+        + BOOL_LENGTH // disputed
+        + BOOL_LENGTH; // settled
+
+    // Explicit double-execution guard. `close` already zeroes the account at the end
+    // of the instruction, but this keeps the invariant independent of that.
+    pub fn mark_settled(&mut self) -> Result<()> {
+        if self.settled {
+            return err!(ErrorCode::EscrowAlreadySettled);
+        }
+        self.settled = true;
+        Ok(())
+    }
+}
+
+// Funds the receiver can only claim once `unlock_at` has passed
+#[account]
+pub struct TimelockedPayment {
+    pub sender: Pubkey,
+    pub receiver: Pubkey,
+    pub amount: u64,
+    pub unlock_at: i64,
+    pub memo: String,
+    pub created_at: i64,
+    pub sequence: u64, // Value of `total_timelocks` at creation, part of the PDA seeds
+    pub bump: u8,
+    pub expires_at: i64, // After this, the receiver can no longer claim and the sender may reclaim; 0 = never
+}
+
+impl TimelockedPayment {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // sender
+        + PUBLIC_KEY_LENGTH // receiver
+        + U64_LENGTH // amount
+        + I64_LENGTH // unlock_at
+        + STRING_LENGTH_PREFIX + MAX_MEMO_BYTES // memo
+        + I64_LENGTH // created_at
+        + U64_LENGTH // sequence
+        + U8_LENGTH // bump
+        + I64_LENGTH; // expires_at
+}
+
+// Buyer-protected payment: the sender may cancel until `grace_ends_at`, after
+// which only the receiver can claim it.
+#[account]
+pub struct RefundablePayment {
+    pub sender: Pubkey,
+    pub receiver: Pubkey,
+    pub amount: u64,
+    pub memo: String,
+    pub created_at: i64,
+    pub grace_ends_at: i64,
+    pub sequence: u64, // Value of `total_refundables` at creation, part of the PDA seeds
+    pub bump: u8,
+}
+
+impl RefundablePayment {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // sender
+        + PUBLIC_KEY_LENGTH // receiver
+        + U64_LENGTH // amount
+        + STRING_LENGTH_PREFIX + MAX_MEMO_BYTES // memo
+        + I64_LENGTH // created_at
+        + I64_LENGTH // grace_ends_at
+        + U64_LENGTH // sequence
+        + U8_LENGTH; // bump
+}
+
+// A payment funded now and executed by any cranker once `execute_at` has passed.
+// The PDA holds `amount + cranker_tip` on top of its rent.
+#[account]
+pub struct ScheduledPayment {
+    pub sender: Pubkey,
+    pub receiver: Pubkey,
+    pub amount: u64,
+    pub cranker_tip: u64, // Paid to whoever executes the payment, 0 = none
+    pub execute_at: i64,
+    pub memo: String,
+    pub created_at: i64,
+    pub sequence: u64, // Value of `total_scheduled` at creation, part of the PDA seeds
+    pub bump: u8,
+    pub executed: bool, // Set before any payout so the payment can never run twice
+}
+
+impl ScheduledPayment {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // sender
+        + PUBLIC_KEY_LENGTH // receiver
+        + U64_LENGTH // amount
+        + U64_LENGTH // cranker_tip
+        + I64_LENGTH // execute_at
+        + STRING_LENGTH_PREFIX + MAX_MEMO_BYTES // memo
+        + I64_LENGTH // created_at
+        + U64_LENGTH // sequence
+        + U8_LENGTH // bump
+        + BOOL_LENGTH; // executed
+}
+
+// Marks a client-chosen idempotency key as used by one of the sender's payments
+#[account]
+pub struct IdempotencyRecord {
+    pub sender: Pubkey,
+    pub key: [u8; 16],
+    pub sequence: u64, // Sequence of the transaction record the key was first used for
+    pub created_at: i64, // 0 until the key's first payment, which is how reuse is detected
+    pub bump: u8,
+}
+
+impl IdempotencyRecord {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // sender
+        + IDEMPOTENCY_KEY_LENGTH // key
+        + U64_LENGTH // sequence
+        + I64_LENGTH // created_at
+        + U8_LENGTH; // bump
+}
+
+// Receiver opt-in: while this exists, send_payment holds payments to the receiver
+// in a PendingTransfer until they accept or reject it
+#[account]
+pub struct AcceptancePolicy {
+    pub receiver: Pubkey,
+    pub bump: u8,
+}
+
+impl AcceptancePolicy {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // receiver
+        + U8_LENGTH; // bump
+}
+
+// A send_payment held for a receiver that requires acceptance. The PDA holds the
+// net amount and the protocol fee on top of its rent; the fee is collected on accept
+// and returned with the amount on reject or reclaim.
+#[account]
+pub struct PendingTransfer {
+    pub sender: Pubkey,
+    pub receiver: Pubkey,
+    pub amount: u64, // Net of the protocol fee
+    pub sequence: u64, // Sequence of the matching transaction record, part of the PDA seeds
+    pub created_at: i64,
+    pub resolved: bool, // Set before any payout so the transfer can never be resolved twice
+    pub bump: u8,
+    pub fee: u64, // Protocol fee held on the PDA; collected on accept, returned on reject/reclaim
+}
+
+impl PendingTransfer {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // sender
+        + PUBLIC_KEY_LENGTH // receiver
+        + U64_LENGTH // amount
+        + U64_LENGTH // sequence
+        + I64_LENGTH // created_at
+        + BOOL_LENGTH // resolved
+        + U8_LENGTH // bump
+        + U64_LENGTH; // fee
+
+    pub fn mark_resolved(&mut self) -> Result<()> {
+        if self.resolved {
+            return err!(ErrorCode::TransferResolved);
+        }
+        self.resolved = true;
+        Ok(())
+    }
+}
+
+// Per-sender state. `transaction_count` is the next sequence number used in
+// that sender's transaction record seeds.
+#[account]
+pub struct UserState {
+    pub owner: Pubkey,
+    pub transaction_count: u64,
+    pub spend_limit_enabled: bool, // Set once the user creates a SpendLimit
+    pub bump: u8,
+    pub last_sequence: u64, // Sequence of the most recent record; only meaningful once transaction_count > 0
+    pub head_sequence: Option<u64>, // Newest record, start of the `prev_sequence` chain; `None` before the first
+}
+
+impl UserState {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // owner
+        + U64_LENGTH // transaction_count
+        + BOOL_LENGTH // spend_limit_enabled
+        + U8_LENGTH // bump
+        + U64_LENGTH // last_sequence
+        + OPTION_PREFIX + U64_LENGTH; // head_sequence
+}
+
+// Recurring payment the merchant can charge once per interval. The payer's
+// unspent deposit is held on the PDA on top of its rent.
+#[account]
+pub struct Subscription {
+    pub payer: Pubkey,
+    pub merchant: Pubkey,
+    pub amount: u64,
+    pub interval_secs: i64,
+    pub last_paid: i64, // 0 until the first charge
+    pub active: bool,
+    pub bump: u8,
+    // Appended later; older accounts are grown by migrate_subscription
+    pub pending_amount: Option<u64>, // Merchant's proposed new amount, applied once the payer accepts
+}
+
+impl Subscription {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // payer
+        + PUBLIC_KEY_LENGTH // merchant
+        + U64_LENGTH // amount
+        + I64_LENGTH // interval_secs
+        + I64_LENGTH // last_paid
+        + BOOL_LENGTH // active
+        + U8_LENGTH // bump
+        + OPTION_PREFIX + U64_LENGTH; // pending_amount
+}
+
+// One leg of a batch payment; `receiver_index` indexes into `remaining_accounts`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PaymentInstruction {
+    pub receiver_index: u8,
+    pub amount: u64,
+}
+
+// Presence of this PDA marks `address` as an approved receiver
+#[account]
+pub struct WhitelistEntry {
+    pub address: Pubkey,
+    pub bump: u8,
+    pub expires_at: i64, // After this the entry counts as absent and can be pruned; 0 = never
+}
+
+impl WhitelistEntry {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // address
+        + U8_LENGTH // bump
+        + I64_LENGTH; // expires_at
+}
+
+// Payment request created by a merchant and fulfilled by exactly one payer
+#[account]
+pub struct Invoice {
+    pub merchant: Pubkey,
+    pub invoice_id: u64, // Merchant-chosen id, part of the PDA seeds
+    pub amount: u64,
+    pub memo: String,
+    pub paid: bool,
+    pub payer: Option<Pubkey>, // Set once paid
+    pub bump: u8,
+}
+
+impl Invoice {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // merchant
+        + U64_LENGTH // invoice_id
+        + U64_LENGTH // amount
+        + STRING_LENGTH_PREFIX + MAX_MEMO_BYTES // memo
+        + BOOL_LENGTH // paid
+        + OPTION_PREFIX + PUBLIC_KEY_LENGTH // payer
+        + U8_LENGTH; // bump
+}
+
+// Self-imposed cap on how much a sender can move per rolling 24h window
+#[account]
+pub struct SpendLimit {
+    pub owner: Pubkey,
+    pub daily_limit: u64,
+    pub spent_today: u64,
+    pub window_start: i64,
+    pub bump: u8,
+}
+
+impl SpendLimit {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // owner
+        + U64_LENGTH // daily_limit
+        + U64_LENGTH // spent_today
+        + I64_LENGTH // window_start
+        + U8_LENGTH; // bump
+
+    // Resets the window once it has elapsed, then adds `amount` if it fits under the limit
+    pub fn record_spend(&mut self, amount: u64, now: i64) -> Result<()> {
+        if now.saturating_sub(self.window_start) >= SPEND_LIMIT_WINDOW_SECS {
+            self.spent_today = 0;
+            self.window_start = now;
+        }
+        let spent = self.spent_today.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+        if spent > self.daily_limit {
+            return err!(ErrorCode::DailyLimitExceeded);
+        }
+        self.spent_today = spent;
+        Ok(())
+    }
+}
+
+// Payments above `threshold_amount` need `required_approvals` distinct approvers.
+// A threshold of 0 disables the requirement.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct MultisigConfig {
+    pub threshold_amount: u64,
+    pub required_approvals: u8,
+    pub approvers: Vec<Pubkey>, // At most MAX_APPROVERS entries
+}
+
+impl MultisigConfig {
+    pub const LEN: usize = U64_LENGTH // threshold_amount
+        + U8_LENGTH // required_approvals
+        + VEC_LENGTH_PREFIX + MAX_APPROVERS * PUBLIC_KEY_LENGTH; // approvers
+
+    pub fn requires_approval(&self, amount: u64) -> bool {
+        self.threshold_amount > 0 && amount > self.threshold_amount
+    }
+}
+
+// One recipient of the protocol fee and its portion of it
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct FeeShare {
+    pub collector: Pubkey,
+    pub share_bps: u16, // Shares of a split sum to BPS_DENOMINATOR
+}
+
+impl FeeShare {
+    pub const LEN: usize = PUBLIC_KEY_LENGTH // collector
+        + U16_LENGTH; // share_bps
+}
+
+// A large payment waiting for multisig approvals before the sender can execute it
+#[account]
+pub struct PendingPayment {
+    pub sender: Pubkey,
+    pub receiver: Pubkey,
+    pub amount: u64,
+    pub memo: String,
+    pub payment_id: u64, // Sender-chosen id, part of the PDA seeds
+    pub approvals: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+impl PendingPayment {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // sender
+        + PUBLIC_KEY_LENGTH // receiver
+        + U64_LENGTH // amount
+        + STRING_LENGTH_PREFIX + MAX_MEMO_BYTES // memo
+        + U64_LENGTH // payment_id
+        + VEC_LENGTH_PREFIX + MAX_APPROVERS * PUBLIC_KEY_LENGTH // approvals
+        + U8_LENGTH; // bump
+}
+
+// Program-owned PDA that can act as the fee collector. Collected fees sit
+// on the account on top of its rent until the authority withdraws them.
+#[account]
+pub struct FeeVault {
+    pub bump: u8,
+}
+
+impl FeeVault {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + U8_LENGTH; // bump
+}
+
+// Funds that vest linearly to `recipient` between `start_ts` and `end_ts`.
+// The unwithdrawn balance is held on the PDA on top of its rent.
+#[account]
+pub struct Stream {
+    pub sender: Pubkey,
+    pub recipient: Pubkey,
+    pub total: u64,
+    pub withdrawn: u64,
+    pub start_ts: i64,
+    pub end_ts: i64,
+    pub sequence: u64, // Value of `total_streams` at creation, part of the PDA seeds
+    pub bump: u8,
+    pub cliff_ts: i64, // Nothing vests before this time, 0 = no cliff
+    pub canceled: bool,
+}
+
+impl Stream {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // sender
+        + PUBLIC_KEY_LENGTH // recipient
+        + U64_LENGTH // total
+        + U64_LENGTH // withdrawn
+        + I64_LENGTH // start_ts
+        + I64_LENGTH // end_ts
+        + U64_LENGTH // sequence
+        + U8_LENGTH // bump
+        + I64_LENGTH // cliff_ts
+        + BOOL_LENGTH; // canceled
+
+    // total * (now - start) / (end - start), clamped to [0, total], and 0 before the cliff
+    pub fn vested_amount(&self, now: i64) -> Result<u64> {
+        if self.cliff_ts != 0 && now < self.cliff_ts {
+            return Ok(0);
+        }
+        if now >= self.end_ts {
+            return Ok(self.total);
+        }
+        if now <= self.start_ts {
+            return Ok(0);
+        }
+        // Widen before subtracting so extreme timestamps cannot overflow
+        let elapsed = (now as i128 - self.start_ts as i128) as u128;
+        let duration = (self.end_ts as i128 - self.start_ts as i128) as u128;
+        let vested = (self.total as u128)
+            .checked_mul(elapsed)
+            .ok_or(ErrorCode::Overflow)?
+            / duration;
+        Ok(vested as u64)
+    }
+}
+
+// Escrowed payment released once the Pyth price for `feed_id` reaches
+// `price_threshold` (in the feed's own units and exponent)
+#[account]
+pub struct ConditionalPayment {
+    pub sender: Pubkey,
+    pub receiver: Pubkey,
+    pub amount: u64,
+    pub price_threshold: i64,
+    pub feed_id: [u8; 32], // Pyth price feed id, e.g. SOL/USD
+    pub memo: String,
+    pub sequence: u64, // Value of `total_conditionals` at creation, part of the PDA seeds
+    pub bump: u8,
+    // Appended after the memo, so payments created before this field existed read
+    // back 0 and may be reclaimed at once, as they could then
+    pub reclaimable_at: i64, // The sender may reclaim only after this
+}
+
+impl ConditionalPayment {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // sender
+        + PUBLIC_KEY_LENGTH // receiver
+        + U64_LENGTH // amount
+        + I64_LENGTH // price_threshold
+        + FEED_ID_LENGTH // feed_id
+        + STRING_LENGTH_PREFIX + MAX_MEMO_BYTES // memo
+        + U64_LENGTH // sequence
+        + U8_LENGTH // bump
+        + I64_LENGTH; // reclaimable_at
+}
+
+// Minimal mirror of the Pyth receiver's `PriceUpdateV2` account layout, so the
+// program can read prices without depending on the Pyth SDK.
+#[derive(AnchorDeserialize)]
+#[allow(dead_code)]
+enum PythVerificationLevel {
+    Partial { num_signatures: u8 },
+    Full,
+}
+
+#[derive(AnchorDeserialize)]
+#[allow(dead_code)]
+struct PythPriceFeedMessage {
+    feed_id: [u8; 32],
+    price: i64,
+    conf: u64,
+    exponent: i32,
+    publish_time: i64,
+    prev_publish_time: i64,
+    ema_price: i64,
+    ema_conf: u64,
+}
+
+#[derive(AnchorDeserialize)]
+#[allow(dead_code)]
+struct PythPriceUpdateV2 {
+    write_authority: Pubkey,
+    verification_level: PythVerificationLevel,
+    price_message: PythPriceFeedMessage,
+    posted_slot: u64,
+}
+
+// Marks an address as blocked; the PDA's existence is the flag
+#[account]
+pub struct Blacklist {
+    pub address: Pubkey,
+    pub bump: u8,
+}
+
+impl Blacklist {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // address
+        + U8_LENGTH; // bump
+}
+
+// Compliance hold on one user's outgoing payments, set and cleared by the authority
+#[account]
+pub struct FreezeFlag {
+    pub user: Pubkey,
+    pub frozen_at: i64,
+    pub bump: u8,
+}
+
+impl FreezeFlag {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // user
+        + I64_LENGTH // frozen_at
+        + U8_LENGTH; // bump
+}
+
+// Fixed memo format such as `INV-` followed by exactly 5 ASCII alphanumerics
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MemoPattern {
+    pub prefix: String, // ASCII, at most MAX_MEMO_PREFIX_BYTES
+    pub suffix_length: u8, // Number of ASCII alphanumeric characters after the prefix
+}
+
+impl MemoPattern {
+    pub const LEN: usize = STRING_LENGTH_PREFIX + MAX_MEMO_PREFIX_BYTES // prefix
+        + U8_LENGTH; // suffix_length
+
+    pub fn validate(&self) -> Result<()> {
+        if self.prefix.len() > MAX_MEMO_PREFIX_BYTES
+            || !self.prefix.is_ascii()
+            || self.prefix.len() + self.suffix_length as usize > MAX_MEMO_LENGTH
+        {
+            return err!(ErrorCode::InvalidMemoPattern);
+        }
+        Ok(())
+    }
+
+    pub fn matches(&self, memo: &str) -> bool {
+        match memo.strip_prefix(self.prefix.as_str()) {
+            Some(suffix) => {
+                suffix.len() == self.suffix_length as usize
+                    && suffix.bytes().all(|b| b.is_ascii_alphanumeric())
+            }
+            None => false,
+        }
+    }
+}
+
+// Lets `delegate` send up to `allowance` lamports from the owner's vault
+#[account]
+pub struct PaymentDelegate {
+    pub owner: Pubkey,
+    pub delegate: Pubkey,
+    pub allowance: u64, // Remaining lamports the delegate may send, decremented per payment
+    pub bump: u8,
+}
+
+impl PaymentDelegate {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // owner
+        + PUBLIC_KEY_LENGTH // delegate
+        + U64_LENGTH // allowance
+        + U8_LENGTH; // bump
+}
+
+// Running totals of what a receiver has been paid in lamports (SOL and wSOL), counted
+// when the funds reach them, so held payments count on release. SPL token payments
+// keep the same totals per mint at `[b"received", receiver, mint]`, in base units.
+#[account]
+pub struct ReceiverStats {
+    pub receiver: Pubkey,
+    pub total_received: u64, // Lamports received, net of protocol fees
+    pub payment_count: u64,
+    pub bump: u8,
+}
+
+impl ReceiverStats {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // receiver
+        + U64_LENGTH // total_received
+        + U64_LENGTH // payment_count
+        + U8_LENGTH; // bump
+
+    // `init_if_needed` may hand us existing stats; identity is only written once
+    pub fn claim(&mut self, receiver: Pubkey, bump: u8) {
+        if self.receiver == Pubkey::default() {
+            self.receiver = receiver;
+            self.bump = bump;
+        }
+    }
+
+    // `max_total_received` is ProgramState's per-receiver cap, 0 = unlimited
+    pub fn record_payment(&mut self, amount: u64, max_total_received: u64) -> Result<()> {
+        self.total_received = self.total_received.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+        if max_total_received != 0 && self.total_received > max_total_received {
+            return err!(ErrorCode::ReceiverCapExceeded);
+        }
+        self.payment_count = self.payment_count.checked_add(1).ok_or(ErrorCode::Overflow)?;
+        Ok(())
+    }
+}
+
+// Fixed-size stand-in for a TransactionRecord, written by `send_payment_compact`.
+// See `compact_receipt_hash` for the preimage layout.
+#[account]
+pub struct CompactReceipt {
+    pub hash: [u8; 32],
+    pub bump: u8,
+}
+
+impl CompactReceipt {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + HASH_LENGTH // hash
+        + U8_LENGTH; // bump
+}
+
+// Numbers a sender's compact receipts; `count` seeds the next one
+#[account]
+pub struct ReceiptCounter {
+    pub sender: Pubkey,
+    pub count: u64,
+    pub bump: u8,
+}
+
+impl ReceiptCounter {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // sender
+        + U64_LENGTH // count
+        + U8_LENGTH; // bump
+
+    // `init_if_needed` may hand us an existing counter; identity is only written once
+    pub fn claim(&mut self, sender: Pubkey, bump: u8) {
+        if self.sender == Pubkey::default() {
+            self.sender = sender;
+            self.bump = bump;
+        }
+    }
+}
+
+// Lifetime lamport flow of one wallet through the native payment instructions, for net-flow views
+#[account]
+pub struct UserLedger {
+    pub user: Pubkey,
+    pub total_sent: u64,     // Gross lamports sent, fees included, net of refunded held transfers
+    pub total_received: u64, // Lamports actually delivered, net of protocol fees
+    pub bump: u8,
+}
+
+impl UserLedger {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // user
+        + U64_LENGTH // total_sent
+        + U64_LENGTH // total_received
+        + U8_LENGTH; // bump
+
+    // `init_if_needed` may hand us an existing ledger; identity is only written once
+    pub fn claim(&mut self, user: Pubkey, bump: u8) {
+        if self.user == Pubkey::default() {
+            self.user = user;
+            self.bump = bump;
+        }
+    }
+
+    pub fn record_sent(&mut self, amount: u64) -> Result<()> {
+        self.total_sent = self.total_sent.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+        emit_ledger_updated(self)
+    }
+
+    pub fn record_received(&mut self, amount: u64) -> Result<()> {
+        self.total_received = self.total_received.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+        emit_ledger_updated(self)
+    }
+
+    // A held transfer that went back to the sender was never really sent
+    pub fn reverse_sent(&mut self, amount: u64) -> Result<()> {
+        self.total_sent = self.total_sent.checked_sub(amount).ok_or(ErrorCode::CounterUnderflow)?;
+        emit_ledger_updated(self)
+    }
+}
+
+// Authority-granted exemption from the protocol fee for a partner sender
+#[account]
+pub struct FeeExemption {
+    pub sender: Pubkey,
+    pub bump: u8,
+}
+
+impl FeeExemption {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // sender
+        + U8_LENGTH; // bump
+}
+
+// Both legs of a settled atomic swap: SOL from `party_a`, SPL tokens from `party_b`
+#[account]
+pub struct SwapRecord {
+    pub party_a: Pubkey,
+    pub party_b: Pubkey,
+    pub swap_id: u64, // Chosen by the parties, part of the PDA seeds
+    pub amount_a: u64, // Lamports sent from A to B
+    pub mint_b: Pubkey,
+    pub amount_b: u64, // Token units sent from B to A
+    pub timestamp: i64,
+    pub bump: u8,
+}
+
+impl SwapRecord {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // party_a
+        + PUBLIC_KEY_LENGTH // party_b
+        + U64_LENGTH // swap_id
+        + U64_LENGTH // amount_a
+        + PUBLIC_KEY_LENGTH // mint_b
+        + U64_LENGTH // amount_b
+        + I64_LENGTH // timestamp
+        + U8_LENGTH; // bump
+}
+
+// Last payment time from one sender to one receiver, for the double-send cooldown
+#[account]
+pub struct PaymentPair {
+    pub last_payment_ts: i64, // 0 until the first payment
+    pub bump: u8,
+}
+
+impl PaymentPair {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + I64_LENGTH // last_payment_ts
+        + U8_LENGTH; // bump
+
+    pub fn check_cooldown(&self, cooldown_secs: i64, now: i64) -> Result<()> {
+        if cooldown_secs > 0
+            && self.last_payment_ts != 0
+            && now < self.last_payment_ts.saturating_add(cooldown_secs)
+        {
+            return err!(ErrorCode::PaymentTooSoon);
+        }
+        Ok(())
+    }
+}
+
+// Community tip jar: anyone can donate, only the beneficiary can withdraw.
+// Donations live on the PDA itself, on top of its rent.
+#[account]
+pub struct Pool {
+    pub beneficiary: Pubkey,
+    pub total_deposited: u64, // Lifetime donations
+    pub total_withdrawn: u64, // Lifetime withdrawals by the beneficiary
+    pub bump: u8,
+}
+
+impl Pool {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // beneficiary
+        + U64_LENGTH // total_deposited
+        + U64_LENGTH // total_withdrawn
+        + U8_LENGTH; // bump
+}
+
+// Running total of one donor's contributions to one pool, for receipts and recognition
+#[account]
+pub struct DonationRecord {
+    pub pool: Pubkey,
+    pub donor: Pubkey,
+    pub total_donated: u64,
+    pub donation_count: u64,
+    pub last_donated_at: i64,
+    pub bump: u8,
+}
+
+impl DonationRecord {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // pool
+        + PUBLIC_KEY_LENGTH // donor
+        + U64_LENGTH // total_donated
+        + U64_LENGTH // donation_count
+        + I64_LENGTH // last_donated_at
+        + U8_LENGTH; // bump
+}
+
+// All-or-nothing crowdfunding: the creator claims everything if `goal` is reached by
+// `deadline`, otherwise each backer reclaims their own contribution
+#[account]
+pub struct Campaign {
+    pub creator: Pubkey,
+    pub campaign_id: u64, // Chosen by the creator, part of the PDA seeds
+    pub goal: u64,
+    pub raised: u64, // Lamports currently pledged, held on this PDA on top of its rent
+    pub deadline: i64,
+    pub bump: u8,
+}
+
+impl Campaign {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // creator
+        + U64_LENGTH // campaign_id
+        + U64_LENGTH // goal
+        + U64_LENGTH // raised
+        + I64_LENGTH // deadline
+        + U8_LENGTH; // bump
+}
+
+// A backer's total pledge to one campaign
+#[account]
+pub struct Contribution {
+    pub campaign: Pubkey,
+    pub backer: Pubkey,
+    pub amount: u64,
+    pub bump: u8,
+}
+
+impl Contribution {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // campaign
+        + PUBLIC_KEY_LENGTH // backer
+        + U64_LENGTH // amount
+        + U8_LENGTH; // bump
+}
+
+// Hash of the sender's last `(receiver, amount, memo)`, used to catch client retries
+#[account]
+pub struct RecentPayment {
+    pub payment_hash: [u8; 32],
+    pub sent_at: i64,
+    pub bump: u8,
+}
+
+impl RecentPayment {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + HASH_LENGTH // payment_hash
+        + I64_LENGTH // sent_at
+        + U8_LENGTH; // bump
+
+    pub fn check_duplicate(&self, payment_hash: &[u8; 32], window_secs: i64, now: i64) -> Result<()> {
+        if window_secs > 0
+            && self.payment_hash == *payment_hash
+            && now < self.sent_at.saturating_add(window_secs)
+        {
+            return err!(ErrorCode::DuplicatePaymentSuspected);
+        }
+        Ok(())
+    }
+}
+
+// Rounding applied to `amount * fee_bps / BPS_DENOMINATOR`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FeeRounding {
+    #[default]
+    Floor, // Truncate, the original behaviour
+    Ceil,
+    Nearest,
+}
+
+// Analytics tag for a payment, stored on the record as its `u8` discriminant so
+// new categories can be appended without changing the account layout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PaymentCategory {
+    Generic = 0,
+    Salary = 1,
+    Invoice = 2,
+    Gift = 3,
+    Refund = 4,
+}
+
+impl TryFrom<u8> for PaymentCategory {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(Self::Generic),
+            1 => Ok(Self::Salary),
+            2 => Ok(Self::Invoice),
+            3 => Ok(Self::Gift),
+            4 => Ok(Self::Refund),
+            _ => err!(ErrorCode::InvalidCategory),
+        }
+    }
+}
+
+// Which ProgramState limit a `LimitChanged` event refers to
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LimitKind {
+    MinAmount,
+    MaxSinglePayment,
+    MinReserve,
+    CooldownSecs, // Value is in seconds
+    DedupWindowSecs, // Value is in seconds
+    MaxTotalReceived,
+    MaxMemoLength, // Value is in characters
+}
+
+// Which per-address list an `AccessListChanged` event refers to
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessList {
+    Whitelist, // Receivers send_payment may pay while the whitelist is enabled
+    Blacklist,
+    Frozen, // Users whose outgoing payments are frozen
+    FeeExempt,
+}
+
+// Program-wide memo text registered under a short code, so records written by
+// send_payment_coded only store the code
+#[account]
+pub struct MemoDictionary {
+    pub code: u16,
+    pub text: String,
+    pub bump: u8,
+}
+
+impl MemoDictionary {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + U16_LENGTH // code
+        + STRING_LENGTH_PREFIX + MAX_MEMO_BYTES // text
+        + U8_LENGTH; // bump
+}
+
+// Reusable memo with `{amount}` (lamports) and `{date}` (UTC, YYYY-MM-DD)
+// placeholders, filled in by send_payment_from_template
+#[account]
+pub struct MemoTemplate {
+    pub owner: Pubkey,
+    pub template_id: u64, // Owner-chosen id, part of the PDA seeds
+    pub template: String,
+    pub bump: u8,
+}
+
+impl MemoTemplate {
+    pub const LEN: usize = DISCRIMINATOR_LENGTH
+        + PUBLIC_KEY_LENGTH // owner
+        + U64_LENGTH // template_id
+        + STRING_LENGTH_PREFIX + MAX_MEMO_BYTES // template
+        + U8_LENGTH; // bump
+}
+
+// Emitted by `send_payment` after a successful transfer. Indexers should rely on
+// this event rather than parsing the msg! logs.
+#[event]
+pub struct PaymentSent {
+    pub sender: Pubkey,
+    pub receiver: Pubkey,
+    pub amount: u64,
+    pub memo: String,
+    pub timestamp: i64,
+    pub sequence: u64,
+}
+
+// Emitted by `get_transaction` so clients can read a record by sequence number
+#[event]
+pub struct TransactionDetails {
+    pub sender: Pubkey,
+    pub receiver: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+    pub memo: String,
+    pub mint: Option<Pubkey>,
+    pub sequence: u64,
+}
+
+// Emitted by `record_exists`
+#[event]
+pub struct RecordExistence {
+    pub sender: Pubkey,
+    pub sequence: u64,
+    pub exists: bool,
+}
+
+// Emitted by `arbitrate_escrow` as the audit trail of the arbiter's decision
+#[event]
+pub struct EscrowArbitrated {
+    pub escrow: Pubkey,
+    pub sequence: u64,
+    pub arbiter: Pubkey,
+    pub sender: Pubkey,
+    pub receiver: Pubkey,
+    pub amount: u64,
+    pub to_seller: bool,
+    pub timestamp: i64,
+}
+
+// Admin events. Every authority action emits one so monitoring can alert on
+// configuration changes without polling ProgramState.
+#[event]
+pub struct AuthorityChanged {
+    pub previous_authority: Pubkey,
+    pub new_authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PausedChanged {
+    pub authority: Pubkey,
+    pub paused: bool,
+    pub timestamp: i64,
+}
+
+// Carries the full fee configuration, whichever part of it changed
+#[event]
+pub struct FeeChanged {
+    pub authority: Pubkey,
+    pub fee_bps: u16,
+    pub fee_collector: Pubkey,
+    pub fee_rounding: FeeRounding,
+    pub fee_split: Vec<FeeShare>,
+    pub timestamp: i64,
+}
+
+// Emitted by `admin_recover_escrow` so every authority recovery is auditable
+#[event]
+pub struct EscrowRecovered {
+    pub authority: Pubkey,
+    pub escrow: Pubkey,
+    pub sender: Pubkey,
+    pub amount: u64,
+    pub sequence: u64,
+    pub timestamp: i64,
+}
+
+// Emitted whenever a `UserLedger` total changes
+#[event]
+pub struct LedgerUpdated {
+    pub user: Pubkey,
+    pub total_sent: u64,
+    pub total_received: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LoggingChanged {
+    pub authority: Pubkey,
+    pub verbose: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LimitChanged {
+    pub authority: Pubkey,
+    pub limit: LimitKind,
+    pub value: u64, // 0 disables the limit
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct StateInitialized {
+    pub authority: Pubkey,
+    pub payer: Pubkey,
+    pub max_memo_length: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct StateMigrated {
+    pub authority: Pubkey,
+    pub from_version: u8,
+    pub to_version: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct StateReset {
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+// Emitted by `finalize_teardown` as the state PDA is closed
+#[event]
+pub struct StateClosed {
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuthorityTransferProposed {
+    pub authority: Pubkey,
+    pub pending_authority: Option<Pubkey>, // `None` when the proposal is cancelled
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct HookProgramChanged {
+    pub authority: Pubkey,
+    pub hook_program: Option<Pubkey>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MultisigConfigChanged {
+    pub authority: Pubkey,
+    pub threshold_amount: u64,
+    pub required_approvals: u8,
+    pub approvers: Vec<Pubkey>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MemoPatternChanged {
+    pub authority: Pubkey,
+    pub memo_pattern: Option<MemoPattern>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MemoRegistered {
+    pub authority: Pubkey,
+    pub code: u16,
+    pub text: String,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct WhitelistEnabledChanged {
+    pub authority: Pubkey,
+    pub enabled: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AccessListChanged {
+    pub changed_by: Pubkey, // The authority, or whoever pruned an expired whitelist entry
+    pub list: AccessList,
+    pub address: Pubkey,
+    pub added: bool,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FeeVaultInitialized {
+    pub authority: Pubkey,
+    pub fee_vault: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FeesWithdrawn {
+    pub authority: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SubscriptionChangeProposed {
+    pub subscription: Pubkey,
+    pub merchant: Pubkey,
+    pub current_amount: u64,
+    pub new_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct SubscriptionChangeAccepted {
+    pub subscription: Pubkey,
+    pub payer: Pubkey,
+    pub previous_amount: u64,
+    pub new_amount: u64,
+    pub timestamp: i64,
+}
+
+// Emitted by `send_payment_compact`; carries the full preimage of the stored hash
+#[event]
+pub struct CompactPaymentSent {
+    pub sender: Pubkey,
+    pub receiver: Pubkey,
+    pub amount: u64,
+    pub memo: String,
+    pub timestamp: i64,
+    pub index: u64, // Seeds the receipt PDA with the sender
+    pub hash: [u8; 32],
+}
+
+// Emitted when a receipt changes hands, so indexers can follow record ownership
+#[event]
+pub struct RecordOwnershipTransferred {
+    pub record: Pubkey,
+    pub previous_owner: Pubkey,
+    pub new_owner: Pubkey,
+    pub timestamp: i64,
+}
+
+// Emitted by `send_batch_payment` just before it fails on an invalid payment. The
+// transaction still reverts; the event only survives in its logs.
+#[event]
+pub struct BatchPaymentFailed {
+    pub index: u8, // Position in the `payments` argument
+    pub reason: String, // Name of the error the batch fails with
+}
+
+// PDA derivations for off-chain Rust clients. Keep these in sync with the `seeds`
+// constraints of the account structs above.
+
+// Record written by a sender's payment number `sequence` (their `transaction_count` at the time)
+pub fn transaction_record_pda(sender: &Pubkey, sequence: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"transaction", sender.as_ref(), sequence.to_le_bytes().as_ref()], &crate::ID)
+}
+
+pub fn program_state_pda() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"state"], &crate::ID)
+}
+
+#[cfg(test)]
+mod pda_tests {
+    use super::*;
+
+    #[test]
+    fn transaction_record_pda_uses_the_record_seeds() {
+        let sender = Pubkey::new_unique();
+        let (address, bump) = transaction_record_pda(&sender, 1);
+        // Same seeds as SendPayment's `transaction_record`, the sequence little-endian
+        let seeds: &[&[u8]] = &[b"transaction", sender.as_ref(), &1u64.to_le_bytes(), &[bump]];
+        assert_eq!(Pubkey::create_program_address(seeds, &crate::ID).unwrap(), address);
+        assert_ne!(address, Pubkey::find_program_address(&[b"transaction", sender.as_ref(), &1u64.to_be_bytes()], &crate::ID).0);
+        assert_ne!(address, transaction_record_pda(&sender, 0).0);
+        assert_ne!(address, transaction_record_pda(&Pubkey::new_unique(), 1).0);
+    }
+
+    #[test]
+    fn program_state_pda_uses_the_state_seed() {
+        let (address, bump) = program_state_pda();
+        assert_eq!(Pubkey::create_program_address(&[b"state", &[bump]], &crate::ID).unwrap(), address);
+    }
+}
+
+// The note never reaches the receiver's UI, so only its size is limited
+fn validate_note(note: &str) -> Result<()> {
+    if note.chars().count() > MAX_NOTE_LENGTH {
+        return err!(ErrorCode::NoteTooLong);
+    }
+    Ok(())
+}
+
+// Shared validation for every payment instruction (SOL and SPL token).
+fn validate_payment(sender: &Pubkey, receiver: &Pubkey, amount: u64, memo: &str, max_memo_length: usize) -> Result<()> {
+    // Check 1: Amount > 0
+    if amount == 0 {
+        return err!(ErrorCode::InvalidAmount);
+    }
+    // Check 2: Sender != Receiver
+    if sender == receiver {
+        return err!(ErrorCode::SelfPayment);
+    }
+    // Check 3: Memo Length and Content
+    validate_memo(memo, max_memo_length)
+}
+
+// Single pass over the memo: enforces the character limit and rejects control
+// characters (other than tab and newlines) that break log parsers and UIs.
+// Limits above MAX_MEMO_LENGTH can't assume 4 bytes per char, so the byte size
+// reserved in every record is checked as well.
+fn validate_memo(memo: &str, max_memo_length: usize) -> Result<()> {
+    validate_memo_within(memo, max_memo_length, MAX_MEMO_BYTES)
+}
+
+// validate_memo for a memo stored in `max_memo_bytes` rather than MAX_MEMO_BYTES
+fn validate_memo_within(memo: &str, max_memo_length: usize, max_memo_bytes: usize) -> Result<()> {
+    if memo.len() > max_memo_bytes {
+        return err!(ErrorCode::MemoTooLong);
+    }
+    let mut length = 0;
+    for c in memo.chars() {
+        length += 1;
+        if length > max_memo_length {
+            return err!(ErrorCode::MemoTooLong);
+        }
+        if c.is_control() && !matches!(c, '\t' | '\n' | '\r') {
+            return err!(ErrorCode::InvalidMemoContent);
+        }
+    }
+    Ok(())
+}
+
+// An escrow that no longer holds its amount on top of rent has already been paid out.
+fn ensure_escrow_funded(escrow: &AccountInfo, amount: u64) -> Result<()> {
+    let rent_exempt_minimum = Rent::get()?.minimum_balance(escrow.data_len());
+    let required = amount.checked_add(rent_exempt_minimum).ok_or(ErrorCode::Overflow)?;
+    if escrow.lamports() < required {
+        return err!(ErrorCode::EscrowAlreadySettled);
+    }
+    Ok(())
+}
+
+// Protocol fee for a gross `amount`, rounded per `rounding`. Callers pay the receiver
+// `amount - fee`, so fee and net always add back up to the gross amount.
+fn compute_fee(amount: u64, fee_bps: u16, rounding: FeeRounding) -> Result<u64> {
+    let scaled = amount.checked_mul(fee_bps as u64).ok_or(ErrorCode::Overflow)?;
+    let bias = match rounding {
+        FeeRounding::Floor => 0,
+        FeeRounding::Ceil => BPS_DENOMINATOR - 1,
+        FeeRounding::Nearest => BPS_DENOMINATOR / 2, // Halves round up
+    };
+    let fee = scaled
+        .checked_add(bias)
+        .ok_or(ErrorCode::Overflow)?
+        .checked_div(BPS_DENOMINATOR)
+        .ok_or(ErrorCode::Overflow)?;
+    Ok(fee)
+}
+
+#[cfg(test)]
+mod fee_tests {
+    use super::*;
+
+    #[test]
+    fn compute_fee_rounds_per_mode() {
+        // 10_001 * 25 bps = 25.0025 lamports
+        assert_eq!(compute_fee(10_001, 25, FeeRounding::Floor).unwrap(), 25);
+        assert_eq!(compute_fee(10_001, 25, FeeRounding::Ceil).unwrap(), 26);
+        assert_eq!(compute_fee(10_001, 25, FeeRounding::Nearest).unwrap(), 25);
+        // Exactly half a lamport: only Floor drops it
+        assert_eq!(compute_fee(200, 25, FeeRounding::Floor).unwrap(), 0);
+        assert_eq!(compute_fee(200, 25, FeeRounding::Ceil).unwrap(), 1);
+        assert_eq!(compute_fee(200, 25, FeeRounding::Nearest).unwrap(), 1);
+        // Whole lamports and a zero fee are the same in every mode
+        for rounding in [FeeRounding::Floor, FeeRounding::Ceil, FeeRounding::Nearest] {
+            assert_eq!(compute_fee(10_000, 25, rounding).unwrap(), 25);
+            assert_eq!(compute_fee(10_001, 0, rounding).unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn compute_fee_reports_overflow_instead_of_wrapping() {
+        assert_eq!(compute_fee(u64::MAX, 2, FeeRounding::Floor).unwrap_err(), error!(ErrorCode::Overflow));
+        // The rounding bias can overflow where the product alone fits
+        assert_eq!(compute_fee(u64::MAX, 1, FeeRounding::Floor).unwrap(), u64::MAX / BPS_DENOMINATOR);
+        assert_eq!(compute_fee(u64::MAX, 1, FeeRounding::Ceil).unwrap_err(), error!(ErrorCode::Overflow));
+    }
+}
+
+// Consumes the sender's next sequence number, links the new record to the previous
+// head of the sender's history and bumps the global analytics count.
+// Returns the sequence used in the transaction record's PDA seeds.
+fn advance_sequence(
+    transaction_record: &mut TransactionRecord,
+    user_state: &mut UserState,
+    program_state: &mut ProgramState,
+) -> Result<u64> {
+    let sequence = user_state.transaction_count;
+    // Every new sequence must directly follow the last recorded one, so 0..=last_sequence
+    // always enumerates the sender's full history without holes.
+    let expected = if sequence == 0 {
+        0
+    } else {
+        user_state.last_sequence.checked_add(1).ok_or(ErrorCode::Overflow)?
+    };
+    if sequence != expected {
+        return err!(ErrorCode::SequenceMismatch);
+    }
+    // Clients page backwards from `head_sequence` via `prev_sequence`. User states
+    // created before the head was tracked fall back to `last_sequence`, which they
+    // have kept since their first record.
+    transaction_record.prev_sequence = user_state
+        .head_sequence
+        .or((sequence > 0).then_some(user_state.last_sequence));
+    user_state.head_sequence = Some(sequence);
+    user_state.last_sequence = sequence;
+    // Use checked_add for safety against overflow
+    user_state.transaction_count = sequence.checked_add(1).ok_or(ErrorCode::Overflow)?;
+    program_state.total_transactions = program_state.total_transactions.checked_add(1).ok_or(ErrorCode::Overflow)?;
+    Ok(sequence)
+}
+
+// Encrypted memos are opaque bytes; only the length and exclusivity are checked on-chain.
+fn validate_encrypted_memo(memo: &str, encrypted_memo: &[u8]) -> Result<()> {
+    if encrypted_memo.len() > MAX_ENCRYPTED_MEMO_BYTES {
+        return err!(ErrorCode::EncryptedMemoTooLong);
+    }
+    if !memo.is_empty() && !encrypted_memo.is_empty() {
+        return err!(ErrorCode::ConflictingMemos);
+    }
+    Ok(())
+}
+
+// Reads a fully verified, fresh price for `feed_id` from a Pyth `PriceUpdateV2` account
+fn load_pyth_price(price_update: &AccountInfo, feed_id: &[u8; 32], now: i64) -> Result<i64> {
+    Ok(load_pyth_price_message(price_update, feed_id, now)?.price)
+}
+
+// Same checks as `load_pyth_price`, but keeps the exponent for unit conversions
+fn load_pyth_price_message(price_update: &AccountInfo, feed_id: &[u8; 32], now: i64) -> Result<PythPriceFeedMessage> {
+    if price_update.owner != &PYTH_RECEIVER_PROGRAM_ID {
+        return err!(ErrorCode::InvalidOracle);
+    }
+    let data = price_update.try_borrow_data()?;
+    if data.len() < DISCRIMINATOR_LENGTH || data[..DISCRIMINATOR_LENGTH] != PRICE_UPDATE_V2_DISCRIMINATOR {
+        return err!(ErrorCode::InvalidOracle);
+    }
+    let update = PythPriceUpdateV2::deserialize(&mut &data[DISCRIMINATOR_LENGTH..])
+        .map_err(|_| error!(ErrorCode::InvalidOracle))?;
+    // Partially verified updates have not been checked against the full guardian set
+    if !matches!(update.verification_level, PythVerificationLevel::Full) {
+        return err!(ErrorCode::InvalidOracle);
+    }
+    if update.price_message.feed_id != *feed_id {
+        return err!(ErrorCode::InvalidOracle);
+    }
+    if update.price_message.publish_time < now.saturating_sub(MAX_PRICE_AGE_SECS) {
+        return err!(ErrorCode::StaleOracle);
+    }
+    Ok(update.price_message)
+}
+
+// Converts micro-USD into lamports at a SOL/USD price of `price * 10^exponent`,
+// rounding down: lamports = usd_amount * 10^(9 - 6 - exponent) / price
+fn usd_to_lamports(usd_amount: u64, price: i64, exponent: i32) -> Result<u64> {
+    if price <= 0 {
+        return err!(ErrorCode::InvalidOracle);
+    }
+    let scale = 9 - USD_AMOUNT_DECIMALS as i32 - exponent;
+    let pow = |exp: i32| 10u128.checked_pow(exp.unsigned_abs()).ok_or(ErrorCode::Overflow);
+    let (numerator, denominator) = if scale >= 0 {
+        ((usd_amount as u128).checked_mul(pow(scale)?).ok_or(ErrorCode::Overflow)?, price as u128)
+    } else {
+        (usd_amount as u128, (price as u128).checked_mul(pow(scale)?).ok_or(ErrorCode::Overflow)?)
+    };
+    u64::try_from(numerator / denominator).map_err(|_| error!(ErrorCode::Overflow))
+}
+
+#[cfg(test)]
+mod usd_conversion_tests {
+    use super::*;
+
+    #[test]
+    fn usd_to_lamports_scales_by_the_price_exponent() {
+        // $1 at $150/SOL, as Pyth reports it (exponent -8): 1/150 SOL, rounded down
+        assert_eq!(usd_to_lamports(1_000_000, 15_000_000_000, -8).unwrap(), 6_666_666);
+        // The same price with fewer decimals converts the same
+        assert_eq!(usd_to_lamports(1_000_000, 150, 0).unwrap(), 6_666_666);
+        // A positive exponent scales the price up instead: $1 at $10,000/SOL
+        assert_eq!(usd_to_lamports(1_000_000, 1, 4).unwrap(), 100_000);
+    }
+
+    #[test]
+    fn usd_to_lamports_rejects_bad_prices_and_overflow() {
+        assert_eq!(usd_to_lamports(1_000_000, 0, -8).unwrap_err(), error!(ErrorCode::InvalidOracle));
+        assert_eq!(usd_to_lamports(1_000_000, -1, -8).unwrap_err(), error!(ErrorCode::InvalidOracle));
+        // 10^103 doesn't fit in a u128
+        assert_eq!(usd_to_lamports(1_000_000, 1, -100).unwrap_err(), error!(ErrorCode::Overflow));
+        // Nor does the result in a u64
+        assert_eq!(usd_to_lamports(u64::MAX, 1, -8).unwrap_err(), error!(ErrorCode::Overflow));
+    }
+}
+
+// A user vault may be emptied completely, but anything left behind must stay rent-exempt
+fn ensure_vault_remainder(vault: &AccountInfo, amount: u64) -> Result<()> {
+    let remaining = vault.lamports().checked_sub(amount).ok_or(ErrorCode::InsufficientBalance)?;
+    if remaining > 0 && remaining < Rent::get()?.minimum_balance(0) {
+        return err!(ErrorCode::InsufficientBalance);
+    }
+    Ok(())
+}
+
+// Moves lamports out of the `[b"vault", owner]` PDA, which signs the System Program
+// transfer via `invoke_signed` under the hood.
+fn vault_transfer<'info>(
+    system_program: &Program<'info, System>,
+    vault: &AccountInfo<'info>,
+    to: &AccountInfo<'info>,
+    owner: &Pubkey,
+    bump: u8,
+    amount: u64,
+) -> Result<()> {
+    let signer_seeds: &[&[&[u8]]] = &[&[b"vault", owner.as_ref(), &[bump]]];
+    let cpi_context = CpiContext::new_with_signer(
+        system_program.to_account_info(),
+        system_program::Transfer {
+            from: vault.clone(),
+            to: to.clone(),
+        },
+        signer_seeds,
+    );
+    system_program::transfer(cpi_context, amount)
+}
+
+// The runtime takes the transaction fee before the handler runs, so the balance is
+// already net of it. What is left must cover `amount`, the rent of the record the
+// handler creates next (`record_rent`, 0 when it already exists) and, unless the
+// payment empties the account, keep the sender itself rent-exempt: the runtime rejects
+// a transaction that leaves an account with a non-zero balance below that minimum.
+// The record is only created after this check, so a short balance fails here with the
+// full breakdown instead of as a generic System Program or rent failure.
+fn ensure_covers_rent_and_payment(sender: &AccountInfo, amount: u64, record_rent: u64) -> Result<()> {
+    let sender_rent = Rent::get()?.minimum_balance(sender.data_len());
+    let covered = match sender.lamports().checked_sub(amount).and_then(|left| left.checked_sub(record_rent)) {
+        Some(remaining) => remaining == 0 || remaining >= sender_rent,
+        None => false,
+    };
+    if !covered {
+        msg!("Sender has {} lamports, needs {}: {} payment + {} record rent + {} to stay rent-exempt",
+             sender.lamports(),
+             amount.saturating_add(record_rent).saturating_add(sender_rent),
+             amount,
+             record_rent,
+             sender_rent);
+        return err!(ErrorCode::InsufficientForRentAndPayment);
+    }
+    Ok(())
+}
+
+// Creates a program account at a PDA from the handler and writes `value` into it, for
+// records whose rent the payment's own checks must see first: Anchor runs every `init`
+// before any other constraint, so the rent would already be gone. An address someone
+// pre-funded is topped up and taken over, as `init` does.
+fn create_program_account<'info, T: AccountSerialize>(
+    payer: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+    system_program: &Program<'info, System>,
+    space: usize,
+    signer_seeds: &[&[u8]],
+    value: &T,
+) -> Result<()> {
+    if account.owner == &crate::ID {
+        return err!(ErrorCode::RecordAlreadyExists);
+    }
+    let signers = &[signer_seeds];
+    let rent = Rent::get()?.minimum_balance(space);
+    let current_lamports = account.lamports();
+    if current_lamports == 0 {
+        let cpi_context = CpiContext::new_with_signer(
+            system_program.to_account_info(),
+            system_program::CreateAccount {
+                from: payer.clone(),
+                to: account.clone(),
+            },
+            signers,
+        );
+        system_program::create_account(cpi_context, rent, space as u64, &crate::ID)?;
+    } else {
+        let top_up = rent.saturating_sub(current_lamports);
+        if top_up > 0 {
+            let cpi_context = CpiContext::new(
+                system_program.to_account_info(),
+                system_program::Transfer {
+                    from: payer.clone(),
+                    to: account.clone(),
+                },
+            );
+            system_program::transfer(cpi_context, top_up)?;
+        }
+        let allocate_context = CpiContext::new_with_signer(
+            system_program.to_account_info(),
+            system_program::Allocate { account_to_allocate: account.clone() },
+            signers,
+        );
+        system_program::allocate(allocate_context, space as u64)?;
+        let assign_context = CpiContext::new_with_signer(
+            system_program.to_account_info(),
+            system_program::Assign { account_to_assign: account.clone() },
+            signers,
+        );
+        system_program::assign(assign_context, &crate::ID)?;
+    }
+    value.try_serialize(&mut &mut account.try_borrow_mut_data()?[..])
+}
+
+// Serializes `value` over an account's data and zeroes whatever follows it. Options,
+// Strings and Vecs can shrink, and a plain write leaves the old bytes past the new
+// end, where a field appended by a later version would read them.
+fn write_account_data<T: AccountSerialize>(value: &T, data: &mut [u8]) -> Result<()> {
+    let unused = {
+        let mut writer = &mut data[..];
+        value.try_serialize(&mut writer)?;
+        writer.len()
+    };
+    let written = data.len() - unused;
+    data[written..].fill(0);
+    Ok(())
+}
+
+// Called by a handler before it shrinks a variable-length field on an `Account`:
+// Anchor's exit write then lands on cleared data and leaves zeroes past the new end,
+// as write_account_data does
+fn clear_account_data(account: &AccountInfo) -> Result<()> {
+    account.try_borrow_mut_data()?[DISCRIMINATOR_LENGTH..].fill(0);
+    Ok(())
+}
+
+// Creates the `[b"transaction", sender, sequence]` record of a payment once it is
+// filled in, see create_program_account. `space` is TransactionRecord::LEN, or
+// CODED_LEN for a record without memo room.
+fn create_transaction_record<'info>(
+    payer: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+    system_program: &Program<'info, System>,
+    space: usize,
+    transaction_record: &TransactionRecord,
+    sequence: u64,
+    bump: u8,
+) -> Result<()> {
+    let sequence_bytes = sequence.to_le_bytes();
+    let signer_seeds: &[&[u8]] = &[b"transaction", transaction_record.sender.as_ref(), &sequence_bytes, &[bump]];
+    create_program_account(payer, account, system_program, space, signer_seeds, transaction_record)
+}
+
+// Split and batch remaining accounts start with the fee split collectors, one per
+// configured share as for send_payment (none without a split). The receivers follow
+// in groups of ACCOUNTS_PER_RECEIVER: the writable receiver, its `[b"blacklist",
+// receiver]` and `[b"whitelist", receiver]` PDAs, then its writable `[b"received",
+// receiver]` stats PDA. Remaining accounts get no Anchor constraints, so the PDA
+// addresses are checked here.
+const ACCOUNTS_PER_RECEIVER: usize = 4;
+
+struct ReceiverAccounts<'a, 'info> {
+    receiver: &'a AccountInfo<'info>,
+    blacklist: &'a AccountInfo<'info>,
+    whitelist: &'a AccountInfo<'info>,
+    stats: &'a AccountInfo<'info>,
+    stats_bump: u8,
+}
+
+type SplitAccounts<'a, 'info> = (&'a [AccountInfo<'info>], Vec<ReceiverAccounts<'a, 'info>>);
+
+fn split_remaining_accounts<'a, 'info>(program_state: &ProgramState, accounts: &'a [AccountInfo<'info>]) -> Result<SplitAccounts<'a, 'info>> {
+    let collector_count = program_state.fee_split.len();
+    if accounts.len() < collector_count {
+        return err!(ErrorCode::InvalidFeeCollector);
+    }
+    let (fee_split_collectors, receivers) = accounts.split_at(collector_count);
+    Ok((fee_split_collectors, receiver_accounts(receivers)?))
+}
+
+fn receiver_accounts<'a, 'info>(accounts: &'a [AccountInfo<'info>]) -> Result<Vec<ReceiverAccounts<'a, 'info>>> {
+    if !accounts.len().is_multiple_of(ACCOUNTS_PER_RECEIVER) {
+        return err!(ErrorCode::SplitLengthMismatch);
+    }
+    accounts
+        .chunks(ACCOUNTS_PER_RECEIVER)
+        .map(|group| {
+            let (receiver, blacklist, whitelist, stats) = (&group[0], &group[1], &group[2], &group[3]);
+            ensure_pda(blacklist, &[b"blacklist", receiver.key.as_ref()])?;
+            ensure_pda(whitelist, &[b"whitelist", receiver.key.as_ref()])?;
+            let stats_bump = ensure_pda(stats, &[b"received", receiver.key.as_ref()])?;
+            Ok(ReceiverAccounts { receiver, blacklist, whitelist, stats, stats_bump })
+        })
+        .collect()
+}
+
+impl<'info> ReceiverAccounts<'_, 'info> {
+    // The receiver is not blacklisted and, while the whitelist is on, has a live entry
+    fn ensure_may_be_paid(&self, program_state: &ProgramState) -> Result<()> {
+        ensure_not_blacklisted(self.blacklist)?;
+        ensure_whitelisted(program_state.whitelist_enabled, whitelist_entry_at(self.whitelist)?.as_ref())
+    }
+
+    // ReceiverStats::record_payment without `init_if_needed`: the stats are created on
+    // the receiver's first payment, paid for by `payer`, and updated in place after
+    fn record_payment(&self, payer: &AccountInfo<'info>, system_program: &Program<'info, System>, amount: u64, max_total_received: u64) -> Result<()> {
+        if self.stats.owner == &crate::ID {
+            let mut receiver_stats = ReceiverStats::try_deserialize(&mut &self.stats.try_borrow_data()?[..])?;
+            receiver_stats.record_payment(amount, max_total_received)?;
+            return receiver_stats.try_serialize(&mut &mut self.stats.try_borrow_mut_data()?[..]);
+        }
+        let mut receiver_stats = ReceiverStats {
+            receiver: self.receiver.key(),
+            total_received: 0,
+            payment_count: 0,
+            bump: self.stats_bump,
+        };
+        receiver_stats.record_payment(amount, max_total_received)?;
+        let signer_seeds: &[&[u8]] = &[b"received", self.receiver.key.as_ref(), &[self.stats_bump]];
+        create_program_account(payer, self.stats, system_program, ReceiverStats::LEN, signer_seeds, &receiver_stats)
+    }
+}
+
+// Seeds check for accounts Anchor can't constrain; returns the canonical bump
+fn ensure_pda(account: &AccountInfo, seeds: &[&[u8]]) -> Result<u8> {
+    let (expected, bump) = Pubkey::find_program_address(seeds, &crate::ID);
+    if account.key() != expected {
+        return err!(anchor_lang::error::ErrorCode::ConstraintSeeds);
+    }
+    Ok(bump)
+}
+
+// The caller passes the `[b"blacklist", address]` PDA whether or not it exists. The
+// address is verified by seeds, so an initialized, program-owned account there means
+// the address is blocked; an empty system account means it is not.
+fn ensure_not_blacklisted(blacklist: &AccountInfo) -> Result<()> {
+    if blacklist.owner == &crate::ID && !blacklist.data_is_empty() {
+        return err!(ErrorCode::AddressBlacklisted);
+    }
+    Ok(())
+}
+
+// A `PendingTransfer` lives at the payment's pinned address. It is closed once
+// resolved, so an existing one still holds the payment for the receiver.
+fn is_transfer_held(pending_transfer: &AccountInfo) -> bool {
+    pending_transfer.owner == &crate::ID && !pending_transfer.data_is_empty()
+}
+
+// An `AcceptancePolicy` lives at the receiver's pinned address
+fn is_acceptance_required(acceptance_policy: &AccountInfo) -> bool {
+    acceptance_policy.owner == &crate::ID && !acceptance_policy.data_is_empty()
+}
+
+// `timestamp + secs` for deadlines and due dates. Intervals are caller-supplied, so a
+// huge one is rejected instead of wrapping around to a time in the past.
+fn add_secs(timestamp: i64, secs: i64) -> Result<i64> {
+    timestamp.checked_add(secs).ok_or(error!(ErrorCode::TimestampOverflow))
+}
+
+// `expires_at == 0` means the payment never expires
+fn is_expired(expires_at: i64, now: i64) -> bool {
+    expires_at != 0 && now > expires_at
+}
+
+// The `[b"whitelist", address]` PDA of a split or batch receiver, passed whether or
+// not an entry exists: same presence check as ensure_not_blacklisted
+fn whitelist_entry_at(whitelist: &AccountInfo) -> Result<Option<WhitelistEntry>> {
+    if whitelist.owner != &crate::ID || whitelist.data_is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(WhitelistEntry::try_deserialize(&mut &whitelist.try_borrow_data()?[..])?))
+}
+
+// While the whitelist is enabled the receiver needs an entry that hasn't expired
+fn ensure_whitelisted(whitelist_enabled: bool, whitelist_entry: Option<&WhitelistEntry>) -> Result<()> {
+    if !whitelist_enabled {
+        return Ok(());
+    }
+    let whitelist_entry = whitelist_entry.ok_or(ErrorCode::ReceiverNotWhitelisted)?;
+    if is_expired(whitelist_entry.expires_at, Clock::get()?.unix_timestamp) {
+        return err!(ErrorCode::WhitelistEntryExpired);
+    }
+    Ok(())
+}
+
+// Policy PDAs of the paying side. Each context pins the blacklist and freeze
+// addresses by their seeds, so they can't be left out; the spend limit is only
+// required once the sender has opted in to one.
+struct SenderPolicy<'a, 'info> {
+    blacklist: &'a AccountInfo<'info>,
+    freeze: &'a AccountInfo<'info>,
+    spend_limit_enabled: bool,
+    spend_limit: Option<&'a mut SpendLimit>,
+}
+
+// Compliance holds, the sender's daily limit and the multisig threshold, checked
+// against everything leaving the sender in one instruction (every leg of a split,
+// the tip included)
+fn ensure_sender_may_pay(program_state: &ProgramState, sender: SenderPolicy, spent: u64) -> Result<()> {
+    ensure_not_blacklisted(sender.blacklist)?;
+    ensure_not_frozen(sender.freeze)?;
+    record_sender_spend(sender.spend_limit_enabled, sender.spend_limit, spent)?;
+    ensure_no_approval_required(program_state, spent)
+}
+
+// Once a sender has set a daily limit every outgoing payment needs their SpendLimit
+fn record_sender_spend(spend_limit_enabled: bool, spend_limit: Option<&mut SpendLimit>, amount: u64) -> Result<()> {
+    if spend_limit_enabled {
+        let spend_limit = spend_limit.ok_or(ErrorCode::MissingSpendLimit)?;
+        spend_limit.record_spend(amount, Clock::get()?.unix_timestamp)?;
+    }
+    Ok(())
+}
+
+// Large payments must go through propose/approve/execute
+fn ensure_no_approval_required(program_state: &ProgramState, amount: u64) -> Result<()> {
+    if program_state.multisig.requires_approval(amount) {
+        return err!(ErrorCode::PaymentRequiresApproval);
+    }
+    Ok(())
+}
+
+// Configured minimum and per-payment cap (0 disables either), checked per payment
+fn ensure_amount_within_bounds(program_state: &ProgramState, amount: u64) -> Result<()> {
+    if amount < program_state.min_amount {
+        return err!(ErrorCode::AmountBelowMinimum);
+    }
+    let max_single_payment = program_state.max_single_payment;
+    if max_single_payment != 0 && amount > max_single_payment {
+        return err!(ErrorCode::AmountAboveMaximum);
+    }
+    Ok(())
+}
+
+// Receiver-side rules for lamports paid straight to the receiver. Only send_payment
+// can hold a payment in a PendingTransfer, so receivers who require acceptance are
+// refused by every other instruction rather than paid around their policy.
+fn ensure_receiver_may_be_paid(
+    program_state: &ProgramState,
+    receiver_blacklist: &AccountInfo,
+    receiver_acceptance: &AccountInfo,
+    whitelist_entry: Option<&WhitelistEntry>,
+) -> Result<()> {
+    ensure_not_blacklisted(receiver_blacklist)?;
+    if is_acceptance_required(receiver_acceptance) {
+        return err!(ErrorCode::ReceiverRequiresAcceptance);
+    }
+    ensure_whitelisted(program_state.whitelist_enabled, whitelist_entry)
+}
+
+// Where the lamports of a payment come from
+enum PaymentSource<'a, 'info> {
+    // A signing wallet, debited through the System Program
+    Wallet(&'a AccountInfo<'info>),
+    // The owner's `[b"vault", owner]` PDA, which the program signs for
+    Vault { vault: &'a AccountInfo<'info>, owner: Pubkey, bump: u8 },
+}
+
+impl<'info> PaymentSource<'_, 'info> {
+    fn transfer(&self, system_program: &Program<'info, System>, to: &AccountInfo<'info>, amount: u64) -> Result<()> {
+        match self {
+            PaymentSource::Wallet(from) => {
+                let cpi_context = CpiContext::new(
+                    system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: (*from).clone(),
+                        to: to.clone(),
+                    },
+                );
+                system_program::transfer(cpi_context, amount)
+            }
+            PaymentSource::Vault { vault, owner, bump } => vault_transfer(system_program, vault, to, owner, *bump, amount),
+        }
+    }
+}
+
+// Everything a single-receiver native payment is checked and settled against. The
+// send_payment variants build one of these and call `validate_and_charge`, so every
+// rule applies no matter which instruction moves the lamports.
+struct DirectPayment<'a, 'info> {
+    program_state: &'a mut ProgramState,
+    system_program: &'a Program<'info, System>,
+    source: PaymentSource<'a, 'info>,
+    receiver: &'a AccountInfo<'info>,
+    fee_collector: &'a AccountInfo<'info>,
+    fee_split_collectors: &'a [AccountInfo<'info>], // `remaining_accounts`, see fee_payouts
+    sender_policy: SenderPolicy<'a, 'info>,
+    receiver_blacklist: &'a AccountInfo<'info>,
+    receiver_acceptance: &'a AccountInfo<'info>,
+    whitelist_entry: Option<&'a WhitelistEntry>,
+    receiver_stats: &'a mut ReceiverStats,
+    receiver_stats_bump: u8,
+    payer: &'a AccountInfo<'info>, // Whose payment this is: the wallet, or the vault's owner
+    hook_program: Option<&'a AccountInfo<'info>>,
+    sender_ledger: &'a mut UserLedger,
+    sender_ledger_bump: u8,
+    receiver_ledger: &'a mut UserLedger,
+    receiver_ledger_bump: u8,
+    fee_exempt: bool,
+    allow_program_receiver: bool, // Opt out of the System Program ownership rule
+    allow_below_rent: bool,
+    // Set when the receiver requires acceptance: the whole amount, fee included, is
+    // held there and only reaches the receiver's stats and ledger once accepted
+    pending_transfer: Option<&'a AccountInfo<'info>>,
+}
+
+impl DirectPayment<'_, '_> {
+    // Checks the payment of `amount` against the deployment's policy, pays the
+    // receiver its share and the fee collectors the fee, and updates the receiver's
+    // stats, both ledgers and the lifetime totals, then notifies the hook. `spent` is
+    // what leaves the sender in total and counts against their limits (`amount` plus
+    // any tip). Returns the fee.
+    fn validate_and_charge(self, amount: u64, spent: u64) -> Result<u64> {
+        ensure_amount_within_bounds(self.program_state, amount)?;
+        // Lamports sent to a program account itself would be stuck for good; other
+        // programs' PDAs only when the caller opts out of the ownership rule
+        if self.receiver.executable {
+            return err!(ErrorCode::ReceiverIsExecutable);
+        }
+        if !self.allow_program_receiver && *self.receiver.owner != system_program::ID {
+            return err!(ErrorCode::InvalidReceiver);
+        }
+        match self.pending_transfer {
+            // Acceptance is what the hold is for
+            Some(_) => {
+                ensure_not_blacklisted(self.receiver_blacklist)?;
+                ensure_whitelisted(self.program_state.whitelist_enabled, self.whitelist_entry)?;
+            }
+            None => ensure_receiver_may_be_paid(self.program_state, self.receiver_blacklist, self.receiver_acceptance, self.whitelist_entry)?,
+        }
+        ensure_sender_may_pay(self.program_state, self.sender_policy, spent)?;
+
+        let fee = if self.fee_exempt {
+            0
+        } else {
+            compute_fee(amount, self.program_state.fee_bps, self.program_state.fee_rounding)?
+        };
+        let net_amount = amount.checked_sub(fee).ok_or(ErrorCode::Overflow)?;
+        match self.pending_transfer {
+            // The fee is escrowed with the payment and only collected if it's accepted
+            Some(pending_transfer) => self.source.transfer(self.system_program, pending_transfer, amount)?,
+            None => {
+                // A tiny first payment must not leave the receiver below rent exemption,
+                // unless the caller explicitly opts out. The runtime's own rent-state
+                // check still applies either way; this just fails early with a clear error.
+                if !self.allow_below_rent {
+                    let receiver_balance = self.receiver.lamports().checked_add(net_amount).ok_or(ErrorCode::Overflow)?;
+                    if receiver_balance < Rent::get()?.minimum_balance(self.receiver.data_len()) {
+                        return err!(ErrorCode::ReceiverWouldNotBeRentExempt);
+                    }
+                }
+                self.source.transfer(self.system_program, self.receiver, net_amount)?;
+                for (collector, part) in fee_payouts(fee, self.program_state, self.fee_collector, self.fee_split_collectors)? {
+                    self.source.transfer(self.system_program, collector, part)?;
+                }
+            }
+        }
+
+        let held = self.pending_transfer.is_some();
+        self.receiver_stats.claim(self.receiver.key(), self.receiver_stats_bump);
+        self.sender_ledger.claim(self.payer.key(), self.sender_ledger_bump);
+        self.receiver_ledger.claim(self.receiver.key(), self.receiver_ledger_bump);
+        self.sender_ledger.record_sent(spent)?;
+        if !held {
+            self.receiver_stats.record_payment(net_amount, self.program_state.max_total_received)?;
+            self.receiver_ledger.record_received(net_amount)?;
+        }
+        if let Some(hook_program_id) = self.program_state.hook_program {
+            let hook_program = self.hook_program.ok_or(ErrorCode::InvalidHookProgram)?;
+            invoke_payment_hook(&hook_program_id, hook_program, self.payer, self.receiver, amount)?;
+        }
+        let program_state = self.program_state;
+        program_state.total_volume = program_state.total_volume.checked_add(spent).ok_or(ErrorCode::Overflow)?;
+        // Escrowed fees are counted by accept_transfer, once actually collected
+        let collected_fee = if held { 0 } else { fee };
+        program_state.total_fees_collected = program_state.total_fees_collected.checked_add(collected_fee).ok_or(ErrorCode::Overflow)?;
+        Ok(fee)
+    }
+}
+
+// Expected compute units of a split payment with `transfer_count` receivers.
+pub const fn split_payment_compute_units(transfer_count: u64) -> u64 {
+    SPLIT_BASE_CU_ESTIMATE + CU_PER_TRANSFER_ESTIMATE * transfer_count
+}
+
+// Expected compute units of a batch payment with `transfer_count` entries.
+pub const fn batch_payment_compute_units(transfer_count: u64) -> u64 {
+    BATCH_BASE_CU_ESTIMATE + CU_PER_TRANSFER_ESTIMATE * transfer_count
+}
+
+// Largest number of transfers an instruction costing `base_cu` plus one
+// CU_PER_TRANSFER_ESTIMATE per transfer can make within the default compute limit
+const fn max_transfers_for_compute_budget(base_cu: u64) -> usize {
+    ((DEFAULT_COMPUTE_UNIT_LIMIT - base_cu) / CU_PER_TRANSFER_ESTIMATE) as usize
+}
+
+fn ensure_within_compute_budget(transfer_count: usize, max_transfers: usize) -> Result<()> {
+    if transfer_count > max_transfers {
+        msg!("{} transfers exceed the compute budget; send in chunks of at most {}",
+             transfer_count,
+             max_transfers);
+        return err!(ErrorCode::BatchExceedsComputeBudget);
+    }
+    Ok(())
+}
+
+// CPI into the configured hook after a payment. The sender is passed without its
+// signer privilege so the hook can never move the sender's funds. A failed invoke is
+// logged with its original error and surfaces as PaymentHookFailed; either way the
+// whole transaction aborts, so the payment never lands without its hook.
+fn invoke_payment_hook<'info>(
+    hook_program_id: &Pubkey,
+    hook_program: &AccountInfo<'info>,
+    sender: &AccountInfo<'info>,
+    receiver: &AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    if hook_program.key() != *hook_program_id || !hook_program.executable {
+        return err!(ErrorCode::InvalidHookProgram);
+    }
+    let mut data = ON_PAYMENT_DISCRIMINATOR.to_vec();
+    data.extend_from_slice(&amount.to_le_bytes());
+    let instruction = Instruction {
+        program_id: *hook_program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(sender.key(), false),
+            AccountMeta::new_readonly(receiver.key(), false),
+        ],
+        data,
+    };
+    invoke(&instruction, &[sender.clone(), receiver.clone(), hook_program.clone()]).map_err(|error| {
+        msg!("Payment hook {} failed: {:?}", hook_program_id, error);
+        error!(ErrorCode::PaymentHookFailed)
+    })
+}
+
+// Divides `total` by basis-point shares that sum to BPS_DENOMINATOR. Each part is
+// rounded down and the remainder goes to the first, so the parts add up to `total`.
+fn split_by_bps(total: u64, shares: impl Iterator<Item = u16>) -> Result<Vec<u64>> {
+    let mut parts = shares
+        .map(|share| (total as u128 * share as u128 / BPS_DENOMINATOR as u128) as u64)
+        .collect::<Vec<u64>>();
+    let distributed = parts.iter().try_fold(0u64, |sum, part| sum.checked_add(*part)).ok_or(ErrorCode::Overflow)?;
+    if let Some(first) = parts.first_mut() {
+        *first = first
+            .checked_add(total.checked_sub(distributed).ok_or(ErrorCode::Overflow)?)
+            .ok_or(ErrorCode::Overflow)?;
+    }
+    Ok(parts)
+}
+
+#[cfg(test)]
+mod split_tests {
+    use super::*;
+
+    #[test]
+    fn split_by_bps_gives_the_dust_to_the_first_part() {
+        assert_eq!(split_by_bps(100, [3_333, 3_333, 3_334].into_iter()).unwrap(), vec![34, 33, 33]);
+        assert_eq!(split_by_bps(7, [5_000, 5_000].into_iter()).unwrap(), vec![4, 3]);
+        assert_eq!(split_by_bps(0, [5_000, 5_000].into_iter()).unwrap(), vec![0, 0]);
+        assert_eq!(split_by_bps(1_000, [10_000].into_iter()).unwrap(), vec![1_000]);
+    }
+
+    #[test]
+    fn split_by_bps_handles_the_full_u64_range() {
+        // The shares are taken in u128, so the largest total splits without overflow
+        let parts = split_by_bps(u64::MAX, [5_000, 5_000].into_iter()).unwrap();
+        assert_eq!(parts, vec![u64::MAX / 2 + 1, u64::MAX / 2]);
+    }
+}
+
+// Where a fee goes: all of it to the fee collector, or a share to each collector of
+// the configured split. Split collectors are passed as writable remaining accounts,
+// in split order. Zero payouts are left out.
+fn fee_payouts<'a, 'info>(
+    fee: u64,
+    program_state: &ProgramState,
+    fee_collector: &'a AccountInfo<'info>,
+    collectors: &'a [AccountInfo<'info>],
+) -> Result<Vec<(&'a AccountInfo<'info>, u64)>> {
+    let fee_split = &program_state.fee_split;
+    if fee == 0 {
+        return Ok(Vec::new());
+    }
+    if fee_split.is_empty() {
+        return Ok(vec![(fee_collector, fee)]);
+    }
+    if collectors.len() != fee_split.len() {
+        return err!(ErrorCode::InvalidFeeCollector);
+    }
+    // The rounding dust goes to the first collector
+    let parts = split_by_bps(fee, fee_split.iter().map(|share| share.share_bps))?;
+    let mut payouts = Vec::with_capacity(parts.len());
+    for ((share, collector), part) in fee_split.iter().zip(collectors).zip(parts) {
+        if collector.key() != share.collector {
+            return err!(ErrorCode::InvalidFeeCollector);
+        }
+        if part > 0 {
+            payouts.push((collector, part));
+        }
+    }
+    Ok(payouts)
+}
+
+fn emit_fee_changed(authority: Pubkey, program_state: &ProgramState) -> Result<()> {
+    emit!(FeeChanged {
+        authority,
+        fee_bps: program_state.fee_bps,
+        fee_collector: program_state.fee_collector,
+        fee_rounding: program_state.fee_rounding,
+        fee_split: program_state.fee_split.clone(),
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+fn emit_ledger_updated(ledger: &UserLedger) -> Result<()> {
+    emit!(LedgerUpdated {
+        user: ledger.user,
+        total_sent: ledger.total_sent,
+        total_received: ledger.total_received,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+fn emit_limit_changed(authority: Pubkey, limit: LimitKind, value: u64) -> Result<()> {
+    emit!(LimitChanged {
+        authority,
+        limit,
+        value,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+fn emit_access_list_changed(changed_by: Pubkey, list: AccessList, address: Pubkey, added: bool) -> Result<()> {
+    emit!(AccessListChanged {
+        changed_by,
+        list,
+        address,
+        added,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+// Fills in a MemoTemplate. The result can be longer than the template, so callers
+// validate it like any other memo.
+fn render_memo_template(template: &str, amount: u64, now: i64) -> String {
+    template
+        .replace("{amount}", &amount.to_string())
+        .replace("{date}", &format_date(now))
+}
+
+// UTC calendar date of a unix timestamp as YYYY-MM-DD (proleptic Gregorian)
+fn format_date(timestamp: i64) -> String {
+    // Days-to-civil conversion over 400-year eras, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = timestamp.div_euclid(86_400) + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod format_date_tests {
+    use super::*;
+
+    #[test]
+    fn format_date_handles_leap_days_and_era_boundaries() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(1_700_000_000), "2023-11-14");
+        assert_eq!(format_date(951_782_400), "2000-02-29"); // Divisible by 400: a leap year
+        assert_eq!(format_date(1_709_164_800), "2024-02-29");
+        assert_eq!(format_date(-2_203_891_201), "1900-02-28"); // Divisible by 100 only: no leap day
+        assert_eq!(format_date(-2_203_891_200), "1900-03-01");
+        assert_eq!(format_date(253_402_300_799), "9999-12-31");
+    }
+
+    #[test]
+    fn format_date_rounds_times_before_the_epoch_down_to_their_day() {
+        assert_eq!(format_date(-1), "1969-12-31");
+        assert_eq!(format_date(-86_400), "1969-12-31");
+        assert_eq!(format_date(-86_401), "1969-12-30");
+    }
+}
+
+// Same presence check as ensure_not_blacklisted for the `[b"freeze", sender]` PDA.
+// The discriminator is checked too, so no other program account can stand in for it.
+fn ensure_not_frozen(freeze_flag: &AccountInfo) -> Result<()> {
+    if freeze_flag.owner != &crate::ID {
+        return Ok(());
+    }
+    let data = freeze_flag.try_borrow_data()?;
+    if data.len() >= DISCRIMINATOR_LENGTH && &data[..DISCRIMINATOR_LENGTH] == FreezeFlag::DISCRIMINATOR {
+        return err!(ErrorCode::AccountFrozen);
+    }
+    Ok(())
+}
+
+// Surfaces which batch entry failed, then hands the error back unchanged
+fn report_batch_failure(index: usize, error: Error) -> Error {
+    let reason = match &error {
+        Error::AnchorError(anchor_error) => anchor_error.error_name.clone(),
+        Error::ProgramError(program_error) => program_error.program_error.to_string(),
+    };
+    msg!("Batch payment {} failed: {}", index, reason);
+    emit!(BatchPaymentFailed {
+        // Batches are capped at MAX_BATCH_SIZE, checked before any entry
+        index: index as u8,
+        reason,
+    });
+    error
+}
+
+// sha256(sender || receiver || amount as u64 LE || timestamp as i64 LE || memo UTF-8).
+// Off-chain verifiers recompute this from their copy of the payment.
+pub fn compact_receipt_hash(sender: &Pubkey, receiver: &Pubkey, amount: u64, timestamp: i64, memo: &str) -> [u8; 32] {
+    hashv(&[
+        sender.as_ref(),
+        receiver.as_ref(),
+        &amount.to_le_bytes(),
+        &timestamp.to_le_bytes(),
+        memo.as_bytes(),
+    ])
+    .to_bytes()
+}
+
+#[cfg(test)]
+mod compact_receipt_tests {
+    use super::*;
+
+    // Verifiers hash the same bytes, so the layout must never change silently
+    #[test]
+    fn compact_receipt_hash_pins_the_preimage_layout() {
+        let (sender, receiver) = (Pubkey::new_from_array([1; 32]), Pubkey::new_from_array([2; 32]));
+        let hash = compact_receipt_hash(&sender, &receiver, 1_000_000, 1_700_000_000, "Coffee ☕");
+        let hex: String = hash.iter().map(|byte| format!("{byte:02x}")).collect();
+        assert_eq!(hex, "30941a13d32b8689a32150ea17da04089b2969dc4795100eec18e7604dbf4474");
+        // Every field is part of the preimage
+        assert_ne!(hash, compact_receipt_hash(&receiver, &sender, 1_000_000, 1_700_000_000, "Coffee ☕"));
+        assert_ne!(hash, compact_receipt_hash(&sender, &receiver, 1_000_001, 1_700_000_000, "Coffee ☕"));
+        assert_ne!(hash, compact_receipt_hash(&sender, &receiver, 1_000_000, 1_700_000_001, "Coffee ☕"));
+        assert_ne!(hash, compact_receipt_hash(&sender, &receiver, 1_000_000, 1_700_000_000, "Coffee"));
+    }
 }
 
 #[error_code]
 pub enum ErrorCode {
-    #[msg("Memo cannot be longer than 200 characters.")]
+    #[msg("Memo is longer than the configured maximum memo length.")]
     MemoTooLong,
     #[msg("Operation overflowed.")]
     Overflow,
@@ -171,5 +9586,249 @@ pub enum ErrorCode {
     InsufficientBalance,
     #[msg("Receiver account is not valid for receiving SOL (must be system-owned).")]
     InvalidReceiver,
+    #[msg("Only the original sender can close this transaction record.")]
+    UnauthorizedClose,
+    #[msg("No transaction record exists for this sender and sequence.")]
+    TransactionNotFound,
+    #[msg("Split payments need one amount per receiver account.")]
+    SplitLengthMismatch,
+    #[msg("This escrow has already been settled.")]
+    EscrowAlreadySettled,
+    #[msg("Only the escrow sender can release or refund it.")]
+    UnauthorizedEscrowAction,
+    #[msg("Unlock time must be in the future.")]
+    InvalidUnlockTime,
+    #[msg("This payment is still time-locked.")]
+    TimelockNotExpired,
+    #[msg("The program is paused.")]
+    ProgramPaused,
+    #[msg("Only the program authority can perform this action.")]
+    Unauthorized,
+    #[msg("Protocol fee cannot exceed 1000 basis points.")]
+    FeeTooHigh,
+    #[msg("Fee collector does not match the program state.")]
+    InvalidFeeCollector,
+    #[msg("Subscription interval must be greater than zero.")]
+    InvalidInterval,
+    #[msg("Subscription is not due for payment yet.")]
+    SubscriptionNotDue,
+    #[msg("Subscription is not active.")]
+    SubscriptionInactive,
+    #[msg("Encrypted memo cannot be longer than 256 bytes.")]
+    EncryptedMemoTooLong,
+    #[msg("Provide either a plaintext memo or an encrypted memo, not both.")]
+    ConflictingMemos,
+    #[msg("Payment amount is below the configured minimum.")]
+    AmountBelowMinimum,
+    #[msg("Token mint is not valid for this instruction.")]
+    InvalidMint,
+    #[msg("Only the receiver of the original payment can refund it.")]
+    UnauthorizedRefund,
+    #[msg("Refund amount exceeds the original payment.")]
+    RefundExceedsOriginal,
+    #[msg("Receiver index does not match a provided account.")]
+    InvalidReceiverIndex,
+    #[msg("Receiver is not on the whitelist.")]
+    ReceiverNotWhitelisted,
+    #[msg("This invoice has already been paid.")]
+    InvoiceAlreadyPaid,
+    #[msg("Payment amount does not match the invoice.")]
+    InvoiceAmountMismatch,
+    #[msg("This payment would exceed the sender's daily spend limit.")]
+    DailyLimitExceeded,
+    #[msg("Sender has a daily spend limit; its account must be provided.")]
+    MissingSpendLimit,
+    #[msg("Multisig config must list at most 5 approvers and require between 1 and that many approvals.")]
+    InvalidMultisigConfig,
+    #[msg("Payments above the multisig threshold must be proposed and approved.")]
+    PaymentRequiresApproval,
+    #[msg("Signer is not a configured approver.")]
+    NotApprover,
+    #[msg("This approver has already approved the payment.")]
+    DuplicateApproval,
+    #[msg("Not enough approvals to execute this payment.")]
+    InsufficientApprovals,
+    #[msg("Withdrawal would leave the fee vault below rent exemption.")]
+    InsufficientFeeBalance,
+    #[msg("Stream end time must be after its start time.")]
+    InvalidStreamSchedule,
+    #[msg("No vested funds are available to withdraw.")]
+    NothingVested,
+    #[msg("This stream has already been canceled.")]
+    StreamAlreadyCanceled,
+    #[msg("Memo cannot contain control characters.")]
+    InvalidMemoContent,
+    #[msg("Transaction sequence does not follow the sender's last recorded sequence.")]
+    SequenceMismatch,
+    #[msg("Oracle account is not a verified Pyth price update for the expected feed.")]
+    InvalidOracle,
+    #[msg("Oracle price is too old.")]
+    StaleOracle,
+    #[msg("Oracle price has not reached the payment's threshold.")]
+    PriceConditionNotMet,
+    #[msg("The memo can no longer be edited.")]
+    MemoEditWindowClosed,
+    #[msg("Only the original sender can edit this memo.")]
+    UnauthorizedMemoEdit,
+    #[msg("Sender cannot cover the payment plus the transaction record's rent and fees.")]
+    InsufficientForRentAndPayment,
+    #[msg("Sender or receiver is blacklisted.")]
+    AddressBlacklisted,
+    #[msg("Memo does not match the required format.")]
+    MemoFormatInvalid,
+    #[msg("Memo pattern prefix is too long, not ASCII, or exceeds the memo limit.")]
+    InvalidMemoPattern,
+    #[msg("Expiry must be in the future and after the unlock time.")]
+    InvalidExpiry,
+    #[msg("Payment has expired and can only be reclaimed by the sender.")]
+    PaymentExpired,
+    #[msg("Payment has not expired yet.")]
+    PaymentNotExpired,
+    #[msg("Payment exceeds the delegate's remaining allowance.")]
+    DelegateAllowanceExceeded,
+    #[msg("Signer is not the owner's authorized delegate.")]
+    NotAuthorizedDelegate,
+    #[msg("A teardown has already been requested.")]
+    TeardownAlreadyRequested,
+    #[msg("No teardown has been requested.")]
+    TeardownNotRequested,
+    #[msg("The teardown delay has not elapsed yet.")]
+    TeardownDelayNotElapsed,
+    #[msg("Payment would leave the receiver below the rent-exempt minimum.")]
+    ReceiverWouldNotBeRentExempt,
+    #[msg("Too many records in one close_records_range call.")]
+    RangeTooLarge,
+    #[msg("Record is already at the current schema version.")]
+    RecordAlreadyMigrated,
+    #[msg("A payment to this receiver was sent too recently.")]
+    PaymentTooSoon,
+    #[msg("Campaign did not reach its goal.")]
+    GoalNotMet,
+    #[msg("Campaign deadline has not passed yet.")]
+    CampaignStillActive,
+    #[msg("Campaign reached its goal; contributions cannot be refunded.")]
+    CampaignSucceeded,
+    #[msg("Campaign deadline has passed.")]
+    CampaignEnded,
+    #[msg("Too many transfers for one instruction's compute budget; see logs for the suggested maximum.")]
+    BatchExceedsComputeBudget,
+    #[msg("Signer is not the pending authority.")]
+    NotPendingAuthority,
+    #[msg("Identical payment sent moments ago; pass force to send it anyway.")]
+    DuplicatePaymentSuspected,
+    #[msg("Hook program is missing, does not match the configured id, or is not allowed.")]
+    InvalidHookProgram,
+    #[msg("Payment hook program failed.")]
+    PaymentHookFailed,
+    #[msg("Signer is not this escrow's arbiter, or the escrow has no neutral arbiter.")]
+    NotArbiter,
+    #[msg("Escrow is disputed and can only be settled by the arbiter.")]
+    EscrowDisputed,
+    #[msg("Escrow has not been disputed.")]
+    EscrowNotDisputed,
+    #[msg("Memo limit exceeds the space reserved for memos.")]
+    MemoLimitTooLarge,
+    #[msg("Unknown payment category.")]
+    InvalidCategory,
+    #[msg("Transaction record does not match the expected amount or receiver.")]
+    RecordMismatch,
+    #[msg("Grace period must be positive.")]
+    InvalidGracePeriod,
+    #[msg("The sender can still cancel this payment.")]
+    GracePeriodActive,
+    #[msg("The grace period for cancelling this payment has ended.")]
+    GracePeriodExpired,
+    #[msg("A program counter would drop below zero.")]
+    CounterUnderflow,
+    #[msg("Payment amount is above the configured maximum.")]
+    AmountAboveMaximum,
+    #[msg("Scheduled payment is not due yet.")]
+    NotYetScheduled,
+    #[msg("Scheduled payment has already been executed.")]
+    ScheduledPaymentAlreadyExecuted,
+    #[msg("Records can grow by 1 to 10240 bytes per instruction.")]
+    ReallocTooLarge,
+    #[msg("This idempotency key was already used for a payment.")]
+    DuplicateIdempotencyKey,
+    #[msg("Pass the idempotency record account exactly when an idempotency key is given.")]
+    MissingIdempotencyRecord,
+    #[msg("Pass the pending transfer account exactly when the receiver requires acceptance.")]
+    MissingPendingTransfer,
+    #[msg("The receiver can still accept or reject this transfer.")]
+    TransferPending,
+    #[msg("This pending transfer has already been resolved.")]
+    TransferResolved,
+    #[msg("Interval is too large; the resulting time does not fit in an i64.")]
+    TimestampOverflow,
+    #[msg("Signer is not the owner of this transaction record.")]
+    NotRecordOwner,
+    #[msg("A transaction record cannot be assigned to the default public key.")]
+    InvalidRecordOwner,
+    #[msg("Payment would leave the sender below the reserve kept for transaction fees.")]
+    WouldDrainFeeReserve,
+    #[msg("Split shares must add up to exactly 10000 basis points.")]
+    InvalidShareSum,
+    #[msg("Receiver is an executable program account.")]
+    ReceiverIsExecutable,
+    #[msg("Fee split shares must add up to exactly 10000 basis points.")]
+    InvalidFeeSplit,
+    #[msg("Too many fee collectors in the fee split.")]
+    TooManyFeeCollectors,
+    #[msg("Escrow can only be recovered 90 days after creation.")]
+    EscrowRecoveryTooEarly,
+    #[msg("Note exceeds maximum length.")]
+    NoteTooLong,
+    #[msg("User batches must hold between 1 and 10 users.")]
+    UserBatchTooLarge,
+    #[msg("Receiver's whitelist entry has expired.")]
+    WhitelistEntryExpired,
+    #[msg("Whitelist entry has not expired.")]
+    WhitelistEntryNotExpired,
+    #[msg("Mint decimals do not match the mint account.")]
+    MintDecimalsMismatch,
+    #[msg("Sender's outgoing payments are frozen.")]
+    AccountFrozen,
+    #[msg("Raw data exceeds maximum length.")]
+    RawDataTooLong,
+    #[msg("Authority cannot be the default public key.")]
+    InvalidAuthority,
+    #[msg("Only the program's upgrade authority can initialize the program state.")]
+    NotUpgradeAuthority,
+    #[msg("No subscription change is pending.")]
+    NoPendingChange,
+    #[msg("Only the subscription's merchant can propose, and only its payer accept, a change.")]
+    UnauthorizedSubscriptionChange,
+    #[msg("Source and destination token accounts cannot be the same.")]
+    SelfTokenPayment,
+    #[msg("Oracle-converted amount is outside the payer's bounds.")]
+    SlippageExceeded,
+    #[msg("Transaction record was not prepared for this sequence or has already been written.")]
+    RecordNotPrepared,
+    #[msg("Payment would push the receiver above the maximum total received.")]
+    ReceiverCapExceeded,
+    #[msg("Program state is already at the current schema version")]
+    StateAlreadyMigrated,
+    #[msg("Account is not an initialized program state")]
+    StateNotInitialized,
+    #[msg("Receiver requires acceptance; pay them with send_payment")]
+    ReceiverRequiresAcceptance,
+    #[msg("A record already exists at this address; a prepared record must be sent with send_payment_prepared or closed first.")]
+    RecordAlreadyExists,
+    #[msg("This record's memo is a dictionary code and can't be edited.")]
+    CodedMemo,
+    #[msg("The pending subscription change is not the amount the payer expected.")]
+    SubscriptionChangeMismatch,
+    #[msg("Account is not a subscription")]
+    SubscriptionNotFound,
+    #[msg("Subscription is already in the current layout")]
+    SubscriptionAlreadyMigrated,
+    #[msg("Escrows or held payments are still open.")]
+    HoldsOutstanding,
+    #[msg("Conditional payment can't be reclaimed until its reclaim delay has passed.")]
+    ConditionalPaymentPending,
+    #[msg("Duplicate payment detection is on, so the recent payment account is required.")]
+    MissingDedupRecord,
+    #[msg("The last payment's duplicate window hasn't passed yet.")]
+    DedupWindowOpen,
     // Add other custom errors as needed
 }
@@ -0,0 +1,1089 @@
+// Instruction tests against solana-program-test's in-process bank, with no local
+// validator: send_payment's transfer, record, counters and validation branches, and
+// the flows that need the clock moved, like teardown. The program runs natively,
+// so compute units aren't metered; the `*_stays_within_its_compute_estimate` tests
+// load target/deploy/my_first_transaction.so instead and check the published
+// estimates against the units it used. They are ignored by default and run with
+//
+//     anchor build && cargo test -p my_first_transaction --test program_test -- --ignored
+
+use anchor_lang::prelude::{AccountInfo, Clock, Pubkey};
+use anchor_lang::solana_program::instruction::AccountMeta;
+use anchor_lang::solana_program::{
+    bpf_loader, bpf_loader_upgradeable, entrypoint::ProgramResult, instruction::Instruction, instruction::InstructionError,
+    rent::Rent, system_program,
+};
+use anchor_lang::{AccountDeserialize, AccountSerialize, Discriminator, InstructionData, ToAccountMetas};
+use my_first_transaction::{ErrorCode, Escrow, PaymentInstruction, ProgramState, Stream, TransactionRecord, UserState};
+use solana_account::Account;
+use solana_keypair::Keypair;
+use solana_program_test::{processor, tokio, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_signer::Signer;
+use solana_transaction::Transaction;
+use solana_transaction_error::TransactionError;
+
+const LAMPORTS_PER_SOL: u64 = 1_000_000_000;
+const SBF_PROGRAM: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../target/deploy/my_first_transaction.so");
+
+// Anchor's entrypoint ties the accounts to one lifetime, which the builtin processor
+// signature can't express, so the slice is leaked for the rest of the test
+fn process_instruction(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let accounts = Box::leak(Box::new(accounts.to_vec()));
+    my_first_transaction::entry(program_id, accounts, data)
+}
+
+fn instruction(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction { program_id: my_first_transaction::ID, accounts: accounts.to_account_metas(None), data: data.data() }
+}
+
+fn pda(seeds: &[&[u8]]) -> Pubkey {
+    Pubkey::find_program_address(seeds, &my_first_transaction::ID).0
+}
+
+// `UpgradeableLoaderState::ProgramData` as bincode lays it out: variant 3, the
+// deployment slot, then the optional upgrade authority
+fn program_data(upgrade_authority: &Pubkey) -> Account {
+    let mut data = 3u32.to_le_bytes().to_vec();
+    data.extend_from_slice(&0u64.to_le_bytes());
+    data.push(1);
+    data.extend_from_slice(upgrade_authority.as_ref());
+    Account {
+        lamports: LAMPORTS_PER_SOL,
+        data,
+        owner: bpf_loader_upgradeable::ID,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+fn wallet() -> Account {
+    Account::new(10 * LAMPORTS_PER_SOL, 0, &system_program::ID)
+}
+
+struct Harness {
+    context: ProgramTestContext,
+    authority: Keypair,
+    sender: Keypair,
+    receiver: Keypair,
+}
+
+impl Harness {
+    async fn start() -> Self {
+        Self::start_with(ProgramTest::new("my_first_transaction", my_first_transaction::ID, processor!(process_instruction))).await
+    }
+
+    // Runs the SBF build, so the bank meters compute units
+    async fn start_sbf() -> Self {
+        let elf = std::fs::read(SBF_PROGRAM)
+            .unwrap_or_else(|_| panic!("{SBF_PROGRAM} not found, run `anchor build` before the compute unit tests"));
+        let mut program_test = ProgramTest::default();
+        program_test.add_account(
+            my_first_transaction::ID,
+            Account {
+                lamports: Rent::default().minimum_balance(elf.len()),
+                data: elf,
+                owner: bpf_loader::ID,
+                executable: true,
+                rent_epoch: 0,
+            },
+        );
+        Self::start_with(program_test).await
+    }
+
+    async fn start_with(mut program_test: ProgramTest) -> Self {
+        let (authority, sender, receiver) = (Keypair::new(), Keypair::new(), Keypair::new());
+        for keypair in [&authority, &sender, &receiver] {
+            program_test.add_account(keypair.pubkey(), wallet());
+        }
+        program_test.add_account(pda_of_program_data(), program_data(&authority.pubkey()));
+        let context = program_test.start_with_context().await;
+        Harness { context, authority, sender, receiver }
+    }
+
+    async fn process(&mut self, instruction: Instruction, signers: &[&Keypair]) -> Result<(), BanksClientError> {
+        let blockhash = self.context.banks_client.get_latest_blockhash().await?;
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+        let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&self.context.payer.pubkey()), &all_signers, blockhash);
+        self.context.banks_client.process_transaction(transaction).await
+    }
+
+    // Processes `instruction`, which must succeed, and returns the compute units it used
+    async fn process_metered(&mut self, instruction: Instruction, signers: &[&Keypair]) -> u64 {
+        let blockhash = self.context.banks_client.get_latest_blockhash().await.unwrap();
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+        let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&self.context.payer.pubkey()), &all_signers, blockhash);
+        let outcome = self.context.banks_client.process_transaction_with_metadata(transaction).await.unwrap();
+        outcome.result.unwrap();
+        outcome.metadata.expect("transaction metadata").compute_units_consumed
+    }
+
+    async fn fetch<T: AccountDeserialize>(&mut self, address: Pubkey) -> T {
+        let account = self.context.banks_client.get_account(address).await.unwrap().expect("account exists");
+        T::try_deserialize(&mut account.data.as_slice()).unwrap()
+    }
+
+    async fn balance(&mut self, address: Pubkey) -> u64 {
+        self.context.banks_client.get_balance(address).await.unwrap()
+    }
+
+    async fn now(&mut self) -> i64 {
+        self.context.banks_client.get_sysvar::<Clock>().await.unwrap().unix_timestamp
+    }
+
+    // Moves the bank's clock forward. The slot moves on too, for a fresh blockhash, so
+    // a transaction retried after the warp isn't taken for the earlier one.
+    async fn warp(&mut self, secs: i64) {
+        let mut clock: Clock = self.context.banks_client.get_sysvar().await.unwrap();
+        self.context.warp_to_slot(clock.slot + 1).unwrap();
+        clock.slot += 1;
+        clock.unix_timestamp += secs;
+        self.context.set_sysvar(&clock);
+    }
+
+    fn initialize_state_instruction(&self, user: &Keypair) -> Instruction {
+        instruction(
+            my_first_transaction::accounts::InitializeState {
+                program_state: pda(&[b"state"]),
+                user: user.pubkey(),
+                program_data: pda_of_program_data(),
+                system_program: system_program::ID,
+            },
+            my_first_transaction::instruction::InitializeState { max_memo_length: 200, authority: self.authority.pubkey() },
+        )
+    }
+
+    async fn initialize(&mut self) {
+        let authority = self.authority.insecure_clone();
+        self.process(self.initialize_state_instruction(&authority), &[&authority]).await.unwrap();
+        let sender = self.sender.insecure_clone();
+        let initialize_user = instruction(
+            my_first_transaction::accounts::InitializeUser {
+                user_state: pda(&[b"user", sender.pubkey().as_ref()]),
+                user: sender.pubkey(),
+                system_program: system_program::ID,
+            },
+            my_first_transaction::instruction::InitializeUser {},
+        );
+        self.process(initialize_user, &[&sender]).await.unwrap();
+    }
+
+    async fn next_record(&mut self) -> Pubkey {
+        let sender = self.sender.pubkey();
+        let user_state: UserState = self.fetch(pda(&[b"user", sender.as_ref()])).await;
+        pda(&[b"transaction", sender.as_ref(), user_state.transaction_count.to_le_bytes().as_ref()])
+    }
+
+    // send_payment from `sender` with no optional accounts, the sender paying the record's rent
+    async fn send_payment(&mut self, receiver: Pubkey, amount: u64, memo: &str) -> Result<Pubkey, BanksClientError> {
+        let sender = self.sender.insecure_clone();
+        let (send_payment, transaction_record) = self.send_payment_instruction(receiver, amount, memo).await;
+        self.process(send_payment, &[&sender]).await?;
+        Ok(transaction_record)
+    }
+
+    async fn send_payment_instruction(&mut self, receiver: Pubkey, amount: u64, memo: &str) -> (Instruction, Pubkey) {
+        self.send_payment_instruction_with(receiver, amount, memo, false).await
+    }
+
+    // `dedup` passes the sender's RecentPayment and leaves `force` off
+    async fn send_payment_instruction_with(&mut self, receiver: Pubkey, amount: u64, memo: &str, dedup: bool) -> (Instruction, Pubkey) {
+        let program_state: ProgramState = self.fetch(pda(&[b"state"])).await;
+        let transaction_record = self.next_record().await;
+        let s = self.sender.pubkey();
+        let send_payment = instruction(
+            my_first_transaction::accounts::SendPayment {
+                sender: s,
+                receiver,
+                fee_collector: program_state.fee_collector,
+                system_program: system_program::ID,
+                transaction_record,
+                user_state: pda(&[b"user", s.as_ref()]),
+                program_state: pda(&[b"state"]),
+                whitelist_entry: None,
+                spend_limit: None,
+                sender_blacklist: pda(&[b"blacklist", s.as_ref()]),
+                receiver_blacklist: pda(&[b"blacklist", receiver.as_ref()]),
+                sender_freeze: pda(&[b"freeze", s.as_ref()]),
+                receiver_stats: pda(&[b"received", receiver.as_ref()]),
+                fee_exemption: None,
+                payment_pair: pda(&[b"pair", s.as_ref(), receiver.as_ref()]),
+                recent_payment: dedup.then(|| pda(&[b"dedup", s.as_ref()])),
+                hook_program: None,
+                idempotency_record: None,
+                receiver_acceptance: pda(&[b"accept", receiver.as_ref()]),
+                pending_transfer: None,
+                sender_ledger: pda(&[b"ledger", s.as_ref()]),
+                receiver_ledger: pda(&[b"ledger", receiver.as_ref()]),
+                rent_payer: s,
+            },
+            my_first_transaction::instruction::SendPayment {
+                amount,
+                memo: memo.to_string(),
+                encrypted_memo: vec![],
+                reference: None,
+                allow_below_rent: false,
+                force: !dedup,
+                category: 0,
+                idempotency_key: None,
+                error_on_duplicate: false,
+                allow_program_receiver: false,
+                note: String::new(),
+            },
+        );
+        (send_payment, transaction_record)
+    }
+
+    // send_batch_payment from `sender` of `payments`, all to `receiver`
+    async fn batch_payment_instruction(&mut self, receiver: Pubkey, payments: Vec<PaymentInstruction>) -> Instruction {
+        let program_state: ProgramState = self.fetch(pda(&[b"state"])).await;
+        let transaction_record = self.next_record().await;
+        let s = self.sender.pubkey();
+        let mut batch_payment = instruction(
+            my_first_transaction::accounts::SendBatchPayment {
+                sender: s,
+                system_program: system_program::ID,
+                transaction_record,
+                user_state: pda(&[b"user", s.as_ref()]),
+                program_state: pda(&[b"state"]),
+                sender_blacklist: pda(&[b"blacklist", s.as_ref()]),
+                sender_freeze: pda(&[b"freeze", s.as_ref()]),
+                spend_limit: None,
+                fee_collector: program_state.fee_collector,
+            },
+            my_first_transaction::instruction::SendBatchPayment { payments, memo: "Batch".to_string() },
+        );
+        batch_payment.accounts.extend([
+            AccountMeta::new(receiver, false),
+            AccountMeta::new_readonly(pda(&[b"blacklist", receiver.as_ref()]), false),
+            AccountMeta::new_readonly(pda(&[b"whitelist", receiver.as_ref()]), false),
+            AccountMeta::new(pda(&[b"received", receiver.as_ref()]), false),
+        ]);
+        batch_payment
+    }
+
+    fn create_escrow_instruction(&self, escrow: Pubkey, amount: u64) -> Instruction {
+        let (s, r) = (self.sender.pubkey(), self.receiver.pubkey());
+        instruction(
+            my_first_transaction::accounts::CreateEscrow {
+                sender: s,
+                receiver: r,
+                escrow,
+                program_state: pda(&[b"state"]),
+                system_program: system_program::ID,
+                sender_blacklist: pda(&[b"blacklist", s.as_ref()]),
+                receiver_blacklist: pda(&[b"blacklist", r.as_ref()]),
+                sender_freeze: pda(&[b"freeze", s.as_ref()]),
+                user_state: pda(&[b"user", s.as_ref()]),
+                spend_limit: None,
+                whitelist_entry: None,
+            },
+            my_first_transaction::instruction::CreateEscrow {
+                amount,
+                memo: "Escrow".to_string(),
+                expires_at: 0,
+                arbiter: Pubkey::default(),
+            },
+        )
+    }
+}
+
+fn pda_of_program_data() -> Pubkey {
+    Pubkey::find_program_address(&[my_first_transaction::ID.as_ref()], &bpf_loader_upgradeable::ID).0
+}
+
+// The program error a failed single-instruction transaction ended with
+fn error_code(error: BanksClientError) -> u32 {
+    match error.unwrap() {
+        TransactionError::InstructionError(0, InstructionError::Custom(code)) => code,
+        other => panic!("expected a program error, got {other:?}"),
+    }
+}
+
+fn code_of(error: ErrorCode) -> u32 {
+    anchor_lang::error::ERROR_CODE_OFFSET + error as u32
+}
+
+// A published estimate must cover the measured units without overstating them by
+// more than a fifth, so clients sizing a ComputeBudget request from it neither fail
+// nor overpay
+fn assert_matches_estimate(instruction: &str, units: u64, estimate: u64) {
+    println!("{instruction}: {units} CU, estimated {estimate}");
+    assert!(units <= estimate, "{instruction} used {units} CU, over its estimate of {estimate}");
+    assert!(units * 5 >= estimate * 4, "{instruction} used {units} CU, under 80% of its estimate of {estimate}");
+}
+
+#[tokio::test]
+async fn send_payment_moves_lamports_and_writes_the_record() {
+    let mut harness = Harness::start().await;
+    harness.initialize().await;
+    let receiver = harness.receiver.pubkey();
+    let amount = LAMPORTS_PER_SOL / 4;
+    let receiver_before = harness.balance(receiver).await;
+
+    let record_address = harness.send_payment(receiver, amount, "Harness payment").await.unwrap();
+
+    // The exported helpers derive the addresses the program created
+    assert_eq!(record_address, my_first_transaction::transaction_record_pda(&harness.sender.pubkey(), 0).0);
+    assert_eq!(pda(&[b"state"]), my_first_transaction::program_state_pda().0);
+    let record: TransactionRecord = harness.fetch(record_address).await;
+    assert_eq!(record.sender, harness.sender.pubkey());
+    assert_eq!(record.receiver, receiver);
+    assert_eq!(record.amount, amount);
+    assert_eq!(record.memo, "Harness payment");
+    assert_eq!(record.mint, None);
+    assert_eq!(record.receiver_count, 1);
+    assert_eq!(record.prev_sequence, None);
+    // The receiver gets the amount net of the fee stored on the record
+    assert_eq!(harness.balance(receiver).await - receiver_before, amount - record.fee);
+    let program_state: ProgramState = harness.fetch(pda(&[b"state"])).await;
+    assert_eq!(program_state.total_transactions, 1);
+
+    // The next record links back to this one
+    let next_address = harness.send_payment(receiver, amount, "Second").await.unwrap();
+    let next: TransactionRecord = harness.fetch(next_address).await;
+    assert_eq!(next.prev_sequence, Some(0));
+}
+
+#[tokio::test]
+async fn send_payment_rejects_invalid_payments() {
+    let mut harness = Harness::start().await;
+    harness.initialize().await;
+    let (sender, receiver) = (harness.sender.pubkey(), harness.receiver.pubkey());
+
+    let error = harness.send_payment(sender, LAMPORTS_PER_SOL / 100, "Self").await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::SelfPayment));
+    let error = harness.send_payment(receiver, 0, "Zero").await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::InvalidAmount));
+    let error = harness.send_payment(receiver, LAMPORTS_PER_SOL / 100, &"a".repeat(201)).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::MemoTooLong));
+    let error = harness.send_payment(receiver, 100 * LAMPORTS_PER_SOL, "Too much").await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::InsufficientForRentAndPayment));
+
+    // None of them wrote a record or moved the sequence
+    let user_state: UserState = harness.fetch(pda(&[b"user", sender.as_ref()])).await;
+    assert_eq!(user_state.transaction_count, 0);
+}
+
+#[tokio::test]
+async fn initialize_state_requires_the_upgrade_authority() {
+    let mut harness = Harness::start().await;
+    let impostor = harness.sender.insecure_clone();
+    let error = harness.process(harness.initialize_state_instruction(&impostor), &[&impostor]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::NotUpgradeAuthority));
+    assert!(harness.context.banks_client.get_account(pda(&[b"state"])).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn finalize_teardown_waits_for_held_payments() {
+    let mut harness = Harness::start().await;
+    harness.initialize().await;
+    let authority = harness.authority.insecure_clone();
+    let (sender, receiver) = (harness.sender.insecure_clone(), harness.receiver.insecure_clone());
+    let (s, r) = (sender.pubkey(), receiver.pubkey());
+    let timelock = pda(&[b"timelock", s.as_ref(), r.as_ref(), 0u64.to_le_bytes().as_ref()]);
+
+    let unlock_at = harness.now().await + 60;
+    let create_timelock = instruction(
+        my_first_transaction::accounts::CreateTimelockedPayment {
+            sender: s,
+            receiver: r,
+            timelock,
+            program_state: pda(&[b"state"]),
+            system_program: system_program::ID,
+            sender_blacklist: pda(&[b"blacklist", s.as_ref()]),
+            receiver_blacklist: pda(&[b"blacklist", r.as_ref()]),
+            sender_freeze: pda(&[b"freeze", s.as_ref()]),
+            user_state: pda(&[b"user", s.as_ref()]),
+            spend_limit: None,
+            whitelist_entry: None,
+        },
+        my_first_transaction::instruction::CreateTimelockedPayment {
+            amount: LAMPORTS_PER_SOL / 10,
+            unlock_at,
+            memo: "Held".to_string(),
+            expires_at: 0,
+        },
+    );
+    harness.process(create_timelock, &[&sender]).await.unwrap();
+    let program_state: ProgramState = harness.fetch(pda(&[b"state"])).await;
+    assert_eq!(program_state.open_holds, 1);
+
+    let finalize = || {
+        instruction(
+            my_first_transaction::accounts::FinalizeTeardown { authority: authority.pubkey(), program_state: pda(&[b"state"]) },
+            my_first_transaction::instruction::FinalizeTeardown {},
+        )
+    };
+    let begin = instruction(
+        my_first_transaction::accounts::BeginTeardown { authority: authority.pubkey(), program_state: pda(&[b"state"]) },
+        my_first_transaction::instruction::BeginTeardown {},
+    );
+    harness.process(begin, &[&authority]).await.unwrap();
+    // Past the teardown delay, but the timelock still needs the state to be claimed
+    harness.warp(8 * 86_400).await;
+    let error = harness.process(finalize(), &[&authority]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::HoldsOutstanding));
+
+    // The teardown keeps the program paused, and the claim goes through anyway
+    let claim = instruction(
+        my_first_transaction::accounts::ClaimTimelockedPayment {
+            receiver: r,
+            sender: s,
+            timelock,
+            program_state: pda(&[b"state"]),
+            receiver_stats: pda(&[b"received", r.as_ref()]),
+            system_program: system_program::ID,
+        },
+        my_first_transaction::instruction::ClaimTimelockedPayment {},
+    );
+    harness.process(claim, &[&receiver]).await.unwrap();
+
+    harness.process(finalize(), &[&authority]).await.unwrap();
+    assert!(harness.context.banks_client.get_account(pda(&[b"state"])).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn send_batch_payment_accepts_at_most_max_batch_size_entries() {
+    let mut harness = Harness::start().await;
+    harness.initialize().await;
+    let sender = harness.sender.insecure_clone();
+    let receiver = harness.receiver.pubkey();
+    let entries = |count: usize| (0..count).map(|_| PaymentInstruction { receiver_index: 0, amount: LAMPORTS_PER_SOL / 100 }).collect();
+
+    let oversized = harness.batch_payment_instruction(receiver, entries(my_first_transaction::MAX_BATCH_SIZE + 1)).await;
+    let error = harness.process(oversized, &[&sender]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::BatchExceedsComputeBudget));
+
+    let full = harness.batch_payment_instruction(receiver, entries(my_first_transaction::MAX_BATCH_SIZE)).await;
+    harness.process(full, &[&sender]).await.unwrap();
+}
+
+#[tokio::test]
+async fn send_batch_payment_counts_the_record_rent_against_the_balance() {
+    let mut harness = Harness::start().await;
+    harness.initialize().await;
+    let sender = harness.sender.insecure_clone();
+    let receiver = harness.receiver.pubkey();
+    let record_rent = harness.context.banks_client.get_rent().await.unwrap().minimum_balance(TransactionRecord::LEN);
+
+    // Enough for the payment itself, but not for the record on top of it
+    let amount = harness.balance(sender.pubkey()).await - record_rent / 2;
+    let short = harness.batch_payment_instruction(receiver, vec![PaymentInstruction { receiver_index: 0, amount }]).await;
+    let error = harness.process(short, &[&sender]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::InsufficientForRentAndPayment));
+    let user_state: UserState = harness.fetch(pda(&[b"user", sender.pubkey().as_ref()])).await;
+    assert_eq!(user_state.transaction_count, 0);
+}
+
+#[tokio::test]
+#[ignore = "needs the SBF build, see the comment at the top of this file"]
+async fn send_payment_stays_within_its_compute_estimate() {
+    let mut harness = Harness::start_sbf().await;
+    harness.initialize().await;
+    let sender = harness.sender.insecure_clone();
+    let receiver = harness.receiver.pubkey();
+    // The first payment to a receiver, which creates every init_if_needed account
+    let (send_payment, _) = harness.send_payment_instruction(receiver, LAMPORTS_PER_SOL / 100, "Metered").await;
+    let units = harness.process_metered(send_payment, &[&sender]).await;
+    assert_matches_estimate("send_payment", units, my_first_transaction::SEND_PAYMENT_CU_ESTIMATE);
+}
+
+#[tokio::test]
+#[ignore = "needs the SBF build, see the comment at the top of this file"]
+async fn send_batch_payment_stays_within_its_compute_estimate() {
+    let mut harness = Harness::start_sbf().await;
+    harness.initialize().await;
+    let sender = harness.sender.insecure_clone();
+    let receiver = harness.receiver.pubkey();
+    let max = my_first_transaction::MAX_BATCH_SIZE;
+    let payments = (0..max).map(|_| PaymentInstruction { receiver_index: 0, amount: LAMPORTS_PER_SOL / 100 }).collect();
+    let batch_payment = harness.batch_payment_instruction(receiver, payments).await;
+    let units = harness.process_metered(batch_payment, &[&sender]).await;
+    assert_matches_estimate("send_batch_payment", units, my_first_transaction::batch_payment_compute_units(max as u64));
+}
+
+#[tokio::test]
+#[ignore = "needs the SBF build, see the comment at the top of this file"]
+async fn send_split_payment_stays_within_its_compute_estimate() {
+    let mut harness = Harness::start_sbf().await;
+    harness.initialize().await;
+    let sender = harness.sender.insecure_clone();
+    let s = sender.pubkey();
+    let program_state: ProgramState = harness.fetch(pda(&[b"state"])).await;
+    // As many fresh receivers as fit in one legacy transaction, each creating its stats PDA
+    let receivers: Vec<Pubkey> = (0..5).map(|_| Pubkey::new_unique()).collect();
+    let mut split_payment = instruction(
+        my_first_transaction::accounts::SendSplitPayment {
+            sender: s,
+            system_program: system_program::ID,
+            transaction_record: harness.next_record().await,
+            user_state: pda(&[b"user", s.as_ref()]),
+            program_state: pda(&[b"state"]),
+            sender_blacklist: pda(&[b"blacklist", s.as_ref()]),
+            sender_freeze: pda(&[b"freeze", s.as_ref()]),
+            spend_limit: None,
+            fee_collector: program_state.fee_collector,
+        },
+        my_first_transaction::instruction::SendSplitPayment {
+            amounts: vec![LAMPORTS_PER_SOL / 100; receivers.len()],
+            memo: "Split".to_string(),
+        },
+    );
+    for receiver in &receivers {
+        split_payment.accounts.extend([
+            AccountMeta::new(*receiver, false),
+            AccountMeta::new_readonly(pda(&[b"blacklist", receiver.as_ref()]), false),
+            AccountMeta::new_readonly(pda(&[b"whitelist", receiver.as_ref()]), false),
+            AccountMeta::new(pda(&[b"received", receiver.as_ref()]), false),
+        ]);
+    }
+    let units = harness.process_metered(split_payment, &[&sender]).await;
+    assert_matches_estimate("send_split_payment", units, my_first_transaction::split_payment_compute_units(receivers.len() as u64));
+}
+
+#[tokio::test]
+#[ignore = "needs the SBF build, see the comment at the top of this file"]
+async fn escrow_instructions_stay_within_their_compute_estimates() {
+    let mut harness = Harness::start_sbf().await;
+    harness.initialize().await;
+    let sender = harness.sender.insecure_clone();
+    let (s, r) = (sender.pubkey(), harness.receiver.pubkey());
+    let escrow = pda(&[b"escrow", s.as_ref(), r.as_ref(), 0u64.to_le_bytes().as_ref()]);
+
+    let units = harness.process_metered(harness.create_escrow_instruction(escrow, LAMPORTS_PER_SOL / 10), &[&sender]).await;
+    assert_matches_estimate("create_escrow", units, my_first_transaction::CREATE_ESCROW_CU_ESTIMATE);
+
+    let program_state: ProgramState = harness.fetch(pda(&[b"state"])).await;
+    let release_escrow = instruction(
+        my_first_transaction::accounts::ReleaseEscrow {
+            sender: s,
+            receiver: r,
+            escrow,
+            transaction_record: harness.next_record().await,
+            user_state: pda(&[b"user", s.as_ref()]),
+            program_state: pda(&[b"state"]),
+            system_program: system_program::ID,
+            fee_collector: program_state.fee_collector,
+            receiver_stats: pda(&[b"received", r.as_ref()]),
+        },
+        my_first_transaction::instruction::ReleaseEscrow {},
+    );
+    let units = harness.process_metered(release_escrow, &[&sender]).await;
+    assert_matches_estimate("release_escrow", units, my_first_transaction::RELEASE_ESCROW_CU_ESTIMATE);
+}
+
+#[tokio::test]
+async fn sweep_dust_keeps_token_records() {
+    let mut harness = Harness::start().await;
+    harness.initialize().await;
+    let sender = harness.sender.insecure_clone();
+    let (s, receiver) = (sender.pubkey(), harness.receiver.pubkey());
+    let sol_record = harness.send_payment(receiver, 50_000, "Dust").await.unwrap();
+
+    // A token payment of 50_000 base units, e.g. 0.05 USDC, at the next sequence
+    let token_record = my_first_transaction::transaction_record_pda(&s, 1).0;
+    let record = TransactionRecord { sender: s, receiver, amount: 50_000, mint: Some(Pubkey::new_unique()), owner: s, ..Default::default() };
+    let mut data = vec![];
+    record.try_serialize(&mut data).unwrap();
+    let account = Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: my_first_transaction::ID,
+        executable: false,
+        rent_epoch: 0,
+    };
+    harness.context.set_account(&token_record, &account.into());
+
+    let mut sweep_dust = instruction(
+        my_first_transaction::accounts::SweepDust { sender: s },
+        my_first_transaction::instruction::SweepDust { sequences: vec![0, 1] },
+    );
+    for (sequence, record) in [(0u64, sol_record), (1, token_record)] {
+        sweep_dust.accounts.push(AccountMeta::new(record, false));
+        sweep_dust.accounts.push(AccountMeta::new_readonly(pda(&[b"pending", s.as_ref(), sequence.to_le_bytes().as_ref()]), false));
+    }
+    harness.process(sweep_dust, &[&sender]).await.unwrap();
+
+    assert!(harness.context.banks_client.get_account(sol_record).await.unwrap().is_none());
+    let kept: TransactionRecord = harness.fetch(token_record).await;
+    assert_eq!(kept.amount, 50_000);
+}
+
+#[tokio::test]
+async fn reinitializing_after_teardown_requires_the_upgrade_authority() {
+    let mut harness = Harness::start().await;
+    harness.initialize().await;
+    let authority = harness.authority.insecure_clone();
+    let begin = instruction(
+        my_first_transaction::accounts::BeginTeardown { authority: authority.pubkey(), program_state: pda(&[b"state"]) },
+        my_first_transaction::instruction::BeginTeardown {},
+    );
+    harness.process(begin, &[&authority]).await.unwrap();
+    harness.warp(8 * 86_400).await;
+    let finalize = instruction(
+        my_first_transaction::accounts::FinalizeTeardown { authority: authority.pubkey(), program_state: pda(&[b"state"]) },
+        my_first_transaction::instruction::FinalizeTeardown {},
+    );
+    harness.process(finalize, &[&authority]).await.unwrap();
+
+    // With the state gone, only the upgrade authority can recreate it
+    let impostor = harness.sender.insecure_clone();
+    let error = harness.process(harness.initialize_state_instruction(&impostor), &[&impostor]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::NotUpgradeAuthority));
+    harness.process(harness.initialize_state_instruction(&authority), &[&authority]).await.unwrap();
+    let program_state: ProgramState = harness.fetch(pda(&[b"state"])).await;
+    assert_eq!(program_state.authority, authority.pubkey());
+}
+
+#[tokio::test]
+async fn cancel_subscription_frees_the_pair_for_a_new_one() {
+    let mut harness = Harness::start().await;
+    harness.initialize().await;
+    let payer = harness.sender.insecure_clone();
+    let (p, merchant) = (payer.pubkey(), harness.receiver.pubkey());
+    let subscription = pda(&[b"sub", p.as_ref(), merchant.as_ref()]);
+    let create = |amount: u64| {
+        instruction(
+            my_first_transaction::accounts::CreateSubscription {
+                payer: p,
+                merchant,
+                subscription,
+                program_state: pda(&[b"state"]),
+                system_program: system_program::ID,
+                payer_freeze: pda(&[b"freeze", p.as_ref()]),
+                payer_blacklist: pda(&[b"blacklist", p.as_ref()]),
+                merchant_blacklist: pda(&[b"blacklist", merchant.as_ref()]),
+                merchant_acceptance: pda(&[b"accept", merchant.as_ref()]),
+                whitelist_entry: None,
+                user_state: pda(&[b"user", p.as_ref()]),
+                spend_limit: None,
+            },
+            my_first_transaction::instruction::CreateSubscription { amount, interval_secs: 3_600, deposit: 2 * amount },
+        )
+    };
+    let cancel = instruction(
+        my_first_transaction::accounts::CancelSubscription { payer: p, subscription, program_state: pda(&[b"state"]) },
+        my_first_transaction::instruction::CancelSubscription {},
+    );
+
+    harness.process(create(LAMPORTS_PER_SOL / 100), &[&payer]).await.unwrap();
+    let held = harness.balance(subscription).await;
+    let payer_before = harness.balance(p).await;
+    harness.process(cancel.clone(), &[&payer]).await.unwrap();
+    // The deposit and the rent both go back, and the hold is released
+    assert!(harness.context.banks_client.get_account(subscription).await.unwrap().is_none());
+    assert_eq!(harness.balance(p).await, payer_before + held);
+    let program_state: ProgramState = harness.fetch(pda(&[b"state"])).await;
+    assert_eq!(program_state.open_holds, 0);
+
+    harness.process(create(LAMPORTS_PER_SOL / 50), &[&payer]).await.unwrap();
+
+    // A blacklisted merchant can't be subscribed to
+    harness.process(cancel, &[&payer]).await.unwrap();
+    let authority = harness.authority.insecure_clone();
+    let add_blacklist = instruction(
+        my_first_transaction::accounts::AddBlacklist {
+            authority: authority.pubkey(),
+            program_state: pda(&[b"state"]),
+            address: merchant,
+            blacklist: pda(&[b"blacklist", merchant.as_ref()]),
+            system_program: system_program::ID,
+        },
+        my_first_transaction::instruction::AddBlacklist {},
+    );
+    harness.process(add_blacklist, &[&authority]).await.unwrap();
+    let error = harness.process(create(LAMPORTS_PER_SOL / 50), &[&payer]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::AddressBlacklisted));
+}
+
+#[tokio::test]
+async fn reclaim_conditional_payment_waits_out_the_reclaim_delay() {
+    let mut harness = Harness::start().await;
+    harness.initialize().await;
+    let sender = harness.sender.insecure_clone();
+    let (s, r) = (sender.pubkey(), harness.receiver.pubkey());
+    let conditional_payment = pda(&[b"conditional", s.as_ref(), r.as_ref(), 0u64.to_le_bytes().as_ref()]);
+    let create = instruction(
+        my_first_transaction::accounts::CreateConditionalPayment {
+            sender: s,
+            receiver: r,
+            conditional_payment,
+            program_state: pda(&[b"state"]),
+            system_program: system_program::ID,
+            sender_blacklist: pda(&[b"blacklist", s.as_ref()]),
+            receiver_blacklist: pda(&[b"blacklist", r.as_ref()]),
+            sender_freeze: pda(&[b"freeze", s.as_ref()]),
+            receiver_stats: pda(&[b"received", r.as_ref()]),
+            user_state: pda(&[b"user", s.as_ref()]),
+            spend_limit: None,
+            whitelist_entry: None,
+        },
+        my_first_transaction::instruction::CreateConditionalPayment {
+            amount: LAMPORTS_PER_SOL / 10,
+            price_threshold: 200,
+            feed_id: [7; 32],
+            memo: "If SOL > 200".to_string(),
+        },
+    );
+    harness.process(create, &[&sender]).await.unwrap();
+    let reclaim = || {
+        instruction(
+            my_first_transaction::accounts::ReclaimConditionalPayment {
+                sender: s,
+                conditional_payment,
+                program_state: pda(&[b"state"]),
+            },
+            my_first_transaction::instruction::ReclaimConditionalPayment {},
+        )
+    };
+
+    let error = harness.process(reclaim(), &[&sender]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::ConditionalPaymentPending));
+    // Still closed at the exact reclaim time, which belongs to execute
+    harness.warp(7 * 86_400).await;
+    let error = harness.process(reclaim(), &[&sender]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::ConditionalPaymentPending));
+    harness.warp(1).await;
+    harness.process(reclaim(), &[&sender]).await.unwrap();
+    assert!(harness.context.banks_client.get_account(conditional_payment).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn set_max_memo_length_changes_the_send_payment_limit() {
+    let mut harness = Harness::start().await;
+    harness.initialize().await;
+    let authority = harness.authority.insecure_clone();
+    let receiver = harness.receiver.pubkey();
+    let set_max_memo_length = |max_memo_length: u16| {
+        instruction(
+            my_first_transaction::accounts::SetMaxMemoLength { authority: authority.pubkey(), program_state: pda(&[b"state"]) },
+            my_first_transaction::instruction::SetMaxMemoLength { max_memo_length },
+        )
+    };
+
+    // Bounded by the bytes each record reserves for its memo
+    let error = harness.process(set_max_memo_length(u16::MAX), &[&authority]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::MemoLimitTooLarge));
+    let impostor = harness.sender.insecure_clone();
+    let mut not_authority = set_max_memo_length(50);
+    not_authority.accounts[0].pubkey = impostor.pubkey();
+    let error = harness.process(not_authority, &[&impostor]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::Unauthorized));
+
+    harness.process(set_max_memo_length(50), &[&authority]).await.unwrap();
+    let program_state: ProgramState = harness.fetch(pda(&[b"state"])).await;
+    assert_eq!(program_state.max_memo_length, 50);
+    let error = harness.send_payment(receiver, LAMPORTS_PER_SOL / 100, &"a".repeat(51)).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::MemoTooLong));
+    harness.send_payment(receiver, LAMPORTS_PER_SOL / 100, &"a".repeat(50)).await.unwrap();
+}
+
+#[tokio::test]
+async fn send_payment_only_keeps_a_dedup_record_while_dedup_is_on() {
+    let mut harness = Harness::start().await;
+    harness.initialize().await;
+    let (authority, sender) = (harness.authority.insecure_clone(), harness.sender.insecure_clone());
+    let (s, receiver) = (sender.pubkey(), harness.receiver.pubkey());
+    let recent_payment = pda(&[b"dedup", s.as_ref()]);
+
+    // Off by default: no record, no rent
+    harness.send_payment(receiver, LAMPORTS_PER_SOL / 100, "No dedup").await.unwrap();
+    assert!(harness.context.banks_client.get_account(recent_payment).await.unwrap().is_none());
+
+    let set_dedup_window = instruction(
+        my_first_transaction::accounts::SetDedupWindow { authority: authority.pubkey(), program_state: pda(&[b"state"]) },
+        my_first_transaction::instruction::SetDedupWindow { dedup_window_secs: 60 },
+    );
+    harness.process(set_dedup_window, &[&authority]).await.unwrap();
+    let error = harness.send_payment(receiver, LAMPORTS_PER_SOL / 100, "Missing").await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::MissingDedupRecord));
+    let (first, _) = harness.send_payment_instruction_with(receiver, LAMPORTS_PER_SOL / 100, "Retry", true).await;
+    harness.process(first, &[&sender]).await.unwrap();
+    let (retry, _) = harness.send_payment_instruction_with(receiver, LAMPORTS_PER_SOL / 100, "Retry", true).await;
+    let error = harness.process(retry, &[&sender]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::DuplicatePaymentSuspected));
+
+    // The sender gets the rent back once the window has passed
+    let close = || {
+        instruction(
+            my_first_transaction::accounts::CloseRecentPayment { sender: s, recent_payment, program_state: pda(&[b"state"]) },
+            my_first_transaction::instruction::CloseRecentPayment {},
+        )
+    };
+    let error = harness.process(close(), &[&sender]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::DedupWindowOpen));
+    harness.warp(60).await;
+    harness.process(close(), &[&sender]).await.unwrap();
+    assert!(harness.context.banks_client.get_account(recent_payment).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn migrate_state_lets_the_upgrade_authority_claim_a_baseline_state() {
+    let mut harness = Harness::start().await;
+    let authority = harness.authority.insecure_clone();
+    // The baseline layout: the discriminator and total_transactions, nothing else
+    let mut data = ProgramState::DISCRIMINATOR.to_vec();
+    data.extend_from_slice(&42u64.to_le_bytes());
+    let account = Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: my_first_transaction::ID,
+        executable: false,
+        rent_epoch: 0,
+    };
+    harness.context.set_account(&pda(&[b"state"]), &account.into());
+    let migrate = |signer: &Keypair| {
+        instruction(
+            my_first_transaction::accounts::MigrateState {
+                authority: signer.pubkey(),
+                program_state: pda(&[b"state"]),
+                system_program: system_program::ID,
+                program_data: Some(pda_of_program_data()),
+            },
+            my_first_transaction::instruction::MigrateState { active_escrows: 0, open_holds: 0 },
+        )
+    };
+
+    let impostor = harness.sender.insecure_clone();
+    let error = harness.process(migrate(&impostor), &[&impostor]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::NotUpgradeAuthority));
+
+    harness.process(migrate(&authority), &[&authority]).await.unwrap();
+    let program_state: ProgramState = harness.fetch(pda(&[b"state"])).await;
+    assert_eq!(program_state.total_transactions, 42);
+    assert_eq!(program_state.authority, authority.pubkey());
+    assert_eq!(program_state.fee_collector, authority.pubkey());
+    assert_eq!(program_state.schema_version, my_first_transaction::PROGRAM_STATE_VERSION);
+    assert_eq!(program_state.max_memo_length, 200);
+}
+
+#[tokio::test]
+async fn batch_and_escrow_payments_respect_the_whitelist_and_its_expiry() {
+    let mut harness = Harness::start().await;
+    harness.initialize().await;
+    let (authority, sender) = (harness.authority.insecure_clone(), harness.sender.insecure_clone());
+    let (s, receiver) = (sender.pubkey(), harness.receiver.pubkey());
+    let entries = || vec![PaymentInstruction { receiver_index: 0, amount: LAMPORTS_PER_SOL / 100 }];
+    let set_whitelist_enabled = instruction(
+        my_first_transaction::accounts::SetWhitelistEnabled { authority: authority.pubkey(), program_state: pda(&[b"state"]) },
+        my_first_transaction::instruction::SetWhitelistEnabled { enabled: true },
+    );
+    harness.process(set_whitelist_enabled, &[&authority]).await.unwrap();
+
+    let batch_payment = harness.batch_payment_instruction(receiver, entries()).await;
+    let error = harness.process(batch_payment, &[&sender]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::ReceiverNotWhitelisted));
+    let escrow = pda(&[b"escrow", s.as_ref(), receiver.as_ref(), 0u64.to_le_bytes().as_ref()]);
+    let error = harness.process(harness.create_escrow_instruction(escrow, LAMPORTS_PER_SOL / 10), &[&sender]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::ReceiverNotWhitelisted));
+
+    let whitelist_entry = pda(&[b"whitelist", receiver.as_ref()]);
+    let expires_at = harness.now().await + 60;
+    let add_whitelist = instruction(
+        my_first_transaction::accounts::AddWhitelist {
+            authority: authority.pubkey(),
+            program_state: pda(&[b"state"]),
+            address: receiver,
+            whitelist_entry,
+            system_program: system_program::ID,
+        },
+        my_first_transaction::instruction::AddWhitelist { expires_at },
+    );
+    harness.process(add_whitelist, &[&authority]).await.unwrap();
+    let batch_payment = harness.batch_payment_instruction(receiver, entries()).await;
+    harness.process(batch_payment, &[&sender]).await.unwrap();
+
+    // Once the entry lapses the receiver can't be paid again until it is renewed
+    harness.warp(61).await;
+    let batch_payment = harness.batch_payment_instruction(receiver, entries()).await;
+    let error = harness.process(batch_payment, &[&sender]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::WhitelistEntryExpired));
+    // The optional whitelist entry is the escrow's last account
+    let mut create_escrow = harness.create_escrow_instruction(escrow, LAMPORTS_PER_SOL / 10);
+    let whitelist_index = create_escrow.accounts.len() - 1;
+    create_escrow.accounts[whitelist_index].pubkey = whitelist_entry;
+    let error = harness.process(create_escrow, &[&sender]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::WhitelistEntryExpired));
+}
+
+#[tokio::test]
+async fn batch_and_escrow_payments_respect_the_amount_bounds() {
+    let mut harness = Harness::start().await;
+    harness.initialize().await;
+    let (authority, sender) = (harness.authority.insecure_clone(), harness.sender.insecure_clone());
+    let (s, receiver) = (sender.pubkey(), harness.receiver.pubkey());
+    let set_max_single_payment = instruction(
+        my_first_transaction::accounts::SetMaxSinglePayment { authority: authority.pubkey(), program_state: pda(&[b"state"]) },
+        my_first_transaction::instruction::SetMaxSinglePayment { max_single_payment: LAMPORTS_PER_SOL / 10 },
+    );
+    harness.process(set_max_single_payment, &[&authority]).await.unwrap();
+
+    // The cap applies to each entry, not just to the batch's total
+    let entry = |amount: u64| PaymentInstruction { receiver_index: 0, amount };
+    let batch_payment = harness.batch_payment_instruction(receiver, vec![entry(LAMPORTS_PER_SOL / 100), entry(LAMPORTS_PER_SOL / 5)]).await;
+    let error = harness.process(batch_payment, &[&sender]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::AmountAboveMaximum));
+    let batch_payment = harness.batch_payment_instruction(receiver, vec![entry(LAMPORTS_PER_SOL / 10), entry(LAMPORTS_PER_SOL / 10)]).await;
+    harness.process(batch_payment, &[&sender]).await.unwrap();
+
+    let escrow = pda(&[b"escrow", s.as_ref(), receiver.as_ref(), 0u64.to_le_bytes().as_ref()]);
+    let error = harness.process(harness.create_escrow_instruction(escrow, LAMPORTS_PER_SOL / 5), &[&sender]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::AmountAboveMaximum));
+    harness.process(harness.create_escrow_instruction(escrow, LAMPORTS_PER_SOL / 10), &[&sender]).await.unwrap();
+}
+
+#[tokio::test]
+async fn settled_escrows_are_never_paid_out_again() {
+    let mut harness = Harness::start().await;
+    harness.initialize().await;
+    let sender = harness.sender.insecure_clone();
+    let (s, r) = (sender.pubkey(), harness.receiver.pubkey());
+    let escrow_address = pda(&[b"escrow", s.as_ref(), r.as_ref(), 0u64.to_le_bytes().as_ref()]);
+    harness.process(harness.create_escrow_instruction(escrow_address, LAMPORTS_PER_SOL / 10), &[&sender]).await.unwrap();
+    let refund = || {
+        instruction(
+            my_first_transaction::accounts::RefundEscrow { sender: s, escrow: escrow_address, program_state: pda(&[b"state"]) },
+            my_first_transaction::instruction::RefundEscrow {},
+        )
+    };
+
+    // An escrow flagged as settled, as if an earlier payout had run in this transaction
+    let mut account = harness.context.banks_client.get_account(escrow_address).await.unwrap().unwrap();
+    let mut escrow = Escrow::try_deserialize(&mut account.data.as_slice()).unwrap();
+    escrow.settled = true;
+    let mut data = vec![];
+    escrow.try_serialize(&mut data).unwrap();
+    let original_data = std::mem::replace(&mut account.data, data);
+    harness.context.set_account(&escrow_address, &account.clone().into());
+    let error = harness.process(refund(), &[&sender]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::EscrowAlreadySettled));
+
+    // Or one whose lamports are already gone, down to its rent
+    account.data = original_data;
+    account.lamports = Rent::default().minimum_balance(account.data.len());
+    harness.context.set_account(&escrow_address, &account.into());
+    harness.warp(1).await;
+    let error = harness.process(refund(), &[&sender]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::EscrowAlreadySettled));
+}
+
+#[tokio::test]
+async fn grow_record_makes_room_for_a_longer_memo() {
+    let mut harness = Harness::start().await;
+    harness.initialize().await;
+    let sender = harness.sender.insecure_clone();
+    let receiver = harness.receiver.pubkey();
+    let record = harness.send_payment(receiver, LAMPORTS_PER_SOL / 100, "Short").await.unwrap();
+    let update_memo = |new_memo: String| {
+        instruction(
+            my_first_transaction::accounts::UpdateMemo {
+                sender: sender.pubkey(),
+                transaction_record: record,
+                program_state: pda(&[b"state"]),
+            },
+            my_first_transaction::instruction::UpdateMemo { new_memo },
+        )
+    };
+    let grow_record = |signer: &Keypair, additional_bytes: u32| {
+        instruction(
+            my_first_transaction::accounts::GrowRecord {
+                sender: signer.pubkey(),
+                transaction_record: record,
+                system_program: system_program::ID,
+            },
+            my_first_transaction::instruction::GrowRecord { additional_bytes },
+        )
+    };
+
+    let error = harness.process(update_memo("a".repeat(300)), &[&sender]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::MemoTooLong));
+    let impostor = harness.receiver.insecure_clone();
+    let error = harness.process(grow_record(&impostor, 400), &[&impostor]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::Unauthorized));
+    let error = harness.process(grow_record(&sender, 10_241), &[&sender]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::ReallocTooLarge));
+
+    // 400 more bytes hold 100 more characters at 4 bytes each
+    harness.process(grow_record(&sender, 400), &[&sender]).await.unwrap();
+    harness.process(update_memo("a".repeat(300)), &[&sender]).await.unwrap();
+    let grown: TransactionRecord = harness.fetch(record).await;
+    assert_eq!(grown.memo, "a".repeat(300));
+    assert_eq!(grown.amount, LAMPORTS_PER_SOL / 100);
+    let error = harness.process(update_memo("a".repeat(301)), &[&sender]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::MemoTooLong));
+
+    // Edits follow the configured limit, not the compile-time default
+    let authority = harness.authority.insecure_clone();
+    let set_max_memo_length = instruction(
+        my_first_transaction::accounts::SetMaxMemoLength { authority: authority.pubkey(), program_state: pda(&[b"state"]) },
+        my_first_transaction::instruction::SetMaxMemoLength { max_memo_length: 50 },
+    );
+    harness.process(set_max_memo_length, &[&authority]).await.unwrap();
+    let error = harness.process(update_memo("a".repeat(151)), &[&sender]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::MemoTooLong));
+    harness.process(update_memo("a".repeat(150)), &[&sender]).await.unwrap();
+}
+
+#[tokio::test]
+async fn canceled_streams_pay_out_nothing_more() {
+    let mut harness = Harness::start().await;
+    harness.initialize().await;
+    let (sender, recipient) = (harness.sender.insecure_clone(), harness.receiver.insecure_clone());
+    let (s, r) = (sender.pubkey(), recipient.pubkey());
+    let stream_address = pda(&[b"stream", s.as_ref(), r.as_ref(), 0u64.to_le_bytes().as_ref()]);
+    let start_ts = harness.now().await;
+    let create_stream = instruction(
+        my_first_transaction::accounts::CreateStream {
+            sender: s,
+            recipient: r,
+            stream: stream_address,
+            program_state: pda(&[b"state"]),
+            system_program: system_program::ID,
+            sender_blacklist: pda(&[b"blacklist", s.as_ref()]),
+            receiver_blacklist: pda(&[b"blacklist", r.as_ref()]),
+            sender_freeze: pda(&[b"freeze", s.as_ref()]),
+            user_state: pda(&[b"user", s.as_ref()]),
+            spend_limit: None,
+            whitelist_entry: None,
+        },
+        my_first_transaction::instruction::CreateStream { total: LAMPORTS_PER_SOL / 10, start_ts, end_ts: start_ts + 100, cliff_ts: 0 },
+    );
+    harness.process(create_stream, &[&sender]).await.unwrap();
+    harness.warp(50).await;
+
+    // A stream flagged as canceled, as if cancel_stream had run earlier in this transaction
+    let mut account = harness.context.banks_client.get_account(stream_address).await.unwrap().unwrap();
+    let mut stream = Stream::try_deserialize(&mut account.data.as_slice()).unwrap();
+    stream.canceled = true;
+    account.data.clear();
+    stream.try_serialize(&mut account.data).unwrap();
+    harness.context.set_account(&stream_address, &account.into());
+
+    let withdraw = instruction(
+        my_first_transaction::accounts::WithdrawStream {
+            recipient: r,
+            stream: stream_address,
+            program_state: pda(&[b"state"]),
+            receiver_stats: pda(&[b"received", r.as_ref()]),
+            system_program: system_program::ID,
+        },
+        my_first_transaction::instruction::WithdrawStream {},
+    );
+    let error = harness.process(withdraw, &[&recipient]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::StreamAlreadyCanceled));
+    let cancel = instruction(
+        my_first_transaction::accounts::CancelStream {
+            sender: s,
+            recipient: r,
+            stream: stream_address,
+            program_state: pda(&[b"state"]),
+            receiver_stats: pda(&[b"received", r.as_ref()]),
+            system_program: system_program::ID,
+        },
+        my_first_transaction::instruction::CancelStream {},
+    );
+    let error = harness.process(cancel, &[&sender]).await.unwrap_err();
+    assert_eq!(error_code(error), code_of(ErrorCode::StreamAlreadyCanceled));
+}
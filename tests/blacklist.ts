@@ -0,0 +1,63 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { MyFirstTransaction } from "../target/types/my_first_transaction";
+import { Keypair, PublicKey, LAMPORTS_PER_SOL } from "@solana/web3.js";
+import { expectError, sendSplitPayment } from "./helpers";
+
+// The blacklist on the payment paths that don't go through send_payment: split
+// receivers carry their blacklist PDA as a remaining account, the single-receiver
+// instructions take both parties' PDAs as named accounts.
+describe("blacklist", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.MyFirstTransaction as Program<MyFirstTransaction>;
+
+  const sender = Keypair.generate();
+  const receiver = Keypair.generate();
+  const blocked = Keypair.generate();
+  const [programStatePDA] = PublicKey.findProgramAddressSync([Buffer.from("state")], program.programId);
+  const blacklistPDA = (address: PublicKey) =>
+    PublicKey.findProgramAddressSync([Buffer.from("blacklist"), address.toBuffer()], program.programId)[0];
+
+  const splitToEach = (receivers: PublicKey[], blacklist?: PublicKey) =>
+    sendSplitPayment(program, sender, receivers, receivers.map(() => new anchor.BN(LAMPORTS_PER_SOL / 100)), { blacklist });
+
+  before(async () => {
+    if (!(await program.account.programState.fetchNullable(programStatePDA))) {
+      await program.methods.initializeState(200, provider.wallet.publicKey).accounts({ user: provider.wallet.publicKey }).rpc();
+    }
+    const airdropSignature = await provider.connection.requestAirdrop(sender.publicKey, LAMPORTS_PER_SOL);
+    await provider.connection.confirmTransaction(airdropSignature, "confirmed");
+    await program.methods.initializeUser().accounts({ user: sender.publicKey }).signers([sender]).rpc({ commitment: "confirmed" });
+    await program.methods
+      .addBlacklist()
+      .accounts({ authority: provider.wallet.publicKey, address: blocked.publicKey })
+      .rpc({ commitment: "confirmed" });
+  });
+
+  it("Rejects a split with a blacklisted receiver", async () => {
+    await expectError(splitToEach([receiver.publicKey, blocked.publicKey]), "AddressBlacklisted");
+  });
+
+  it("Rejects a split whose blacklist PDA doesn't belong to the receiver", async () => {
+    // An empty PDA of some other address would otherwise hide the blacklisting
+    await expectError(splitToEach([blocked.publicKey], blacklistPDA(receiver.publicKey)), "ConstraintSeeds");
+  });
+
+  it("Rejects an escrow for a blacklisted receiver", async () => {
+    const state = await program.account.programState.fetch(programStatePDA);
+    const [escrowPDA] = PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow"), sender.publicKey.toBuffer(), blocked.publicKey.toBuffer(), state.totalEscrows.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    await expectError(
+      program.methods
+        .createEscrow(new anchor.BN(LAMPORTS_PER_SOL / 100), "Escrow", new anchor.BN(0), PublicKey.default)
+        .accountsPartial({ sender: sender.publicKey, receiver: blocked.publicKey, escrow: escrowPDA, whitelistEntry: null, spendLimit: null })
+        .signers([sender])
+        .rpc({ commitment: "confirmed" }),
+      "AddressBlacklisted"
+    );
+  });
+});
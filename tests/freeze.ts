@@ -0,0 +1,68 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { MyFirstTransaction } from "../target/types/my_first_transaction";
+import { Keypair, PublicKey, LAMPORTS_PER_SOL } from "@solana/web3.js";
+import { expect } from "chai";
+import { expectError, sendSplitPayment } from "./helpers";
+
+// freeze_user holds every outgoing payment, not just send_payment: the split and
+// escrow paths are checked here, and both work again once the user is unfrozen.
+describe("freeze", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.MyFirstTransaction as Program<MyFirstTransaction>;
+
+  const sender = Keypair.generate();
+  const receiver = Keypair.generate();
+  const [programStatePDA] = PublicKey.findProgramAddressSync([Buffer.from("state")], program.programId);
+  const [freezeFlagPDA] = PublicKey.findProgramAddressSync([Buffer.from("freeze"), sender.publicKey.toBuffer()], program.programId);
+  const amount = new anchor.BN(LAMPORTS_PER_SOL / 100);
+
+  const createEscrow = async () => {
+    const state = await program.account.programState.fetch(programStatePDA);
+    const [escrow] = PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow"), sender.publicKey.toBuffer(), receiver.publicKey.toBuffer(), state.totalEscrows.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    return program.methods
+      .createEscrow(amount, "Escrow", new anchor.BN(0), PublicKey.default)
+      .accountsPartial({ sender: sender.publicKey, receiver: receiver.publicKey, escrow, whitelistEntry: null, spendLimit: null })
+      .signers([sender])
+      .rpc({ commitment: "confirmed" });
+  };
+
+  before(async () => {
+    if (!(await program.account.programState.fetchNullable(programStatePDA))) {
+      await program.methods.initializeState(200, provider.wallet.publicKey).accounts({ user: provider.wallet.publicKey }).rpc();
+    }
+    for (const wallet of [sender, receiver]) {
+      const airdropSignature = await provider.connection.requestAirdrop(wallet.publicKey, LAMPORTS_PER_SOL);
+      await provider.connection.confirmTransaction(airdropSignature, "confirmed");
+    }
+    await program.methods.initializeUser().accounts({ user: sender.publicKey }).signers([sender]).rpc({ commitment: "confirmed" });
+    await program.methods
+      .freezeUser()
+      .accounts({ authority: provider.wallet.publicKey, user: sender.publicKey })
+      .rpc({ commitment: "confirmed" });
+  });
+
+  it("Holds a frozen sender's split payment", async () => {
+    await expectError(sendSplitPayment(program, sender, [receiver.publicKey], [amount]), "AccountFrozen");
+  });
+
+  it("Holds a frozen sender's escrow", async () => {
+    await expectError(createEscrow(), "AccountFrozen");
+  });
+
+  it("Lets the payments through once unfrozen", async () => {
+    await program.methods
+      .unfreezeUser()
+      .accountsPartial({ authority: provider.wallet.publicKey, freezeFlag: freezeFlagPDA })
+      .rpc({ commitment: "confirmed" });
+    const receiverBefore = await provider.connection.getBalance(receiver.publicKey);
+    await sendSplitPayment(program, sender, [receiver.publicKey], [amount]);
+    expect((await provider.connection.getBalance(receiver.publicKey)) - receiverBefore).to.equal(amount.toNumber());
+    await createEscrow();
+  });
+});
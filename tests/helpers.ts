@@ -0,0 +1,70 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { MyFirstTransaction } from "../target/types/my_first_transaction";
+import { AccountMeta, Keypair, PublicKey } from "@solana/web3.js";
+import { expect } from "chai";
+
+// Shared by the suites in this directory. Holds no tests, so mocha loading it through
+// the tests/**/*.ts glob runs nothing.
+
+const pda = (program: Program<MyFirstTransaction>, prefix: string, address: PublicKey) =>
+  PublicKey.findProgramAddressSync([Buffer.from(prefix), address.toBuffer()], program.programId)[0];
+
+// PDA of the record the sender's next payment will create
+export const nextRecordPDA = async (program: Program<MyFirstTransaction>, sender: PublicKey): Promise<PublicKey> => {
+  const userState = await program.account.userState.fetch(pda(program, "user", sender));
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("transaction"), sender.toBuffer(), userState.transactionCount.toArrayLike(Buffer, "le", 8)],
+    program.programId
+  )[0];
+};
+
+// Fails unless the promise rejects with the program error named `code`
+export const expectError = async (promise: Promise<unknown>, code: string) => {
+  let error: unknown;
+  try {
+    await promise;
+  } catch (e) {
+    error = e;
+  }
+  expect(error, `Expected ${code}`).to.be.instanceOf(anchor.AnchorError);
+  expect((error as anchor.AnchorError).error.errorCode.code).to.equal(code);
+};
+
+// Split and batch remaining accounts: each receiver followed by its blacklist PDA,
+// unless `blacklist` overrides it, its whitelist PDA and its stats PDA
+export const receiverAccounts = (program: Program<MyFirstTransaction>, receivers: PublicKey[], blacklist?: PublicKey): AccountMeta[] =>
+  receivers.flatMap((pubkey) => [
+    { pubkey, isWritable: true, isSigner: false },
+    { pubkey: blacklist ?? pda(program, "blacklist", pubkey), isWritable: false, isSigner: false },
+    { pubkey: pda(program, "whitelist", pubkey), isWritable: false, isSigner: false },
+    { pubkey: pda(program, "received", pubkey), isWritable: true, isSigner: false },
+  ]);
+
+export type SplitOptions = {
+  memo?: string;
+  spendLimit?: PublicKey; // The sender's limit PDA, required once they have set a daily limit
+  blacklist?: PublicKey; // Passed in place of every receiver's own blacklist PDA
+};
+
+// send_split_payment of `amounts[i]` to `receivers[i]`, with no fee split configured
+export const sendSplitPayment = async (
+  program: Program<MyFirstTransaction>,
+  sender: Keypair,
+  receivers: PublicKey[],
+  amounts: anchor.BN[],
+  { memo = "Split", spendLimit, blacklist }: SplitOptions = {}
+) => {
+  const [programStatePDA] = PublicKey.findProgramAddressSync([Buffer.from("state")], program.programId);
+  return program.methods
+    .sendSplitPayment(amounts, memo)
+    .accountsPartial({
+      sender: sender.publicKey,
+      feeCollector: (await program.account.programState.fetch(programStatePDA)).feeCollector,
+      transactionRecord: await nextRecordPDA(program, sender.publicKey),
+      spendLimit: spendLimit ?? null,
+    })
+    .remainingAccounts(receiverAccounts(program, receivers, blacklist))
+    .signers([sender])
+    .rpc({ commitment: "confirmed" });
+};
@@ -0,0 +1,96 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { MyFirstTransaction } from "../target/types/my_first_transaction";
+import { Keypair, PublicKey, LAMPORTS_PER_SOL } from "@solana/web3.js";
+import { expect } from "chai";
+import { expectError, nextRecordPDA, sendSplitPayment } from "./helpers";
+
+// The multisig threshold outside send_payment: a split above it is refused, and the
+// same amount goes through propose/approve/execute only once it has its approval.
+// The threshold is switched off again afterwards for the other suites.
+describe("multisig", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.MyFirstTransaction as Program<MyFirstTransaction>;
+
+  const sender = Keypair.generate();
+  const receiver = Keypair.generate();
+  const approver = Keypair.generate();
+  const threshold = new anchor.BN(LAMPORTS_PER_SOL / 10);
+  const amount = threshold.muln(2);
+  const paymentId = new anchor.BN(1);
+  const [programStatePDA] = PublicKey.findProgramAddressSync([Buffer.from("state")], program.programId);
+  const [pendingPaymentPDA] = PublicKey.findProgramAddressSync(
+    [Buffer.from("pending"), sender.publicKey.toBuffer(), paymentId.toArrayLike(Buffer, "le", 8)],
+    program.programId
+  );
+
+  const setMultisigConfig = (thresholdAmount: anchor.BN, requiredApprovals: number, approvers: PublicKey[]) =>
+    program.methods
+      .setMultisigConfig(thresholdAmount, requiredApprovals, approvers)
+      .accounts({ authority: provider.wallet.publicKey })
+      .rpc({ commitment: "confirmed" });
+
+  const executePayment = async () => {
+    const state = await program.account.programState.fetch(programStatePDA);
+    return program.methods
+      .executePayment()
+      .accountsPartial({
+        sender: sender.publicKey,
+        receiver: receiver.publicKey,
+        feeCollector: state.feeCollector,
+        pendingPayment: pendingPaymentPDA,
+        transactionRecord: await nextRecordPDA(program, sender.publicKey),
+        whitelistEntry: null,
+        spendLimit: null,
+      })
+      .signers([sender])
+      .rpc({ commitment: "confirmed" });
+  };
+
+  before(async () => {
+    if (!(await program.account.programState.fetchNullable(programStatePDA))) {
+      await program.methods.initializeState(200, provider.wallet.publicKey).accounts({ user: provider.wallet.publicKey }).rpc();
+    }
+    const airdropSignature = await provider.connection.requestAirdrop(sender.publicKey, LAMPORTS_PER_SOL);
+    await provider.connection.confirmTransaction(airdropSignature, "confirmed");
+    await program.methods.initializeUser().accounts({ user: sender.publicKey }).signers([sender]).rpc({ commitment: "confirmed" });
+  });
+
+  after(async () => {
+    await setMultisigConfig(new anchor.BN(0), 1, [approver.publicKey]);
+  });
+
+  it("Rejects a config that needs no approvals", async () => {
+    await expectError(setMultisigConfig(threshold, 0, [approver.publicKey]), "InvalidMultisigConfig");
+    // Also while the threshold is off, since execute_payment still applies the count
+    await expectError(setMultisigConfig(new anchor.BN(0), 0, []), "InvalidMultisigConfig");
+  });
+
+  it("Refuses a split above the threshold", async () => {
+    await setMultisigConfig(threshold, 1, [approver.publicKey]);
+    await expectError(
+      sendSplitPayment(program, sender, [receiver.publicKey], [amount], { memo: "Too large" }),
+      "PaymentRequiresApproval"
+    );
+  });
+
+  it("Executes the proposed payment only once it is approved", async () => {
+    await program.methods
+      .proposePayment(paymentId, amount, "Approved payment")
+      .accounts({ sender: sender.publicKey, receiver: receiver.publicKey })
+      .signers([sender])
+      .rpc({ commitment: "confirmed" });
+    await expectError(executePayment(), "InsufficientApprovals");
+
+    await program.methods
+      .approvePayment()
+      .accounts({ approver: approver.publicKey, pendingPayment: pendingPaymentPDA })
+      .signers([approver])
+      .rpc({ commitment: "confirmed" });
+    await executePayment();
+    expect(await provider.connection.getBalance(receiver.publicKey)).to.be.greaterThan(0);
+    expect(await program.account.pendingPayment.fetchNullable(pendingPaymentPDA)).to.be.null;
+  });
+});
@@ -3,6 +3,7 @@ import { Program } from "@coral-xyz/anchor";
 import { MyFirstTransaction } from "../target/types/my_first_transaction";
 import { Keypair, SystemProgram, LAMPORTS_PER_SOL } from "@solana/web3.js";
 import { expect } from "chai";
+import { expectError, nextRecordPDA, receiverAccounts } from "./helpers";
 
 describe("peer_to_peer_payment", () => {
   // Configure the client to use the local cluster.
@@ -48,7 +49,7 @@ describe("peer_to_peer_payment", () => {
 
     // Execute the send_payment instruction
     const txSignature = await program.methods
-      .sendPayment(amountToSend, memo)
+      .sendPayment(amountToSend, memo, Buffer.alloc(0), null, false, false, 0, null, false, false, "")
       .accounts({
         sender: sender.publicKey,
         receiver: receiver.publicKey,
@@ -92,7 +93,7 @@ describe("peer_to_peer_payment", () => {
 
     /*
     await program.methods
-      .sendPayment(amountToSend, memo)
+      .sendPayment(amountToSend, memo, Buffer.alloc(0), null, false, false, 0, null, false, false, "")
       .accounts({
         sender: sender.publicKey,
         receiver: receiver.publicKey,
@@ -125,7 +126,7 @@ describe("peer_to_peer_payment", () => {
 
     try {
       await program.methods
-        .sendPayment(amountToSend, longMemo)
+        .sendPayment(amountToSend, longMemo, Buffer.alloc(0), null, false, false, 0, null, false, false, "")
         .accounts({
           sender: sender.publicKey,
           receiver: receiver.publicKey,
@@ -146,6 +147,224 @@ describe("peer_to_peer_payment", () => {
     }
   });
 
+  it("Fails when memo contains control characters", async () => {
+    const amountToSend = new anchor.BN(0.01 * LAMPORTS_PER_SOL);
+    const badMemo = "Invoice\u0000#42"; // Embedded null byte
+
+    try {
+      await program.methods
+        .sendPayment(amountToSend, badMemo, Buffer.alloc(0), null, false, false, 0, null, false, false, "")
+        .accounts({
+          sender: sender.publicKey,
+          receiver: receiver.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([sender])
+        .rpc();
+      expect.fail("Transaction should have failed due to control characters in memo");
+    } catch (error) {
+      expect(error.message).to.contain("InvalidMemoContent");
+    }
+  });
+
+  // Benchmark: verbose msg! logging vs the default event-only path of send_payment.
+  // Needs the provider wallet to be the program authority so it can toggle the flag.
+  it("Uses fewer compute units without verbose logging", async function () {
+    const [programStatePDA] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from("state")], program.programId);
+    let state = await program.account.programState.fetchNullable(programStatePDA);
+    if (!state) {
+      await program.methods.initializeState(200, provider.wallet.publicKey).accounts({ user: provider.wallet.publicKey }).rpc();
+      state = await program.account.programState.fetch(programStatePDA);
+    }
+    if (!state.authority.equals(provider.wallet.publicKey)) {
+      this.skip(); // Someone else administers this deployment
+    }
+
+    const [userStatePDA] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from("user"), sender.publicKey.toBuffer()], program.programId);
+    if (!(await program.account.userState.fetchNullable(userStatePDA))) {
+      await program.methods.initializeUser().accounts({ user: sender.publicKey }).signers([sender]).rpc();
+    }
+
+    const computeUnitsFor = async (verbose: boolean): Promise<number> => {
+      await program.methods.setVerboseLogging(verbose).accounts({ authority: provider.wallet.publicKey }).rpc();
+      const transactionRecordPDA = await nextRecordPDA(program, sender.publicKey);
+      const signature = await program.methods
+        .sendPayment(new anchor.BN(0.01 * LAMPORTS_PER_SOL), "Benchmark payment", Buffer.alloc(0), null, false, true, 0, null, false, false, "")
+        .accountsPartial({
+          sender: sender.publicKey,
+          receiver: receiver.publicKey,
+          feeCollector: state.feeCollector,
+          transactionRecord: transactionRecordPDA,
+          whitelistEntry: null,
+          spendLimit: null,
+          feeExemption: null,
+          hookProgram: null,
+          idempotencyRecord: null,
+          pendingTransfer: null,
+          rentPayer: sender.publicKey,
+        })
+        .signers([sender])
+        .rpc({ commitment: "confirmed" });
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      return tx.meta.computeUnitsConsumed;
+    };
+
+    const quietUnits = await computeUnitsFor(false);
+    const verboseUnits = await computeUnitsFor(true);
+    await program.methods.setVerboseLogging(false).accounts({ authority: provider.wallet.publicKey }).rpc();
+
+    console.log(`send_payment compute units: ${quietUnits} default, ${verboseUnits} verbose (${verboseUnits - quietUnits} saved)`);
+    expect(quietUnits).to.be.lessThan(verboseUnits);
+  });
+
+  // Checks the exported *_CU_ESTIMATE constants (see the IDL) against measured usage
+  it("Stays within the published compute unit estimates", async () => {
+    const estimate = (name: string): number => {
+      const constant = program.idl.constants.find((c) => c.name === name);
+      return Number(constant.value.replace(/_/g, ""));
+    };
+    const unitsOf = async (signature: string): Promise<number> => {
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      return tx.meta.computeUnitsConsumed;
+    };
+    // Same band as tests/program_test.rs: the estimate covers the measured units and
+    // overstates them by at most a fifth
+    const expectWithinEstimate = (units: number, estimated: number) =>
+      expect(units).to.be.within(Math.ceil(estimated * 0.8), estimated);
+
+    const [programStatePDA] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from("state")], program.programId);
+    let state = await program.account.programState.fetchNullable(programStatePDA);
+    if (!state) {
+      await program.methods.initializeState(200, provider.wallet.publicKey).accounts({ user: provider.wallet.publicKey }).rpc();
+      state = await program.account.programState.fetch(programStatePDA);
+    }
+    const [userStatePDA] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from("user"), sender.publicKey.toBuffer()], program.programId);
+    if (!(await program.account.userState.fetchNullable(userStatePDA))) {
+      await program.methods.initializeUser().accounts({ user: sender.publicKey }).signers([sender]).rpc();
+    }
+    const amount = new anchor.BN(0.01 * LAMPORTS_PER_SOL);
+
+    // A fresh receiver is the worst case: every per-receiver account is created
+    const freshReceiver = Keypair.generate();
+    const paymentSignature = await program.methods
+      .sendPayment(amount, "CU estimate", Buffer.alloc(0), null, false, true, 0, null, false, false, "")
+      .accountsPartial({
+        sender: sender.publicKey,
+        receiver: freshReceiver.publicKey,
+        feeCollector: state.feeCollector,
+        transactionRecord: await nextRecordPDA(program, sender.publicKey),
+        whitelistEntry: null,
+        spendLimit: null,
+        feeExemption: null,
+        hookProgram: null,
+        idempotencyRecord: null,
+        pendingTransfer: null,
+        rentPayer: sender.publicKey,
+      })
+      .signers([sender])
+      .rpc({ commitment: "confirmed" });
+    expectWithinEstimate(await unitsOf(paymentSignature), estimate("SEND_PAYMENT_CU_ESTIMATE"));
+
+    const splitReceivers = [Keypair.generate(), Keypair.generate()];
+    const splitSignature = await program.methods
+      .sendSplitPayment([amount, amount], "CU estimate")
+      .accountsPartial({ sender: sender.publicKey, feeCollector: state.feeCollector, transactionRecord: await nextRecordPDA(program, sender.publicKey), spendLimit: null })
+      .remainingAccounts(receiverAccounts(program, splitReceivers.map((r) => r.publicKey)))
+      .signers([sender])
+      .rpc({ commitment: "confirmed" });
+    expectWithinEstimate(
+      await unitsOf(splitSignature),
+      estimate("SPLIT_BASE_CU_ESTIMATE") + splitReceivers.length * estimate("CU_PER_TRANSFER_ESTIMATE")
+    );
+
+    const escrowPDA = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow"), sender.publicKey.toBuffer(), freshReceiver.publicKey.toBuffer(), state.totalEscrows.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    )[0];
+    const createSignature = await program.methods
+      .createEscrow(amount, "CU estimate", new anchor.BN(0), anchor.web3.PublicKey.default)
+      .accountsPartial({ sender: sender.publicKey, receiver: freshReceiver.publicKey, escrow: escrowPDA, whitelistEntry: null, spendLimit: null })
+      .signers([sender])
+      .rpc({ commitment: "confirmed" });
+    expectWithinEstimate(await unitsOf(createSignature), estimate("CREATE_ESCROW_CU_ESTIMATE"));
+
+    const releaseSignature = await program.methods
+      .releaseEscrow()
+      .accountsPartial({
+        sender: sender.publicKey,
+        receiver: freshReceiver.publicKey,
+        feeCollector: state.feeCollector,
+        escrow: escrowPDA,
+        transactionRecord: await nextRecordPDA(program, sender.publicKey),
+      })
+      .signers([sender])
+      .rpc({ commitment: "confirmed" });
+    expectWithinEstimate(await unitsOf(releaseSignature), estimate("RELEASE_ESCROW_CU_ESTIMATE"));
+  });
+
+  it("Rejects i64::MAX intervals with TimestampOverflow instead of wrapping", async () => {
+    const I64_MAX = new anchor.BN("9223372036854775807");
+    const merchant = Keypair.generate();
+    const airdropSignature = await provider.connection.requestAirdrop(merchant.publicKey, 0.1 * LAMPORTS_PER_SOL);
+    await provider.connection.confirmTransaction(airdropSignature, "confirmed");
+
+    // A never-charged subscription is due immediately, so the first charge works; the
+    // next due date (last charge + i64::MAX) does not fit in an i64 and must be rejected cleanly.
+    const chargeAmount = new anchor.BN(0.001 * LAMPORTS_PER_SOL);
+    const [userStatePDA] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from("user"), sender.publicKey.toBuffer()], program.programId);
+    if (!(await program.account.userState.fetchNullable(userStatePDA))) {
+      await program.methods.initializeUser().accounts({ user: sender.publicKey }).signers([sender]).rpc();
+    }
+    await program.methods
+      .createSubscription(chargeAmount, I64_MAX, chargeAmount.muln(2))
+      .accounts({ payer: sender.publicKey, merchant: merchant.publicKey, whitelistEntry: null, spendLimit: null })
+      .signers([sender])
+      .rpc();
+    const [subscriptionPDA] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("sub"), sender.publicKey.toBuffer(), merchant.publicKey.toBuffer()],
+      program.programId
+    );
+    await program.methods
+      .chargeSubscription()
+      .accountsPartial({ merchant: merchant.publicKey, subscription: subscriptionPDA })
+      .signers([merchant])
+      .rpc();
+
+    // The first charge stamped a real last_paid, so last_paid + i64::MAX overflows
+    const subscription = await program.account.subscription.fetch(subscriptionPDA);
+    expect(subscription.lastPaid.toNumber()).to.be.greaterThan(0);
+    await expectError(
+      program.methods
+        .chargeSubscription()
+        .accountsPartial({ merchant: merchant.publicKey, subscription: subscriptionPDA })
+        .signers([merchant])
+        .rpc(),
+      "TimestampOverflow"
+    );
+
+    // Same for a grace period that would end past i64::MAX
+    const [programStatePDA] = anchor.web3.PublicKey.findProgramAddressSync([Buffer.from("state")], program.programId);
+    const state = await program.account.programState.fetch(programStatePDA);
+    const [refundablePDA] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("refundable"), sender.publicKey.toBuffer(), merchant.publicKey.toBuffer(), state.totalRefundables.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    await expectError(
+      program.methods
+        .sendPaymentRefundable(chargeAmount, "Overflow test", I64_MAX)
+        .accountsPartial({ sender: sender.publicKey, receiver: merchant.publicKey, refundable: refundablePDA, whitelistEntry: null, spendLimit: null })
+        .signers([sender])
+        .rpc(),
+      "TimestampOverflow"
+    );
+  });
+
   it("Fails when sender has insufficient funds", async () => {
     // Create a new sender with zero balance initially
     const brokeSender = Keypair.generate();
@@ -156,7 +375,7 @@ describe("peer_to_peer_payment", () => {
 
     try {
       await program.methods
-        .sendPayment(amountToSend, memo)
+        .sendPayment(amountToSend, memo, Buffer.alloc(0), null, false, false, 0, null, false, false, "")
         .accounts({
           sender: brokeSender.publicKey, // Use the broke sender
           receiver: receiver.publicKey,
@@ -0,0 +1,73 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { MyFirstTransaction } from "../target/types/my_first_transaction";
+import { Keypair, PublicKey, LAMPORTS_PER_SOL } from "@solana/web3.js";
+import { expect } from "chai";
+import { expectError, sendSplitPayment } from "./helpers";
+
+// set_max_total_received and the per-receiver cap it sets. Split payments create
+// and update the receiver's stats from remaining accounts, so they are the path
+// checked here; the cap is reset to unlimited afterwards for the other suites.
+describe("receiver cap", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.MyFirstTransaction as Program<MyFirstTransaction>;
+
+  const sender = Keypair.generate();
+  const receiver = Keypair.generate();
+  const cap = new anchor.BN(LAMPORTS_PER_SOL / 10);
+  const [programStatePDA] = PublicKey.findProgramAddressSync([Buffer.from("state")], program.programId);
+  const [receiverStatsPDA] = PublicKey.findProgramAddressSync([Buffer.from("received"), receiver.publicKey.toBuffer()], program.programId);
+
+  const splitToReceiver = (amount: anchor.BN) =>
+    sendSplitPayment(program, sender, [receiver.publicKey], [amount], { memo: "Capped" });
+
+  const setMaxTotalReceived = (max: anchor.BN, authority?: Keypair) =>
+    program.methods
+      .setMaxTotalReceived(max)
+      .accounts({ authority: authority?.publicKey ?? provider.wallet.publicKey })
+      .signers(authority ? [authority] : [])
+      .rpc({ commitment: "confirmed" });
+
+  before(async () => {
+    if (!(await program.account.programState.fetchNullable(programStatePDA))) {
+      await program.methods.initializeState(200, provider.wallet.publicKey).accounts({ user: provider.wallet.publicKey }).rpc();
+    }
+    const airdropSignature = await provider.connection.requestAirdrop(sender.publicKey, LAMPORTS_PER_SOL);
+    await provider.connection.confirmTransaction(airdropSignature, "confirmed");
+    await program.methods.initializeUser().accounts({ user: sender.publicKey }).signers([sender]).rpc({ commitment: "confirmed" });
+  });
+
+  after(async () => {
+    await setMaxTotalReceived(new anchor.BN(0));
+  });
+
+  it("Only lets the authority set the cap", async () => {
+    await expectError(setMaxTotalReceived(cap, sender), "Unauthorized");
+    await setMaxTotalReceived(cap);
+    expect((await program.account.programState.fetch(programStatePDA)).maxTotalReceived.eq(cap)).to.be.true;
+  });
+
+  it("Counts split payments towards the receiver's stats", async () => {
+    const amount = cap.divn(2);
+    await splitToReceiver(amount);
+    const stats = await program.account.receiverStats.fetch(receiverStatsPDA);
+    expect(stats.receiver.equals(receiver.publicKey)).to.be.true;
+    expect(stats.totalReceived.eq(amount)).to.be.true;
+    expect(stats.paymentCount.toNumber()).to.equal(1);
+  });
+
+  it("Rejects a payment that takes the receiver past the cap", async () => {
+    const receiverBefore = await provider.connection.getBalance(receiver.publicKey);
+    await expectError(splitToReceiver(cap), "ReceiverCapExceeded");
+    expect(await provider.connection.getBalance(receiver.publicKey)).to.equal(receiverBefore);
+    expect((await program.account.receiverStats.fetch(receiverStatsPDA)).totalReceived.eq(cap.divn(2))).to.be.true;
+  });
+
+  it("Lets the receiver be paid again once the cap is lifted", async () => {
+    await setMaxTotalReceived(new anchor.BN(0));
+    await splitToReceiver(cap);
+    expect((await program.account.receiverStats.fetch(receiverStatsPDA)).totalReceived.eq(cap.muln(3).divn(2))).to.be.true;
+  });
+});
@@ -0,0 +1,195 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { MyFirstTransaction } from "../target/types/my_first_transaction";
+import { Keypair, PublicKey, LAMPORTS_PER_SOL } from "@solana/web3.js";
+import { expect } from "chai";
+import { expectError, nextRecordPDA } from "./helpers";
+
+// End-to-end coverage of send_payment against a local validator: the transfer itself,
+// the TransactionRecord it writes, the global counter, and each validation branch.
+describe("send_payment", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.MyFirstTransaction as Program<MyFirstTransaction>;
+
+  const sender = Keypair.generate();
+  const receiver = Keypair.generate();
+  const [programStatePDA] = PublicKey.findProgramAddressSync([Buffer.from("state")], program.programId);
+  const [userStatePDA] = PublicKey.findProgramAddressSync([Buffer.from("user"), sender.publicKey.toBuffer()], program.programId);
+
+  // The sender pays the record's rent unless a sponsor is given
+  const sendPayment = async (to: PublicKey, amount: anchor.BN, memo: string, sponsor?: Keypair): Promise<PublicKey> => {
+    const state = await program.account.programState.fetch(programStatePDA);
+    const transactionRecordPDA = await nextRecordPDA(program, sender.publicKey);
+    await program.methods
+      .sendPayment(amount, memo, Buffer.alloc(0), null, false, true, 0, null, false, false, "")
+      .accountsPartial({
+        sender: sender.publicKey,
+        receiver: to,
+        feeCollector: state.feeCollector,
+        transactionRecord: transactionRecordPDA,
+        whitelistEntry: null,
+        spendLimit: null,
+        feeExemption: null,
+        hookProgram: null,
+        idempotencyRecord: null,
+        pendingTransfer: null,
+        rentPayer: (sponsor ?? sender).publicKey,
+      })
+      .signers(sponsor ? [sender, sponsor] : [sender])
+      .rpc({ commitment: "confirmed" });
+    return transactionRecordPDA;
+  };
+
+  before(async () => {
+    if (!(await program.account.programState.fetchNullable(programStatePDA))) {
+      await program.methods.initializeState(200, provider.wallet.publicKey).accounts({ user: provider.wallet.publicKey }).rpc();
+    }
+    const airdropSignature = await provider.connection.requestAirdrop(sender.publicKey, 2 * LAMPORTS_PER_SOL);
+    await provider.connection.confirmTransaction(airdropSignature, "confirmed");
+    await program.methods.initializeUser().accounts({ user: sender.publicKey }).signers([sender]).rpc({ commitment: "confirmed" });
+  });
+
+  it("Moves lamports, writes the record and counts the transaction", async () => {
+    const amount = new anchor.BN(0.25 * LAMPORTS_PER_SOL);
+    const memo = "Harness payment";
+    const receiverBefore = await provider.connection.getBalance(receiver.publicKey);
+    const totalBefore = (await program.account.programState.fetch(programStatePDA)).totalTransactions;
+
+    const recordPDA = await sendPayment(receiver.publicKey, amount, memo);
+
+    const record = await program.account.transactionRecord.fetch(recordPDA);
+    expect(record.sender.equals(sender.publicKey)).to.be.true;
+    expect(record.receiver.equals(receiver.publicKey)).to.be.true;
+    expect(record.amount.eq(amount)).to.be.true;
+    expect(record.memo).to.equal(memo);
+    expect(record.mint).to.be.null;
+    expect(record.receiverCount).to.equal(1);
+    expect(record.timestamp.toNumber()).to.be.greaterThan(0);
+
+    // The receiver gets the amount net of the protocol fee stored on the record
+    const receiverAfter = await provider.connection.getBalance(receiver.publicKey);
+    expect(receiverAfter - receiverBefore).to.equal(amount.sub(record.fee).toNumber());
+
+    const totalAfter = (await program.account.programState.fetch(programStatePDA)).totalTransactions;
+    expect(totalAfter.eq(totalBefore.addn(1))).to.be.true;
+  });
+
+  it("Lets a sponsor pay the record's rent", async () => {
+    const sponsor = Keypair.generate();
+    const airdropSignature = await provider.connection.requestAirdrop(sponsor.publicKey, LAMPORTS_PER_SOL);
+    await provider.connection.confirmTransaction(airdropSignature, "confirmed");
+    const amount = new anchor.BN(0.05 * LAMPORTS_PER_SOL);
+    const senderBefore = await provider.connection.getBalance(sender.publicKey, "confirmed");
+    const sponsorBefore = await provider.connection.getBalance(sponsor.publicKey, "confirmed");
+
+    const recordPDA = await sendPayment(receiver.publicKey, amount, "Sponsored payment", sponsor);
+
+    // The receiver's accounts exist from the first test, so the record is all that's created
+    const recordSize = (await provider.connection.getAccountInfo(recordPDA, "confirmed")).data.length;
+    const recordRent = await provider.connection.getMinimumBalanceForRentExemption(recordSize);
+    expect(senderBefore - (await provider.connection.getBalance(sender.publicKey, "confirmed"))).to.equal(amount.toNumber());
+    expect(sponsorBefore - (await provider.connection.getBalance(sponsor.publicKey, "confirmed"))).to.equal(recordRent);
+    // The receipt still belongs to the sender
+    expect((await program.account.transactionRecord.fetch(recordPDA)).owner.equals(sender.publicKey)).to.be.true;
+  });
+
+  it("Keeps a held payment's record open until the receiver resolves it", async () => {
+    const cautious = Keypair.generate();
+    const airdropSignature = await provider.connection.requestAirdrop(cautious.publicKey, LAMPORTS_PER_SOL / 10);
+    await provider.connection.confirmTransaction(airdropSignature, "confirmed");
+    await program.methods.requireAcceptance().accounts({ receiver: cautious.publicKey }).signers([cautious]).rpc({ commitment: "confirmed" });
+
+    const state = await program.account.programState.fetch(programStatePDA);
+    const sequence = (await program.account.userState.fetch(userStatePDA)).transactionCount;
+    const transactionRecord = await nextRecordPDA(program, sender.publicKey);
+    const [pendingTransfer] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pending"), sender.publicKey.toBuffer(), sequence.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    await program.methods
+      .sendPayment(new anchor.BN(LAMPORTS_PER_SOL / 100), "Held", Buffer.alloc(0), null, false, true, 0, null, false, false, "")
+      .accountsPartial({
+        sender: sender.publicKey,
+        receiver: cautious.publicKey,
+        feeCollector: state.feeCollector,
+        transactionRecord,
+        whitelistEntry: null,
+        spendLimit: null,
+        feeExemption: null,
+        hookProgram: null,
+        idempotencyRecord: null,
+        pendingTransfer,
+        rentPayer: sender.publicKey,
+      })
+      .signers([sender])
+      .rpc({ commitment: "confirmed" });
+
+    const closeRecord = () =>
+      program.methods
+        .closeTransactionRecord(sequence)
+        .accountsPartial({ sender: sender.publicKey, transactionRecord, pendingTransfer })
+        .signers([sender])
+        .rpc({ commitment: "confirmed" });
+    // reject_transfer and reclaim_transfer still need the record
+    await expectError(closeRecord(), "TransferPending");
+
+    await program.methods
+      .rejectTransfer()
+      .accountsPartial({ receiver: cautious.publicKey, sender: sender.publicKey, pendingTransfer })
+      .signers([cautious])
+      .rpc({ commitment: "confirmed" });
+    await closeRecord();
+    expect(await program.account.transactionRecord.fetchNullable(transactionRecord)).to.be.null;
+  });
+
+  it("Rejects a payment to the sender itself", async () => {
+    await expectError(sendPayment(sender.publicKey, new anchor.BN(LAMPORTS_PER_SOL / 100), "Self"), "SelfPayment");
+  });
+
+  it("Rejects a zero amount", async () => {
+    await expectError(sendPayment(receiver.publicKey, new anchor.BN(0), "Zero"), "InvalidAmount");
+  });
+
+  it("Rejects a memo over the configured limit", async () => {
+    const state = await program.account.programState.fetch(programStatePDA);
+    const memo = "a".repeat(state.maxMemoLength + 1);
+    await expectError(sendPayment(receiver.publicKey, new anchor.BN(LAMPORTS_PER_SOL / 100), memo), "MemoTooLong");
+  });
+
+  // Regression: leaving the sender with a non-zero balance below its own rent-exempt
+  // minimum used to pass the program's check and then fail in the runtime.
+  it("Rejects exactly one lamport past the sender's rent-exempt minimum", async () => {
+    // Warm up so the per-receiver accounts exist and only the record is created below
+    const warmUpRecord = await sendPayment(receiver.publicKey, new anchor.BN(LAMPORTS_PER_SOL / 100), "Warm-up");
+    const recordSize = (await provider.connection.getAccountInfo(warmUpRecord)).data.length;
+    const recordRent = await provider.connection.getMinimumBalanceForRentExemption(recordSize);
+    const senderRent = await provider.connection.getMinimumBalanceForRentExemption(0);
+
+    // The provider wallet pays the transaction fee, so the sender only pays the record's rent
+    const balance = await provider.connection.getBalance(sender.publicKey, "confirmed");
+    const spendable = balance - recordRent - senderRent;
+    const rejectedRecord = await nextRecordPDA(program, sender.publicKey);
+    await expectError(
+      sendPayment(receiver.publicKey, new anchor.BN(spendable + 1), "One lamport too many"),
+      "InsufficientForRentAndPayment"
+    );
+    // The rejected attempt moved nothing and created no record
+    expect(await provider.connection.getBalance(sender.publicKey, "confirmed")).to.equal(balance);
+    expect(await provider.connection.getAccountInfo(rejectedRecord, "confirmed")).to.be.null;
+
+    await sendPayment(receiver.publicKey, new anchor.BN(spendable), "Exactly at the boundary");
+    expect(await provider.connection.getBalance(sender.publicKey, "confirmed")).to.equal(senderRent);
+  });
+
+  it("Rejects a payment larger than the sender's balance", async () => {
+    // The boundary test left the sender unable to cover even the record's rent, which
+    // used to fail inside the System Program before the program's own check ran
+    const balance = await provider.connection.getBalance(sender.publicKey);
+    await expectError(
+      sendPayment(receiver.publicKey, new anchor.BN(balance + LAMPORTS_PER_SOL), "Too much"),
+      "InsufficientForRentAndPayment"
+    );
+  });
+});
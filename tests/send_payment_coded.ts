@@ -0,0 +1,73 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { MyFirstTransaction } from "../target/types/my_first_transaction";
+import { Keypair, PublicKey, LAMPORTS_PER_SOL } from "@solana/web3.js";
+import { expect } from "chai";
+import { expectError, nextRecordPDA } from "./helpers";
+
+// register_memo + send_payment_coded against a local validator: the record stores
+// only the dictionary code, is allocated without memo room, and keeps its code.
+describe("send_payment_coded", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.MyFirstTransaction as Program<MyFirstTransaction>;
+
+  const sender = Keypair.generate();
+  const receiver = Keypair.generate();
+  const memoCode = 7;
+  const memoText = "Monthly rent";
+  const [programStatePDA] = PublicKey.findProgramAddressSync([Buffer.from("state")], program.programId);
+
+  const registerMemo = (code: number, text: string) =>
+    program.methods.registerMemo(code, text).accounts({ authority: provider.wallet.publicKey }).rpc({ commitment: "confirmed" });
+
+  before(async () => {
+    if (!(await program.account.programState.fetchNullable(programStatePDA))) {
+      await program.methods.initializeState(200, provider.wallet.publicKey).accounts({ user: provider.wallet.publicKey }).rpc();
+    }
+    const airdropSignature = await provider.connection.requestAirdrop(sender.publicKey, LAMPORTS_PER_SOL);
+    await provider.connection.confirmTransaction(airdropSignature, "confirmed");
+    await program.methods.initializeUser().accounts({ user: sender.publicKey }).signers([sender]).rpc({ commitment: "confirmed" });
+  });
+
+  it("Only registers text within the configured memo limit", async () => {
+    const state = await program.account.programState.fetch(programStatePDA);
+    await expectError(registerMemo(memoCode + 1, "a".repeat(state.maxMemoLength + 1)), "MemoTooLong");
+    await registerMemo(memoCode, memoText);
+  });
+
+  it("Writes a record with the code and no memo room", async () => {
+    const state = await program.account.programState.fetch(programStatePDA);
+    const transactionRecord = await nextRecordPDA(program, sender.publicKey);
+    await program.methods
+      .sendPaymentCoded(new anchor.BN(LAMPORTS_PER_SOL / 100), memoCode)
+      .accountsPartial({
+        sender: sender.publicKey,
+        receiver: receiver.publicKey,
+        feeCollector: state.feeCollector,
+        transactionRecord,
+        whitelistEntry: null,
+        spendLimit: null,
+        hookProgram: null,
+      })
+      .signers([sender])
+      .rpc({ commitment: "confirmed" });
+
+    const record = await program.account.transactionRecord.fetch(transactionRecord);
+    expect(record.memoCode).to.equal(memoCode);
+    expect(record.memo).to.equal("");
+    // TransactionRecord::LEN (1806, as in the app) minus the memo, encrypted memo, note and data room
+    const codedSize = (await provider.connection.getAccountInfo(transactionRecord, "confirmed")).data.length;
+    expect(codedSize).to.equal(1806 - 4 * 200 - 256 - 4 * 100 - 64);
+
+    await expectError(
+      program.methods
+        .updateMemo("Edited")
+        .accounts({ sender: sender.publicKey, transactionRecord })
+        .signers([sender])
+        .rpc({ commitment: "confirmed" }),
+      "CodedMemo"
+    );
+  });
+});
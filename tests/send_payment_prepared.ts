@@ -0,0 +1,164 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { MyFirstTransaction } from "../target/types/my_first_transaction";
+import { Keypair, PublicKey, LAMPORTS_PER_SOL } from "@solana/web3.js";
+import { expect } from "chai";
+import { expectError, nextRecordPDA } from "./helpers";
+
+// prepare_record + send_payment_prepared against a local validator: the prepared
+// record is filled in by the payment, goes through the same policy checks as
+// send_payment, and blocks the other payment instructions at its sequence.
+describe("send_payment_prepared", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.MyFirstTransaction as Program<MyFirstTransaction>;
+
+  const sender = Keypair.generate();
+  const receiver = Keypair.generate();
+  const [programStatePDA] = PublicKey.findProgramAddressSync([Buffer.from("state")], program.programId);
+  const [userStatePDA] = PublicKey.findProgramAddressSync([Buffer.from("user"), sender.publicKey.toBuffer()], program.programId);
+
+  const prepareRecord = async (): Promise<PublicKey> => {
+    const transactionRecord = await nextRecordPDA(program, sender.publicKey);
+    await program.methods
+      .prepareRecord()
+      .accountsPartial({ sender: sender.publicKey, transactionRecord })
+      .signers([sender])
+      .rpc({ commitment: "confirmed" });
+    return transactionRecord;
+  };
+
+  const sendPaymentPrepared = async (to: PublicKey, amount: anchor.BN, memo: string) => {
+    const state = await program.account.programState.fetch(programStatePDA);
+    await program.methods
+      .sendPaymentPrepared(amount, memo)
+      .accountsPartial({
+        sender: sender.publicKey,
+        receiver: to,
+        feeCollector: state.feeCollector,
+        transactionRecord: await nextRecordPDA(program, sender.publicKey),
+        whitelistEntry: null,
+        spendLimit: null,
+        hookProgram: null,
+      })
+      .signers([sender])
+      .rpc({ commitment: "confirmed" });
+  };
+
+  before(async () => {
+    if (!(await program.account.programState.fetchNullable(programStatePDA))) {
+      await program.methods.initializeState(200, provider.wallet.publicKey).accounts({ user: provider.wallet.publicKey }).rpc();
+    }
+    const airdropSignature = await provider.connection.requestAirdrop(sender.publicKey, 2 * LAMPORTS_PER_SOL);
+    await provider.connection.confirmTransaction(airdropSignature, "confirmed");
+    await program.methods.initializeUser().accounts({ user: sender.publicKey }).signers([sender]).rpc({ commitment: "confirmed" });
+  });
+
+  it("Fills in the prepared record and credits the receiver", async () => {
+    const amount = new anchor.BN(0.1 * LAMPORTS_PER_SOL);
+    const recordPDA = await prepareRecord();
+    const receiverBefore = await provider.connection.getBalance(receiver.publicKey);
+    const countBefore = (await program.account.userState.fetch(userStatePDA)).transactionCount;
+
+    await sendPaymentPrepared(receiver.publicKey, amount, "Prepared payment");
+
+    const record = await program.account.transactionRecord.fetch(recordPDA);
+    expect(record.receiver.equals(receiver.publicKey)).to.be.true;
+    expect(record.amount.eq(amount)).to.be.true;
+    expect(record.timestamp.toNumber()).to.be.greaterThan(0);
+    const net = amount.sub(record.fee);
+    expect((await provider.connection.getBalance(receiver.publicKey)) - receiverBefore).to.equal(net.toNumber());
+    expect((await program.account.userState.fetch(userStatePDA)).transactionCount.eq(countBefore.addn(1))).to.be.true;
+
+    // Stats and ledgers are updated exactly as send_payment updates them
+    const [receiverStatsPDA] = PublicKey.findProgramAddressSync([Buffer.from("received"), receiver.publicKey.toBuffer()], program.programId);
+    expect((await program.account.receiverStats.fetch(receiverStatsPDA)).totalReceived.eq(net)).to.be.true;
+    const [receiverLedgerPDA] = PublicKey.findProgramAddressSync([Buffer.from("ledger"), receiver.publicKey.toBuffer()], program.programId);
+    expect((await program.account.userLedger.fetch(receiverLedgerPDA)).totalReceived.eq(net)).to.be.true;
+  });
+
+  it("Only fills in the record at the sender's next sequence", async () => {
+    const recordPDA = await nextRecordPDA(program, sender.publicKey);
+    const state = await program.account.programState.fetch(programStatePDA);
+    await sendPaymentPrepared(receiver.publicKey, new anchor.BN(LAMPORTS_PER_SOL / 100), "First");
+    // Aim at the record just written rather than the next sequence
+    await expectError(
+      program.methods
+        .sendPaymentPrepared(new anchor.BN(LAMPORTS_PER_SOL / 100), "Second")
+        .accountsPartial({
+          sender: sender.publicKey,
+          receiver: receiver.publicKey,
+          feeCollector: state.feeCollector,
+          transactionRecord: recordPDA,
+          whitelistEntry: null,
+          spendLimit: null,
+          hookProgram: null,
+        })
+        .signers([sender])
+        .rpc({ commitment: "confirmed" }),
+      "ConstraintSeeds"
+    );
+  });
+
+  it("Rejects a blacklisted receiver and leaves the record prepared", async () => {
+    const blocked = Keypair.generate();
+    await program.methods
+      .addBlacklist()
+      .accounts({ authority: provider.wallet.publicKey, address: blocked.publicKey })
+      .rpc({ commitment: "confirmed" });
+    const recordPDA = await prepareRecord();
+
+    await expectError(sendPaymentPrepared(blocked.publicKey, new anchor.BN(LAMPORTS_PER_SOL / 100), "Blocked"), "AddressBlacklisted");
+    expect((await program.account.transactionRecord.fetch(recordPDA)).timestamp.toNumber()).to.equal(0);
+  });
+
+  it("Refuses receivers who require acceptance", async () => {
+    const cautious = Keypair.generate();
+    const airdropSignature = await provider.connection.requestAirdrop(cautious.publicKey, LAMPORTS_PER_SOL / 10);
+    await provider.connection.confirmTransaction(airdropSignature, "confirmed");
+    await program.methods.requireAcceptance().accounts({ receiver: cautious.publicKey }).signers([cautious]).rpc({ commitment: "confirmed" });
+
+    await expectError(
+      sendPaymentPrepared(cautious.publicKey, new anchor.BN(LAMPORTS_PER_SOL / 100), "Needs acceptance"),
+      "ReceiverRequiresAcceptance"
+    );
+  });
+
+  it("Blocks other payment instructions at a prepared sequence until the record is closed", async () => {
+    const state = await program.account.programState.fetch(programStatePDA);
+    // The previous test left a prepared record at the next sequence
+    const recordPDA = await nextRecordPDA(program, sender.publicKey);
+    expect((await program.account.transactionRecord.fetch(recordPDA)).timestamp.toNumber()).to.equal(0);
+
+    const sendPayment = () =>
+      program.methods
+        .sendPayment(new anchor.BN(LAMPORTS_PER_SOL / 100), "Plain", Buffer.alloc(0), null, false, true, 0, null, false, false, "")
+        .accountsPartial({
+          sender: sender.publicKey,
+          receiver: receiver.publicKey,
+          feeCollector: state.feeCollector,
+          transactionRecord: recordPDA,
+          whitelistEntry: null,
+          spendLimit: null,
+          feeExemption: null,
+          hookProgram: null,
+          idempotencyRecord: null,
+          pendingTransfer: null,
+          rentPayer: sender.publicKey,
+        })
+        .signers([sender])
+        .rpc({ commitment: "confirmed" });
+
+    await expectError(sendPayment(), "RecordAlreadyExists");
+
+    const sequence = (await program.account.userState.fetch(userStatePDA)).transactionCount;
+    await program.methods
+      .closeTransactionRecord(sequence)
+      .accountsPartial({ sender: sender.publicKey, transactionRecord: recordPDA })
+      .signers([sender])
+      .rpc({ commitment: "confirmed" });
+    await sendPayment();
+    expect((await program.account.transactionRecord.fetch(recordPDA)).timestamp.toNumber()).to.be.greaterThan(0);
+  });
+});
@@ -0,0 +1,106 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { MyFirstTransaction } from "../target/types/my_first_transaction";
+import {
+  Keypair,
+  PublicKey,
+  LAMPORTS_PER_SOL,
+  SystemProgram,
+  Transaction,
+  TransactionInstruction,
+  SYSVAR_RENT_PUBKEY,
+} from "@solana/web3.js";
+import { expectError, nextRecordPDA } from "./helpers";
+
+const TOKEN_PROGRAM_ID = new PublicKey("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+const ASSOCIATED_TOKEN_PROGRAM_ID = new PublicKey("ATokenGPvbd6vcSYsdwbiGk1bXdLvEQrY5jjVvaqqAZS");
+const MINT_SIZE = 82;
+
+// send_token_payment against a local validator. The SPL instructions are built by
+// hand to keep @solana/spl-token out of the dependencies.
+describe("send_token_payment", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.MyFirstTransaction as Program<MyFirstTransaction>;
+
+  const sender = Keypair.generate();
+  const mint = Keypair.generate();
+  const decimals = 6;
+  const [programStatePDA] = PublicKey.findProgramAddressSync([Buffer.from("state")], program.programId);
+  const senderTokenAccount = anchor.utils.token.associatedAddress({ mint: mint.publicKey, owner: sender.publicKey });
+
+  before(async () => {
+    if (!(await program.account.programState.fetchNullable(programStatePDA))) {
+      await program.methods.initializeState(200, provider.wallet.publicKey).accounts({ user: provider.wallet.publicKey }).rpc();
+    }
+    const airdropSignature = await provider.connection.requestAirdrop(sender.publicKey, LAMPORTS_PER_SOL);
+    await provider.connection.confirmTransaction(airdropSignature, "confirmed");
+    await program.methods.initializeUser().accounts({ user: sender.publicKey }).signers([sender]).rpc({ commitment: "confirmed" });
+
+    // Mint with the sender as mint authority, plus the sender's ATA holding 1,000 tokens
+    const mintAmount = new anchor.BN(1_000 * 10 ** decimals);
+    const tx = new Transaction().add(
+      SystemProgram.createAccount({
+        fromPubkey: sender.publicKey,
+        newAccountPubkey: mint.publicKey,
+        space: MINT_SIZE,
+        lamports: await provider.connection.getMinimumBalanceForRentExemption(MINT_SIZE),
+        programId: TOKEN_PROGRAM_ID,
+      }),
+      new TransactionInstruction({
+        programId: TOKEN_PROGRAM_ID,
+        keys: [
+          { pubkey: mint.publicKey, isSigner: false, isWritable: true },
+          { pubkey: SYSVAR_RENT_PUBKEY, isSigner: false, isWritable: false },
+        ],
+        // InitializeMint: decimals, mint authority, no freeze authority
+        data: Buffer.concat([Buffer.from([0, decimals]), sender.publicKey.toBuffer(), Buffer.from([0])]),
+      }),
+      new TransactionInstruction({
+        programId: ASSOCIATED_TOKEN_PROGRAM_ID,
+        keys: [
+          { pubkey: sender.publicKey, isSigner: true, isWritable: true },
+          { pubkey: senderTokenAccount, isSigner: false, isWritable: true },
+          { pubkey: sender.publicKey, isSigner: false, isWritable: false },
+          { pubkey: mint.publicKey, isSigner: false, isWritable: false },
+          { pubkey: SystemProgram.programId, isSigner: false, isWritable: false },
+          { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false },
+        ],
+        data: Buffer.alloc(0),
+      }),
+      new TransactionInstruction({
+        programId: TOKEN_PROGRAM_ID,
+        keys: [
+          { pubkey: mint.publicKey, isSigner: false, isWritable: true },
+          { pubkey: senderTokenAccount, isSigner: false, isWritable: true },
+          { pubkey: sender.publicKey, isSigner: true, isWritable: false },
+        ],
+        // MintTo
+        data: Buffer.concat([Buffer.from([7]), mintAmount.toArrayLike(Buffer, "le", 8)]),
+      })
+    );
+    await provider.sendAndConfirm(tx, [sender, mint], { commitment: "confirmed" });
+  });
+
+  it("Rejects the same token account as source and destination", async () => {
+    const amount = new anchor.BN(10 ** decimals);
+    await expectError(
+      program.methods
+        .sendTokenPayment(amount, "Self", decimals)
+        .accountsPartial({
+          sender: sender.publicKey,
+          receiver: sender.publicKey,
+          mint: mint.publicKey,
+          senderTokenAccount,
+          receiverTokenAccount: senderTokenAccount,
+          transactionRecord: await nextRecordPDA(program, sender.publicKey),
+          whitelistEntry: null,
+          spendLimit: null,
+        })
+        .signers([sender])
+        .rpc({ commitment: "confirmed" }),
+      "SelfTokenPayment"
+    );
+  });
+});
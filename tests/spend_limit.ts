@@ -0,0 +1,59 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { MyFirstTransaction } from "../target/types/my_first_transaction";
+import { Keypair, PublicKey, LAMPORTS_PER_SOL } from "@solana/web3.js";
+import { expect } from "chai";
+import { expectError, sendSplitPayment } from "./helpers";
+
+// The daily spend limit outside send_payment: split payments count against it, and
+// an escrow can't be created without passing the sender's SpendLimit.
+describe("spend limit", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.MyFirstTransaction as Program<MyFirstTransaction>;
+
+  const sender = Keypair.generate();
+  const receiver = Keypair.generate();
+  const dailyLimit = new anchor.BN(LAMPORTS_PER_SOL / 20);
+  const amount = new anchor.BN(LAMPORTS_PER_SOL / 50);
+  const [programStatePDA] = PublicKey.findProgramAddressSync([Buffer.from("state")], program.programId);
+  const [spendLimitPDA] = PublicKey.findProgramAddressSync([Buffer.from("limit"), sender.publicKey.toBuffer()], program.programId);
+
+  const splitToReceiver = () =>
+    sendSplitPayment(program, sender, [receiver.publicKey], [amount], { memo: "Limited", spendLimit: spendLimitPDA });
+
+  before(async () => {
+    if (!(await program.account.programState.fetchNullable(programStatePDA))) {
+      await program.methods.initializeState(200, provider.wallet.publicKey).accounts({ user: provider.wallet.publicKey }).rpc();
+    }
+    const airdropSignature = await provider.connection.requestAirdrop(sender.publicKey, LAMPORTS_PER_SOL);
+    await provider.connection.confirmTransaction(airdropSignature, "confirmed");
+    await program.methods.initializeUser().accounts({ user: sender.publicKey }).signers([sender]).rpc({ commitment: "confirmed" });
+    await program.methods.setSpendLimit(dailyLimit).accounts({ owner: sender.publicKey }).signers([sender]).rpc({ commitment: "confirmed" });
+  });
+
+  it("Counts split payments against the daily limit", async () => {
+    await splitToReceiver();
+    await splitToReceiver();
+    expect((await program.account.spendLimit.fetch(spendLimitPDA)).spentToday.eq(amount.muln(2))).to.be.true;
+    // A third one would take the day's total past the limit
+    await expectError(splitToReceiver(), "DailyLimitExceeded");
+  });
+
+  it("Requires the SpendLimit for an escrow", async () => {
+    const state = await program.account.programState.fetch(programStatePDA);
+    const [escrow] = PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow"), sender.publicKey.toBuffer(), receiver.publicKey.toBuffer(), state.totalEscrows.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    await expectError(
+      program.methods
+        .createEscrow(amount, "Escrow", new anchor.BN(0), PublicKey.default)
+        .accountsPartial({ sender: sender.publicKey, receiver: receiver.publicKey, escrow, whitelistEntry: null, spendLimit: null })
+        .signers([sender])
+        .rpc({ commitment: "confirmed" }),
+      "MissingSpendLimit"
+    );
+  });
+});
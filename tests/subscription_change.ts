@@ -0,0 +1,78 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { MyFirstTransaction } from "../target/types/my_first_transaction";
+import { Keypair, PublicKey, LAMPORTS_PER_SOL } from "@solana/web3.js";
+import { expect } from "chai";
+import { expectError } from "./helpers";
+
+// propose/accept_subscription_change: the payer accepts a specific amount, so a
+// proposal replaced before their accept lands is refused rather than agreed to.
+describe("subscription change", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.MyFirstTransaction as Program<MyFirstTransaction>;
+
+  const payer = Keypair.generate();
+  const merchant = Keypair.generate();
+  const amount = new anchor.BN(LAMPORTS_PER_SOL / 100);
+  const [programStatePDA] = PublicKey.findProgramAddressSync([Buffer.from("state")], program.programId);
+  const [subscriptionPDA] = PublicKey.findProgramAddressSync(
+    [Buffer.from("sub"), payer.publicKey.toBuffer(), merchant.publicKey.toBuffer()],
+    program.programId
+  );
+
+  const proposeChange = (newAmount: anchor.BN) =>
+    program.methods
+      .proposeSubscriptionChange(newAmount)
+      .accountsPartial({ merchant: merchant.publicKey, subscription: subscriptionPDA })
+      .signers([merchant])
+      .rpc({ commitment: "confirmed" });
+
+  const acceptChange = (expectedAmount: anchor.BN) =>
+    program.methods
+      .acceptSubscriptionChange(expectedAmount)
+      .accountsPartial({ payer: payer.publicKey, subscription: subscriptionPDA })
+      .signers([payer])
+      .rpc({ commitment: "confirmed" });
+
+  before(async () => {
+    if (!(await program.account.programState.fetchNullable(programStatePDA))) {
+      await program.methods.initializeState(200, provider.wallet.publicKey).accounts({ user: provider.wallet.publicKey }).rpc();
+    }
+    const airdropSignature = await provider.connection.requestAirdrop(payer.publicKey, LAMPORTS_PER_SOL);
+    await provider.connection.confirmTransaction(airdropSignature, "confirmed");
+    await program.methods.initializeUser().accounts({ user: payer.publicKey }).signers([payer]).rpc({ commitment: "confirmed" });
+    await program.methods
+      .createSubscription(amount, new anchor.BN(3600), amount.muln(2))
+      .accounts({ payer: payer.publicKey, merchant: merchant.publicKey, whitelistEntry: null, spendLimit: null })
+      .signers([payer])
+      .rpc({ commitment: "confirmed" });
+  });
+
+  it("Refuses an accept for a proposal that was replaced", async () => {
+    await proposeChange(amount.muln(2));
+    // The merchant raises the price again before the payer's accept lands
+    await proposeChange(amount.muln(5));
+    await expectError(acceptChange(amount.muln(2)), "SubscriptionChangeMismatch");
+    expect((await program.account.subscription.fetch(subscriptionPDA)).amount.eq(amount)).to.be.true;
+  });
+
+  it("Applies the amount the payer accepted", async () => {
+    await acceptChange(amount.muln(5));
+    const subscription = await program.account.subscription.fetch(subscriptionPDA);
+    expect(subscription.amount.eq(amount.muln(5))).to.be.true;
+    expect(subscription.pendingAmount).to.be.null;
+  });
+
+  it("Leaves current subscriptions alone in migrate_subscription", async () => {
+    await expectError(
+      program.methods
+        .migrateSubscription()
+        .accountsPartial({ payer: payer.publicKey, merchant: merchant.publicKey, subscription: subscriptionPDA })
+        .signers([payer])
+        .rpc({ commitment: "confirmed" }),
+      "SubscriptionAlreadyMigrated"
+    );
+  });
+});